@@ -1,11 +1,17 @@
 use crate::helpers::*;
 use crate::state::State;
 use crate::types::*;
-use crate::{bitcoin, ckbtc, ordinals, runes, schnorr, solana, vetkeys};
+use crate::{
+    assets, bitcoin, ckbtc, collateral, deadman, disbursements, documents, guards, musig2, notes,
+    oracle, ordinals, pool, psbt, repayments, rotation, runes, schnorr, share_token, solana, terms,
+    timelock, vetkeys,
+};
 use candid::Principal;
+use disbursements::Outcome;
+use std::collections::HashMap;
 
 /// Deposits a Bitcoin UTXO as collateral
-#[ic_cdk::update]
+#[ic_cdk::update(guard = "deadman::record_activity")]
 pub async fn deposit_utxo(request: DepositUtxoRequest) -> Result<UtxoId, String> {
     let caller = ic_cdk::api::caller();
 
@@ -93,9 +99,11 @@ pub async fn deposit_utxo(request: DepositUtxoRequest) -> Result<UtxoId, String>
 }
 
 /// Locks a deposited UTXO as collateral and creates a loan offer
-#[ic_cdk::update]
+#[ic_cdk::update(guard = "deadman::record_activity")]
 pub async fn lock_collateral(utxo_id: UtxoId) -> Result<LoanOffer, String> {
     let caller = ic_cdk::api::caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_lock = guards::UtxoGuard::acquire(utxo_id)?;
 
     // Get UTXO
     let utxo = State::with_read(|state| state.utxos.get(&utxo_id).cloned());
@@ -117,8 +125,10 @@ pub async fn lock_collateral(utxo_id: UtxoId) -> Result<LoanOffer, String> {
         return Err("UTXO is already locked or withdrawn".to_string());
     }
 
-    // Calculate max borrowable (50% LTV = 5000 basis points)
-    let max_borrowable = calculate_max_borrowable(&utxo, 5000);
+    // Calculate max borrowable at the LTV of the UTXO's collateral class
+    let carries_runes = State::with_read(|state| state.rune_utxos.contains(&utxo_id));
+    let ltv = collateral::max_ltv(collateral::class_of(&utxo, carries_runes));
+    let max_borrowable = calculate_max_borrowable(&utxo, ltv);
 
     // Lock UTXO and create loan offer
     let created_at = get_timestamp();
@@ -137,7 +147,7 @@ pub async fn lock_collateral(utxo_id: UtxoId) -> Result<LoanOffer, String> {
             user_id: caller,
             utxo_id,
             max_borrowable,
-            ltv_percent: 50, // 50% LTV
+            ltv_percent: ltv / 100,
             status: LoanOfferStatus::Active,
             created_at,
         };
@@ -165,103 +175,388 @@ pub async fn lock_collateral(utxo_id: UtxoId) -> Result<LoanOffer, String> {
         user_id: caller,
         utxo_id,
         max_borrowable,
-        ltv_percent: 50,
+        ltv_percent: ltv / 100,
         status: LoanOfferStatus::Active,
         created_at,
     })
 }
 
 /// Borrows ckBTC against deposited collateral
-#[ic_cdk::update]
+/// With `term_days` the loan matures after that many days; see `terms`. The
+/// ledger fee is deducted from the proceeds
+#[ic_cdk::update(guard = "deadman::record_activity")]
 pub async fn borrow(request: BorrowRequest) -> Result<LoanId, String> {
     let caller = ic_cdk::api::caller();
+    let mut basket = vec![request.utxo_id];
+    basket.extend(request.additional_utxo_ids.unwrap_or_default());
+
+    // Hold the caller and the UTXOs until the call completes, so a concurrent
+    // call cannot act on state this one has checked but not yet updated
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    ensure_distinct(&basket)?;
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&basket)?;
+
+    let asset = request.asset.unwrap_or(LoanAsset::CkBtc);
 
     // 1. Validate inputs and authorization (no state changes)
-    if request.amount == 0 {
-        return Err("Invalid borrow amount: must be greater than 0".to_string());
+    if let Some(days) = request.term_days {
+        terms::validate_term_days(days)?;
     }
+    disbursements::validate_amount(asset, request.amount)?;
+    // Loans in other assets are checked against the collateral at oracle prices
+    let amount_in_sats = if asset == LoanAsset::CkBtc {
+        request.amount
+    } else {
+        let (btc_price, asset_price) = assets::fetch_prices(asset).await?;
+        assets::to_sats(asset, request.amount, &btc_price, &asset_price)?
+    };
+    validate_borrow(caller, &basket, amount_in_sats)?;
 
-    // Get UTXO
-    let utxo = State::with_read(|state| state.utxos.get(&request.utxo_id).cloned());
+    // Fail before reserving anything if the asset has no ledger
+    assets::ledger_id(asset)?;
 
-    let utxo = utxo.ok_or("UTXO not found".to_string())?;
+    // ckBTC loans are funded from the lending pool; reserve the liquidity
+    // before the transfer so concurrent borrows cannot overdraw it
+    if asset == LoanAsset::CkBtc {
+        State::with(|state| pool::lend(&mut state.lending_pool, request.amount))?;
+    } else {
+        assets::ensure_price_timer();
+    }
 
-    // Check UTXO belongs to caller
-    let user_utxos = State::with_read(|state| state.user_utxos.get(&caller).cloned());
+    // 2. Record the loan, then pay it out; a rejected transfer cancels the loan
+    let term = request
+        .term_days
+        .map(|days| terms::new_term(get_timestamp(), days));
+    let loan_id = record_loan(caller, &basket, asset, request.amount, term, None);
+    pay_out(loan_id, asset, caller, request.amount, false).await?;
+    Ok(loan_id)
+}
 
-    if !user_utxos
-        .map(|utxos| utxos.contains(&request.utxo_id))
-        .unwrap_or(false)
-    {
-        return Err("Unauthorized: UTXO does not belong to caller".to_string());
-    }
+/// Draws additional funds on an active loan against the same collateral
+/// The new total debt on the collateral must stay within its borrowing limit;
+/// the ledger fee is deducted from the draw
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn increase_loan(request: IncreaseLoanRequest) -> Result<Loan, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
 
-    // Check if UTXO is withdrawn (cannot borrow against withdrawn UTXO)
-    if utxo.status == UtxoStatus::Withdrawn {
-        return Err("UTXO has been withdrawn".to_string());
-    }
-
-    // Calculate max borrowable based on UTXO status
-    let max_borrowable = if utxo.status == UtxoStatus::Locked {
-        // For locked UTXOs, try to find an active loan offer first
-        let loan_offer_max = State::with_read(|state| {
-            state.user_loan_offers.get(&caller).and_then(|offer_ids| {
-                offer_ids.iter().find_map(|offer_id| {
-                    state.loan_offers.get(offer_id).and_then(|offer| {
-                        if offer.utxo_id == request.utxo_id
-                            && offer.status == LoanOfferStatus::Active
-                        {
-                            Some(offer.max_borrowable)
-                        } else {
-                            None
-                        }
-                    })
-                })
-            })
-        });
+    // 1. Validate inputs and authorization (no state changes)
+    let loan = get_loan_by_id(request.loan_id)?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: loan does not belong to caller".to_string());
+    }
+    if loan.status != LoanStatus::Active {
+        return Err("Loan is not active".to_string());
+    }
+    if loan.solana_disbursement.is_some() {
+        return Err("Loans paid out on Solana cannot be increased".to_string());
+    }
+    let basket = collateral::basket(&loan);
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&basket)?;
+    if State::with_read(|state| state.pending_disbursements.contains_key(&loan.id)) {
+        return Err(format!(
+            "Loan {} has a payout pending; try again once it settles",
+            loan.id
+        ));
+    }
+    disbursements::validate_amount(loan.asset, request.amount)?;
 
-        // If no active loan offer found, calculate max borrowable directly
-        // This allows borrowing from locked UTXOs even without an active offer
-        loan_offer_max.unwrap_or_else(|| {
-            ic_cdk::println!("⚠️ No active loan offer found for locked UTXO {}, calculating max borrowable directly", request.utxo_id);
-            calculate_max_borrowable(&utxo, 5000) // 50% LTV
-        })
+    let amount_in_sats = if loan.asset == LoanAsset::CkBtc {
+        request.amount
     } else {
-        // UTXO is Deposited, calculate max borrowable (50% LTV = 5000 basis points)
-        calculate_max_borrowable(&utxo, 5000)
+        let (btc_price, asset_price) = assets::fetch_prices(loan.asset).await?;
+        assets::to_sats(loan.asset, request.amount, &btc_price, &asset_price)?
     };
+    validate_borrow(caller, &basket, amount_in_sats)?;
+    assets::ledger_id(loan.asset)?;
 
-    if request.amount > max_borrowable {
-        return Err(format!(
-            "Amount {} exceeds maximum borrowable: {} (50% LTV)",
-            request.amount, max_borrowable
-        ));
+    // 2. Reserve pool liquidity and add the draw to the loan before paying it out
+    if pool::is_pool_loan(&loan) {
+        State::with(|state| pool::lend(&mut state.lending_pool, request.amount))?;
     }
+    State::with(|state| {
+        if let Some(loan) = state.loans.get_mut(&request.loan_id) {
+            loan.borrowed_amount += request.amount;
+        }
+    });
 
-    // 2. Transfer ckBTC to user using real ckBTC ledger
-    let block_index = ckbtc::transfer_ckbtc(caller, request.amount).await?;
+    // 3. Pay out; a rejected transfer removes the draw again
+    pay_out(loan.id, loan.asset, caller, request.amount, true).await?;
+    get_loan_by_id(loan.id)
+}
 
-    ic_cdk::println!(
-        "Successfully transferred {} satoshis ckBTC to {}, block index: {}",
-        request.amount,
+/// Journals a loan payout (or a draw on an existing loan) and sends it to the borrower
+/// The borrower receives `amount` minus the ledger fee. Returns the ledger block
+/// index; an unconfirmed payout stays journaled and is retried by the
+/// reconciliation timer
+async fn pay_out(
+    loan_id: LoanId,
+    asset: LoanAsset,
+    to: Principal,
+    amount: u64,
+    draw: bool,
+) -> Result<u64, String> {
+    let entry = disbursements::new_entry(loan_id, asset, to, amount, draw, get_timestamp());
+    let paid = disbursements::payout_amount(&entry);
+    disbursements::begin(entry);
+
+    match disbursements::execute(loan_id).await {
+        Outcome::Paid(block_index) => {
+            ic_cdk::println!(
+                "Successfully transferred {} {:?} base units to {}, block index: {}",
+                paid,
+                asset,
+                to,
+                block_index
+            );
+            Ok(block_index)
+        }
+        Outcome::Rejected(e) => Err(format!("Transfer failed: {}", e)),
+        Outcome::Unknown(e) => Err(format!(
+            "Payout of loan {} is pending and will be retried: {}",
+            loan_id, e
+        )),
+    }
+}
+
+/// Lists the caller's loan payouts the ledger has not confirmed yet
+#[ic_cdk::query]
+pub fn get_pending_disbursements() -> Vec<PendingDisbursement> {
+    let caller = ic_cdk::api::msg_caller();
+    State::with_read(|state| {
+        state
+            .pending_disbursements
+            .values()
+            .filter(|entry| entry.to == caller)
+            .cloned()
+            .collect()
+    })
+}
+
+/// Borrows against BTC collateral with the proceeds paid out on Solana
+/// The debt is in satoshis and repaid in ckBTC like any other loan. The loan
+/// is recorded before the payout is broadcast, so proceeds are never sent
+/// without a loan; a payout that never lands cancels the loan.
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn borrow_to_solana(request: SolanaBorrowRequest) -> Result<Loan, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_lock = guards::UtxoGuard::acquire(request.utxo_id)?;
+
+    // 1. Validate inputs and authorization (no state changes)
+    validate_borrow(caller, &[request.utxo_id], request.amount)?;
+    let recipient = solana::SolanaAddress::parse(&request.recipient)?;
+
+    // 2. Convert the loan value at oracle prices and sign the payout
+    let (symbol, decimals) = match request.asset {
+        SolanaLoanAsset::Sol => ("SOL", solana::SOL_DECIMALS),
+        SolanaLoanAsset::UsdcSpl => ("USDC", solana::USDC_DECIMALS),
+    };
+    let btc_price = oracle::get_usd_price("BTC").await?;
+    let asset_price = oracle::get_usd_price(symbol).await?;
+    let payout = oracle::convert_amount(request.amount, 8, &btc_price, decimals, &asset_price)?;
+
+    let treasury = solana::treasury();
+    let instructions = match request.asset {
+        SolanaLoanAsset::Sol => {
+            solana::sol_transfer_instructions(treasury, &recipient, payout).await?
+        }
+        SolanaLoanAsset::UsdcSpl => {
+            solana::spl_transfer_instructions(
+                treasury,
+                &recipient,
+                &request.network.usdc_mint()?,
+                payout,
+                &request.network,
+            )
+            .await?
+        }
+    };
+    let transaction = solana::sign_transaction(treasury, &instructions, &request.network).await?;
+
+    // 3. Record the loan, re-checking the collateral since state may have changed
+    validate_borrow(caller, &[request.utxo_id], request.amount)?;
+    let loan_id = record_loan(
         caller,
-        block_index
+        &[request.utxo_id],
+        LoanAsset::CkBtc,
+        request.amount,
+        None,
+        Some(SolanaDisbursement {
+            asset: request.asset,
+            network: request.network.clone(),
+            recipient,
+            amount: payout,
+            btc_usd_rate: btc_price.rate,
+            asset_usd_rate: asset_price.rate,
+            signature: transaction.signature.clone(),
+            signed_at: get_timestamp(),
+            status: DisbursementStatus::Pending,
+        }),
     );
 
-    // 3. Only modify state after successful ckBTC transfer
-    let loan_id = State::with(|state| {
+    // 4. Broadcast; an unconfirmed payout stays pending for `refresh_solana_disbursement`
+    match solana::submit_transaction(&transaction, &request.network).await {
+        Ok(response) if response.success => {
+            set_disbursement_status(loan_id, DisbursementStatus::Confirmed)
+        }
+        Ok(response) => ic_cdk::println!(
+            "⚠️ Payout for loan {} not confirmed yet: {:?}",
+            loan_id,
+            response.error
+        ),
+        Err(e) => ic_cdk::println!("⚠️ Payout for loan {} not submitted: {}", loan_id, e),
+    }
+
+    get_loan_by_id(loan_id)
+}
+
+/// Re-checks a pending Solana payout, cancelling the loan if it failed or expired
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn refresh_solana_disbursement(loan_id: LoanId) -> Result<Loan, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+
+    let loan = get_loan_by_id(loan_id)?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: loan does not belong to caller".to_string());
+    }
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
+    let disbursement = loan
+        .solana_disbursement
+        .ok_or("Loan was not paid out on Solana".to_string())?;
+    if disbursement.status != DisbursementStatus::Pending {
+        return get_loan_by_id(loan_id);
+    }
+
+    let status = match solana::verify_solana_transaction(
+        &disbursement.signature,
+        disbursement.network,
+        solana::SolanaCommitment::Confirmed,
+    )
+    .await
+    {
+        Ok(status) if status.verified => DisbursementStatus::Confirmed,
+        Ok(status) if status.err.is_some() => DisbursementStatus::Failed,
+        Ok(_) => DisbursementStatus::Pending,
+        Err(solana::SolanaVerificationError::UnknownSignature(_))
+            if get_timestamp().saturating_sub(disbursement.signed_at)
+                > DISBURSEMENT_EXPIRY_SECONDS * 1_000_000_000 =>
+        {
+            DisbursementStatus::Failed
+        }
+        Err(solana::SolanaVerificationError::UnknownSignature(_)) => DisbursementStatus::Pending,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    set_disbursement_status(loan_id, status);
+    get_loan_by_id(loan_id)
+}
+
+/// Gets the Solana address holding the vault's payout funds
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_treasury_address() -> Result<String, String> {
+    Ok(solana::user_solana_address(solana::treasury())
+        .await?
+        .to_string())
+}
+
+/// After this long a payout the cluster has never seen can no longer land:
+/// its blockhash (valid for 150 blocks, about a minute) has expired
+const DISBURSEMENT_EXPIRY_SECONDS: u64 = 300;
+
+/// Checks that `caller` may borrow `amount` (valued in satoshis) against the
+/// UTXOs in `basket`, on top of the debt of loans they already back
+fn validate_borrow(caller: Principal, basket: &[UtxoId], amount: u64) -> Result<(), String> {
+    if amount == 0 {
+        return Err("Invalid borrow amount: must be greater than 0".to_string());
+    }
+    ensure_distinct(basket)?;
+
+    State::with_read(|state| {
+        let user_utxos = state.user_utxos.get(&caller);
+        for utxo_id in basket {
+            let utxo = state
+                .utxos
+                .get(utxo_id)
+                .ok_or(format!("UTXO {} not found", utxo_id))?;
+
+            // Check UTXO belongs to caller
+            if !user_utxos
+                .map(|utxos| utxos.contains(utxo_id))
+                .unwrap_or(false)
+            {
+                return Err(format!(
+                    "Unauthorized: UTXO {} does not belong to caller",
+                    utxo_id
+                ));
+            }
+
+            // Check if UTXO is withdrawn (cannot borrow against withdrawn UTXO)
+            if utxo.status == UtxoStatus::Withdrawn {
+                return Err(format!("UTXO {} has been withdrawn", utxo_id));
+            }
+        }
+
+        // Each UTXO lends at its class's LTV; loans already open on any of
+        // them count against the same limit
+        let max_borrowable = collateral::value_utxos(state, basket)?.max_borrowable;
+        let outstanding_debt = assets::collateral_debt_in_sats(basket, state.loans.values())?;
+        let available = max_borrowable.saturating_sub(outstanding_debt);
+        if amount > available {
+            return Err(format!(
+                "Amount {} exceeds available borrowing capacity: {} ({} of {} already owed)",
+                amount, available, outstanding_debt, max_borrowable
+            ));
+        }
+
+        Ok(())
+    })
+}
+
+/// Rejects a basket that lists the same UTXO twice
+fn ensure_distinct(basket: &[UtxoId]) -> Result<(), String> {
+    for (i, utxo_id) in basket.iter().enumerate() {
+        if basket[..i].contains(utxo_id) {
+            return Err(format!(
+                "UTXO {} appears twice in the collateral basket",
+                utxo_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Records a new loan, locking its collateral and accepting any matching offer
+fn record_loan(
+    caller: Principal,
+    basket: &[UtxoId],
+    asset: LoanAsset,
+    amount: u64,
+    term: Option<LoanTerm>,
+    solana_disbursement: Option<SolanaDisbursement>,
+) -> LoanId {
+    repayments::ensure_poll_timer();
+    if term.is_some() {
+        terms::ensure_timer();
+    }
+    State::with(|state| {
         let id = state.next_loan_id;
         state.next_loan_id += 1;
 
         let loan = Loan {
             id,
             user_id: caller,
-            collateral_utxo_id: request.utxo_id,
-            borrowed_amount: request.amount,
+            collateral_utxo_id: basket[0],
+            extra_collateral: basket[1..].to_vec(),
+            asset,
+            borrowed_amount: amount,
             repaid_amount: 0,
-            interest_rate: 500, // 5% annual interest
+            interest_rate: assets::config(asset).interest_rate,
             created_at: get_timestamp(),
             status: LoanStatus::Active,
+            solana_disbursement,
+            term,
         };
 
         state.loans.insert(id, loan.clone());
@@ -271,10 +566,12 @@ pub async fn borrow(request: BorrowRequest) -> Result<LoanId, String> {
             .or_insert_with(Vec::new)
             .push(id);
 
-        // Lock UTXO as collateral (if not already locked)
-        if let Some(utxo) = state.utxos.get_mut(&request.utxo_id) {
-            if utxo.status == UtxoStatus::Deposited {
-                utxo.status = UtxoStatus::Locked;
+        // Lock the UTXOs as collateral (if not already locked)
+        for utxo_id in basket {
+            if let Some(utxo) = state.utxos.get_mut(utxo_id) {
+                if utxo.status == UtxoStatus::Deposited {
+                    utxo.status = UtxoStatus::Locked;
+                }
             }
         }
 
@@ -282,7 +579,7 @@ pub async fn borrow(request: BorrowRequest) -> Result<LoanId, String> {
         if let Some(offer_ids) = state.user_loan_offers.get(&caller) {
             for offer_id in offer_ids {
                 if let Some(offer) = state.loan_offers.get_mut(offer_id) {
-                    if offer.utxo_id == request.utxo_id && offer.status == LoanOfferStatus::Active {
+                    if basket.contains(&offer.utxo_id) && offer.status == LoanOfferStatus::Active {
                         offer.status = LoanOfferStatus::Accepted;
                         ic_cdk::println!(
                             "Marked loan offer {} as accepted for loan {}",
@@ -295,23 +592,54 @@ pub async fn borrow(request: BorrowRequest) -> Result<LoanId, String> {
         }
 
         ic_cdk::println!(
-            "Created loan {} for user {}: borrowed {} satoshis against UTXO {}",
+            "Created loan {} for user {}: borrowed {} {:?} base units against UTXOs {:?}",
             id,
             caller,
-            request.amount,
-            request.utxo_id
+            amount,
+            asset,
+            basket
         );
 
         id
+    })
+}
+
+/// Updates a loan's Solana payout; a failed payout cancels the loan and
+/// unlocks its collateral unless another active loan still uses it
+fn set_disbursement_status(loan_id: LoanId, status: DisbursementStatus) {
+    State::with(|state| {
+        let Some(loan) = state.loans.get_mut(&loan_id) else {
+            return;
+        };
+        let Some(disbursement) = loan.solana_disbursement.as_mut() else {
+            return;
+        };
+        disbursement.status = status.clone();
+        if status != DisbursementStatus::Failed || loan.status != LoanStatus::Active {
+            return;
+        }
+
+        loan.status = LoanStatus::Cancelled;
+        let basket = collateral::basket(loan);
+        collateral::unlock_unused(state, &basket);
+        ic_cdk::println!(
+            "❌ Solana payout for loan {} failed, loan cancelled",
+            loan_id
+        );
     });
+}
 
-    Ok(loan_id)
+fn get_loan_by_id(loan_id: LoanId) -> Result<Loan, String> {
+    State::with_read(|state| state.loans.get(&loan_id).cloned()).ok_or("Loan not found".to_string())
 }
 
 /// Repays a loan
-#[ic_cdk::update]
+/// The caller must first approve the vault to spend `amount` plus the ledger fee
+/// on the loan asset's ledger
+#[ic_cdk::update(guard = "deadman::record_activity")]
 pub async fn repay(request: RepayRequest) -> Result<(), String> {
     let caller = ic_cdk::api::caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
 
     // 1. Validate inputs and authorization (no state changes)
     if request.amount == 0 {
@@ -328,13 +656,14 @@ pub async fn repay(request: RepayRequest) -> Result<(), String> {
         return Err("Unauthorized: loan does not belong to caller".to_string());
     }
 
-    // Check loan is active
-    if loan.status != LoanStatus::Active {
-        return Err("Loan is not active".to_string());
+    // Check loan is still open; defaulted loans can be repaid until liquidated
+    if !is_loan_open(&loan) {
+        return Err("Loan is no longer open".to_string());
     }
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
 
-    // Calculate remaining debt (borrowed + interest - repaid)
-    let remaining_debt = calculate_loan_value(&loan);
+    // Calculate remaining debt (borrowed + interest + fees - repaid)
+    let remaining_debt = calculate_loan_value(&loan)?;
     if request.amount > remaining_debt {
         return Err(format!(
             "Amount {} exceeds remaining debt: {}",
@@ -342,59 +671,99 @@ pub async fn repay(request: RepayRequest) -> Result<(), String> {
         ));
     }
 
-    // 2. Verify user has transferred ckBTC to canister using real ckBTC ledger
-    let verified = ckbtc::verify_transfer_to_canister(caller, request.amount).await?;
-    if !verified {
-        return Err("ckBTC transfer verification failed: no matching transfer found".to_string());
-    }
+    // 2. Pull the repayment from the caller's ICRC-2 approval; the memo ties
+    // the ledger transfer to this loan. Nothing is credited unless this succeeds.
+    let ledger_id = assets::ledger_id(loan.asset)?;
+    let block_index = ckbtc::transfer_from(
+        ledger_id,
+        caller,
+        request.amount,
+        Some(loan_memo(request.loan_id)),
+    )
+    .await?;
 
     ic_cdk::println!(
-        "Verified ckBTC transfer of {} satoshis from {} for loan {}",
+        "Received {} base units from {} for loan {}, block index: {}",
         request.amount,
         caller,
-        request.loan_id
+        request.loan_id,
+        block_index
     );
-
-    // Note: In production, you might want to actually burn the ckBTC tokens here
-    // For now, the canister holds the ckBTC (which effectively removes it from circulation)
-
-    // 3. Only modify state after successful verification
-    State::with(|state| {
-        if let Some(loan) = state.loans.get_mut(&request.loan_id) {
-            loan.repaid_amount += request.amount;
-
-            // Check if fully repaid
-            if is_loan_repaid(loan) {
-                loan.status = LoanStatus::Repaid;
-
-                // Unlock UTXO
-                if let Some(utxo) = state.utxos.get_mut(&loan.collateral_utxo_id) {
-                    utxo.status = UtxoStatus::Deposited;
-                }
-
+    // The pulled block must not be credited again through repay_with_block
+    repayments::consume_block(loan.asset, block_index);
+
+    // 3. Credit the repayment. The loan may have changed while the transfer was
+    // in flight (e.g. a concurrent repayment), so it is re-checked before applying
+    let credited = repayments::credit_repayment(request.loan_id, request.amount);
+
+    // Return funds that could not be applied, minus the ledger fee
+    if let Err(e) = credited {
+        let refund = request
+            .amount
+            .saturating_sub(assets::config(loan.asset).transfer_fee);
+        if refund > 0 {
+            if let Err(refund_error) = ckbtc::transfer(ledger_id, caller, refund).await {
                 ic_cdk::println!(
-                    "Loan {} fully repaid, unlocked UTXO {}",
+                    "⚠️ Refund of {} base units to {} for loan {} failed: {}",
+                    refund,
+                    caller,
                     request.loan_id,
-                    loan.collateral_utxo_id
-                );
-            } else {
-                ic_cdk::println!(
-                    "Partial repayment for loan {}: {} / {} satoshis",
-                    request.loan_id,
-                    loan.repaid_amount,
-                    loan.borrowed_amount
+                    refund_error
                 );
             }
         }
-    });
+        return Err(e);
+    }
 
     Ok(())
 }
 
+/// Repays a loan with an ICRC-1 transfer already made to the vault
+/// The transfer must come from the borrower and carry the loan ID
+/// (8 bytes, big-endian) as memo; returns the amount credited
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn repay_with_block(request: BlockRepaymentRequest) -> Result<u64, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+
+    let loan = get_loan_by_id(request.loan_id)?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: loan does not belong to caller".to_string());
+    }
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
+
+    repayments::credit_block(request.loan_id, request.block_index).await
+}
+
+/// Gets the account a loan is repaid into
+/// Transfers to it are credited to the loan when the vault polls it
+#[ic_cdk::query]
+pub fn get_repayment_account(loan_id: LoanId) -> Result<ckbtc::Account, String> {
+    get_loan_by_id(loan_id)?;
+    Ok(repayments::repayment_account(
+        ic_cdk::api::canister_self(),
+        loan_id,
+    ))
+}
+
+/// Credits funds sitting in a loan's repayment account without waiting for
+/// the next poll; returns the amount credited
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn check_repayment(loan_id: LoanId) -> Result<u64, String> {
+    // Crediting changes the loan, so hold its borrower and collateral
+    let loan = get_loan_by_id(loan_id)?;
+    let _borrower_lock = guards::PrincipalGuard::acquire(loan.user_id)?;
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
+
+    repayments::poll_loan(loan_id).await
+}
+
 /// Withdraws collateral after full repayment
-#[ic_cdk::update]
+#[ic_cdk::update(guard = "deadman::record_activity")]
 pub async fn withdraw_collateral(utxo_id: UtxoId) -> Result<(), String> {
     let caller = ic_cdk::api::caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_lock = guards::UtxoGuard::acquire(utxo_id)?;
 
     // 1. Validate and check authorization (no state changes)
     // Get UTXO
@@ -422,18 +791,27 @@ pub async fn withdraw_collateral(utxo_id: UtxoId) -> Result<(), String> {
         return Err("UTXO has already been withdrawn".to_string());
     }
 
-    // Verify no active loans exist for this UTXO
-    let has_active_loan = State::with_read(|state| {
+    // Check any time lock has expired
+    let time_lock = State::with_read(|state| state.time_locks.get(&utxo_id).cloned());
+    if let Some(lock) = time_lock {
+        if !timelock::is_unlocked(&lock, get_timestamp()) {
+            return Err(format!(
+                "Cannot withdraw: UTXO is time-locked until {}",
+                lock.unlock_timestamp
+            ));
+        }
+    }
+
+    // Verify no open loans exist for this UTXO
+    let has_open_loan = State::with_read(|state| {
         state
             .loans
             .values()
-            .any(|loan| loan.collateral_utxo_id == utxo_id && loan.status == LoanStatus::Active)
+            .any(|loan| is_loan_open(loan) && collateral::is_backed_by(loan, utxo_id))
     });
 
-    if has_active_loan {
-        return Err(
-            "Cannot withdraw: UTXO has an active loan that must be repaid first".to_string(),
-        );
+    if has_open_loan {
+        return Err("Cannot withdraw: UTXO has an open loan that must be repaid first".to_string());
     }
 
     // 2. Only modify state after all validations pass
@@ -547,96 +925,459 @@ pub fn get_user_loan_offers() -> Vec<LoanOffer> {
 // Additional Vault Management Functions (Task 10)
 // ============================================================================
 
-/// Gets loan health information
+/// Gets the debt outstanding against a collateral UTXO across all of its loans
 #[ic_cdk::query]
-pub fn get_loan_health(loan_id: LoanId) -> Result<LoanHealth, String> {
+pub fn get_collateral_debt(utxo_id: UtxoId) -> Result<CollateralDebt, String> {
     State::with_read(|state| {
-        let loan = state
-            .loans
-            .get(&loan_id)
-            .ok_or("Loan not found".to_string())?;
-
         let utxo = state
             .utxos
-            .get(&loan.collateral_utxo_id)
-            .ok_or("Collateral UTXO not found".to_string())?;
-
-        // Calculate current loan value (borrowed + interest - repaid)
-        let loan_value = calculate_loan_value(loan);
-
-        // Calculate current LTV: (loan_value / collateral_value) * 10000
-        let current_ltv = if utxo.amount > 0 {
-            (loan_value * 10000) / utxo.amount
-        } else {
-            10000 // 100% if no collateral
-        };
-
-        // Liquidation threshold is 80% (8000 basis points)
-        let liquidation_threshold = 8000u64;
-
-        // Health factor: distance from liquidation (higher is better)
-        // health_factor = liquidation_threshold / current_ltv
-        // If > 1.0, loan is healthy. If < 1.0, loan can be liquidated
-        let health_factor = if current_ltv > 0 {
-            (liquidation_threshold * 100) / current_ltv
-        } else {
-            10000 // Perfect health if no debt
-        };
+            .get(&utxo_id)
+            .ok_or("UTXO not found".to_string())?;
+        let max_borrowable = collateral::value_utxos(state, &[utxo_id])?.max_borrowable;
+        let outstanding_debt = assets::collateral_debt_in_sats(&[utxo_id], state.loans.values())?;
+        let active_loans = state
+            .loans
+            .values()
+            .filter(|loan| is_loan_open(loan) && collateral::is_backed_by(loan, utxo_id))
+            .count() as u32;
 
-        Ok(LoanHealth {
-            loan_id,
-            current_ltv,
-            liquidation_threshold,
-            health_factor,
-            can_be_liquidated: current_ltv >= liquidation_threshold,
+        Ok(CollateralDebt {
+            utxo_id,
             collateral_value: utxo.amount,
-            loan_value,
+            max_borrowable,
+            outstanding_debt,
+            available_to_borrow: max_borrowable.saturating_sub(outstanding_debt),
+            active_loans,
         })
     })
 }
 
-/// Gets statistics for a specific user
+/// Gets the UTXOs backing a loan and how much they can be borrowed against
 #[ic_cdk::query]
-pub fn get_user_stats() -> UserStats {
-    let caller = ic_cdk::api::caller();
-
+pub fn get_collateral_basket(loan_id: LoanId) -> Result<CollateralBasket, String> {
     State::with_read(|state| {
-        // Get user's UTXOs
-        let user_utxos = state.user_utxos.get(&caller).cloned().unwrap_or_default();
-
-        // Calculate total collateral value
-        let total_collateral_value: u64 = user_utxos
-            .iter()
-            .filter_map(|id| state.utxos.get(id))
-            .map(|utxo| utxo.amount)
-            .sum();
+        let loan = state
+            .loans
+            .get(&loan_id)
+            .ok_or("Loan not found".to_string())?;
+        let utxo_ids = collateral::basket(loan);
+        let value = collateral::value_utxos(state, &utxo_ids)?;
+        let loan_value = assets::cached_debt_in_sats(loan)?;
 
-        // Get user's loans
-        let user_loans = state.user_loans.get(&caller).cloned().unwrap_or_default();
+        Ok(CollateralBasket {
+            loan_id,
+            utxo_ids,
+            collateral_value: value.collateral_value,
+            max_borrowable: value.max_borrowable,
+            weighted_ltv: value.weighted_ltv(),
+            loan_value,
+            current_ltv: collateral::ltv(loan_value, value.collateral_value),
+        })
+    })
+}
 
-        // Calculate total borrowed and total debt
-        let mut total_borrowed = 0u64;
-        let mut total_debt = 0u64;
-        let mut active_loans = 0u64;
+/// Adds a deposited UTXO to an active loan's collateral basket, improving its health
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn add_collateral(loan_id: LoanId, utxo_id: UtxoId) -> Result<CollateralBasket, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_lock = guards::UtxoGuard::acquire(utxo_id)?;
 
-        for loan_id in &user_loans {
-            if let Some(loan) = state.loans.get(loan_id) {
-                if loan.status == LoanStatus::Active {
-                    active_loans += 1;
-                    total_borrowed += loan.borrowed_amount;
-                    total_debt += calculate_loan_value(loan);
-                }
-            }
+    State::with(|state| {
+        let mut loan = state
+            .loans
+            .get(&loan_id)
+            .cloned()
+            .ok_or("Loan not found".to_string())?;
+        if loan.user_id != caller {
+            return Err("Unauthorized: loan does not belong to caller".to_string());
+        }
+        if loan.status != LoanStatus::Active {
+            return Err("Loan is not active".to_string());
         }
 
-        // Calculate average LTV
-        let average_ltv = if total_collateral_value > 0 {
-            (total_debt * 10000) / total_collateral_value
-        } else {
-            0
-        };
+        let owned = state
+            .user_utxos
+            .get(&caller)
+            .map(|utxos| utxos.contains(&utxo_id))
+            .unwrap_or(false);
+        if !owned {
+            return Err("Unauthorized: UTXO does not belong to caller".to_string());
+        }
+        // Only free collateral can be added; locked UTXOs back other loans or offers
+        let utxo = state
+            .utxos
+            .get(&utxo_id)
+            .ok_or("UTXO not found".to_string())?;
+        if utxo.status != UtxoStatus::Deposited {
+            return Err("UTXO is already locked or withdrawn".to_string());
+        }
+        collateral::add_to_basket(&mut loan, utxo_id)?;
 
-        UserStats {
+        state.loans.insert(loan_id, loan);
+        if let Some(utxo) = state.utxos.get_mut(&utxo_id) {
+            utxo.status = UtxoStatus::Locked;
+        }
+        ic_cdk::println!(
+            "Added UTXO {} to the collateral of loan {}",
+            utxo_id,
+            loan_id
+        );
+        Ok(())
+    })?;
+
+    get_collateral_basket(loan_id)
+}
+
+/// Releases one UTXO from an active loan's collateral basket
+/// The UTXOs that remain must still cover the debt at their LTVs
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn release_collateral(
+    loan_id: LoanId,
+    utxo_id: UtxoId,
+) -> Result<CollateralBasket, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+
+    let loan = get_loan_by_id(loan_id)?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: loan does not belong to caller".to_string());
+    }
+    if loan.status != LoanStatus::Active {
+        return Err("Loan is not active".to_string());
+    }
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
+
+    // Value the debt at current prices before deciding
+    if loan.asset != LoanAsset::CkBtc {
+        assets::fetch_prices(loan.asset).await?;
+    }
+
+    State::with(|state| {
+        let mut loan = state
+            .loans
+            .get(&loan_id)
+            .cloned()
+            .ok_or("Loan not found".to_string())?;
+        collateral::remove_from_basket(&mut loan, utxo_id)?;
+
+        let remaining = collateral::value_utxos(state, &collateral::basket(&loan))?;
+        let loan_value = assets::cached_debt_in_sats(&loan)?;
+        if loan_value > remaining.max_borrowable {
+            return Err(format!(
+                "Cannot release UTXO {}: remaining collateral supports {} satoshis, {} owed",
+                utxo_id, remaining.max_borrowable, loan_value
+            ));
+        }
+
+        state.loans.insert(loan_id, loan);
+        collateral::unlock_unused(state, &[utxo_id]);
+        ic_cdk::println!(
+            "Released UTXO {} from the collateral of loan {}",
+            utxo_id,
+            loan_id
+        );
+        Ok(())
+    })?;
+
+    get_collateral_basket(loan_id)
+}
+
+/// Substitutes one UTXO in a loan's collateral basket for another
+/// The replacement must be free collateral worth at least as much at its
+/// class's LTV; both UTXOs change hands in one state update
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn swap_collateral(
+    loan_id: LoanId,
+    old_utxo_id: UtxoId,
+    new_utxo_id: UtxoId,
+) -> Result<CollateralBasket, String> {
+    let caller = ic_cdk::api::msg_caller();
+    if old_utxo_id == new_utxo_id {
+        return Err("Cannot swap a UTXO for itself".to_string());
+    }
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&[old_utxo_id, new_utxo_id])?;
+
+    State::with(|state| {
+        let mut loan = state
+            .loans
+            .get(&loan_id)
+            .cloned()
+            .ok_or("Loan not found".to_string())?;
+        if loan.user_id != caller {
+            return Err("Unauthorized: loan does not belong to caller".to_string());
+        }
+        if loan.status != LoanStatus::Active {
+            return Err("Loan is not active".to_string());
+        }
+
+        let owned = state
+            .user_utxos
+            .get(&caller)
+            .map(|utxos| utxos.contains(&new_utxo_id))
+            .unwrap_or(false);
+        if !owned {
+            return Err("Unauthorized: UTXO does not belong to caller".to_string());
+        }
+        let new_utxo = state
+            .utxos
+            .get(&new_utxo_id)
+            .ok_or("UTXO not found".to_string())?;
+        if new_utxo.status != UtxoStatus::Deposited {
+            return Err("UTXO is already locked or withdrawn".to_string());
+        }
+
+        let old_value = collateral::value_utxos(state, &[old_utxo_id])?.max_borrowable;
+        let new_value = collateral::value_utxos(state, &[new_utxo_id])?.max_borrowable;
+        if new_value < old_value {
+            return Err(format!(
+                "UTXO {} supports {} satoshis of borrowing, less than the {} of UTXO {}",
+                new_utxo_id, new_value, old_value, old_utxo_id
+            ));
+        }
+        collateral::replace_in_basket(&mut loan, old_utxo_id, new_utxo_id)?;
+
+        state.loans.insert(loan_id, loan);
+        if let Some(utxo) = state.utxos.get_mut(&new_utxo_id) {
+            utxo.status = UtxoStatus::Locked;
+        }
+        collateral::unlock_unused(state, &[old_utxo_id]);
+        ic_cdk::println!(
+            "Swapped UTXO {} for UTXO {} as collateral of loan {}",
+            old_utxo_id,
+            new_utxo_id,
+            loan_id
+        );
+        Ok(())
+    })?;
+
+    get_collateral_basket(loan_id)
+}
+
+/// Moves a loan to the rate the borrower requests and, with `term_days`, to a
+/// new term starting now; a fixed-term loan keeps its term otherwise
+/// The rate must be within `terms::rate_bounds` for the resulting term. It
+/// replaces the old one on the loan's principal instead of being charged on top
+/// of it, and the repriced loan must stay within its collateral's borrowing
+/// limit. Loans past maturity can only be extended.
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn refinance(loan_id: LoanId, new_rate_terms: RateTerms) -> Result<Loan, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+
+    let loan = get_loan_by_id(loan_id)?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: loan does not belong to caller".to_string());
+    }
+    if loan.status != LoanStatus::Active {
+        return Err("Loan is not active".to_string());
+    }
+    let basket = collateral::basket(&loan);
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&basket)?;
+
+    let asset_rate = assets::config(loan.asset).interest_rate;
+    let interest_rate = new_rate_terms.interest_rate;
+    if let Some(days) = new_rate_terms.term_days {
+        terms::validate_term_days(days)?;
+    }
+
+    // Value the debt at current prices before deciding
+    if loan.asset != LoanAsset::CkBtc {
+        assets::fetch_prices(loan.asset).await?;
+    }
+
+    State::with(|state| {
+        let mut loan = state
+            .loans
+            .get(&loan_id)
+            .cloned()
+            .filter(|loan| loan.status == LoanStatus::Active)
+            .ok_or("Loan is not active".to_string())?;
+        if state.pending_disbursements.contains_key(&loan_id) {
+            return Err(format!(
+                "Loan {} has a payout pending; try again once it settles",
+                loan_id
+            ));
+        }
+        let now = get_timestamp();
+        if loan
+            .term
+            .as_ref()
+            .is_some_and(|term| terms::is_past_maturity(term, now))
+        {
+            return Err(format!(
+                "Loan {} is past maturity; extend or repay it instead",
+                loan_id
+            ));
+        }
+
+        // The rate is bounded by the term the loan runs for after refinancing
+        let term_days = new_rate_terms.term_days.or_else(|| {
+            loan.term
+                .as_ref()
+                .map(|term| terms::remaining_days(term, now))
+        });
+        terms::validate_rate(interest_rate, asset_rate, term_days)?;
+
+        let remaining_debt = reprice_loan(&mut loan, interest_rate)?;
+        if let Some(days) = new_rate_terms.term_days {
+            loan.term = Some(terms::renew(loan.term.as_ref(), now, days)?);
+        }
+
+        // Other loans on the same collateral still count against its limit
+        let max_borrowable = collateral::value_utxos(state, &basket)?.max_borrowable;
+        let other_debt = assets::collateral_debt_in_sats(
+            &basket,
+            state.loans.values().filter(|other| other.id != loan_id),
+        )?;
+        let repriced_debt = assets::cached_debt_in_sats(&loan)?;
+        if other_debt.saturating_add(repriced_debt) > max_borrowable {
+            return Err(format!(
+                "Refinanced debt of {} satoshis exceeds the collateral's borrowing capacity of {} ({} owed on other loans)",
+                repriced_debt, max_borrowable, other_debt
+            ));
+        }
+
+        ic_cdk::println!(
+            "Refinanced loan {} at {} basis points: {} base units owed",
+            loan_id,
+            interest_rate,
+            remaining_debt
+        );
+        if loan.term.is_some() {
+            terms::ensure_timer();
+        }
+        state.loans.insert(loan_id, loan);
+        Ok(())
+    })?;
+
+    get_loan_by_id(loan_id)
+}
+
+/// Pushes back the maturity of a fixed-term loan
+/// The extension fee is added to the loan's debt; a loan already in its grace
+/// period can be extended as long as the new maturity is in the future
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn extend_loan(request: ExtendLoanRequest) -> Result<Loan, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let _caller_lock = guards::PrincipalGuard::acquire(caller)?;
+    let _utxo_locks =
+        guards::UtxoGuard::acquire_all(&collateral::basket(&get_loan_by_id(request.loan_id)?))?;
+
+    State::with(|state| {
+        let loan = state
+            .loans
+            .get_mut(&request.loan_id)
+            .ok_or("Loan not found".to_string())?;
+        if loan.user_id != caller {
+            return Err("Unauthorized: loan does not belong to caller".to_string());
+        }
+
+        let fee = terms::extend(loan, request.extra_days, get_timestamp())?;
+        ic_cdk::println!(
+            "Extended loan {} by {} days for a fee of {} base units",
+            loan.id,
+            request.extra_days,
+            fee
+        );
+        Ok(loan.clone())
+    })
+}
+
+/// Gets loan health information
+#[ic_cdk::query]
+pub fn get_loan_health(loan_id: LoanId) -> Result<LoanHealth, String> {
+    State::with_read(|state| {
+        let loan = state
+            .loans
+            .get(&loan_id)
+            .ok_or("Loan not found".to_string())?;
+
+        // Loans sharing collateral are valued together against all of it
+        let group = collateral::collateral_group(loan, &state.loans);
+        let collateral_value = collateral::value_utxos(state, &group.utxo_ids)?.collateral_value;
+
+        // Calculate current debt (borrowed + interest - repaid) in satoshis
+        let loan_value = assets::loans_debt_in_sats(&group.loan_ids, &state.loans)?;
+
+        // Calculate current LTV: (loan_value / collateral_value) * 10000
+        let current_ltv = collateral::ltv(loan_value, collateral_value);
+
+        // Liquidation threshold is 80% (8000 basis points)
+        let liquidation_threshold = collateral::LIQUIDATION_THRESHOLD_BPS;
+
+        // Health factor: distance from liquidation (higher is better)
+        // health_factor = liquidation_threshold / current_ltv
+        // If > 1.0, loan is healthy. If < 1.0, loan can be liquidated
+        let health_factor = if current_ltv > 0 {
+            (liquidation_threshold * 100) / current_ltv
+        } else {
+            10000 // Perfect health if no debt
+        };
+
+        Ok(LoanHealth {
+            loan_id,
+            current_ltv,
+            liquidation_threshold,
+            health_factor,
+            can_be_liquidated: current_ltv >= liquidation_threshold
+                || any_defaulted(&group, &state.loans),
+            collateral_value,
+            loan_value,
+            shared_with: group
+                .loan_ids
+                .into_iter()
+                .filter(|id| *id != loan_id)
+                .collect(),
+        })
+    })
+}
+
+/// Gets statistics for a specific user
+#[ic_cdk::query]
+pub fn get_user_stats() -> UserStats {
+    let caller = ic_cdk::api::caller();
+
+    State::with_read(|state| {
+        // Get user's UTXOs
+        let user_utxos = state.user_utxos.get(&caller).cloned().unwrap_or_default();
+
+        // Calculate total collateral value
+        let total_collateral_value: u64 = user_utxos
+            .iter()
+            .filter_map(|id| state.utxos.get(id))
+            .map(|utxo| utxo.amount)
+            .sum();
+
+        // Get user's loans
+        let user_loans = state.user_loans.get(&caller).cloned().unwrap_or_default();
+
+        // Calculate total borrowed and total debt
+        let mut total_borrowed = 0u64;
+        let mut total_debt = 0u64;
+        let mut active_loans = 0u64;
+
+        for loan_id in &user_loans {
+            if let Some(loan) = state.loans.get(loan_id) {
+                if loan.status == LoanStatus::Active {
+                    // Valued in satoshis; a loan whose price is not cached yet counts as zero
+                    active_loans += 1;
+                    total_borrowed +=
+                        assets::cached_to_sats(loan.asset, loan.borrowed_amount).unwrap_or(0);
+                    total_debt += assets::cached_debt_in_sats(loan).unwrap_or(0);
+                }
+            }
+        }
+
+        // Calculate average LTV
+        let average_ltv = if total_collateral_value > 0 {
+            (total_debt * 10000) / total_collateral_value
+        } else {
+            0
+        };
+
+        UserStats {
             total_collateral_value,
             total_borrowed,
             total_debt,
@@ -661,19 +1402,15 @@ pub fn get_vault_stats() -> VaultStats {
         for loan in state.loans.values() {
             if loan.status == LoanStatus::Active {
                 active_loans_count += 1;
-                total_loans_outstanding += calculate_loan_value(loan);
+                total_loans_outstanding += assets::cached_debt_in_sats(loan).unwrap_or(0);
             }
         }
 
         // Count unique users
         let total_users = state.user_utxos.len() as u64;
 
-        // Calculate utilization rate: (total_loans / total_collateral) * 10000
-        let utilization_rate = if total_value_locked > 0 {
-            (total_loans_outstanding * 10000) / total_value_locked
-        } else {
-            0
-        };
+        // Utilization of the lending pool: borrowed / supplied, in basis points
+        let utilization_rate = pool::utilization_rate(&state.lending_pool);
 
         VaultStats {
             total_value_locked,
@@ -686,318 +1423,1775 @@ pub fn get_vault_stats() -> VaultStats {
     })
 }
 
-/// Gets all loans in the system (paginated)
+/// Lists the assets loans can be taken in
 #[ic_cdk::query]
-pub fn get_all_loans(offset: u64, limit: u64) -> LoansPage {
-    State::with_read(|state| {
-        let all_loans: Vec<Loan> = state.loans.values().cloned().collect();
-        let total = all_loans.len() as u64;
-
-        let start = offset as usize;
-        let end = ((offset + limit) as usize).min(all_loans.len());
-
-        let loans = if start < all_loans.len() {
-            all_loans[start..end].to_vec()
-        } else {
-            vec![]
-        };
+pub fn get_loan_assets() -> Result<Vec<LoanAssetInfo>, String> {
+    assets::ASSETS
+        .iter()
+        .map(|config| assets::info(config.asset))
+        .collect()
+}
 
-        LoansPage {
-            loans,
-            total,
-            offset,
-            limit,
+/// Gets liquidity and interest totals per loan asset
+/// Liquidity is read from each ledger, so this is an update call; ckBTC
+/// liquidity is the lending pool's idle cash
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_asset_markets() -> Result<Vec<AssetMarket>, String> {
+    let mut markets = Vec::with_capacity(assets::ASSETS.len());
+    for config in &assets::ASSETS {
+        if config.asset == LoanAsset::CkBtc {
+            markets.push(State::with_read(|state| {
+                let liquidity = state.lending_pool.cash;
+                assets::market_totals(config.asset, state.loans.values(), liquidity)
+            }));
+            continue;
         }
-    })
+        let liquidity = ckbtc::balance_on(
+            assets::ledger_id(config.asset)?,
+            ic_cdk::api::canister_self(),
+        )
+        .await?;
+        markets.push(State::with_read(|state| {
+            assets::market_totals(config.asset, state.loans.values(), liquidity)
+        }));
+    }
+    Ok(markets)
 }
 
-/// Liquidates a loan that exceeds the liquidation threshold
-#[ic_cdk::update]
-pub async fn liquidate_loan(loan_id: LoanId) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-
-    // Get loan and check if it can be liquidated
-    let (loan, utxo, can_liquidate) = State::with_read(|state| {
-        let loan = state
-            .loans
-            .get(&loan_id)
-            .ok_or("Loan not found".to_string())?;
+/// Supplies ckBTC to the lending pool in exchange for pool shares
+/// The caller must first approve the vault to spend `amount` plus the ledger fee;
+/// shares are minted to the caller's default share token account. If the pool
+/// state changed so that no shares can be minted, the deposit is refunded minus
+/// the ledger fee
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn supply_liquidity(amount: u64) -> Result<SupplierPosition, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let supplier = share_token::account(caller, None)?;
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers cannot supply liquidity".to_string());
+    }
+    if amount == 0 {
+        return Err("Invalid amount: must be greater than 0".to_string());
+    }
 
-        let utxo = state
-            .utxos
-            .get(&loan.collateral_utxo_id)
-            .ok_or("Collateral UTXO not found".to_string())?;
+    // Mint against the current rate first so a deposit that cannot mint a
+    // share is rejected before any ckBTC moves
+    State::with_read(|state| {
+        let mut preview = state.lending_pool.clone();
+        pool::supply(&mut preview, &supplier, amount)
+    })?;
 
-        // Check if loan is active
-        if loan.status != LoanStatus::Active {
-            return Err("Loan is not active".to_string());
-        }
+    let ledger_id = assets::ledger_id(LoanAsset::CkBtc)?;
+    let block_index = ckbtc::transfer_from(ledger_id, caller, amount, None).await?;
 
-        // Calculate current LTV
-        let loan_value = calculate_loan_value(loan);
-        let current_ltv = if utxo.amount > 0 {
-            (loan_value * 10000) / utxo.amount
-        } else {
-            10000
-        };
+    // The pool may have changed during the transfer (e.g. a write-off)
+    let supplied = State::with(|state| {
+        let shares = pool::supply(&mut state.lending_pool, &supplier, amount)?;
+        ic_cdk::println!(
+            "{} supplied {} satoshis for {} shares, block index: {}",
+            caller,
+            amount,
+            shares,
+            block_index
+        );
+        Ok::<SupplierPosition, String>(pool::position(&state.lending_pool, &supplier))
+    });
+    let e = match supplied {
+        Ok(position) => return Ok(position),
+        Err(e) => e,
+    };
 
-        // Liquidation threshold is 80%
-        let can_liquidate = current_ltv >= 8000;
+    let refund = amount.saturating_sub(assets::config(LoanAsset::CkBtc).transfer_fee);
+    if refund == 0 {
+        return Err(format!("{}; deposit too small to refund", e));
+    }
+    match ckbtc::transfer(ledger_id, caller, refund).await {
+        Ok(refund_block) => {
+            ic_cdk::println!(
+                "Refunded {} satoshis to {} after failed supply: {}, block index: {}",
+                refund,
+                caller,
+                e,
+                refund_block
+            );
+            Err(format!("{}; refunded {} satoshis", e, refund))
+        }
+        Err(refund_error) => {
+            ic_cdk::println!(
+                "⚠️ Refund of {} satoshis to {} failed (deposit block {}): {}",
+                refund,
+                caller,
+                block_index,
+                refund_error
+            );
+            Err(format!(
+                "{}; refund of deposit block {} failed: {}",
+                e, block_index, refund_error
+            ))
+        }
+    }
+}
 
-        if !can_liquidate {
+/// Redeems pool shares held in one of the caller's share token accounts for
+/// ckBTC, limited by the pool's idle cash
+/// Returns the amount paid out, which is the shares' value minus the ledger fee
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn withdraw_liquidity(
+    shares: u64,
+    from_subaccount: Option<Vec<u8>>,
+) -> Result<u64, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let supplier = share_token::account(caller, from_subaccount)?;
+    let fee = assets::config(LoanAsset::CkBtc).transfer_fee;
+
+    // Burn the shares before the transfer so they cannot be redeemed twice
+    let amount = State::with(|state| {
+        let amount = pool::redeem(&mut state.lending_pool, &supplier, shares)?;
+        if amount <= fee {
+            pool::restore_redemption(&mut state.lending_pool, &supplier, shares, amount);
             return Err(format!(
-                "Loan cannot be liquidated: LTV {}% is below 80% threshold",
-                current_ltv / 100
+                "Withdrawal of {} satoshis does not cover the ledger fee",
+                amount
             ));
         }
-
-        Ok((loan.clone(), utxo.clone(), can_liquidate))
+        Ok(amount)
     })?;
 
-    // Liquidate the loan
-    State::with(|state| {
-        if let Some(loan) = state.loans.get_mut(&loan_id) {
-            loan.status = LoanStatus::Liquidated;
+    let ledger_id = assets::ledger_id(LoanAsset::CkBtc)?;
+    match ckbtc::transfer(ledger_id, caller, amount - fee).await {
+        Ok(block_index) => {
+            ic_cdk::println!(
+                "{} withdrew {} satoshis for {} shares, block index: {}",
+                caller,
+                amount,
+                shares,
+                block_index
+            );
+            Ok(amount - fee)
+        }
+        Err(e) => {
+            State::with(|state| {
+                pool::restore_redemption(&mut state.lending_pool, &supplier, shares, amount)
+            });
+            Err(e)
+        }
+    }
+}
+
+/// Gets lending pool totals, exchange rate and utilization
+#[ic_cdk::query]
+pub fn get_pool_stats() -> PoolStats {
+    State::with_read(|state| pool::stats(&state.lending_pool))
+}
+
+/// Gets the pool shares in the caller's default account and their current value
+#[ic_cdk::query]
+pub fn get_supplier_position() -> SupplierPosition {
+    let supplier = ckbtc::Account {
+        owner: ic_cdk::api::msg_caller(),
+        subaccount: None,
+    };
+    State::with_read(|state| pool::position(&state.lending_pool, &supplier))
+}
+
+// ============================================================================
+// Pool Share Token (ICRC-1 / ICRC-2)
+// ============================================================================
+
+#[ic_cdk::query]
+pub fn icrc1_name() -> String {
+    share_token::TOKEN_NAME.to_string()
+}
+
+#[ic_cdk::query]
+pub fn icrc1_symbol() -> String {
+    share_token::TOKEN_SYMBOL.to_string()
+}
+
+#[ic_cdk::query]
+pub fn icrc1_decimals() -> u8 {
+    share_token::TOKEN_DECIMALS
+}
+
+#[ic_cdk::query]
+pub fn icrc1_fee() -> candid::Nat {
+    candid::Nat::from(share_token::TRANSFER_FEE)
+}
+
+/// Token metadata, including the share's current exchange rate to ckBTC
+#[ic_cdk::query]
+pub fn icrc1_metadata() -> Vec<(String, share_token::MetadataValue)> {
+    State::with_read(|state| share_token::metadata(&state.lending_pool))
+}
+
+#[ic_cdk::query]
+pub fn icrc1_total_supply() -> candid::Nat {
+    State::with_read(|state| candid::Nat::from(state.lending_pool.total_shares))
+}
+
+/// The vault mints shares on supply and burns them on withdrawal
+#[ic_cdk::query]
+pub fn icrc1_minting_account() -> Option<ckbtc::Account> {
+    Some(ckbtc::Account {
+        owner: ic_cdk::api::canister_self(),
+        subaccount: None,
+    })
+}
+
+#[ic_cdk::query]
+pub fn icrc1_balance_of(account: ckbtc::Account) -> candid::Nat {
+    State::with_read(|state| {
+        candid::Nat::from(share_token::balance_of(&state.lending_pool, &account))
+    })
+}
+
+#[ic_cdk::query]
+pub fn icrc1_supported_standards() -> Vec<share_token::StandardRecord> {
+    share_token::supported_standards()
+}
+
+/// Transfers pool shares; burning goes through `withdraw_liquidity`
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn icrc1_transfer(args: ckbtc::TransferArgs) -> Result<candid::Nat, ckbtc::TransferError> {
+    if args.to.owner == ic_cdk::api::canister_self() {
+        return Err(ckbtc::TransferError::GenericError {
+            error_code: candid::Nat::from(0u64),
+            message: "Shares are burned with withdraw_liquidity".to_string(),
+        });
+    }
+    let caller = ic_cdk::api::msg_caller();
+    let now = get_timestamp();
+    State::with(|state| share_token::transfer(&mut state.lending_pool, caller, args, now))
+}
+
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn icrc2_approve(
+    args: share_token::ApproveArgs,
+) -> Result<candid::Nat, share_token::ApproveError> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = get_timestamp();
+    State::with(|state| share_token::approve(&mut state.lending_pool, caller, args, now))
+}
+
+#[ic_cdk::query]
+pub fn icrc2_allowance(args: share_token::AllowanceArgs) -> share_token::Allowance {
+    let now = get_timestamp();
+    State::with_read(|state| share_token::allowance(&state.lending_pool, &args, now))
+}
+
+/// Transfers pool shares on behalf of an account that approved the caller
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn icrc2_transfer_from(
+    args: ckbtc::TransferFromArgs,
+) -> Result<candid::Nat, ckbtc::TransferFromError> {
+    if args.to.owner == ic_cdk::api::canister_self() {
+        return Err(ckbtc::TransferFromError::GenericError {
+            error_code: candid::Nat::from(0u64),
+            message: "Shares are burned with withdraw_liquidity".to_string(),
+        });
+    }
+    let caller = ic_cdk::api::msg_caller();
+    let now = get_timestamp();
+    State::with(|state| share_token::transfer_from(&mut state.lending_pool, caller, args, now))
+}
+
+/// Gets all loans in the system (paginated)
+#[ic_cdk::query]
+pub fn get_all_loans(offset: u64, limit: u64) -> LoansPage {
+    State::with_read(|state| {
+        let all_loans: Vec<Loan> = state.loans.values().cloned().collect();
+        let total = all_loans.len() as u64;
+
+        let start = offset as usize;
+        let end = ((offset + limit) as usize).min(all_loans.len());
+
+        let loans = if start < all_loans.len() {
+            all_loans[start..end].to_vec()
+        } else {
+            vec![]
+        };
+
+        LoansPage {
+            loans,
+            total,
+            offset,
+            limit,
+        }
+    })
+}
+
+/// Liquidates a loan that exceeds the liquidation threshold or has defaulted
+/// Every loan sharing collateral with it is liquidated along with it
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn liquidate_loan(loan_id: LoanId) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+
+    // Hold the borrower and the collateral across the price fetches
+    let loan = get_loan_by_id(loan_id)?;
+    let _borrower_lock = guards::PrincipalGuard::acquire(loan.user_id)?;
+    let _utxo_locks = guards::UtxoGuard::acquire_all(&collateral::basket(&loan))?;
+
+    // Refresh prices so loans in other assets are valued at current rates
+    let group_assets = State::with_read(|state| {
+        let loan = state
+            .loans
+            .get(&loan_id)
+            .ok_or("Loan not found".to_string())?;
+        let group = collateral::collateral_group(loan, &state.loans);
+        let mut group_assets: Vec<LoanAsset> = vec![];
+        for loan in group.loan_ids.iter().filter_map(|id| state.loans.get(id)) {
+            if loan.asset != LoanAsset::CkBtc && !group_assets.contains(&loan.asset) {
+                group_assets.push(loan.asset);
+            }
+        }
+        Ok::<_, String>(group_assets)
+    })?;
+    for asset in group_assets {
+        assets::fetch_prices(asset).await?;
+    }
+
+    State::with(|state| {
+        let loan = state
+            .loans
+            .get(&loan_id)
+            .ok_or("Loan not found".to_string())?;
+
+        // Check if loan is still open
+        if !is_loan_open(loan) {
+            return Err("Loan is no longer open".to_string());
+        }
+
+        // The group is taken after the price fetches, which may have let
+        // loans open on the same collateral
+        let group = collateral::collateral_group(loan, &state.loans);
+        let collateral_value = collateral::value_utxos(state, &group.utxo_ids)?.collateral_value;
+        let debt = assets::loans_debt_in_sats(&group.loan_ids, &state.loans)?;
+        let current_ltv = collateral::ltv(debt, collateral_value);
+
+        // Liquidation threshold is 80%; a defaulted loan can be liquidated at any LTV
+        let can_liquidate = current_ltv >= collateral::LIQUIDATION_THRESHOLD_BPS
+            || any_defaulted(&group, &state.loans);
+        if !can_liquidate {
+            return Err(format!(
+                "Loan cannot be liquidated: LTV {}% is below 80% threshold",
+                current_ltv / 100
+            ));
+        }
+
+        // The collateral goes to the liquidator; the pool loses the unpaid
+        // principal of each of its loans on it
+        for id in &group.loan_ids {
+            if let Some(loan) = state.loans.get_mut(id) {
+                loan.status = LoanStatus::Liquidated;
+                if pool::is_pool_loan(loan) {
+                    let (unpaid_principal, _) = pool::repayment_split(loan, u64::MAX);
+                    pool::write_off(&mut state.lending_pool, unpaid_principal);
+                }
+            }
+        }
+
+        // Mark the group's UTXOs as withdrawn (transferred to liquidator)
+        for utxo_id in &group.utxo_ids {
+            if let Some(utxo) = state.utxos.get_mut(utxo_id) {
+                utxo.status = UtxoStatus::Withdrawn;
+            }
+        }
+
+        ic_cdk::println!(
+            "Loans {:?} liquidated by {}. Collateral UTXOs {:?} transferred.",
+            group.loan_ids,
+            caller,
+            group.utxo_ids
+        );
+        Ok(())
+    })
+}
+
+/// Whether any loan of a collateral group has defaulted
+fn any_defaulted(group: &collateral::CollateralGroup, loans: &HashMap<LoanId, Loan>) -> bool {
+    group.loan_ids.iter().any(|id| {
+        loans
+            .get(id)
+            .is_some_and(|loan| loan.status == LoanStatus::Defaulted)
+    })
+}
+
+// ============================================================================
+// Advanced Features: Threshold Schnorr, Runes, Solana, vetKeys
+// ============================================================================
+
+/// Deposits a UTXO with Runes support
+/// Checks for both Ordinals and Runes
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn deposit_utxo_with_runes(request: DepositUtxoRequest) -> Result<UtxoId, String> {
+    let caller = ic_cdk::api::caller();
+
+    // First, deposit the UTXO normally
+    let utxo_id = deposit_utxo(request.clone()).await?;
+
+    // Then check for Runes
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    if network != "local" && network != "playground" {
+        match runes::verify_runes(&request.txid, request.vout).await {
+            Ok(Some(runes_info)) => {
+                ic_cdk::println!("✅ Found {} Rune(s) in UTXO", runes_info.len());
+                // Rune-bearing UTXOs are borrowed against at a lower LTV
+                State::with(|state| state.rune_utxos.insert(utxo_id));
+            }
+            Ok(None) => {
+                ic_cdk::println!("ℹ️  No Runes found in UTXO");
+            }
+            Err(e) => {
+                ic_cdk::println!("⚠️  Error checking for Runes: {}", e);
+            }
+        }
+    }
+
+    Ok(utxo_id)
+}
+
+/// Creates a Taproot address using Threshold Schnorr signatures
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn create_taproot_address() -> Result<String, String> {
+    let caller = ic_cdk::api::caller();
+
+    ic_cdk::println!("🔐 Creating Taproot address for user: {}", caller);
+
+    schnorr::create_taproot_address(None).await
+}
+
+/// Signs a Taproot transaction using Threshold Schnorr
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn sign_taproot_transaction(
+    message: Vec<u8>,
+    derivation_path: Vec<Vec<u8>>,
+) -> Result<schnorr::SchnorrSignResponse, String> {
+    let caller = ic_cdk::api::caller();
+
+    ic_cdk::println!("✍️  Signing Taproot transaction for user: {}", caller);
+
+    schnorr::sign_taproot_transaction(schnorr::SchnorrSignRequest {
+        message,
+        derivation_path,
+        key_id: None,
+    })
+    .await
+}
+
+/// Creates a multi-sig Taproot address
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn create_multisig_taproot(
+    required_signatures: u32,
+    total_signers: u32,
+) -> Result<String, String> {
+    let caller = ic_cdk::api::caller();
+
+    if required_signatures > total_signers {
+        return Err("Required signatures cannot exceed total signers".to_string());
+    }
+
+    ic_cdk::println!(
+        "🔐 Creating {}-of-{} multi-sig Taproot address for user: {}",
+        required_signatures,
+        total_signers,
+        caller
+    );
+
+    // In production, would get key IDs from state or parameters
+    let key_ids = vec![]; // Placeholder
+
+    schnorr::create_multisig_taproot_address(required_signatures, total_signers, key_ids).await
+}
+
+/// Gets the SOL balance (lamports) of a Solana address
+/// An update call: queries cannot make HTTP outcalls. Results are cached briefly
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_balance(
+    address: String,
+    network: solana::SolanaNetwork,
+) -> Result<u64, String> {
+    solana::get_solana_balance(&solana::SolanaAddress::parse(&address)?, network).await
+}
+
+/// Gets a Solana account's balance and owning program
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_account(
+    address: String,
+    network: solana::SolanaNetwork,
+) -> Result<solana::SolanaAccount, String> {
+    solana::get_solana_account(&solana::SolanaAddress::parse(&address)?, network).await
+}
+
+/// Gets a wallet's balance of one SPL token (raw base units)
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_token_balance(
+    address: String,
+    token_mint: String,
+    network: solana::SolanaNetwork,
+) -> Result<u64, String> {
+    solana::get_solana_token_balance(
+        &solana::SolanaAddress::parse(&address)?,
+        &solana::SolanaAddress::parse(&token_mint)?,
+        network,
+    )
+    .await
+}
+
+/// Lists a wallet's SPL token accounts, optionally for a single mint
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_token_accounts(
+    address: String,
+    token_mint: Option<String>,
+    network: solana::SolanaNetwork,
+) -> Result<Vec<solana::SplTokenAccount>, String> {
+    let token_mint = token_mint
+        .map(|mint| solana::SolanaAddress::parse(&mint))
+        .transpose()?;
+    solana::get_solana_token_accounts(
+        &solana::SolanaAddress::parse(&address)?,
+        token_mint.as_ref(),
+        network,
+    )
+    .await
+}
+
+/// Gets SOL and SPL token holdings of a wallet for display
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_holdings(
+    address: String,
+    network: solana::SolanaNetwork,
+) -> Result<solana::SolanaHoldings, String> {
+    solana::get_solana_holdings(&solana::SolanaAddress::parse(&address)?, network).await
+}
+
+/// Normalizes Solana RPC responses so all replicas reach consensus
+#[ic_cdk::query]
+pub fn transform_solana_response(
+    args: ic_cdk::management_canister::TransformArgs,
+) -> ic_cdk::management_canister::HttpRequestResult {
+    solana::transform_response(args)
+}
+
+/// Gets the Solana address the vault controls for the caller
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_solana_address() -> Result<String, String> {
+    let caller = solana_caller()?;
+
+    Ok(solana::user_solana_address(caller).await?.to_string())
+}
+
+/// Sends SOL from the caller's vault-controlled Solana account
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn send_sol(
+    to: String,
+    lamports: u64,
+    network: solana::SolanaNetwork,
+) -> Result<solana::SolanaTransactionResponse, String> {
+    let caller = solana_caller()?;
+
+    solana::transfer_sol(
+        caller,
+        &solana::SolanaAddress::parse(&to)?,
+        lamports,
+        network,
+    )
+    .await
+}
+
+/// Sends SPL tokens from the caller's vault-controlled Solana account
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn send_spl_token(
+    to: String,
+    token_mint: String,
+    amount: u64,
+    network: solana::SolanaNetwork,
+) -> Result<solana::SolanaTransactionResponse, String> {
+    let caller = solana_caller()?;
+
+    solana::transfer_spl(
+        caller,
+        &solana::SolanaAddress::parse(&to)?,
+        &solana::SolanaAddress::parse(&token_mint)?,
+        amount,
+        network,
+    )
+    .await
+}
+
+/// Creates a cross-chain BTC-SOL swap
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn create_btc_sol_swap(
+    sol_address: String,
+    sol_amount: u64,
+    network: solana::SolanaNetwork,
+) -> Result<solana::SolanaTransactionResponse, String> {
+    let caller = solana_caller()?;
+
+    ic_cdk::println!("🔄 Creating BTC-SOL swap for user: {}", caller);
+
+    solana::create_btc_sol_swap(
+        caller,
+        solana::SolanaTransactionRequest {
+            to: solana::SolanaAddress::parse(&sol_address)?,
+            amount: sol_amount,
+            network,
+        },
+    )
+    .await
+}
+
+/// Verifies that a Solana transaction succeeded at the required commitment
+/// (confirmed unless specified)
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn verify_solana_transaction(
+    signature: String,
+    network: solana::SolanaNetwork,
+    required_commitment: Option<solana::SolanaCommitment>,
+) -> Result<solana::SolanaTransactionStatus, solana::SolanaVerificationError> {
+    let signature = solana::SolanaSignature::parse(&signature)
+        .map_err(solana::SolanaVerificationError::InvalidSignature)?;
+
+    solana::verify_solana_transaction(
+        &signature,
+        network,
+        required_commitment.unwrap_or(solana::SolanaCommitment::Confirmed),
+    )
+    .await
+}
+
+fn solana_caller() -> Result<Principal, String> {
+    let caller = ic_cdk::api::msg_caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers have no Solana account".to_string());
+    }
+    Ok(caller)
+}
+
+/// Gets the vetKD public key clients use to verify their derived keys
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_vetkd_public_key() -> Result<vetkeys::VetKeyInfo, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let key_version = vetkeys::current_key_version(caller);
+
+    vetkeys::get_vetkey(&vetkeys::versioned_key_id(caller, key_version)).await
+}
+
+/// Derives the caller's vetKey encrypted to their transport public key
+/// Defaults to the current key version; older versions remain available only
+/// while a rotation is moving the caller's data off them.
+/// The client decrypts it locally, so the key never leaves the client in plaintext
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn get_encrypted_vetkey(
+    transport_public_key: Vec<u8>,
+    key_version: Option<u32>,
+) -> Result<Vec<u8>, String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers cannot derive keys".to_string());
+    }
+
+    let key_version = key_version.unwrap_or_else(|| vetkeys::current_key_version(caller));
+    vetkeys::ensure_key_version(caller, key_version)?;
+
+    vetkeys::vetkd_derive_key(
+        vetkeys::versioned_key_id(caller, key_version).as_bytes(),
+        vetkeys::CONTEXT,
+        &transport_public_key,
+    )
+    .await
+}
+
+/// Moves the caller to a new vetKD key version
+/// New data keys are wrapped under the new version immediately; the client then
+/// re-keys the caller's stored data batch by batch (`get_key_rotation_batch`)
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn rotate_encryption_key() -> Result<KeyRotationStatus, String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers cannot rotate keys".to_string());
+    }
+
+    rotation::start(caller)?;
+    Ok(rotation::status(caller))
+}
+
+/// Gets the caller's key version and rotation progress
+#[ic_cdk::query]
+pub fn get_key_rotation_status() -> KeyRotationStatus {
+    rotation::status(ic_cdk::api::msg_caller())
+}
+
+/// Gets the next items the caller's client has to re-key for a running rotation
+/// Owned notes are re-keyed with `rekey_encrypted_note` and owned loan documents
+/// with `commit_loan_document_rekey`; other items are re-wrapped
+#[ic_cdk::query]
+pub fn get_key_rotation_batch() -> Vec<RotationTask> {
+    rotation::next_batch(ic_cdk::api::msg_caller())
+}
+
+/// Reports that the caller's client failed to re-key an item of the running
+/// rotation; permanent errors skip the item, transient ones leave it pending for
+/// a retry until `MAX_ROTATION_ATTEMPTS`
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn report_key_rotation_failure(
+    item: RotationItem,
+    error: String,
+) -> Result<RotationFailure, String> {
+    rotation::record_failure(ic_cdk::api::msg_caller(), item, error)
+}
+
+/// Gets the key version data keys are wrapped under for `user`
+/// Clients wrap keys they share to the user's identity at this version
+#[ic_cdk::query]
+pub fn get_encryption_key_version(user: Principal) -> u32 {
+    vetkeys::current_key_version(user)
+}
+
+/// Stores a note the client encrypted under a fresh data key (secure note application)
+/// `wrapped_key` is that data key wrapped for the caller; only the owner can read
+/// the note until it is shared
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn create_encrypted_note(
+    ciphertext: Vec<u8>,
+    wrapped_key: Vec<u8>,
+) -> Result<EncryptedNote, String> {
+    let caller = ic_cdk::api::msg_caller();
+    notes::validate_ciphertext(&ciphertext)?;
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(caller))?;
+
+    let now = get_timestamp();
+    State::with(|state| {
+        let id = state.next_note_id.max(1);
+        state.next_note_id = id + 1;
+
+        let note = EncryptedNote {
+            id,
+            owner: caller,
+            ciphertext,
+            wrapped_keys: [(caller, wrapped_key)].into_iter().collect(),
+            created_at: now,
+            updated_at: now,
+        };
+        state.notes.insert(id, note.clone());
+        state.user_notes.entry(caller).or_default().push(id);
+        Ok(note)
+    })
+}
+
+/// Gets a note the caller owns or that was shared with them, still encrypted
+/// The client unwraps its copy of the data key and decrypts the note locally
+#[ic_cdk::query]
+pub fn get_encrypted_note(note_id: u64) -> Result<EncryptedNote, String> {
+    get_readable_note(ic_cdk::api::msg_caller(), note_id)
+}
+
+/// Lists notes the caller owns or that were shared with them (still encrypted)
+#[ic_cdk::query]
+pub fn list_encrypted_notes() -> Vec<EncryptedNote> {
+    let caller = ic_cdk::api::msg_caller();
+
+    State::with_read(|state| {
+        let mut notes: Vec<EncryptedNote> = state
+            .notes
+            .values()
+            .filter(|note| note.wrapped_keys.contains_key(&caller))
+            .cloned()
+            .collect();
+        notes.sort_by_key(|note| note.id);
+        notes
+    })
+}
+
+/// Replaces the content of a note the caller owns
+/// The client encrypts the new content under the note's existing data key
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn update_encrypted_note(note_id: u64, ciphertext: Vec<u8>) -> Result<EncryptedNote, String> {
+    let caller = ic_cdk::api::msg_caller();
+    notes::validate_ciphertext(&ciphertext)?;
+    get_owned_note(caller, note_id)?;
+
+    State::with(|state| {
+        let stored = state
+            .notes
+            .get_mut(&note_id)
+            .ok_or("Note not found".to_string())?;
+        stored.ciphertext = ciphertext;
+        stored.updated_at = get_timestamp();
+        Ok(stored.clone())
+    })
+}
+
+/// Deletes a note the caller owns
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn delete_encrypted_note(note_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let note = get_owned_note(caller, note_id)?;
+
+    State::with(|state| {
+        state.notes.remove(&note_id);
+        if let Some(ids) = state.user_notes.get_mut(&caller) {
+            ids.retain(|id| *id != note_id);
+        }
+    });
+    for reader in note.wrapped_keys.keys() {
+        rotation::settle(*reader);
+    }
+
+    Ok(())
+}
+
+/// Shares a note with another principal (e.g. a dead man switch beneficiary)
+/// `wrapped_key` is the note's data key wrapped by the client for the recipient,
+/// under the recipient's current key version (`get_encryption_key_version`)
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn share_encrypted_note(
+    note_id: u64,
+    recipient: Principal,
+    wrapped_key: Vec<u8>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if recipient == caller || recipient == Principal::anonymous() {
+        return Err("Recipient must be a different, non-anonymous principal".to_string());
+    }
+    get_owned_note(caller, note_id)?;
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(recipient))?;
+
+    State::with(|state| {
+        let stored = state
+            .notes
+            .get_mut(&note_id)
+            .ok_or("Note not found".to_string())?;
+        stored.wrapped_keys.insert(recipient, wrapped_key);
+        Ok(())
+    })
+}
+
+/// Revokes a recipient's access to a note
+/// The client re-encrypts the note under a fresh data key and wraps that key for
+/// every remaining reader, so the revoked copy becomes useless
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn unshare_encrypted_note(
+    note_id: u64,
+    recipient: Principal,
+    ciphertext: Vec<u8>,
+    wrapped_keys: Vec<(Principal, Vec<u8>)>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if recipient == caller {
+        return Err("The owner cannot revoke their own access".to_string());
+    }
+
+    let note = get_owned_note(caller, note_id)?;
+    if !note.wrapped_keys.contains_key(&recipient) {
+        return Err("Note is not shared with this principal".to_string());
+    }
+    let readers: Vec<Principal> = note
+        .wrapped_keys
+        .keys()
+        .copied()
+        .filter(|principal| *principal != recipient)
+        .collect();
+    replace_note_key(note_id, &readers, ciphertext, wrapped_keys)?;
+    rotation::settle(recipient);
+    Ok(())
+}
+
+/// Re-encrypts a note the caller owns under a fresh data key, e.g. during a key
+/// rotation; the client wraps the new key for every reader
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn rekey_encrypted_note(
+    note_id: u64,
+    ciphertext: Vec<u8>,
+    wrapped_keys: Vec<(Principal, Vec<u8>)>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let note = get_owned_note(caller, note_id)?;
+    let readers: Vec<Principal> = note.wrapped_keys.keys().copied().collect();
+    replace_note_key(note_id, &readers, ciphertext, wrapped_keys)
+}
+
+/// Replaces the caller's copy of the data key of a note shared with them,
+/// re-wrapped by the client under the caller's current key version during a
+/// key rotation; owners re-key their notes instead
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn rewrap_encrypted_note_key(note_id: u64, wrapped_key: Vec<u8>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    if get_readable_note(caller, note_id)?.owner == caller {
+        return Err("Owners re-key their notes with rekey_encrypted_note".to_string());
+    }
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(caller))?;
+
+    State::with(|state| {
+        if let Some(note) = state.notes.get_mut(&note_id) {
+            note.wrapped_keys.insert(caller, wrapped_key);
+        }
+    });
+    rotation::settle(caller);
+    Ok(())
+}
+
+/// Stores a note's content re-encrypted under a fresh data key, wrapped for
+/// exactly `readers`
+fn replace_note_key(
+    note_id: u64,
+    readers: &[Principal],
+    ciphertext: Vec<u8>,
+    wrapped_keys: Vec<(Principal, Vec<u8>)>,
+) -> Result<(), String> {
+    notes::validate_ciphertext(&ciphertext)?;
+    let wrapped_keys =
+        vetkeys::validate_wrapped_keys(readers, wrapped_keys, vetkeys::current_key_version)?;
+
+    State::with(|state| {
+        let stored = state
+            .notes
+            .get_mut(&note_id)
+            .ok_or("Note not found".to_string())?;
+        stored.ciphertext = ciphertext;
+        stored.wrapped_keys = wrapped_keys;
+        stored.updated_at = get_timestamp();
+        Ok::<(), String>(())
+    })?;
+    for reader in readers {
+        rotation::settle(*reader);
+    }
+    Ok(())
+}
+
+fn get_readable_note(caller: Principal, note_id: u64) -> Result<EncryptedNote, String> {
+    let note = State::with_read(|state| state.notes.get(&note_id).cloned())
+        .ok_or("Note not found".to_string())?;
+    if !note.wrapped_keys.contains_key(&caller) {
+        return Err("Unauthorized: Note is not shared with caller".to_string());
+    }
+    Ok(note)
+}
+
+fn get_owned_note(caller: Principal, note_id: u64) -> Result<EncryptedNote, String> {
+    let note = get_readable_note(caller, note_id)?;
+    if note.owner != caller {
+        return Err("Unauthorized: Note does not belong to caller".to_string());
+    }
+    Ok(note)
+}
+
+/// Sets up a dead man switch
+/// Transfers unencumbered funds to the beneficiary after `inactivity_threshold_seconds`
+/// of inactivity followed by `warning_period_seconds` without the warning being cancelled
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn setup_dead_man_switch(
+    inactivity_threshold_seconds: u64,
+    warning_period_seconds: u64,
+    beneficiary: Principal,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if inactivity_threshold_seconds == 0 {
+        return Err("Inactivity threshold must be greater than 0".to_string());
+    }
+
+    if beneficiary == caller || beneficiary == Principal::anonymous() {
+        return Err("Beneficiary must be a different, non-anonymous principal".to_string());
+    }
+
+    ic_cdk::println!(
+        "⏰ Setting up dead man switch for user: {} (threshold: {}s, warning: {}s, beneficiary: {})",
+        caller,
+        inactivity_threshold_seconds,
+        warning_period_seconds,
+        beneficiary
+    );
+
+    State::with(|state| {
+        let dms = DeadManSwitch {
+            user_id: caller,
+            last_activity: get_timestamp(),
+            inactivity_threshold: inactivity_threshold_seconds.saturating_mul(1_000_000_000), // Convert to nanoseconds
+            beneficiary,
+            enabled: true,
+            warning_period: warning_period_seconds.saturating_mul(1_000_000_000),
+            warning_started_at: None,
+            triggered_at: None,
+        };
+
+        state.dead_man_switches.insert(caller, dms);
+    });
+
+    deadman::ensure_timer();
+    ic_cdk::println!("✅ Dead man switch configured");
+
+    Ok(())
+}
+
+/// Updates last activity timestamp (call this periodically to keep dead man switch from triggering)
+/// Any update call counts as activity; this endpoint exists for users with nothing else to do
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn update_activity() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    ic_cdk::println!("✅ Activity updated for user: {}", caller);
+
+    Ok(())
+}
+
+/// Disables the caller's dead man switch
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn cancel_dead_man_switch() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    State::with(|state| {
+        let dms = state
+            .dead_man_switches
+            .get_mut(&caller)
+            .ok_or("No dead man switch configured".to_string())?;
+        if dms.triggered_at.is_some() {
+            return Err("Dead man switch has already fired".to_string());
+        }
+
+        dms.enabled = false;
+        dms.warning_started_at = None;
+        Ok(())
+    })
+}
+
+/// Gets the caller's dead man switch, including any pending warning
+#[ic_cdk::query]
+pub fn get_dead_man_switch() -> Option<DeadManSwitch> {
+    let caller = ic_cdk::api::msg_caller();
+
+    State::with_read(|state| state.dead_man_switches.get(&caller).cloned())
+}
+
+/// Gets Rune balances for a Bitcoin address
+#[ic_cdk::query]
+pub async fn get_rune_balances(address: String) -> Result<Vec<runes::RuneBalance>, String> {
+    runes::get_rune_balances(&address).await
+}
+
+// ============================================================================
+// PSBT Construction and Export/Import
+// ============================================================================
+
+/// Builds an unsigned PSBT spending the caller's custodied UTXOs
+/// Only UTXOs that are deposited and not locked as collateral can be spent
+#[ic_cdk::query]
+pub fn build_psbt(request: psbt::BuildPsbtRequest) -> Result<String, String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    let inputs = State::with_read(|state| {
+        let user_utxos = state.user_utxos.get(&caller).cloned().unwrap_or_default();
+
+        request
+            .inputs
+            .iter()
+            .map(|input| {
+                if !user_utxos.contains(&input.utxo_id) {
+                    return Err(format!(
+                        "Unauthorized: UTXO {} does not belong to caller",
+                        input.utxo_id
+                    ));
+                }
+
+                let utxo = state
+                    .utxos
+                    .get(&input.utxo_id)
+                    .ok_or(format!("UTXO {} not found", input.utxo_id))?;
+
+                if utxo.status != UtxoStatus::Deposited {
+                    return Err(format!(
+                        "UTXO {} is locked as collateral or already withdrawn",
+                        input.utxo_id
+                    ));
+                }
+
+                Ok(psbt::PsbtInputSpec {
+                    txid: utxo.txid.clone(),
+                    vout: utxo.vout,
+                    amount: utxo.amount,
+                    script_pubkey: psbt::parse_address(&utxo.address)?
+                        .script_pubkey()
+                        .to_bytes(),
+                    sequence: input.sequence,
+                    taproot: input.taproot.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+
+    let built = psbt::build_psbt(&inputs, &request.outputs, request.lock_time)?;
+    psbt::to_base64(&built, request.version)
+}
+
+/// Combines PSBTs carrying signatures from different co-signers
+#[ic_cdk::query]
+pub fn combine_psbts(psbts: Vec<String>) -> Result<String, String> {
+    let mut version = psbt::PsbtVersion::V0;
+    let mut parsed = Vec::with_capacity(psbts.len());
+    for encoded in &psbts {
+        let (p, v) = psbt::from_base64(encoded)?;
+        version = v;
+        parsed.push(p);
+    }
 
-            ic_cdk::println!(
-                "Loan {} liquidated by {}. Collateral UTXO {} transferred.",
-                loan_id,
-                caller,
-                loan.collateral_utxo_id
-            );
-        }
+    let combined = psbt::combine(parsed)?;
+    psbt::to_base64(&combined, version)
+}
 
-        // Mark UTXO as withdrawn (transferred to liquidator)
-        if let Some(utxo) = state.utxos.get_mut(&loan.collateral_utxo_id) {
-            utxo.status = UtxoStatus::Withdrawn;
-        }
-    });
+/// Adds an external co-signer's partial signature to a PSBT input
+#[ic_cdk::query]
+pub fn add_psbt_signature(
+    psbt_base64: String,
+    input_index: u32,
+    signature: psbt::PartialSignature,
+) -> Result<String, String> {
+    let (mut parsed, version) = psbt::from_base64(&psbt_base64)?;
+    psbt::add_partial_signature(&mut parsed, input_index as usize, &signature)?;
+    psbt::to_base64(&parsed, version)
+}
 
-    Ok(())
+/// Finalizes a fully signed PSBT and extracts the network transaction
+#[ic_cdk::query]
+pub fn finalize_psbt(psbt_base64: String) -> Result<psbt::FinalizedTransaction, String> {
+    let (mut parsed, _) = psbt::from_base64(&psbt_base64)?;
+    psbt::finalize(&mut parsed)?;
+    psbt::extract(parsed)
 }
 
 // ============================================================================
-// Advanced Features: Threshold Schnorr, Runes, Solana, vetKeys
+// MuSig2 2-of-2 Vaults
 // ============================================================================
 
-/// Deposits a UTXO with Runes support
-/// Checks for both Ordinals and Runes
-#[ic_cdk::update]
-pub async fn deposit_utxo_with_runes(request: DepositUtxoRequest) -> Result<UtxoId, String> {
-    let caller = ic_cdk::api::caller();
-
-    // First, deposit the UTXO normally
-    let utxo_id = deposit_utxo(request.clone()).await?;
-
-    // Then check for Runes
-    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
-    if network != "local" && network != "playground" {
-        match runes::verify_runes(&request.txid, request.vout).await {
-            Ok(Some(runes_info)) => {
-                ic_cdk::println!("✅ Found {} Rune(s) in UTXO", runes_info.len());
-                // Store Runes info in state (would need to extend state)
-            }
-            Ok(None) => {
-                ic_cdk::println!("ℹ️  No Runes found in UTXO");
-            }
-            Err(e) => {
-                ic_cdk::println!("⚠️  Error checking for Runes: {}", e);
-            }
-        }
+/// Creates a 2-of-2 vault between the caller's key and a threshold Schnorr key
+/// of the canister
+/// The user can refund alone through the script path after `refund_delay_blocks`
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn create_musig_vault(
+    user_public_key: Vec<u8>,
+    refund_delay_blocks: u16,
+) -> Result<MuSigVault, String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    if user_public_key.len() != 33 {
+        return Err("User public key must be a 33-byte compressed key".to_string());
     }
 
-    Ok(utxo_id)
-}
+    // Reserve the id first: the key derivation below awaits
+    let vault_id = State::with(|state| {
+        let id = state.next_musig_vault_id.max(1);
+        state.next_musig_vault_id = id + 1;
+        id
+    });
+    let canister_public_key = musig2::canister_public_key(vault_id).await?;
+    let taproot =
+        musig2::vault_taproot(&user_public_key, &canister_public_key, refund_delay_blocks)?;
 
-/// Creates a Taproot address using Threshold Schnorr signatures
-#[ic_cdk::update]
-pub async fn create_taproot_address() -> Result<String, String> {
-    let caller = ic_cdk::api::caller();
+    let vault = MuSigVault {
+        id: vault_id,
+        user_id: caller,
+        user_public_key,
+        canister_public_key,
+        internal_key: taproot.internal_key.to_vec(),
+        cosign_script: taproot.cosign_script,
+        cosign_control_block: taproot.cosign_control_block,
+        refund_script: taproot.refund_script,
+        refund_control_block: taproot.control_block,
+        merkle_root: taproot.merkle_root.to_vec(),
+        refund_delay_blocks,
+        address: taproot.address,
+        created_at: get_timestamp(),
+    };
 
-    ic_cdk::println!("🔐 Creating Taproot address for user: {}", caller);
+    State::with(|state| {
+        state.musig_vaults.insert(vault_id, vault.clone());
+        state
+            .user_musig_vaults
+            .entry(caller)
+            .or_default()
+            .push(vault_id);
+    });
 
-    schnorr::create_taproot_address(None).await
+    Ok(vault)
 }
 
-/// Signs a Taproot transaction using Threshold Schnorr
-#[ic_cdk::update]
-pub async fn sign_taproot_transaction(
-    message: Vec<u8>,
-    derivation_path: Vec<Vec<u8>>,
-) -> Result<schnorr::SchnorrSignResponse, String> {
-    let caller = ic_cdk::api::caller();
-
-    ic_cdk::println!("✍️  Signing Taproot transaction for user: {}", caller);
+/// Returns the caller's MuSig2 vaults
+#[ic_cdk::query]
+pub fn get_musig_vaults() -> Vec<MuSigVault> {
+    let caller = ic_cdk::api::msg_caller();
 
-    schnorr::sign_taproot_transaction(schnorr::SchnorrSignRequest {
-        message,
-        derivation_path,
-        key_id: None,
+    State::with_read(|state| {
+        state
+            .user_musig_vaults
+            .get(&caller)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| state.musig_vaults.get(id).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
     })
-    .await
 }
 
-/// Creates a multi-sig Taproot address
-#[ic_cdk::update]
-pub async fn create_multisig_taproot(
-    required_signatures: u32,
-    total_signers: u32,
+/// Adds the canister's signature for the 2-of-2 leaf to one input of a PSBT
+/// spending a vault
+/// The user then adds their own signature for the same leaf and finalizes
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub async fn cosign_musig_vault_spend(
+    vault_id: u64,
+    psbt_base64: String,
+    input_index: u32,
 ) -> Result<String, String> {
-    let caller = ic_cdk::api::caller();
+    let caller = ic_cdk::api::msg_caller();
+    let vault = get_caller_musig_vault(caller, vault_id)?;
+
+    let (mut parsed, version) = psbt::from_base64(&psbt_base64)?;
+    let input = parsed
+        .inputs
+        .get(input_index as usize)
+        .ok_or(format!("Input {} not found in PSBT", input_index))?;
+    let vault_script = psbt::parse_address(&vault.address)?.script_pubkey();
+    match &input.witness_utxo {
+        Some(prevout) if prevout.script_pubkey == vault_script => {}
+        _ => return Err("PSBT input does not spend this vault".to_string()),
+    }
+    ensure_musig_inputs_unencumbered(&parsed)?;
+
+    let sighash = psbt::taproot_sighash(&parsed, input_index as usize, Some(&vault.cosign_script))?;
+    let signature = musig2::sign_as_canister(vault.id, sighash).await?;
+
+    // Loans may have been opened while the signature was being made
+    ensure_musig_inputs_unencumbered(&parsed)?;
+
+    psbt::add_partial_signature(
+        &mut parsed,
+        input_index as usize,
+        &psbt::PartialSignature::TaprootScript {
+            public_key: vault.canister_public_key[1..].to_vec(),
+            leaf_script: vault.cosign_script,
+            signature: signature.to_vec(),
+        },
+    )?;
+    psbt::to_base64(&parsed, version)
+}
 
-    if required_signatures > total_signers {
-        return Err("Required signatures cannot exceed total signers".to_string());
+fn get_caller_musig_vault(caller: Principal, vault_id: u64) -> Result<MuSigVault, String> {
+    let vault = State::with_read(|state| state.musig_vaults.get(&vault_id).cloned())
+        .ok_or("Vault not found".to_string())?;
+    if vault.user_id != caller {
+        return Err("Unauthorized: Vault does not belong to caller".to_string());
     }
+    Ok(vault)
+}
+
+/// The canister refuses to co-sign while any spent UTXO backs an open loan
+fn ensure_musig_inputs_unencumbered(parsed: &::bitcoin::Psbt) -> Result<(), String> {
+    State::with_read(|state| {
+        collateral::ensure_unencumbered(&psbt::outpoints(parsed), &state.utxos, &state.loans)
+    })
+}
+
+// ============================================================================
+// Time-Locked Collateral
+// ============================================================================
+
+/// Attaches a time lock to one of the caller's deposited UTXOs
+/// At unlock the UTXO is auto-withdrawn or handed to the beneficiary if configured
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn set_time_lock(utxo_id: UtxoId, config: TimeLockConfig) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+
+    State::with(|state| {
+        let owns = state
+            .user_utxos
+            .get(&caller)
+            .map(|utxos| utxos.contains(&utxo_id))
+            .unwrap_or(false);
+        if !owns {
+            return Err("Unauthorized: UTXO does not belong to caller".to_string());
+        }
+
+        let utxo = state
+            .utxos
+            .get(&utxo_id)
+            .ok_or("UTXO not found".to_string())?;
+        if utxo.status == UtxoStatus::Withdrawn {
+            return Err("UTXO has already been withdrawn".to_string());
+        }
+
+        timelock::validate_time_lock(&config, state.time_locks.get(&utxo_id), get_timestamp())?;
+        state.time_locks.insert(utxo_id, config.clone());
+        Ok(())
+    })?;
+
+    timelock::schedule_unlock(utxo_id, config.unlock_timestamp);
 
     ic_cdk::println!(
-        "🔐 Creating {}-of-{} multi-sig Taproot address for user: {}",
-        required_signatures,
-        total_signers,
+        "🔒 UTXO {} time-locked until {} by {}",
+        utxo_id,
+        config.unlock_timestamp,
         caller
     );
 
-    // In production, would get key IDs from state or parameters
-    let key_ids = vec![]; // Placeholder
+    Ok(())
+}
 
-    schnorr::create_multisig_taproot_address(required_signatures, total_signers, key_ids).await
+/// Gets the time lock attached to a UTXO, if any
+#[ic_cdk::query]
+pub fn get_time_lock(utxo_id: UtxoId) -> Option<TimeLockConfig> {
+    State::with_read(|state| state.time_locks.get(&utxo_id).cloned())
 }
 
-/// Gets Solana balance for a user
+/// Creates a Taproot address that enforces the time lock on-chain
+/// Funds sent here can only be spent by the user's key after `unlock_timestamp`
 #[ic_cdk::query]
-pub async fn get_solana_balance(
-    address: String,
-    network: solana::SolanaNetwork,
-) -> Result<u64, String> {
-    solana::get_solana_balance(&address, network).await
+pub fn create_time_lock_address(
+    user_public_key: Vec<u8>,
+    unlock_timestamp: u64,
+) -> Result<timelock::TimeLockAddress, String> {
+    timelock::time_lock_address(&user_public_key, unlock_timestamp)
 }
 
-/// Creates a cross-chain BTC-SOL swap
-#[ic_cdk::update]
-pub async fn create_btc_sol_swap(
-    sol_address: String,
-    sol_amount: u64,
-    network: solana::SolanaNetwork,
-) -> Result<solana::SolanaTransactionResponse, String> {
-    let caller = ic_cdk::api::caller();
+// ============================================================================
+// Encrypted Loan Documents
+// ============================================================================
 
-    ic_cdk::println!("🔄 Creating BTC-SOL swap for user: {}", caller);
+/// Registers the document key of one of the caller's loans
+/// The client creates the key and wraps it for the caller; it is needed before
+/// the first document is uploaded and cannot be replaced
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn create_loan_document_key(loan_id: LoanId, wrapped_key: Vec<u8>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(caller))?;
 
-    solana::create_btc_sol_swap(solana::SolanaTransactionRequest {
-        from: "vault".to_string(), // Vault address
-        to: sol_address,
-        amount: sol_amount,
-        network,
+    State::with(|state| {
+        let grants = state.loan_document_grants.entry(loan_id).or_default();
+        if grants.contains_key(&caller) {
+            return Err("Loan already has a document key".to_string());
+        }
+        grants.insert(
+            caller,
+            DocumentGrant {
+                role: DocumentRole::Borrower,
+                wrapped_key,
+                granted_at: get_timestamp(),
+            },
+        );
+        Ok(())
     })
-    .await
 }
 
-/// Encrypts user data using vetKeys
-#[ic_cdk::update]
-pub async fn encrypt_user_data(data: Vec<u8>) -> Result<vetkeys::EncryptResponse, String> {
-    let caller = ic_cdk::api::caller();
+/// Gets the loan's document key wrapped for the caller
+/// The client unwraps it to encrypt or decrypt the loan's documents
+#[ic_cdk::query]
+pub fn get_loan_document_key(loan_id: LoanId) -> Result<Vec<u8>, String> {
+    let caller = ic_cdk::api::msg_caller();
+    State::with_read(|state| {
+        state
+            .loan_document_grants
+            .get(&loan_id)
+            .and_then(|grants| grants.get(&caller))
+            .map(|grant| grant.wrapped_key.clone())
+    })
+    .ok_or("Unauthorized: No access to this loan's documents".to_string())
+}
 
-    ic_cdk::println!("🔐 Encrypting user data for: {}", caller);
+/// Starts a chunked upload of a document attached to one of the caller's loans
+/// `total_size` is the size of the client's ciphertext; returns the document ID
+/// to upload chunks to
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn begin_loan_document(
+    loan_id: LoanId,
+    name: String,
+    content_type: String,
+    total_size: u64,
+) -> Result<u64, String> {
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
 
-    let key_id = format!("user_{}", caller);
+    State::with(|state| {
+        let has_key = state
+            .loan_document_grants
+            .get(&loan_id)
+            .is_some_and(|grants| grants.contains_key(&caller));
+        if !has_key {
+            return Err("Create the loan's document key first".to_string());
+        }
+        let existing = state
+            .loan_documents
+            .values()
+            .filter(|doc| doc.loan_id == loan_id)
+            .count();
+        let reserved = state
+            .loan_documents
+            .values()
+            .map(documents::stored_size)
+            .sum();
+        documents::validate_new_document(&name, total_size, existing)?;
+        documents::validate_storage(reserved, total_size)?;
 
-    vetkeys::encrypt_data(vetkeys::EncryptRequest {
-        plaintext: data,
-        public_key_id: key_id,
-        associated_data: Some(caller.as_slice().to_vec()),
+        let id = state.next_loan_document_id.max(1);
+        state.next_loan_document_id = id + 1;
+        state.loan_documents.insert(
+            id,
+            LoanDocument {
+                id,
+                loan_id,
+                name,
+                content_type,
+                total_size,
+                uploaded_size: 0,
+                chunks: Vec::new(),
+                complete: false,
+                created_at: get_timestamp(),
+                rekeyed_chunks: None,
+            },
+        );
+        Ok(id)
     })
-    .await
 }
 
-/// Decrypts user data using vetKeys
-#[ic_cdk::update]
-pub async fn decrypt_user_data(ciphertext: Vec<u8>) -> Result<vetkeys::DecryptResponse, String> {
-    let caller = ic_cdk::api::caller();
+/// Uploads the next chunk of a document, encrypted by the client under the
+/// loan's document key; chunks must arrive in order
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn upload_loan_document_chunk(
+    document_id: u64,
+    chunk_index: u32,
+    data: Vec<u8>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let document = get_loan_document(document_id)?;
+    get_borrowed_loan(caller, document.loan_id)?;
 
-    ic_cdk::println!("🔓 Decrypting user data for: {}", caller);
+    State::with(|state| {
+        let document = state
+            .loan_documents
+            .get_mut(&document_id)
+            .ok_or("Document not found".to_string())?;
+        documents::validate_chunk(document, chunk_index, data.len())?;
+
+        document.uploaded_size += data.len() as u64;
+        document.chunks.push(data);
+        Ok(())
+    })
+}
+
+/// Marks a document as complete once every declared byte has been uploaded
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn finalize_loan_document(document_id: u64) -> Result<LoanDocumentInfo, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let document = get_loan_document(document_id)?;
+    get_borrowed_loan(caller, document.loan_id)?;
 
-    let key_id = format!("user_{}", caller);
+    State::with(|state| {
+        let document = state
+            .loan_documents
+            .get_mut(&document_id)
+            .ok_or("Document not found".to_string())?;
+        if document.uploaded_size != document.total_size {
+            return Err(format!(
+                "Upload incomplete: {} of {} bytes received",
+                document.uploaded_size, document.total_size
+            ));
+        }
 
-    vetkeys::decrypt_data(vetkeys::DecryptRequest {
-        ciphertext,
-        public_key_id: key_id,
-        associated_data: Some(caller.as_slice().to_vec()),
+        document.complete = true;
+        Ok(loan_document_info(document))
     })
-    .await
 }
 
-/// Creates an encrypted note (secure note application)
-#[ic_cdk::update]
-pub async fn create_encrypted_note(
-    note_content: String,
-) -> Result<vetkeys::EncryptResponse, String> {
-    let caller = ic_cdk::api::caller();
+/// Lists the documents attached to a loan the caller can read
+#[ic_cdk::query]
+pub fn list_loan_documents(loan_id: LoanId) -> Result<Vec<LoanDocumentInfo>, String> {
+    let caller = ic_cdk::api::msg_caller();
+    ensure_loan_document_reader(caller, loan_id)?;
 
-    vetkeys::create_encrypted_note(caller, note_content).await
+    State::with_read(|state| {
+        let mut infos: Vec<LoanDocumentInfo> = state
+            .loan_documents
+            .values()
+            .filter(|doc| doc.loan_id == loan_id)
+            .map(loan_document_info)
+            .collect();
+        infos.sort_by_key(|info| info.id);
+        Ok(infos)
+    })
 }
 
-/// Gets an encrypted note
+/// Downloads one chunk of a completed document, still encrypted
+/// The client decrypts it with the loan's document key
 #[ic_cdk::query]
-pub async fn get_encrypted_note(ciphertext: Vec<u8>) -> Result<String, String> {
-    let caller = ic_cdk::api::caller();
+pub fn download_loan_document_chunk(document_id: u64, chunk_index: u32) -> Result<Vec<u8>, String> {
+    let caller = ic_cdk::api::msg_caller();
+    let document = get_loan_document(document_id)?;
+    ensure_loan_document_reader(caller, document.loan_id)?;
+
+    if !document.complete {
+        return Err("Document upload is not finalized".to_string());
+    }
+    document
+        .chunks
+        .get(chunk_index as usize)
+        .cloned()
+        .ok_or(format!("Chunk {} not found", chunk_index))
+}
 
-    vetkeys::get_encrypted_note(caller, ciphertext).await
+/// Deletes a document from one of the caller's loans
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn delete_loan_document(document_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let document = get_loan_document(document_id)?;
+    get_borrowed_loan(caller, document.loan_id)?;
+
+    State::with(|state| state.loan_documents.remove(&document_id));
+    Ok(())
 }
 
-/// Sets up a dead man switch
-/// Automatically transfers funds if user stops logging in
-#[ic_cdk::update]
-pub async fn setup_dead_man_switch(
-    inactivity_threshold_seconds: u64,
-    beneficiary: Principal,
+/// Grants an auditor or counterparty read access to a loan's documents
+/// `wrapped_key` is the loan's document key wrapped by the client for the
+/// grantee, under the grantee's current key version (`get_encryption_key_version`)
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn grant_loan_document_access(
+    loan_id: LoanId,
+    principal: Principal,
+    role: DocumentRole,
+    wrapped_key: Vec<u8>,
 ) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
 
-    ic_cdk::println!(
-        "⏰ Setting up dead man switch for user: {} (threshold: {}s, beneficiary: {})",
-        caller,
-        inactivity_threshold_seconds,
-        beneficiary
-    );
+    if role == DocumentRole::Borrower {
+        return Err("Borrower access cannot be granted".to_string());
+    }
+    if principal == caller || principal == Principal::anonymous() {
+        return Err("Grantee must be a different, non-anonymous principal".to_string());
+    }
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(principal))?;
 
-    // Store dead man switch configuration in state
     State::with(|state| {
-        let dms = DeadManSwitch {
-            user_id: caller,
-            last_activity: get_timestamp(),
-            inactivity_threshold: inactivity_threshold_seconds * 1_000_000_000, // Convert to nanoseconds
-            beneficiary,
-            enabled: true,
-        };
+        let grants = state
+            .loan_document_grants
+            .get_mut(&loan_id)
+            .filter(|grants| grants.contains_key(&caller))
+            .ok_or("Create the loan's document key first".to_string())?;
+        grants.insert(
+            principal,
+            DocumentGrant {
+                role,
+                wrapped_key,
+                granted_at: get_timestamp(),
+            },
+        );
+        Ok(())
+    })
+}
 
-        // Would need to add dead_man_switches to state
-        // state.dead_man_switches.insert(caller, dms);
+/// Uploads the next chunk of a completed document re-encrypted by the client
+/// under a new loan document key; chunks must arrive in order and take effect
+/// with `commit_loan_document_rekey`. Sending chunk 0 again restarts the re-key
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn upload_rekeyed_loan_document_chunk(
+    document_id: u64,
+    chunk_index: u32,
+    data: Vec<u8>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let document = get_loan_document(document_id)?;
+    get_borrowed_loan(caller, document.loan_id)?;
 
-        ic_cdk::println!("✅ Dead man switch configured");
-    });
+    State::with(|state| {
+        if chunk_index == 0 {
+            if let Some(document) = state.loan_documents.get_mut(&document_id) {
+                document.rekeyed_chunks = None;
+            }
+        }
+        let stored: u64 = state
+            .loan_documents
+            .values()
+            .map(documents::stored_size)
+            .sum();
+        let document = state
+            .loan_documents
+            .get_mut(&document_id)
+            .ok_or("Document not found".to_string())?;
+        documents::validate_rekeyed_chunk(document, chunk_index, data.len())?;
+        documents::validate_storage(stored, data.len() as u64)?;
+
+        document
+            .rekeyed_chunks
+            .get_or_insert_with(Vec::new)
+            .push(data);
+        Ok(())
+    })
+}
+
+/// Switches a loan's documents to a new document key, e.g. during a key rotation
+/// Every document must have been re-uploaded with
+/// `upload_rekeyed_loan_document_chunk`; the client wraps the new key for every
+/// principal with access, including the caller
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn commit_loan_document_rekey(
+    loan_id: LoanId,
+    wrapped_keys: Vec<(Principal, Vec<u8>)>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
+    let readers: Vec<Principal> = State::with_read(|state| {
+        state
+            .loan_document_grants
+            .get(&loan_id)
+            .filter(|grants| grants.contains_key(&caller))
+            .map(|grants| grants.keys().copied().collect())
+    })
+    .ok_or("Create the loan's document key first".to_string())?;
+    let mut wrapped_keys =
+        vetkeys::validate_wrapped_keys(&readers, wrapped_keys, vetkeys::current_key_version)?;
 
+    State::with(|state| {
+        documents::ensure_rekeyed(
+            state
+                .loan_documents
+                .values()
+                .filter(|doc| doc.loan_id == loan_id),
+        )?;
+        for document in state
+            .loan_documents
+            .values_mut()
+            .filter(|doc| doc.loan_id == loan_id)
+        {
+            document.chunks = document.rekeyed_chunks.take().unwrap_or_default();
+            document.total_size = document.chunks.iter().map(|chunk| chunk.len() as u64).sum();
+            document.uploaded_size = document.total_size;
+        }
+        if let Some(grants) = state.loan_document_grants.get_mut(&loan_id) {
+            for (principal, grant) in grants.iter_mut() {
+                if let Some(wrapped_key) = wrapped_keys.remove(principal) {
+                    grant.wrapped_key = wrapped_key;
+                }
+            }
+        }
+        Ok::<(), String>(())
+    })?;
+    for reader in readers {
+        rotation::settle(reader);
+    }
     Ok(())
 }
 
-/// Updates last activity timestamp (call this periodically to keep dead man switch from triggering)
-#[ic_cdk::update]
-pub async fn update_activity() -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
+/// Replaces the caller's copy of the document key of a loan they were granted
+/// access to, re-wrapped by the client under the caller's current key version
+/// during a key rotation; borrowers re-key with `commit_loan_document_rekey`
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn rewrap_loan_document_key(loan_id: LoanId, wrapped_key: Vec<u8>) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    if get_borrowed_loan(caller, loan_id).is_ok() {
+        return Err("Borrowers re-key their documents with commit_loan_document_rekey".to_string());
+    }
+    vetkeys::validate_wrapped_key(&wrapped_key, vetkeys::current_key_version(caller))?;
 
-    // Update last activity timestamp
     State::with(|state| {
-        // Would need to add dead_man_switches to state
-        // if let Some(dms) = state.dead_man_switches.get_mut(&caller) {
-        //     dms.last_activity = get_timestamp();
-        // }
+        state
+            .loan_document_grants
+            .get_mut(&loan_id)
+            .and_then(|grants| grants.get_mut(&caller))
+            .map(|grant| grant.wrapped_key = wrapped_key)
+            .ok_or("Unauthorized: No access to this loan's documents".to_string())
+    })?;
+    rotation::settle(caller);
+    Ok(())
+}
 
-        ic_cdk::println!("✅ Activity updated for user: {}", caller);
-    });
+/// Revokes a principal's access to a loan's documents
+/// Access through the canister ends immediately; the borrower re-keys the
+/// documents (`commit_loan_document_rekey`) to cut off copies of the old key
+#[ic_cdk::update(guard = "deadman::record_activity")]
+pub fn revoke_loan_document_access(loan_id: LoanId, principal: Principal) -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
+
+    if principal == caller {
+        return Err("The borrower cannot revoke their own access".to_string());
+    }
 
+    State::with(|state| {
+        state
+            .loan_document_grants
+            .get_mut(&loan_id)
+            .and_then(|grants| grants.remove(&principal))
+            .map(|_| ())
+            .ok_or("Principal has no access to this loan's documents".to_string())
+    })?;
+    rotation::settle(principal);
     Ok(())
 }
 
-/// Gets Rune balances for a Bitcoin address
+/// Lists who can read a loan's documents and in which role
 #[ic_cdk::query]
-pub async fn get_rune_balances(address: String) -> Result<Vec<runes::RuneBalance>, String> {
-    runes::get_rune_balances(&address).await
+pub fn get_loan_document_grants(loan_id: LoanId) -> Result<Vec<(Principal, DocumentRole)>, String> {
+    let caller = ic_cdk::api::msg_caller();
+    get_borrowed_loan(caller, loan_id)?;
+
+    Ok(State::with_read(|state| {
+        state
+            .loan_document_grants
+            .get(&loan_id)
+            .map(|grants| {
+                grants
+                    .iter()
+                    .map(|(principal, grant)| (*principal, grant.role.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }))
+}
+
+fn get_borrowed_loan(caller: Principal, loan_id: LoanId) -> Result<Loan, String> {
+    let loan = State::with_read(|state| state.loans.get(&loan_id).cloned())
+        .ok_or("Loan not found".to_string())?;
+    if loan.user_id != caller {
+        return Err("Unauthorized: Loan does not belong to caller".to_string());
+    }
+    Ok(loan)
+}
+
+fn get_loan_document(document_id: u64) -> Result<LoanDocument, String> {
+    State::with_read(|state| state.loan_documents.get(&document_id).cloned())
+        .ok_or("Document not found".to_string())
+}
+
+fn ensure_loan_document_reader(caller: Principal, loan_id: LoanId) -> Result<(), String> {
+    State::with_read(|state| {
+        let is_borrower = state
+            .loans
+            .get(&loan_id)
+            .map(|loan| loan.user_id == caller)
+            .ok_or("Loan not found".to_string())?;
+        let has_grant = state
+            .loan_document_grants
+            .get(&loan_id)
+            .map(|grants| grants.contains_key(&caller))
+            .unwrap_or(false);
+
+        if is_borrower || has_grant {
+            Ok(())
+        } else {
+            Err("Unauthorized: No access to this loan's documents".to_string())
+        }
+    })
+}
+
+fn loan_document_info(document: &LoanDocument) -> LoanDocumentInfo {
+    LoanDocumentInfo {
+        id: document.id,
+        loan_id: document.loan_id,
+        name: document.name.clone(),
+        content_type: document.content_type.clone(),
+        total_size: document.total_size,
+        chunk_count: document.chunks.len() as u32,
+        complete: document.complete,
+        created_at: document.created_at,
+    }
 }
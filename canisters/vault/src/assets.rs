@@ -0,0 +1,265 @@
+// Loan Assets
+// Registry of the ICRC-1 ledgers the vault lends from. Collateral is always BTC,
+// so loans in other assets are valued in satoshis at oracle prices for LTV checks.
+// Prices are refreshed by a timer so queries can value debt without outcalls.
+
+use crate::ckbtc::CKBTC_LEDGER_CANISTER_ID;
+use crate::collateral;
+use crate::helpers::{calculate_interest, calculate_loan_value, is_loan_open};
+use crate::oracle::{self, UsdPrice};
+use crate::state::State;
+use crate::types::{AssetMarket, Loan, LoanAsset, LoanAssetInfo, LoanId, LoanStatus, UtxoId};
+use candid::Principal;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Decimals of BTC collateral amounts (satoshis)
+pub const BTC_DECIMALS: u32 = 8;
+
+/// How often cached prices are refreshed while non-ckBTC loans are open
+pub const PRICE_REFRESH_SECONDS: u64 = 300;
+
+/// Ledger and lending terms for one asset
+pub struct AssetConfig {
+    pub asset: LoanAsset,
+    pub symbol: &'static str, // Oracle symbol of the underlying
+    pub ledger_id: &'static str,
+    pub decimals: u32,
+    pub transfer_fee: u64,  // Ledger fee in base units
+    pub interest_rate: u64, // basis points
+}
+
+/// Supported loan assets
+/// Testnet ledgers; mainnet: ckUSDC xevnm-gaaaa-aaaar-qafnq-cai, ckETH ss2fx-dyaaa-aaaar-qacoq-cai
+/// Amounts are u64 base units, which caps a single ckETH loan at ~18.4 ETH
+pub const ASSETS: [AssetConfig; 3] = [
+    AssetConfig {
+        asset: LoanAsset::CkBtc,
+        symbol: "BTC",
+        ledger_id: CKBTC_LEDGER_CANISTER_ID,
+        decimals: 8,
+        transfer_fee: 10,
+        interest_rate: 500,
+    },
+    AssetConfig {
+        asset: LoanAsset::CkUsdc,
+        symbol: "USDC",
+        ledger_id: "yfumr-cyaaa-aaaar-qaela-cai", // ckSepoliaUSDC
+        decimals: 6,
+        transfer_fee: 4_000,
+        interest_rate: 800,
+    },
+    AssetConfig {
+        asset: LoanAsset::CkEth,
+        symbol: "ETH",
+        ledger_id: "apia6-jaaaa-aaaar-qabma-cai", // ckSepoliaETH
+        decimals: 18,
+        transfer_fee: 10_000_000_000,
+        interest_rate: 600,
+    },
+];
+
+thread_local! {
+    static PRICE_TIMER_STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Configuration of a loan asset
+pub fn config(asset: LoanAsset) -> &'static AssetConfig {
+    ASSETS
+        .iter()
+        .find(|config| config.asset == asset)
+        .expect("every loan asset is registered")
+}
+
+/// Ledger canister of a loan asset
+pub fn ledger_id(asset: LoanAsset) -> Result<Principal, String> {
+    Principal::from_text(config(asset).ledger_id)
+        .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))
+}
+
+/// Public description of a loan asset
+pub fn info(asset: LoanAsset) -> Result<LoanAssetInfo, String> {
+    let config = config(asset);
+    Ok(LoanAssetInfo {
+        asset,
+        symbol: config.symbol.to_string(),
+        ledger_id: ledger_id(asset)?,
+        decimals: config.decimals,
+        transfer_fee: config.transfer_fee,
+        interest_rate: config.interest_rate,
+    })
+}
+
+/// Values `amount` of `asset` in satoshis
+pub fn to_sats(
+    asset: LoanAsset,
+    amount: u64,
+    btc_price: &UsdPrice,
+    asset_price: &UsdPrice,
+) -> Result<u64, String> {
+    if asset == LoanAsset::CkBtc {
+        return Ok(amount);
+    }
+    oracle::convert_amount(
+        amount,
+        config(asset).decimals,
+        asset_price,
+        BTC_DECIMALS,
+        btc_price,
+    )
+}
+
+/// Converts `sats` into base units of `asset`
+pub fn from_sats(
+    asset: LoanAsset,
+    sats: u64,
+    btc_price: &UsdPrice,
+    asset_price: &UsdPrice,
+) -> Result<u64, String> {
+    if asset == LoanAsset::CkBtc {
+        return Ok(sats);
+    }
+    oracle::convert_amount(
+        sats,
+        BTC_DECIMALS,
+        btc_price,
+        config(asset).decimals,
+        asset_price,
+    )
+}
+
+/// Fetches BTC and `asset` prices from the oracle
+pub async fn fetch_prices(asset: LoanAsset) -> Result<(UsdPrice, UsdPrice), String> {
+    let btc_price = oracle::get_usd_price("BTC").await?;
+    let asset_price = if asset == LoanAsset::CkBtc {
+        btc_price
+    } else {
+        oracle::get_usd_price(config(asset).symbol).await?
+    };
+    Ok((btc_price, asset_price))
+}
+
+/// Values `amount` of `asset` in satoshis at the last fetched prices
+pub fn cached_to_sats(asset: LoanAsset, amount: u64) -> Result<u64, String> {
+    if asset == LoanAsset::CkBtc {
+        return Ok(amount);
+    }
+
+    let price = |symbol: &str| {
+        oracle::cached_usd_price(symbol)
+            .ok_or_else(|| format!("{} price not available yet", symbol))
+    };
+    to_sats(asset, amount, &price("BTC")?, &price(config(asset).symbol)?)
+}
+
+/// Remaining debt of a loan valued in satoshis at the last fetched prices
+pub fn cached_debt_in_sats(loan: &Loan) -> Result<u64, String> {
+    cached_to_sats(loan.asset, calculate_loan_value(loan)?)
+}
+
+/// Remaining debt of the open loans backed by any of `utxo_ids`, in
+/// satoshis at the last fetched prices; each loan is counted once
+pub fn collateral_debt_in_sats<'a>(
+    utxo_ids: &[UtxoId],
+    loans: impl Iterator<Item = &'a Loan>,
+) -> Result<u64, String> {
+    loans
+        .filter(|loan| {
+            is_loan_open(loan)
+                && utxo_ids
+                    .iter()
+                    .any(|utxo_id| collateral::is_backed_by(loan, *utxo_id))
+        })
+        .try_fold(0u64, |total, loan| {
+            Ok(total.saturating_add(cached_debt_in_sats(loan)?))
+        })
+}
+
+/// Remaining debt of every loan in `loan_ids`, in satoshis at the last
+/// fetched prices
+pub fn loans_debt_in_sats(
+    loan_ids: &[LoanId],
+    loans: &HashMap<LoanId, Loan>,
+) -> Result<u64, String> {
+    loan_ids.iter().try_fold(0u64, |total, loan_id| {
+        let loan = loans
+            .get(loan_id)
+            .ok_or(format!("Loan {} not found", loan_id))?;
+        Ok(total.saturating_add(cached_debt_in_sats(loan)?))
+    })
+}
+
+/// Lending totals for one asset across `loans`
+/// Interest is the simple interest charged on every loan that was not cancelled
+pub fn market_totals<'a>(
+    asset: LoanAsset,
+    loans: impl Iterator<Item = &'a Loan>,
+    available_liquidity: u64,
+) -> AssetMarket {
+    let mut market = AssetMarket {
+        asset,
+        interest_rate: config(asset).interest_rate,
+        available_liquidity,
+        active_loans: 0,
+        outstanding_principal: 0,
+        outstanding_debt: 0,
+        total_borrowed: 0,
+        total_interest: 0,
+    };
+
+    for loan in loans.filter(|loan| loan.asset == asset) {
+        if loan.status == LoanStatus::Cancelled {
+            continue;
+        }
+        market.total_borrowed = market.total_borrowed.saturating_add(loan.borrowed_amount);
+        market.total_interest = market
+            .total_interest
+            .saturating_add(calculate_interest(loan).unwrap_or(u64::MAX));
+
+        if loan.status == LoanStatus::Active {
+            market.active_loans += 1;
+        }
+        if is_loan_open(loan) {
+            market.outstanding_principal = market
+                .outstanding_principal
+                .saturating_add(loan.borrowed_amount.saturating_sub(loan.repaid_amount));
+            market.outstanding_debt = market
+                .outstanding_debt
+                .saturating_add(calculate_loan_value(loan).unwrap_or(u64::MAX));
+        }
+    }
+
+    market
+}
+
+/// Starts refreshing prices periodically (once per canister instance)
+pub fn ensure_price_timer() {
+    if PRICE_TIMER_STARTED.with(|started| started.replace(true)) {
+        return;
+    }
+
+    ic_cdk_timers::set_timer(Duration::ZERO, refresh_prices());
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(PRICE_REFRESH_SECONDS), refresh_prices);
+}
+
+/// Restarts the price timer after an upgrade if any non-ckBTC loan is open
+pub fn resume() {
+    let needed = State::with_read(|state| {
+        state
+            .loans
+            .values()
+            .any(|loan| loan.asset != LoanAsset::CkBtc && is_loan_open(loan))
+    });
+    if needed {
+        ensure_price_timer();
+    }
+}
+
+async fn refresh_prices() {
+    for config in &ASSETS {
+        if let Err(e) = oracle::get_usd_price(config.symbol).await {
+            ic_cdk::println!("⚠️ Price refresh for {} failed: {}", config.symbol, e);
+        }
+    }
+}
@@ -1,14 +1,15 @@
 use candid::{CandidType, Deserialize, Nat, Principal};
 use ic_cdk::call;
+use ic_cdk::call::Call;
 use serde::Serialize;
 
 /// ckBTC Ledger Canister ID
 /// Testnet: mc6ru-gyaaa-aaaar-qaaaq-cai
 /// Mainnet: mxzaz-hqaaa-aaaar-qaada-cai
-const CKBTC_LEDGER_CANISTER_ID: &str = "mc6ru-gyaaa-aaaar-qaaaq-cai"; // ckBTC Testnet Ledger
+pub const CKBTC_LEDGER_CANISTER_ID: &str = "mc6ru-gyaaa-aaaar-qaaaq-cai"; // ckBTC Testnet Ledger
 
 /// ICRC-1 Account structure
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Account {
     pub owner: Principal,
     pub subaccount: Option<Vec<u8>>,
@@ -45,6 +46,39 @@ pub enum TransferError {
     GenericError { error_code: Nat, message: String },
 }
 
+/// ICRC-2 TransferFrom arguments
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+/// ICRC-2 TransferFrom result
+#[derive(CandidType, Deserialize, Debug)]
+pub enum TransferFromResult {
+    Ok(Nat),
+    Err(TransferFromError),
+}
+
+/// ICRC-2 TransferFrom error
+#[derive(CandidType, Deserialize, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
 /// ICRC-1 Transaction structure for querying
 #[derive(CandidType, Deserialize, Debug, Clone)]
 pub struct Transaction {
@@ -72,6 +106,37 @@ pub struct Transfer {
     pub from: Account,
     pub to: Account,
     pub amount: Nat,
+    pub memo: Option<Vec<u8>>,
+}
+
+/// ICRC-1 ledger `get_transactions` request
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransactionsRequest {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+/// Blocks that moved to an archive canister, fetched through `callback`
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ArchivedTransactions {
+    pub start: Nat,
+    pub length: Nat,
+    pub callback: candid::Func,
+}
+
+/// ICRC-1 ledger `get_transactions` response
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransactionsResponse {
+    pub log_length: Nat,
+    pub first_index: Nat,
+    pub transactions: Vec<Transaction>,
+    pub archived_transactions: Vec<ArchivedTransactions>,
+}
+
+/// Transactions returned by an archive canister
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransactionRange {
+    pub transactions: Vec<Transaction>,
 }
 
 /// Transfers ckBTC from the canister to a user
@@ -80,21 +145,27 @@ pub struct Transfer {
 /// For production/testnet: Performs real ckBTC transfer via ICRC-1
 /// For local development: Can simulate if ledger not available
 pub async fn transfer_ckbtc(to: Principal, amount: u64) -> Result<u64, String> {
+    let ledger_id = Principal::from_text(CKBTC_LEDGER_CANISTER_ID)
+        .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))?;
+
+    transfer(ledger_id, to, amount).await
+}
+
+/// Transfers tokens of any ICRC-1 ledger from the canister to a user
+/// Returns the block index on success
+pub async fn transfer(ledger_id: Principal, to: Principal, amount: u64) -> Result<u64, String> {
     // Check if we're in local development mode
     let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
     let skip_transfer = network == "local" || network == "playground";
     
     if skip_transfer {
-        ic_cdk::println!("⚠️  WARNING: ckBTC transfer SKIPPED ({} mode)", network);
-        ic_cdk::println!("✅ Simulating ckBTC transfer: {} sats to {}", amount, to);
+        ic_cdk::println!("⚠️  WARNING: transfer on {} SKIPPED ({} mode)", ledger_id, network);
+        ic_cdk::println!("✅ Simulating transfer: {} base units to {}", amount, to);
         // Return a mock block index
         return Ok(12345u64);
     }
     
-    ic_cdk::println!("💸 Transferring {} sats ckBTC to {}", amount, to);
-    
-    let ledger_id = Principal::from_text(CKBTC_LEDGER_CANISTER_ID)
-        .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))?;
+    ic_cdk::println!("💸 Transferring {} base units on {} to {}", amount, ledger_id, to);
 
     let transfer_args = TransferArgs {
         from_subaccount: None,
@@ -114,7 +185,7 @@ pub async fn transfer_ckbtc(to: Principal, amount: u64) -> Result<u64, String> {
         Ok((TransferResult::Ok(block_index),)) => {
             // Convert Nat to u64
             let block_idx = nat_to_u64(&block_index)?;
-            ic_cdk::println!("✅ ckBTC transfer successful! Block: {}", block_idx);
+            ic_cdk::println!("✅ Transfer successful! Block: {}", block_idx);
             Ok(block_idx)
         }
         Ok((TransferResult::Err(err),)) => {
@@ -132,20 +203,31 @@ pub async fn transfer_ckbtc(to: Principal, amount: u64) -> Result<u64, String> {
 /// For production/testnet: Queries ledger for actual transactions
 /// For local development: Can skip if ledger not available
 pub async fn verify_transfer_to_canister(from: Principal, amount: u64) -> Result<bool, String> {
+    let ledger_id = Principal::from_text(CKBTC_LEDGER_CANISTER_ID)
+        .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))?;
+
+    verify_transfer_on(ledger_id, from, amount).await
+}
+
+/// Verifies that a user has transferred tokens of any ICRC-1 ledger to the canister
+/// Best effort only: it cannot tell which transfer it matched, so the same
+/// transfer can be matched again. Pull funds with `transfer_from` instead.
+pub async fn verify_transfer_on(
+    ledger_id: Principal,
+    from: Principal,
+    amount: u64,
+) -> Result<bool, String> {
     // Check if we're in local development mode
     let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
     let skip_verification = network == "local" || network == "playground";
     
     if skip_verification {
-        ic_cdk::println!("⚠️  WARNING: ckBTC verification SKIPPED ({} mode)", network);
-        ic_cdk::println!("✅ Assuming ckBTC transfer verified: {} sats from {}", amount, from);
+        ic_cdk::println!("⚠️  WARNING: transfer verification SKIPPED ({} mode)", network);
+        ic_cdk::println!("✅ Assuming transfer verified: {} base units from {}", amount, from);
         return Ok(true);
     }
     
-    ic_cdk::println!("🔍 Verifying ckBTC transfer: {} sats from {}", amount, from);
-    
-    let ledger_id = Principal::from_text(CKBTC_LEDGER_CANISTER_ID)
-        .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))?;
+    ic_cdk::println!("🔍 Verifying transfer on {}: {} base units from {}", ledger_id, amount, from);
 
     let canister_id = ic_cdk::api::id();
     
@@ -209,7 +291,7 @@ pub async fn verify_transfer_to_canister(from: Principal, amount: u64) -> Result
                     if transfer.from.owner == from && 
                        transfer.to.owner == canister_id &&
                        nat_to_u64(&transfer.amount)? >= amount {
-                        ic_cdk::println!("✅ ckBTC transfer verified: {} sats from {}", amount, from);
+                        ic_cdk::println!("✅ Transfer verified: {} base units from {}", amount, from);
                         return Ok(true);
                     }
                 }
@@ -228,11 +310,202 @@ pub async fn verify_transfer_to_canister(from: Principal, amount: u64) -> Result
     }
 }
 
+/// Pulls tokens from a user into the canister's default account using the
+/// allowance they granted with `icrc2_approve`; the user pays the ledger fee
+/// Returns the block index on success
+pub async fn transfer_from(
+    ledger_id: Principal,
+    from: Principal,
+    amount: u64,
+    memo: Option<Vec<u8>>,
+) -> Result<u64, String> {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    if network == "local" || network == "playground" {
+        ic_cdk::println!(
+            "⚠️  WARNING: transfer_from on {} SKIPPED ({} mode)",
+            ledger_id,
+            network
+        );
+        return Ok(12345u64);
+    }
+
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account {
+            owner: from,
+            subaccount: None,
+        },
+        to: Account {
+            owner: ic_cdk::api::canister_self(),
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        fee: None,
+        memo,
+        created_at_time: None,
+    };
+
+    let result: TransferFromResult = Call::unbounded_wait(ledger_id, "icrc2_transfer_from")
+        .with_arg(args)
+        .await
+        .map_err(|e| format!("Transfer call failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode transfer result: {:?}", e))?;
+
+    match result {
+        TransferFromResult::Ok(block_index) => nat_to_u64(&block_index),
+        TransferFromResult::Err(TransferFromError::InsufficientAllowance { allowance }) => {
+            Err(format!(
+                "Insufficient allowance: approved {}, need {} plus the ledger fee",
+                allowance, amount
+            ))
+        }
+        TransferFromResult::Err(err) => Err(format!("Transfer failed: {:?}", err)),
+    }
+}
+
+/// Transfers tokens from the canister with a memo and `created_at_time`, so
+/// the ledger rejects a retry of a transfer it already executed as `Duplicate`
+/// The outer error means the call itself failed and the outcome is unknown;
+/// the inner one is the ledger's answer.
+pub async fn transfer_deduplicated(
+    ledger_id: Principal,
+    to: Principal,
+    amount: u64,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<Result<u64, TransferError>, String> {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    if network == "local" || network == "playground" {
+        ic_cdk::println!(
+            "⚠️  WARNING: transfer on {} SKIPPED ({} mode)",
+            ledger_id,
+            network
+        );
+        return Ok(Ok(12345u64));
+    }
+
+    let args = TransferArgs {
+        from_subaccount: None,
+        to: Account {
+            owner: to,
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo),
+        created_at_time: Some(created_at_time),
+    };
+
+    let result: TransferResult = Call::unbounded_wait(ledger_id, "icrc1_transfer")
+        .with_arg(args)
+        .await
+        .map_err(|e| format!("Transfer call failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode transfer result: {:?}", e))?;
+
+    match result {
+        TransferResult::Ok(block_index) => Ok(Ok(nat_to_u64(&block_index)?)),
+        TransferResult::Err(err) => Ok(Err(err)),
+    }
+}
+
+/// Transfers tokens out of one of the canister's subaccounts
+/// Returns the block index on success
+pub async fn transfer_from_subaccount(
+    ledger_id: Principal,
+    from_subaccount: [u8; 32],
+    to: Account,
+    amount: u64,
+    memo: Option<Vec<u8>>,
+) -> Result<u64, String> {
+    let args = TransferArgs {
+        from_subaccount: Some(from_subaccount.to_vec()),
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo,
+        created_at_time: None,
+    };
+
+    let result: TransferResult = Call::unbounded_wait(ledger_id, "icrc1_transfer")
+        .with_arg(args)
+        .await
+        .map_err(|e| format!("Transfer call failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode transfer result: {:?}", e))?;
+
+    match result {
+        TransferResult::Ok(block_index) => nat_to_u64(&block_index),
+        TransferResult::Err(err) => Err(format!("Transfer failed: {:?}", err)),
+    }
+}
+
+/// Gets the balance of any account (including subaccounts) on an ICRC-1 ledger
+/// Balances above u64 are reported as u64::MAX
+pub async fn balance_of_account(ledger_id: Principal, account: Account) -> Result<u64, String> {
+    let balance: Nat = Call::unbounded_wait(ledger_id, "icrc1_balance_of")
+        .with_arg(account)
+        .await
+        .map_err(|e| format!("Balance query failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode balance: {:?}", e))?;
+
+    Ok(nat_to_u64(&balance).unwrap_or(u64::MAX))
+}
+
+/// Fetches the transaction in block `block_index` of an ICRC-1 ledger,
+/// following the ledger to its archive for older blocks
+/// Returns None if the block does not exist yet
+pub async fn get_transaction(
+    ledger_id: Principal,
+    block_index: u64,
+) -> Result<Option<Transaction>, String> {
+    let request = TransactionsRequest {
+        start: Nat::from(block_index),
+        length: Nat::from(1u64),
+    };
+    let response: TransactionsResponse = Call::unbounded_wait(ledger_id, "get_transactions")
+        .with_arg(request.clone())
+        .await
+        .map_err(|e| format!("Transaction query failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode transactions: {:?}", e))?;
+
+    if nat_to_u64(&response.first_index)? == block_index {
+        return Ok(response.transactions.into_iter().next());
+    }
+
+    let archive = response.archived_transactions.into_iter().find(|range| {
+        let start = nat_to_u64(&range.start).unwrap_or(u64::MAX);
+        let length = nat_to_u64(&range.length).unwrap_or(0);
+        start <= block_index && block_index < start.saturating_add(length)
+    });
+    let Some(archive) = archive else {
+        return Ok(None);
+    };
+
+    let range: TransactionRange =
+        Call::unbounded_wait(archive.callback.principal, &archive.callback.method)
+            .with_arg(request)
+            .await
+            .map_err(|e| format!("Archive query failed: {:?}", e))?
+            .candid()
+            .map_err(|e| format!("Failed to decode archived transactions: {:?}", e))?;
+    Ok(range.transactions.into_iter().next())
+}
+
 /// Gets the ckBTC balance for a principal
 pub async fn get_balance(principal: Principal) -> Result<u64, String> {
     let ledger_id = Principal::from_text(CKBTC_LEDGER_CANISTER_ID)
         .map_err(|e| format!("Invalid ledger canister ID: {:?}", e))?;
 
+    balance_on(ledger_id, principal).await
+}
+
+/// Gets a principal's balance on any ICRC-1 ledger
+/// Balances above u64 (possible for 18-decimal tokens) are reported as u64::MAX
+pub async fn balance_on(ledger_id: Principal, principal: Principal) -> Result<u64, String> {
     let account = Account {
         owner: principal,
         subaccount: None,
@@ -242,7 +515,7 @@ pub async fn get_balance(principal: Principal) -> Result<u64, String> {
 
     match result {
         Ok((balance,)) => {
-            let balance_u64 = nat_to_u64(&balance)?;
+            let balance_u64 = nat_to_u64(&balance).unwrap_or(u64::MAX);
             Ok(balance_u64)
         }
         Err((code, msg)) => {
@@ -252,7 +525,7 @@ pub async fn get_balance(principal: Principal) -> Result<u64, String> {
 }
 
 /// Helper function to convert Nat to u64
-fn nat_to_u64(nat: &Nat) -> Result<u64, String> {
+pub fn nat_to_u64(nat: &Nat) -> Result<u64, String> {
     let bytes = nat.0.to_bytes_le();
     if bytes.len() > 8 {
         return Err("Nat value too large to fit in u64".to_string());
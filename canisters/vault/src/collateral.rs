@@ -0,0 +1,273 @@
+// Collateral Baskets
+// A loan is backed by the UTXO it was opened against plus any UTXOs added to
+// its basket later. Each UTXO is borrowed against at the LTV of its class:
+// sats carrying inscriptions or Runes are harder to sell at their BTC value
+// than plain ones, so a mixed basket lends at the value-weighted average.
+// UTXOs can be released one at a time while the rest still covers the debt.
+
+use crate::helpers::{calculate_max_borrowable, get_timestamp, is_loan_open};
+use crate::state::State;
+use crate::timelock::{self, UnlockAction};
+use crate::types::{CollateralClass, Loan, LoanId, UtxoId, UtxoStatus, UTXO};
+use std::collections::HashMap;
+
+/// Maximum LTV of plain BTC, in basis points
+pub const PLAIN_LTV_BPS: u64 = 5000;
+
+/// Maximum LTV of UTXOs carrying Runes, in basis points
+pub const RUNE_LTV_BPS: u64 = 4000;
+
+/// Maximum LTV of inscribed UTXOs, in basis points
+pub const INSCRIPTION_LTV_BPS: u64 = 3000;
+
+/// LTV at which a loan can be liquidated, in basis points
+pub const LIQUIDATION_THRESHOLD_BPS: u64 = 8000;
+
+/// Value and borrowing capacity of a set of UTXOs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BasketValue {
+    pub collateral_value: u64, // in satoshis
+    pub max_borrowable: u64,   // in satoshis
+}
+
+impl BasketValue {
+    /// Value-weighted maximum LTV of the basket, in basis points
+    pub fn weighted_ltv(&self) -> u64 {
+        if self.collateral_value == 0 {
+            return 0;
+        }
+        (self.max_borrowable as u128 * 10_000 / self.collateral_value as u128) as u64
+    }
+}
+
+/// Class of a UTXO; an inscription outweighs any Runes on the same output
+pub fn class_of(utxo: &UTXO, carries_runes: bool) -> CollateralClass {
+    if utxo.ordinal_info.is_some() {
+        CollateralClass::Inscribed
+    } else if carries_runes {
+        CollateralClass::RuneBearing
+    } else {
+        CollateralClass::Plain
+    }
+}
+
+/// Maximum LTV of a collateral class, in basis points
+pub fn max_ltv(class: CollateralClass) -> u64 {
+    match class {
+        CollateralClass::Plain => PLAIN_LTV_BPS,
+        CollateralClass::RuneBearing => RUNE_LTV_BPS,
+        CollateralClass::Inscribed => INSCRIPTION_LTV_BPS,
+    }
+}
+
+/// Totals the value and borrowing capacity of `utxos`
+pub fn value_basket<'a>(utxos: impl Iterator<Item = (&'a UTXO, CollateralClass)>) -> BasketValue {
+    utxos.fold(BasketValue::default(), |total, (utxo, class)| BasketValue {
+        collateral_value: total.collateral_value.saturating_add(utxo.amount),
+        max_borrowable: total
+            .max_borrowable
+            .saturating_add(calculate_max_borrowable(utxo, max_ltv(class))),
+    })
+}
+
+/// Current LTV of `debt` against `collateral_value`, in basis points
+pub fn ltv(debt: u64, collateral_value: u64) -> u64 {
+    if collateral_value == 0 {
+        return 10_000; // 100% if no collateral
+    }
+    (debt as u128 * 10_000 / collateral_value as u128) as u64
+}
+
+/// UTXOs backing a loan, the one it was opened against first
+pub fn basket(loan: &Loan) -> Vec<UtxoId> {
+    std::iter::once(loan.collateral_utxo_id)
+        .chain(loan.extra_collateral.iter().copied())
+        .collect()
+}
+
+/// Whether `utxo_id` is part of a loan's collateral basket
+pub fn is_backed_by(loan: &Loan, utxo_id: UtxoId) -> bool {
+    loan.collateral_utxo_id == utxo_id || loan.extra_collateral.contains(&utxo_id)
+}
+
+/// Open loans linked to each other through shared collateral UTXOs, directly
+/// or through other loans, and every UTXO backing them
+/// Selling a shared UTXO leaves each loan on it short, so they are valued and
+/// liquidated together
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollateralGroup {
+    pub loan_ids: Vec<LoanId>,
+    pub utxo_ids: Vec<UtxoId>,
+}
+
+/// The collateral group of `loan`; a closed loan stands alone
+pub fn collateral_group(loan: &Loan, loans: &HashMap<LoanId, Loan>) -> CollateralGroup {
+    let mut group = CollateralGroup {
+        loan_ids: vec![loan.id],
+        utxo_ids: basket(loan),
+    };
+    if !is_loan_open(loan) {
+        return group;
+    }
+
+    loop {
+        let joining: Vec<&Loan> = loans
+            .values()
+            .filter(|other| {
+                is_loan_open(other)
+                    && !group.loan_ids.contains(&other.id)
+                    && group
+                        .utxo_ids
+                        .iter()
+                        .any(|utxo_id| is_backed_by(other, *utxo_id))
+            })
+            .collect();
+        if joining.is_empty() {
+            break;
+        }
+        for other in joining {
+            group.loan_ids.push(other.id);
+            for utxo_id in basket(other) {
+                if !group.utxo_ids.contains(&utxo_id) {
+                    group.utxo_ids.push(utxo_id);
+                }
+            }
+        }
+    }
+
+    group.loan_ids.sort_unstable();
+    group.utxo_ids.sort_unstable();
+    group
+}
+
+/// Checks that none of the spent outpoints (txid, vout) is a locked UTXO or
+/// sits in the collateral basket of an open loan
+pub fn ensure_unencumbered(
+    outpoints: &[(String, u32)],
+    utxos: &HashMap<UtxoId, UTXO>,
+    loans: &HashMap<LoanId, Loan>,
+) -> Result<(), String> {
+    let spends = |utxo: &UTXO| {
+        outpoints
+            .iter()
+            .any(|(txid, vout)| utxo.txid.eq_ignore_ascii_case(txid) && utxo.vout == *vout)
+    };
+
+    for loan in loans.values().filter(|loan| is_loan_open(loan)) {
+        for utxo_id in basket(loan) {
+            if let Some(utxo) = utxos.get(&utxo_id).filter(|utxo| spends(utxo)) {
+                return Err(format!(
+                    "UTXO {}:{} backs loan {}; repay it before spending",
+                    utxo.txid, utxo.vout, loan.id
+                ));
+            }
+        }
+    }
+    if let Some(utxo) = utxos
+        .values()
+        .find(|utxo| utxo.status == UtxoStatus::Locked && spends(utxo))
+    {
+        return Err(format!("UTXO {}:{} is locked", utxo.txid, utxo.vout));
+    }
+    Ok(())
+}
+
+/// Adds a UTXO to a loan's basket
+pub fn add_to_basket(loan: &mut Loan, utxo_id: UtxoId) -> Result<(), String> {
+    if is_backed_by(loan, utxo_id) {
+        return Err(format!("UTXO {} already backs loan {}", utxo_id, loan.id));
+    }
+    loan.extra_collateral.push(utxo_id);
+    Ok(())
+}
+
+/// Takes a UTXO out of a loan's basket; the last UTXO cannot be removed
+pub fn remove_from_basket(loan: &mut Loan, utxo_id: UtxoId) -> Result<(), String> {
+    if !is_backed_by(loan, utxo_id) {
+        return Err(format!("UTXO {} does not back loan {}", utxo_id, loan.id));
+    }
+    if loan.extra_collateral.is_empty() {
+        return Err("Cannot release the only UTXO backing a loan".to_string());
+    }
+
+    if loan.collateral_utxo_id == utxo_id {
+        loan.collateral_utxo_id = loan.extra_collateral.remove(0);
+    } else {
+        loan.extra_collateral.retain(|id| *id != utxo_id);
+    }
+    Ok(())
+}
+
+/// Puts `new_utxo_id` in the place of `old_utxo_id` in a loan's basket
+pub fn replace_in_basket(
+    loan: &mut Loan,
+    old_utxo_id: UtxoId,
+    new_utxo_id: UtxoId,
+) -> Result<(), String> {
+    if !is_backed_by(loan, old_utxo_id) {
+        return Err(format!(
+            "UTXO {} does not back loan {}",
+            old_utxo_id, loan.id
+        ));
+    }
+    if is_backed_by(loan, new_utxo_id) {
+        return Err(format!(
+            "UTXO {} already backs loan {}",
+            new_utxo_id, loan.id
+        ));
+    }
+
+    if loan.collateral_utxo_id == old_utxo_id {
+        loan.collateral_utxo_id = new_utxo_id;
+    } else {
+        for utxo_id in loan.extra_collateral.iter_mut() {
+            if *utxo_id == old_utxo_id {
+                *utxo_id = new_utxo_id;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Values the UTXOs in `utxo_ids` from state; every one of them must exist
+pub(crate) fn value_utxos(state: &State, utxo_ids: &[UtxoId]) -> Result<BasketValue, String> {
+    let utxos = utxo_ids
+        .iter()
+        .map(|utxo_id| {
+            let utxo = state
+                .utxos
+                .get(utxo_id)
+                .ok_or(format!("Collateral UTXO {} not found", utxo_id))?;
+            Ok((utxo, class_of(utxo, state.rune_utxos.contains(utxo_id))))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(value_basket(utxos.into_iter()))
+}
+
+/// Returns locked UTXOs no open loan is backed by anymore to the deposited state
+/// Time locks that expired while a UTXO was collateral are applied now
+pub(crate) fn unlock_unused(state: &mut State, utxo_ids: &[UtxoId]) {
+    let now = get_timestamp();
+    for utxo_id in utxo_ids {
+        let still_used = state
+            .loans
+            .values()
+            .any(|loan| is_loan_open(loan) && is_backed_by(loan, *utxo_id));
+        if still_used {
+            continue;
+        }
+        match state.utxos.get_mut(utxo_id) {
+            Some(utxo) if utxo.status == UtxoStatus::Locked => utxo.status = UtxoStatus::Deposited,
+            _ => continue,
+        }
+
+        let action = timelock::apply_unlock(state, *utxo_id, now);
+        if !matches!(action, UnlockAction::Skipped(_)) {
+            ic_cdk::println!(
+                "⏰ Time lock on released UTXO {} applied: {:?}",
+                utxo_id,
+                action
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Dead Man's Switch
+// Tracks user activity and hands unencumbered funds to a beneficiary after a
+// period of inactivity followed by a cancellable warning period
+
+use crate::collateral;
+use crate::helpers::{get_timestamp, is_loan_open};
+use crate::state::State;
+use crate::types::{DeadManSwitch, LoanStatus, UtxoStatus};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// How often the timer checks switches for inactivity
+pub const CHECK_INTERVAL_SECONDS: u64 = 3_600;
+
+thread_local! {
+    static TIMER_STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Next step for a switch at a given time
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SwitchAction {
+    None,         // User is active, or the warning period is still running
+    StartWarning, // Inactivity threshold crossed
+    Trigger,      // Warning period elapsed without activity
+}
+
+/// Funds moved to the beneficiary when a switch fires
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SwitchTransfer {
+    pub utxo_ids: Vec<u64>,
+    pub loan_ids: Vec<u64>, // Repaid loans whose collateral claims moved
+}
+
+/// Decides what the timer should do with a switch
+pub fn evaluate(switch: &DeadManSwitch, now: u64) -> SwitchAction {
+    if !switch.enabled || switch.triggered_at.is_some() {
+        return SwitchAction::None;
+    }
+
+    match switch.warning_started_at {
+        Some(started) if now.saturating_sub(started) >= switch.warning_period => {
+            SwitchAction::Trigger
+        }
+        Some(_) => SwitchAction::None,
+        None if now.saturating_sub(switch.last_activity) >= switch.inactivity_threshold => {
+            SwitchAction::StartWarning
+        }
+        None => SwitchAction::None,
+    }
+}
+
+/// Bumps the caller's activity, cancelling any pending warning
+/// Used as a guard on user update calls so activity is tracked automatically
+pub fn record_activity() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    let now = get_timestamp();
+
+    State::with(|state| {
+        if let Some(switch) = state.dead_man_switches.get_mut(&caller) {
+            if switch.triggered_at.is_none() {
+                switch.last_activity = now;
+                if switch.warning_started_at.take().is_some() {
+                    ic_cdk::println!("✅ Dead man switch warning cancelled for {}", caller);
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Starts the periodic check (once per canister instance)
+pub fn ensure_timer() {
+    if TIMER_STARTED.with(|started| started.replace(true)) {
+        return;
+    }
+
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(CHECK_INTERVAL_SECONDS), || async {
+        check_switches(get_timestamp());
+    });
+}
+
+/// Restarts the timer after an upgrade if any switch is configured
+pub fn resume() {
+    if State::with_read(|state| !state.dead_man_switches.is_empty()) {
+        ensure_timer();
+    }
+}
+
+/// Advances every switch: starts warnings and fires expired ones
+pub fn check_switches(now: u64) {
+    let actions: Vec<(Principal, SwitchAction)> = State::with_read(|state| {
+        state
+            .dead_man_switches
+            .iter()
+            .map(|(user, switch)| (*user, evaluate(switch, now)))
+            .filter(|(_, action)| *action != SwitchAction::None)
+            .collect()
+    });
+
+    for (user, action) in actions {
+        match action {
+            SwitchAction::StartWarning => {
+                State::with(|state| {
+                    if let Some(switch) = state.dead_man_switches.get_mut(&user) {
+                        switch.warning_started_at = Some(now);
+                    }
+                });
+                ic_cdk::println!("⚠️ Dead man switch warning started for {}", user);
+            }
+            SwitchAction::Trigger => {
+                let transfer = trigger(user, now);
+                ic_cdk::println!(
+                    "⏰ Dead man switch fired for {}: {} UTXOs, {} repaid loans transferred",
+                    user,
+                    transfer.utxo_ids.len(),
+                    transfer.loan_ids.len()
+                );
+            }
+            SwitchAction::None => {}
+        }
+    }
+}
+
+/// Reassigns the user's unencumbered UTXOs and repaid-loan claims to the beneficiary
+fn trigger(user: Principal, now: u64) -> SwitchTransfer {
+    State::with(|state| {
+        let beneficiary = match state.dead_man_switches.get_mut(&user) {
+            Some(switch) => {
+                switch.triggered_at = Some(now);
+                switch.enabled = false;
+                switch.beneficiary
+            }
+            None => return SwitchTransfer::default(),
+        };
+
+        let mut transfer = SwitchTransfer::default();
+
+        // UTXOs backing an open loan stay with the borrower until repaid
+        let utxo_ids = state.user_utxos.get(&user).cloned().unwrap_or_default();
+        for utxo_id in utxo_ids {
+            let encumbered = state.loans.values().any(|loan| {
+                is_loan_open(loan) && collateral::is_backed_by(loan, utxo_id)
+            });
+            let deposited = state
+                .utxos
+                .get(&utxo_id)
+                .map(|utxo| utxo.status == UtxoStatus::Deposited)
+                .unwrap_or(false);
+            if deposited && !encumbered {
+                transfer.utxo_ids.push(utxo_id);
+            }
+        }
+
+        let loan_ids = state.user_loans.get(&user).cloned().unwrap_or_default();
+        for loan_id in loan_ids {
+            if let Some(loan) = state.loans.get_mut(&loan_id) {
+                if loan.status == LoanStatus::Repaid {
+                    loan.user_id = beneficiary;
+                    transfer.loan_ids.push(loan_id);
+                }
+            }
+        }
+
+        if let Some(ids) = state.user_utxos.get_mut(&user) {
+            ids.retain(|id| !transfer.utxo_ids.contains(id));
+        }
+        if let Some(ids) = state.user_loans.get_mut(&user) {
+            ids.retain(|id| !transfer.loan_ids.contains(id));
+        }
+        state
+            .user_utxos
+            .entry(beneficiary)
+            .or_default()
+            .extend(&transfer.utxo_ids);
+        state
+            .user_loans
+            .entry(beneficiary)
+            .or_default()
+            .extend(&transfer.loan_ids);
+
+        transfer
+    })
+}
@@ -0,0 +1,247 @@
+// Loan Disbursements
+// Ledger payouts of loans are journaled before the transfer is attempted.
+// Every transfer carries a memo (the loan ID, marked for draws on an existing
+// loan) and a fixed created_at_time, so the ledger deduplicates retries: retrying a transfer that already went
+// through returns Duplicate instead of paying twice. Entries left behind by a
+// failed call, a trap or an upgrade are resolved by a reconciliation timer.
+// The ledger fee is deducted from the payout, so the borrower owes the full
+// amount and the vault's ledger balance matches the pool's accounting.
+
+use crate::ckbtc::{self, nat_to_u64, TransferError};
+use crate::helpers::{draw_memo, loan_memo};
+use crate::state::State;
+use crate::types::{LoanAsset, LoanId, LoanStatus, PendingDisbursement};
+use crate::{assets, collateral, pool};
+use candid::Principal;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// How often pending disbursements are retried
+pub const RECONCILE_SECONDS: u64 = 60;
+
+/// Ledger deduplication window (ICRC-1 ledgers use 24 hours)
+pub const LEDGER_TX_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Retries stop this long before the window closes, leaving room for clock drift
+pub const RETRY_MARGIN_NANOS: u64 = 10 * 60 * 1_000_000_000;
+
+thread_local! {
+    static RECONCILE_TIMER_STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Result of a disbursement attempt
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Paid(u64),        // Block index of the payout
+    Rejected(String), // The ledger refused; nothing was paid
+    Unknown(String),  // The transfer may or may not have happened
+}
+
+/// Checks that a payout of `amount` covers the asset's ledger fee
+pub fn validate_amount(asset: LoanAsset, amount: u64) -> Result<(), String> {
+    let fee = assets::config(asset).transfer_fee;
+    if amount <= fee {
+        return Err(format!(
+            "Invalid amount: must be greater than the ledger fee of {} base units",
+            fee
+        ));
+    }
+    Ok(())
+}
+
+/// Journal entry for a payout about to be attempted
+/// `draw` marks a payout drawn on an existing loan rather than its opening one.
+/// The fee is fixed here so every retry sends the same amount
+pub fn new_entry(
+    loan_id: LoanId,
+    asset: LoanAsset,
+    to: Principal,
+    amount: u64,
+    draw: bool,
+    now: u64,
+) -> PendingDisbursement {
+    PendingDisbursement {
+        loan_id,
+        asset,
+        to,
+        amount,
+        created_at_time: now,
+        attempts: 0,
+        last_error: None,
+        memo: draw.then(|| draw_memo(loan_id)),
+        fee: Some(assets::config(asset).transfer_fee),
+    }
+}
+
+/// Amount the borrower receives: the entry's amount minus the ledger fee
+pub fn payout_amount(entry: &PendingDisbursement) -> u64 {
+    entry.amount.saturating_sub(entry.fee.unwrap_or(0))
+}
+
+/// Ledger memo the payout of an entry is sent with
+pub fn memo(entry: &PendingDisbursement) -> Vec<u8> {
+    entry
+        .memo
+        .clone()
+        .unwrap_or_else(|| loan_memo(entry.loan_id))
+}
+
+/// Whether an entry pays out a draw on an existing loan
+pub fn is_draw(entry: &PendingDisbursement) -> bool {
+    memo(entry) == draw_memo(entry.loan_id)
+}
+
+/// Interprets a ledger transfer result
+/// A duplicate means an earlier attempt already paid, so it counts as paid
+pub fn classify(result: Result<Result<u64, TransferError>, String>) -> Outcome {
+    match result {
+        Ok(Ok(block_index)) => Outcome::Paid(block_index),
+        Ok(Err(TransferError::Duplicate { duplicate_of })) => {
+            Outcome::Paid(nat_to_u64(&duplicate_of).unwrap_or(u64::MAX))
+        }
+        Ok(Err(
+            e @ (TransferError::TemporarilyUnavailable
+            | TransferError::CreatedInFuture { .. }
+            | TransferError::TooOld),
+        )) => Outcome::Unknown(format!("{:?}", e)),
+        Ok(Err(e)) => Outcome::Rejected(format!("{:?}", e)),
+        Err(e) => Outcome::Unknown(e),
+    }
+}
+
+/// Whether an entry can still be retried safely within the ledger's dedup window
+pub fn can_retry(entry: &PendingDisbursement, now: u64) -> bool {
+    now < entry
+        .created_at_time
+        .saturating_add(LEDGER_TX_WINDOW_NANOS - RETRY_MARGIN_NANOS)
+}
+
+/// Journals a payout; the reconciliation timer takes over if the caller
+/// never gets to resolve it
+pub fn begin(entry: PendingDisbursement) {
+    State::with(|state| state.pending_disbursements.insert(entry.loan_id, entry));
+    ensure_reconcile_timer();
+}
+
+/// Attempts (or retries) the payout of a journaled loan and resolves the entry
+pub async fn execute(loan_id: LoanId) -> Outcome {
+    let Some(entry) = State::with_read(|state| state.pending_disbursements.get(&loan_id).cloned())
+    else {
+        return Outcome::Unknown(format!("No pending disbursement for loan {}", loan_id));
+    };
+
+    let outcome = match assets::ledger_id(entry.asset) {
+        Ok(ledger_id) => classify(
+            ckbtc::transfer_deduplicated(
+                ledger_id,
+                entry.to,
+                payout_amount(&entry),
+                memo(&entry),
+                entry.created_at_time,
+            )
+            .await,
+        ),
+        Err(e) => Outcome::Unknown(e),
+    };
+    resolve(loan_id, &outcome);
+    outcome
+}
+
+/// Applies an outcome to the journal: a paid entry is removed, a rejected one
+/// cancels its loan (or removes the draw from a loan that was increased), an
+/// unknown one stays for the next reconciliation
+pub fn resolve(loan_id: LoanId, outcome: &Outcome) {
+    State::with(|state| {
+        match outcome {
+            Outcome::Paid(block_index) => {
+                if state.pending_disbursements.remove(&loan_id).is_some() {
+                    ic_cdk::println!("Disbursed loan {}, block index: {}", loan_id, block_index);
+                }
+            }
+            Outcome::Rejected(reason) => {
+                // Only the first resolution releases the loan's reservations
+                let Some(entry) = state.pending_disbursements.remove(&loan_id) else {
+                    return;
+                };
+                let Some(loan) = state.loans.get_mut(&loan_id) else {
+                    return;
+                };
+                if loan.status != LoanStatus::Active {
+                    return;
+                }
+                if pool::is_pool_loan(loan) {
+                    pool::cancel_lend(&mut state.lending_pool, entry.amount);
+                }
+
+                // A rejected draw on an existing loan is removed from it
+                if is_draw(&entry) {
+                    loan.borrowed_amount = loan.borrowed_amount.saturating_sub(entry.amount);
+                    ic_cdk::println!(
+                        "❌ Draw of {} on loan {} rejected ({}), removed from the loan",
+                        entry.amount,
+                        loan_id,
+                        reason
+                    );
+                    return;
+                }
+
+                loan.status = LoanStatus::Cancelled;
+                let basket = collateral::basket(loan);
+                collateral::unlock_unused(state, &basket);
+                ic_cdk::println!(
+                    "❌ Payout for loan {} rejected ({}), loan cancelled",
+                    loan_id,
+                    reason
+                );
+            }
+            Outcome::Unknown(reason) => {
+                if let Some(entry) = state.pending_disbursements.get_mut(&loan_id) {
+                    entry.attempts += 1;
+                    entry.last_error = Some(reason.clone());
+                }
+            }
+        }
+    });
+}
+
+/// Retries every pending disbursement that is still inside the ledger's
+/// dedup window; older entries are left for manual review
+pub async fn reconcile() {
+    let now = ic_cdk::api::time();
+    let pending: Vec<PendingDisbursement> =
+        State::with_read(|state| state.pending_disbursements.values().cloned().collect());
+
+    for entry in pending {
+        if !can_retry(&entry, now) {
+            ic_cdk::println!(
+                "⚠️ Disbursement of loan {} is past the ledger dedup window; needs manual review",
+                entry.loan_id
+            );
+            continue;
+        }
+        if let Outcome::Unknown(e) = execute(entry.loan_id).await {
+            ic_cdk::println!(
+                "⚠️ Disbursement of loan {} still pending: {}",
+                entry.loan_id,
+                e
+            );
+        }
+    }
+}
+
+/// Starts the reconciliation timer (once per canister instance)
+pub fn ensure_reconcile_timer() {
+    if RECONCILE_TIMER_STARTED.with(|started| started.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(RECONCILE_SECONDS), reconcile);
+}
+
+/// Resolves disbursements interrupted by an upgrade
+pub fn resume() {
+    let pending = State::with_read(|state| !state.pending_disbursements.is_empty());
+    if pending {
+        ic_cdk_timers::set_timer(Duration::ZERO, reconcile());
+        ensure_reconcile_timer();
+    }
+}
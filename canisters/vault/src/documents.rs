@@ -0,0 +1,151 @@
+// Encrypted Loan Documents
+// Agreements and off-chain terms attached to a loan. Every loan has one data key,
+// which the borrower's client wraps for itself and each principal they grant
+// access to. Documents are encrypted by the client chunk by chunk and uploaded
+// in order; the canister stores the ciphertext and never sees the content.
+// All sizes below are ciphertext sizes.
+
+use crate::types::LoanDocument;
+
+/// Maximum size of one uploaded chunk (ingress messages are capped at 2 MiB)
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Maximum total size of a document
+pub const MAX_DOCUMENT_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Maximum number of documents attached to one loan
+pub const MAX_DOCUMENTS_PER_LOAN: usize = 20;
+
+/// Maximum size of all documents in the canister together
+/// Documents live in the heap state, which is serialized on every upgrade
+pub const MAX_TOTAL_DOCUMENT_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Checks a new document against the size and per-loan limits
+pub fn validate_new_document(
+    name: &str,
+    total_size: u64,
+    existing_documents: usize,
+) -> Result<(), String> {
+    if name.trim().is_empty() || name.len() > 256 {
+        return Err("Document name must be 1-256 characters".to_string());
+    }
+    if total_size == 0 {
+        return Err("Document cannot be empty".to_string());
+    }
+    if total_size > MAX_DOCUMENT_SIZE {
+        return Err(format!(
+            "Document too large: maximum is {} bytes",
+            MAX_DOCUMENT_SIZE
+        ));
+    }
+    if existing_documents >= MAX_DOCUMENTS_PER_LOAN {
+        return Err(format!(
+            "Loan already has the maximum of {} documents",
+            MAX_DOCUMENTS_PER_LOAN
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the canister has room for a new document of `total_size` bytes,
+/// given the bytes already reserved by existing documents
+pub fn validate_storage(reserved_size: u64, total_size: u64) -> Result<(), String> {
+    if reserved_size.saturating_add(total_size) > MAX_TOTAL_DOCUMENT_SIZE {
+        return Err(format!(
+            "Document storage is full: at most {} bytes can be stored in total",
+            MAX_TOTAL_DOCUMENT_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that a chunk continues the upload in order and within the declared size
+pub fn validate_chunk(
+    document: &LoanDocument,
+    chunk_index: u32,
+    chunk_len: usize,
+) -> Result<(), String> {
+    if document.complete {
+        return Err("Document upload is already finalized".to_string());
+    }
+    if chunk_index as usize != document.chunks.len() {
+        return Err(format!(
+            "Expected chunk {}, got {}",
+            document.chunks.len(),
+            chunk_index
+        ));
+    }
+    if chunk_len == 0 || chunk_len > MAX_CHUNK_SIZE {
+        return Err(format!("Chunk must be 1-{} bytes", MAX_CHUNK_SIZE));
+    }
+    if document.uploaded_size + chunk_len as u64 > document.total_size {
+        return Err("Chunk exceeds the declared document size".to_string());
+    }
+    Ok(())
+}
+
+/// Bytes a document holds in storage, including chunks of an uncommitted re-key
+pub fn stored_size(document: &LoanDocument) -> u64 {
+    let rekeyed: u64 = document
+        .rekeyed_chunks
+        .iter()
+        .flatten()
+        .map(|chunk| chunk.len() as u64)
+        .sum();
+    document.total_size.saturating_add(rekeyed)
+}
+
+/// Checks that a chunk re-encrypted under a fresh loan key continues the
+/// document's re-key in order and within the document size limit
+pub fn validate_rekeyed_chunk(
+    document: &LoanDocument,
+    chunk_index: u32,
+    chunk_len: usize,
+) -> Result<(), String> {
+    if !document.complete {
+        return Err("Document upload is not finalized".to_string());
+    }
+    let rekeyed = document.rekeyed_chunks.as_deref().unwrap_or_default();
+    if chunk_index as usize != rekeyed.len() || rekeyed.len() >= document.chunks.len() {
+        return Err(format!(
+            "Expected re-keyed chunk {} of {}, got {}",
+            rekeyed.len(),
+            document.chunks.len(),
+            chunk_index
+        ));
+    }
+    if chunk_len == 0 || chunk_len > MAX_CHUNK_SIZE {
+        return Err(format!("Chunk must be 1-{} bytes", MAX_CHUNK_SIZE));
+    }
+    let size = stored_size(document) - document.total_size;
+    if size + chunk_len as u64 > MAX_DOCUMENT_SIZE {
+        return Err(format!(
+            "Document too large: maximum is {} bytes",
+            MAX_DOCUMENT_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that every document of a loan has all its chunks re-encrypted under
+/// the fresh loan key, so the key can be switched over
+pub fn ensure_rekeyed<'a>(documents: impl Iterator<Item = &'a LoanDocument>) -> Result<(), String> {
+    for document in documents {
+        if !document.complete {
+            return Err(format!(
+                "Document {} is still uploading; finish or delete it first",
+                document.id
+            ));
+        }
+        let rekeyed = document.rekeyed_chunks.as_ref().map_or(0, Vec::len);
+        if rekeyed != document.chunks.len() {
+            return Err(format!(
+                "Document {} has {} of {} chunks re-keyed",
+                document.id,
+                rekeyed,
+                document.chunks.len()
+            ));
+        }
+    }
+    Ok(())
+}
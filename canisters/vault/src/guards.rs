@@ -0,0 +1,80 @@
+// Concurrency Guards
+// Update calls interleave at every `await`: a second call can run against state
+// the first one has already checked but not yet written. Endpoints that read,
+// await and then write hold a lock on the caller and on the UTXO they touch
+// for their whole duration. Locks live in heap memory only and are released
+// when the guard is dropped, including when the call traps and unwinds.
+
+use crate::types::UtxoId;
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+thread_local! {
+    static PRINCIPALS_IN_PROGRESS: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
+    static UTXOS_IN_PROGRESS: RefCell<HashSet<UtxoId>> = RefCell::new(HashSet::new());
+}
+
+/// Held while an operation for a principal is in progress
+#[derive(Debug)]
+pub struct PrincipalGuard(Principal);
+
+impl PrincipalGuard {
+    pub fn acquire(principal: Principal) -> Result<Self, String> {
+        let acquired = PRINCIPALS_IN_PROGRESS.with(|locks| locks.borrow_mut().insert(principal));
+        if !acquired {
+            return Err(format!(
+                "Operation in progress: another call from {} has not finished yet",
+                principal
+            ));
+        }
+        Ok(PrincipalGuard(principal))
+    }
+}
+
+impl Drop for PrincipalGuard {
+    fn drop(&mut self) {
+        PRINCIPALS_IN_PROGRESS.with(|locks| locks.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Held while an operation on a UTXO is in progress
+#[derive(Debug)]
+pub struct UtxoGuard(UtxoId);
+
+impl UtxoGuard {
+    pub fn acquire(utxo_id: UtxoId) -> Result<Self, String> {
+        let acquired = UTXOS_IN_PROGRESS.with(|locks| locks.borrow_mut().insert(utxo_id));
+        if !acquired {
+            return Err(format!(
+                "Operation in progress: UTXO {} is being used by another call",
+                utxo_id
+            ));
+        }
+        Ok(UtxoGuard(utxo_id))
+    }
+
+    /// Locks every UTXO of a collateral basket, or none of them
+    pub fn acquire_all(utxo_ids: &[UtxoId]) -> Result<Vec<Self>, String> {
+        utxo_ids
+            .iter()
+            .map(|utxo_id| Self::acquire(*utxo_id))
+            .collect()
+    }
+}
+
+impl Drop for UtxoGuard {
+    fn drop(&mut self) {
+        UTXOS_IN_PROGRESS.with(|locks| locks.borrow_mut().remove(&self.0));
+    }
+}
+
+/// Whether an operation for `principal` is in progress
+pub fn is_principal_locked(principal: Principal) -> bool {
+    PRINCIPALS_IN_PROGRESS.with(|locks| locks.borrow().contains(&principal))
+}
+
+/// Whether an operation on `utxo_id` is in progress
+pub fn is_utxo_locked(utxo_id: UtxoId) -> bool {
+    UTXOS_IN_PROGRESS.with(|locks| locks.borrow().contains(&utxo_id))
+}
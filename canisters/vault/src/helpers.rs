@@ -1,4 +1,4 @@
-use crate::types::{Loan, UTXO};
+use crate::types::{Loan, LoanId, LoanStatus, UTXO};
 
 /// Calculates the maximum borrowable amount based on LTV ratio
 /// 
@@ -30,36 +30,96 @@ pub fn calculate_max_borrowable(utxo: &UTXO, ltv_ratio: u64) -> u64 {
     }
 }
 
-/// Calculates current loan value (borrowed + interest - repaid)
+/// Calculates the simple interest charged on a loan
+/// 
+/// # Returns
+/// Interest in the loan asset's base units, or an error if it does not fit in u64
+/// 
+/// # Notes
+/// - Computed in u128: base units of 18-decimal assets (ckETH) overflow u64
+///   when multiplied by the rate
+pub fn calculate_interest(loan: &Loan) -> Result<u64, String> {
+    let interest = loan.borrowed_amount as u128 * loan.interest_rate as u128 / 10_000;
+    u64::try_from(interest).map_err(|_| format!("Interest on loan {} overflows", loan.id))
+}
+
+/// Calculates current loan value (borrowed + interest + fees - repaid)
 /// 
 /// # Arguments
 /// * `loan` - The loan to calculate value for
 /// 
 /// # Returns
-/// Current loan value in satoshis (amount still owed)
+/// Current loan value in the asset's base units (amount still owed), or an
+/// error if the total debt does not fit in u64
 /// 
 /// # Formula
-/// Loan value = borrowed_amount + interest - repaid_amount
+/// Loan value = borrowed_amount + interest + fees - repaid_amount
 /// Interest = (borrowed_amount × interest_rate) / 10000
 /// 
 /// # Notes
 /// - Interest rate is in basis points (e.g., 500 = 5%)
 /// - Uses simple interest calculation
+/// - Fees are the late penalties and extension fees of fixed-term loans
 /// - Returns 0 if fully repaid
-pub fn calculate_loan_value(loan: &Loan) -> u64 {
+pub fn calculate_loan_value(loan: &Loan) -> Result<u64, String> {
     // Calculate simple interest: (borrowed × rate) / 10000
-    let interest = (loan.borrowed_amount * loan.interest_rate) / 10000;
+    let interest = calculate_interest(loan)?;
     
-    // Total debt = borrowed + interest
-    let total_debt = loan.borrowed_amount.saturating_add(interest);
+    // Late penalties and extension fees of fixed-term loans
+    let fees = loan.term.as_ref().map_or(0, |term| term.fees);
+    
+    // Total debt = borrowed + interest + fees
+    let total_debt = loan
+        .borrowed_amount
+        .checked_add(interest)
+        .and_then(|debt| debt.checked_add(fees))
+        .ok_or(format!("Debt of loan {} overflows", loan.id))?;
     
     // Remaining debt = total - repaid (saturating_sub prevents underflow)
-    total_debt.saturating_sub(loan.repaid_amount)
+    Ok(total_debt.saturating_sub(loan.repaid_amount))
 }
 
 /// Checks if a loan is fully repaid
 pub fn is_loan_repaid(loan: &Loan) -> bool {
-    calculate_loan_value(loan) == 0
+    calculate_loan_value(loan) == Ok(0)
+}
+
+/// Checks if a loan is still owed and holds its collateral
+pub fn is_loan_open(loan: &Loan) -> bool {
+    matches!(loan.status, LoanStatus::Active | LoanStatus::Defaulted)
+}
+
+/// Moves a loan to `interest_rate`, charged on its principal in place of the
+/// old rate; interest is a flat charge, so nothing is rolled into principal
+/// Returns the remaining debt at the new rate, or an error if more than that
+/// has already been repaid
+pub fn reprice_loan(loan: &mut Loan, interest_rate: u64) -> Result<u64, String> {
+    let repriced = Loan {
+        interest_rate,
+        repaid_amount: 0,
+        ..loan.clone()
+    };
+    let total_debt = calculate_loan_value(&repriced)?;
+    if loan.repaid_amount > total_debt {
+        return Err(format!(
+            "Loan {} has already repaid more than it would owe at {} basis points",
+            loan.id, interest_rate
+        ));
+    }
+    loan.interest_rate = interest_rate;
+    Ok(total_debt - loan.repaid_amount)
+}
+
+/// Ledger memo identifying the loan a repayment is for (big-endian loan ID)
+pub fn loan_memo(loan_id: LoanId) -> Vec<u8> {
+    loan_id.to_be_bytes().to_vec()
+}
+
+/// Ledger memo of a payout drawn on an existing loan (loan memo followed by "draw")
+pub fn draw_memo(loan_id: LoanId) -> Vec<u8> {
+    let mut memo = loan_memo(loan_id);
+    memo.extend_from_slice(b"draw");
+    memo
 }
 
 /// Validates Bitcoin address format
@@ -2,14 +2,31 @@
 // This canister manages Bitcoin UTXO collateral and ckBTC loans
 
 mod api;
+pub mod assets;
 pub mod bitcoin;
 pub mod ckbtc;
+pub mod collateral;
+pub mod deadman;
+pub mod disbursements;
+pub mod documents;
+pub mod guards;
 pub mod helpers;
+pub mod musig2;
+pub mod notes;
+pub mod oracle;
 pub mod ordinals;
+pub mod pool;
+pub mod psbt;
+pub mod repayments;
+pub mod rotation;
 pub mod runes;
 pub mod schnorr;
+pub mod share_token;
 pub mod solana;
+pub mod solana_tx;
 mod state;
+pub mod terms;
+pub mod timelock;
 pub mod types;
 pub mod vetkeys;
 
@@ -0,0 +1,571 @@
+// MuSig2 Multi-Signatures (BIP-327)
+// Key aggregation, nonce exchange and partial signatures for 2-of-2 Taproot vaults
+// shared between a user and the canister
+//
+// The canister's key is a threshold BIP-340 key, so its secret never exists on
+// any replica. The threshold Schnorr API only returns complete signatures and
+// cannot take part in a MuSig2 session, so vaults are spent through a 2-of-2
+// script leaf in which the user and the canister each sign with their own key.
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CSV, OP_DROP};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Secp256k1, XOnlyPublicKey};
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::Address;
+use ic_cdk::management_canister::{
+    schnorr_public_key, sign_with_schnorr, SchnorrAlgorithm, SchnorrKeyId, SchnorrPublicKeyArgs,
+    SignWithSchnorrArgs,
+};
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::point::AffineCoordinates;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::{FieldBytes, ProjectivePoint, PublicKey, Scalar, U256};
+
+/// Length of a serialized public nonce (two compressed points)
+pub const PUBNONCE_LEN: usize = 66;
+
+/// Length of a serialized secret nonce (k1 || k2 || public key)
+pub const SECNONCE_LEN: usize = 97;
+
+/// Aggregated key state (BIP-327 KeyAgg Context)
+#[derive(Clone, Debug)]
+pub struct KeyAggContext {
+    pubkeys: Vec<[u8; 33]>,
+    q: ProjectivePoint,
+    gacc: Scalar,
+    tacc: Scalar,
+}
+
+/// Everything a signer needs to produce or verify partial signatures
+#[derive(Clone, Debug)]
+pub struct SessionContext {
+    pub aggnonce: [u8; PUBNONCE_LEN],
+    pub pubkeys: Vec<Vec<u8>>, // 33-byte compressed keys, in KeyAgg order
+    pub tweaks: Vec<[u8; 32]>, // X-only tweaks applied after aggregation
+    pub message: Vec<u8>,      // Message being signed (usually a 32-byte sighash)
+}
+
+/// Values derived from a session (BIP-327 GetSessionValues)
+struct SessionValues {
+    key_agg: KeyAggContext,
+    b: Scalar,
+    r: ProjectivePoint,
+    e: Scalar,
+}
+
+/// Aggregates public keys into a single MuSig2 key
+///
+/// Key order matters: every participant must pass the keys in the same order.
+pub fn key_agg(pubkeys: &[Vec<u8>]) -> Result<KeyAggContext, String> {
+    if pubkeys.is_empty() {
+        return Err("At least one public key is required".to_string());
+    }
+
+    let keys = pubkeys
+        .iter()
+        .map(|pk| {
+            <[u8; 33]>::try_from(pk.as_slice())
+                .map_err(|_| "Public keys must be 33-byte compressed keys".to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut q = ProjectivePoint::IDENTITY;
+    for pk in &keys {
+        q += point_from_compressed(pk)? * key_agg_coeff(&keys, pk);
+    }
+    if q == ProjectivePoint::IDENTITY {
+        return Err("Aggregate public key is the point at infinity".to_string());
+    }
+
+    Ok(KeyAggContext {
+        pubkeys: keys,
+        q,
+        gacc: Scalar::ONE,
+        tacc: Scalar::ZERO,
+    })
+}
+
+impl KeyAggContext {
+    /// Applies an x-only tweak (BIP-327 ApplyTweak with is_xonly_t = true)
+    pub fn apply_xonly_tweak(&mut self, tweak: &[u8; 32]) -> Result<(), String> {
+        let t = scalar_from_bytes(tweak).ok_or("Tweak is not a valid scalar".to_string())?;
+        let g = if has_even_y(&self.q) {
+            Scalar::ONE
+        } else {
+            -Scalar::ONE
+        };
+
+        let q = self.q * g + ProjectivePoint::GENERATOR * t;
+        if q == ProjectivePoint::IDENTITY {
+            return Err("Tweaked key is the point at infinity".to_string());
+        }
+
+        self.q = q;
+        self.gacc *= g;
+        self.tacc = t + g * self.tacc;
+        Ok(())
+    }
+
+    /// 33-byte compressed aggregate key
+    pub fn aggregate_key(&self) -> [u8; 33] {
+        cbytes(&self.q)
+    }
+
+    /// 32-byte x-only aggregate key (usable as a Taproot internal or output key)
+    pub fn xonly_key(&self) -> [u8; 32] {
+        xbytes(&self.q)
+    }
+
+    fn coefficient(&self, pk: &[u8; 33]) -> Scalar {
+        key_agg_coeff(&self.pubkeys, pk)
+    }
+}
+
+/// Computes the BIP-341 TapTweak for an internal key and optional script tree
+pub fn taproot_tweak(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> [u8; 32] {
+    let mut msg = internal_key.to_vec();
+    if let Some(root) = merkle_root {
+        msg.extend_from_slice(root);
+    }
+    tagged_hash("TapTweak", &msg)
+}
+
+/// Generates a nonce pair (BIP-327 NonceGen)
+///
+/// `rand` must be fresh randomness (e.g. from `raw_rand`); a secret nonce must
+/// never be used for more than one partial signature.
+pub fn nonce_gen(
+    rand: &[u8; 32],
+    secret_key: Option<&[u8; 32]>,
+    public_key: &[u8],
+    aggregate_xonly: Option<&[u8; 32]>,
+    message: Option<&[u8]>,
+) -> Result<([u8; SECNONCE_LEN], [u8; PUBNONCE_LEN]), String> {
+    if public_key.len() != 33 {
+        return Err("Public key must be 33 bytes".to_string());
+    }
+
+    let mut seed = *rand;
+    if let Some(sk) = secret_key {
+        let aux = tagged_hash("MuSig/aux", rand);
+        for (byte, (s, a)) in seed.iter_mut().zip(sk.iter().zip(aux.iter())) {
+            *byte = s ^ a;
+        }
+    }
+
+    let aggpk: &[u8] = aggregate_xonly.map(|k| k.as_slice()).unwrap_or(&[]);
+    let mut msg_prefixed = Vec::new();
+    match message {
+        None => msg_prefixed.push(0u8),
+        Some(m) => {
+            msg_prefixed.push(1u8);
+            msg_prefixed.extend_from_slice(&(m.len() as u64).to_be_bytes());
+            msg_prefixed.extend_from_slice(m);
+        }
+    }
+
+    let mut secnonce = [0u8; SECNONCE_LEN];
+    let mut pubnonce = [0u8; PUBNONCE_LEN];
+    for i in 0..2u8 {
+        let mut data = seed.to_vec();
+        data.push(public_key.len() as u8);
+        data.extend_from_slice(public_key);
+        data.push(aggpk.len() as u8);
+        data.extend_from_slice(aggpk);
+        data.extend_from_slice(&msg_prefixed);
+        data.extend_from_slice(&0u32.to_be_bytes()); // no extra input
+        data.push(i);
+
+        let k = scalar_reduce(&tagged_hash("MuSig/nonce", &data));
+        if k == Scalar::ZERO {
+            return Err("Generated nonce is zero".to_string());
+        }
+
+        let offset = i as usize * 32;
+        secnonce[offset..offset + 32].copy_from_slice(&k.to_bytes());
+        let r = cbytes(&(ProjectivePoint::GENERATOR * k));
+        pubnonce[i as usize * 33..(i as usize + 1) * 33].copy_from_slice(&r);
+    }
+    secnonce[64..].copy_from_slice(public_key);
+
+    Ok((secnonce, pubnonce))
+}
+
+/// Aggregates public nonces from all signers (BIP-327 NonceAgg)
+pub fn nonce_agg(pubnonces: &[Vec<u8>]) -> Result<[u8; PUBNONCE_LEN], String> {
+    if pubnonces.is_empty() {
+        return Err("At least one public nonce is required".to_string());
+    }
+
+    let mut aggnonce = [0u8; PUBNONCE_LEN];
+    for j in 0..2 {
+        let mut r = ProjectivePoint::IDENTITY;
+        for (i, nonce) in pubnonces.iter().enumerate() {
+            if nonce.len() != PUBNONCE_LEN {
+                return Err(format!("Public nonce {} must be {} bytes", i, PUBNONCE_LEN));
+            }
+            r += point_from_compressed(&nonce[j * 33..(j + 1) * 33])
+                .map_err(|e| format!("Invalid public nonce {}: {}", i, e))?;
+        }
+        aggnonce[j * 33..(j + 1) * 33].copy_from_slice(&cbytes_ext(&r));
+    }
+    Ok(aggnonce)
+}
+
+fn session_values(ctx: &SessionContext) -> Result<SessionValues, String> {
+    let mut key_agg = key_agg(&ctx.pubkeys)?;
+    for tweak in &ctx.tweaks {
+        key_agg.apply_xonly_tweak(tweak)?;
+    }
+    let q_x = key_agg.xonly_key();
+
+    let mut data = ctx.aggnonce.to_vec();
+    data.extend_from_slice(&q_x);
+    data.extend_from_slice(&ctx.message);
+    let b = scalar_reduce(&tagged_hash("MuSig/noncecoef", &data));
+
+    let r1 = point_from_compressed_ext(&ctx.aggnonce[..33])?;
+    let r2 = point_from_compressed_ext(&ctx.aggnonce[33..])?;
+    let mut r = r1 + r2 * b;
+    if r == ProjectivePoint::IDENTITY {
+        r = ProjectivePoint::GENERATOR;
+    }
+
+    let mut data = xbytes(&r).to_vec();
+    data.extend_from_slice(&q_x);
+    data.extend_from_slice(&ctx.message);
+    let e = scalar_reduce(&tagged_hash("BIP0340/challenge", &data));
+
+    Ok(SessionValues { key_agg, b, r, e })
+}
+
+/// Produces a partial signature (BIP-327 Sign)
+///
+/// The caller must erase `secnonce` afterwards; reusing it leaks the secret key.
+pub fn partial_sign(
+    secnonce: &[u8],
+    secret_key: &[u8; 32],
+    ctx: &SessionContext,
+) -> Result<[u8; 32], String> {
+    if secnonce.len() != SECNONCE_LEN {
+        return Err(format!("Secret nonce must be {} bytes", SECNONCE_LEN));
+    }
+    let values = session_values(ctx)?;
+
+    let k1 = scalar_from_bytes(&secnonce[..32]).ok_or("Invalid secret nonce".to_string())?;
+    let k2 = scalar_from_bytes(&secnonce[32..64]).ok_or("Invalid secret nonce".to_string())?;
+    if k1 == Scalar::ZERO || k2 == Scalar::ZERO {
+        return Err("Secret nonce has already been used".to_string());
+    }
+    let (k1, k2) = if has_even_y(&values.r) {
+        (k1, k2)
+    } else {
+        (-k1, -k2)
+    };
+
+    let d_prime = scalar_from_bytes(secret_key)
+        .filter(|d| *d != Scalar::ZERO)
+        .ok_or("Invalid secret key".to_string())?;
+    let pk = cbytes(&(ProjectivePoint::GENERATOR * d_prime));
+    if pk[..] != secnonce[64..] {
+        return Err("Secret nonce does not belong to this key".to_string());
+    }
+    if !values.key_agg.pubkeys.contains(&pk) {
+        return Err("Signer's key is not part of the session".to_string());
+    }
+
+    let a = values.key_agg.coefficient(&pk);
+    let g = if has_even_y(&values.key_agg.q) {
+        Scalar::ONE
+    } else {
+        -Scalar::ONE
+    };
+    let d = g * values.key_agg.gacc * d_prime;
+    let s = k1 + values.b * k2 + values.e * a * d;
+
+    Ok(s.to_bytes().into())
+}
+
+/// Verifies a partial signature from one signer (BIP-327 PartialSigVerify)
+pub fn partial_sig_verify(
+    partial_sig: &[u8],
+    pubnonce: &[u8],
+    public_key: &[u8],
+    ctx: &SessionContext,
+) -> Result<(), String> {
+    if pubnonce.len() != PUBNONCE_LEN {
+        return Err(format!("Public nonce must be {} bytes", PUBNONCE_LEN));
+    }
+    let pk =
+        <[u8; 33]>::try_from(public_key).map_err(|_| "Public key must be 33 bytes".to_string())?;
+    let s = scalar_from_bytes(partial_sig).ok_or("Invalid partial signature".to_string())?;
+    let values = session_values(ctx)?;
+    if !values.key_agg.pubkeys.contains(&pk) {
+        return Err("Signer's key is not part of the session".to_string());
+    }
+
+    let r1 = point_from_compressed(&pubnonce[..33])?;
+    let r2 = point_from_compressed(&pubnonce[33..])?;
+    let mut re = r1 + r2 * values.b;
+    if !has_even_y(&values.r) {
+        re = -re;
+    }
+
+    let a = values.key_agg.coefficient(&pk);
+    let g = if has_even_y(&values.key_agg.q) {
+        Scalar::ONE
+    } else {
+        -Scalar::ONE
+    };
+    let expected = re + point_from_compressed(&pk)? * (values.e * a * g * values.key_agg.gacc);
+
+    if ProjectivePoint::GENERATOR * s == expected {
+        Ok(())
+    } else {
+        Err("Partial signature verification failed".to_string())
+    }
+}
+
+/// Aggregates partial signatures into a BIP-340 signature (BIP-327 PartialSigAgg)
+pub fn partial_sig_agg(partial_sigs: &[Vec<u8>], ctx: &SessionContext) -> Result<[u8; 64], String> {
+    let values = session_values(ctx)?;
+
+    let mut s = Scalar::ZERO;
+    for (i, psig) in partial_sigs.iter().enumerate() {
+        s += scalar_from_bytes(psig).ok_or(format!("Invalid partial signature {}", i))?;
+    }
+    let g = if has_even_y(&values.key_agg.q) {
+        Scalar::ONE
+    } else {
+        -Scalar::ONE
+    };
+    s += values.e * g * values.key_agg.tacc;
+
+    let mut sig = [0u8; 64];
+    sig[..32].copy_from_slice(&xbytes(&values.r));
+    sig[32..].copy_from_slice(&s.to_bytes());
+    Ok(sig)
+}
+
+// ============================================================================
+// Vault Taproot construction
+// ============================================================================
+
+/// Taproot output of a 2-of-2 vault
+#[derive(Clone, Debug)]
+pub struct VaultTaproot {
+    pub internal_key: [u8; 32], // MuSig2 aggregate of user and canister keys
+    pub cosign_script: Vec<u8>, // <user> OP_CHECKSIGVERIFY <canister> OP_CHECKSIG
+    pub cosign_control_block: Vec<u8>, // Proof for spending the 2-of-2 leaf
+    pub refund_script: Vec<u8>, // <delay> OP_CSV OP_DROP <user> OP_CHECKSIG
+    pub control_block: Vec<u8>, // Proof for spending the refund leaf
+    pub merkle_root: [u8; 32],
+    pub tweak: [u8; 32], // TapTweak applied on top of the aggregate key
+    pub output_key: [u8; 32],
+    pub address: String,
+}
+
+/// Builds the vault output: one leaf needs both signers, the other lets the
+/// user refund alone once `refund_delay_blocks` have passed
+/// The key path commits to both keys too but stays unused, as the canister's
+/// threshold key cannot produce MuSig2 partial signatures
+pub fn vault_taproot(
+    user_public_key: &[u8],
+    canister_public_key: &[u8],
+    refund_delay_blocks: u16,
+) -> Result<VaultTaproot, String> {
+    if refund_delay_blocks == 0 {
+        return Err("Refund delay must be greater than 0 blocks".to_string());
+    }
+
+    let key_agg = key_agg(&[user_public_key.to_vec(), canister_public_key.to_vec()])?;
+    let internal_key = key_agg.xonly_key();
+
+    let user_xonly = XOnlyPublicKey::from_slice(&user_public_key[1..])
+        .map_err(|e| format!("Invalid user public key: {}", e))?;
+    let canister_xonly = XOnlyPublicKey::from_slice(&canister_public_key[1..])
+        .map_err(|e| format!("Invalid canister public key: {}", e))?;
+    let cosign_script = Builder::new()
+        .push_x_only_key(&user_xonly)
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_x_only_key(&canister_xonly)
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+    let refund_script = Builder::new()
+        .push_int(refund_delay_blocks as i64)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_x_only_key(&user_xonly)
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+
+    let secp = Secp256k1::verification_only();
+    let internal = XOnlyPublicKey::from_slice(&internal_key)
+        .map_err(|e| format!("Invalid aggregate key: {}", e))?;
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(1, cosign_script.clone())
+        .and_then(|builder| builder.add_leaf(1, refund_script.clone()))
+        .map_err(|e| format!("Failed to build script tree: {}", e))?
+        .finalize(&secp, internal)
+        .map_err(|_| "Failed to finalize script tree".to_string())?;
+    let merkle_root = spend_info
+        .merkle_root()
+        .ok_or("Script tree has no merkle root".to_string())?
+        .to_byte_array();
+    let control_block = spend_info
+        .control_block(&(refund_script.clone(), LeafVersion::TapScript))
+        .ok_or("Failed to compute refund control block".to_string())?;
+    let cosign_control_block = spend_info
+        .control_block(&(cosign_script.clone(), LeafVersion::TapScript))
+        .ok_or("Failed to compute 2-of-2 control block".to_string())?;
+
+    let address = Address::p2tr_tweaked(spend_info.output_key(), crate::psbt::NETWORK);
+
+    Ok(VaultTaproot {
+        internal_key,
+        cosign_script: cosign_script.to_bytes(),
+        cosign_control_block: cosign_control_block.serialize(),
+        refund_script: refund_script.to_bytes(),
+        control_block: control_block.serialize(),
+        merkle_root,
+        tweak: taproot_tweak(&internal_key, Some(&merkle_root)),
+        output_key: spend_info.output_key().to_x_only_public_key().serialize(),
+        address: address.to_string(),
+    })
+}
+
+// ============================================================================
+// Threshold Schnorr
+// ============================================================================
+
+/// Derivation path of the canister's threshold key for a vault
+pub fn canister_derivation_path(vault_id: u64) -> Vec<Vec<u8>> {
+    vec![b"musig-vault".to_vec(), vault_id.to_be_bytes().to_vec()]
+}
+
+fn bip340_key_id() -> SchnorrKeyId {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    SchnorrKeyId {
+        algorithm: SchnorrAlgorithm::Bip340secp256k1,
+        name: if network == "ic" {
+            "key_1"
+        } else {
+            "dfx_test_key"
+        }
+        .to_string(),
+    }
+}
+
+/// The canister's threshold public key for a vault (33-byte compressed)
+pub(crate) async fn canister_public_key(vault_id: u64) -> Result<Vec<u8>, String> {
+    let result = schnorr_public_key(&SchnorrPublicKeyArgs {
+        canister_id: None,
+        derivation_path: canister_derivation_path(vault_id),
+        key_id: bip340_key_id(),
+    })
+    .await
+    .map_err(|e| format!("schnorr_public_key failed: {:?}", e))?;
+
+    if result.public_key.len() != 33 {
+        return Err("BIP-340 public key must be 33 bytes".to_string());
+    }
+    Ok(result.public_key)
+}
+
+/// Signs a script path sighash with the canister's untweaked threshold key for a vault
+pub(crate) async fn sign_as_canister(vault_id: u64, sighash: [u8; 32]) -> Result<[u8; 64], String> {
+    let result = sign_with_schnorr(&SignWithSchnorrArgs {
+        message: sighash.to_vec(),
+        derivation_path: canister_derivation_path(vault_id),
+        key_id: bip340_key_id(),
+        aux: None,
+    })
+    .await
+    .map_err(|e| format!("sign_with_schnorr failed: {:?}", e))?;
+
+    <[u8; 64]>::try_from(result.signature.as_slice())
+        .map_err(|_| "BIP-340 signature must be 64 bytes".to_string())
+}
+
+// ============================================================================
+// Curve helpers
+// ============================================================================
+
+fn key_agg_coeff(pubkeys: &[[u8; 33]], pk: &[u8; 33]) -> Scalar {
+    // The second distinct key gets coefficient 1 (BIP-327 GetSecondKey)
+    if pubkeys.iter().find(|k| *k != &pubkeys[0]) == Some(pk) {
+        return Scalar::ONE;
+    }
+    let list_hash = tagged_hash("KeyAgg list", &pubkeys.concat());
+    let mut data = list_hash.to_vec();
+    data.extend_from_slice(pk);
+    scalar_reduce(&tagged_hash("KeyAgg coefficient", &data))
+}
+
+/// BIP-340 tagged hash: SHA256(SHA256(tag) || SHA256(tag) || msg)
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(msg);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn scalar_reduce(bytes: &[u8; 32]) -> Scalar {
+    <Scalar as Reduce<U256>>::reduce_bytes(FieldBytes::from_slice(bytes))
+}
+
+fn scalar_from_bytes(bytes: &[u8]) -> Option<Scalar> {
+    if bytes.len() != 32 {
+        return None;
+    }
+    Scalar::from_repr(*FieldBytes::from_slice(bytes)).into()
+}
+
+fn point_from_compressed(bytes: &[u8]) -> Result<ProjectivePoint, String> {
+    PublicKey::from_sec1_bytes(bytes)
+        .map(|pk| pk.to_projective())
+        .map_err(|_| "Invalid compressed point".to_string())
+}
+
+/// Like `point_from_compressed`, but 33 zero bytes encode the point at infinity
+fn point_from_compressed_ext(bytes: &[u8]) -> Result<ProjectivePoint, String> {
+    if bytes.iter().all(|b| *b == 0) {
+        Ok(ProjectivePoint::IDENTITY)
+    } else {
+        point_from_compressed(bytes)
+    }
+}
+
+fn cbytes(p: &ProjectivePoint) -> [u8; 33] {
+    let mut out = [0u8; 33];
+    out.copy_from_slice(p.to_affine().to_encoded_point(true).as_bytes());
+    out
+}
+
+fn cbytes_ext(p: &ProjectivePoint) -> [u8; 33] {
+    if *p == ProjectivePoint::IDENTITY {
+        [0u8; 33]
+    } else {
+        cbytes(p)
+    }
+}
+
+fn xbytes(p: &ProjectivePoint) -> [u8; 32] {
+    p.to_affine().x().into()
+}
+
+fn has_even_y(p: &ProjectivePoint) -> bool {
+    !bool::from(p.to_affine().y_is_odd())
+}
+
+/// Derives the public key for a secret key (33-byte compressed)
+pub fn public_key_from_secret(secret_key: &[u8; 32]) -> Result<[u8; 33], String> {
+    let d = scalar_from_bytes(secret_key)
+        .filter(|d| *d != Scalar::ZERO)
+        .ok_or("Invalid secret key".to_string())?;
+    Ok(cbytes(&(ProjectivePoint::GENERATOR * d)))
+}
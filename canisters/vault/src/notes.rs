@@ -0,0 +1,21 @@
+// Encrypted Notes
+// Notes are encrypted by the client, each under its own random data key. The
+// client wraps that key for every principal with access (see `vetkeys`); the
+// canister stores the ciphertext and wrapped keys and never sees the content.
+
+/// Maximum size of a note's ciphertext in bytes
+pub const MAX_NOTE_SIZE: usize = 64 * 1024;
+
+/// Checks a note ciphertext sent by the client
+pub fn validate_ciphertext(ciphertext: &[u8]) -> Result<(), String> {
+    if ciphertext.is_empty() {
+        return Err("Note cannot be empty".to_string());
+    }
+    if ciphertext.len() > MAX_NOTE_SIZE {
+        return Err(format!(
+            "Note too large: maximum is {} bytes",
+            MAX_NOTE_SIZE
+        ));
+    }
+    Ok(())
+}
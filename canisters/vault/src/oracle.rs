@@ -0,0 +1,205 @@
+// Price Oracle
+// USD prices from the IC Exchange Rate Canister (XRC), used to convert loan
+// values between assets. Local and playground deployments use fixed prices.
+
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::call::Call;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Exchange Rate Canister on the NNS subnet
+const XRC_CANISTER_ID: &str = "uf6dk-hyaaa-aaaaq-qaaaq-cai";
+
+/// Cycles attached to every XRC request (unused cycles are refunded)
+const XRC_CALL_CYCLES: u128 = 1_000_000_000;
+
+/// Decimals every price is normalized to
+pub const PRICE_DECIMALS: u32 = 9;
+
+thread_local! {
+    // Last price fetched per symbol, for queries that cannot call the XRC
+    static PRICES: RefCell<HashMap<String, UsdPrice>> = RefCell::new(HashMap::new());
+}
+
+/// USD price of one whole unit of an asset, scaled by 10^PRICE_DECIMALS
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct UsdPrice {
+    pub rate: u64,
+    pub timestamp: u64, // Seconds; the minute the XRC rate was computed for
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum AssetClass {
+    Cryptocurrency,
+    FiatCurrency,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct Asset {
+    symbol: String,
+    class: AssetClass,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct GetExchangeRateRequest {
+    base_asset: Asset,
+    quote_asset: Asset,
+    timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ExchangeRateMetadata {
+    decimals: u32,
+    base_asset_num_queried_sources: u64,
+    base_asset_num_received_rates: u64,
+    quote_asset_num_queried_sources: u64,
+    quote_asset_num_received_rates: u64,
+    standard_deviation: u64,
+    forex_timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ExchangeRate {
+    base_asset: Asset,
+    quote_asset: Asset,
+    timestamp: u64,
+    rate: u64,
+    metadata: ExchangeRateMetadata,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct OtherError {
+    code: u32,
+    description: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum ExchangeRateError {
+    AnonymousPrincipalNotAllowed,
+    Pending,
+    CryptoBaseAssetNotFound,
+    CryptoQuoteAssetNotFound,
+    StablecoinRateNotFound,
+    StablecoinRateTooFewRates,
+    StablecoinRateZeroRate,
+    ForexInvalidTimestamp,
+    ForexBaseAssetNotFound,
+    ForexQuoteAssetNotFound,
+    ForexAssetsNotFound,
+    RateLimited,
+    NotEnoughCycles,
+    FailedToAcceptCycles,
+    InconsistentRatesReceived,
+    Other(OtherError),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum GetExchangeRateResult {
+    Ok(ExchangeRate),
+    Err(ExchangeRateError),
+}
+
+/// Rescales a rate from `decimals` to `PRICE_DECIMALS`
+pub fn normalize_rate(rate: u64, decimals: u32) -> Result<u64, String> {
+    let scaled = if decimals >= PRICE_DECIMALS {
+        rate as u128 / 10u128.pow(decimals - PRICE_DECIMALS)
+    } else {
+        rate as u128 * 10u128.pow(PRICE_DECIMALS - decimals)
+    };
+    u64::try_from(scaled).map_err(|_| "Price out of range".to_string())
+}
+
+/// Converts `amount` base units of one asset into base units of another at the
+/// given USD prices, rounding down
+pub fn convert_amount(
+    amount: u64,
+    from_decimals: u32,
+    from_price: &UsdPrice,
+    to_decimals: u32,
+    to_price: &UsdPrice,
+) -> Result<u64, String> {
+    if to_price.rate == 0 {
+        return Err("Price of target asset is zero".to_string());
+    }
+
+    let overflow = || "Conversion overflow".to_string();
+    let numerator = (amount as u128)
+        .checked_mul(from_price.rate as u128)
+        .and_then(|v| v.checked_mul(10u128.pow(to_decimals)))
+        .ok_or_else(overflow)?;
+    let denominator = 10u128
+        .pow(from_decimals)
+        .checked_mul(to_price.rate as u128)
+        .ok_or_else(overflow)?;
+
+    u64::try_from(numerator / denominator).map_err(|_| overflow())
+}
+
+/// Last price fetched for `symbol`, if any
+pub fn cached_usd_price(symbol: &str) -> Option<UsdPrice> {
+    PRICES.with(|prices| prices.borrow().get(symbol).copied())
+}
+
+/// Gets the USD price of a cryptocurrency (e.g. "BTC", "SOL", "USDC")
+/// and remembers it for `cached_usd_price`
+pub async fn get_usd_price(symbol: &str) -> Result<UsdPrice, String> {
+    let price = fetch_usd_price(symbol).await?;
+    PRICES.with(|prices| prices.borrow_mut().insert(symbol.to_string(), price));
+    Ok(price)
+}
+
+async fn fetch_usd_price(symbol: &str) -> Result<UsdPrice, String> {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    if network == "local" || network == "playground" {
+        ic_cdk::println!("⚠️ Using fixed {} price ({} mode)", symbol, network);
+        return mock_price(symbol);
+    }
+
+    let request = GetExchangeRateRequest {
+        base_asset: Asset {
+            symbol: symbol.to_string(),
+            class: AssetClass::Cryptocurrency,
+        },
+        quote_asset: Asset {
+            symbol: "USD".to_string(),
+            class: AssetClass::FiatCurrency,
+        },
+        timestamp: None,
+    };
+
+    let xrc = Principal::from_text(XRC_CANISTER_ID)
+        .map_err(|e| format!("Invalid XRC canister ID: {:?}", e))?;
+    let result: GetExchangeRateResult = Call::unbounded_wait(xrc, "get_exchange_rate")
+        .with_arg(request)
+        .with_cycles(XRC_CALL_CYCLES)
+        .await
+        .map_err(|e| format!("Exchange rate call failed: {:?}", e))?
+        .candid()
+        .map_err(|e| format!("Failed to decode exchange rate: {:?}", e))?;
+
+    match result {
+        GetExchangeRateResult::Ok(rate) => Ok(UsdPrice {
+            rate: normalize_rate(rate.rate, rate.metadata.decimals)?,
+            timestamp: rate.timestamp,
+        }),
+        GetExchangeRateResult::Err(e) => {
+            Err(format!("Exchange rate for {} unavailable: {:?}", symbol, e))
+        }
+    }
+}
+
+fn mock_price(symbol: &str) -> Result<UsdPrice, String> {
+    let dollars = match symbol {
+        "BTC" => 50_000,
+        "ETH" => 3_000,
+        "SOL" => 150,
+        "USDC" => 1,
+        _ => return Err(format!("No price for {}", symbol)),
+    };
+
+    Ok(UsdPrice {
+        rate: dollars * 10u64.pow(PRICE_DECIMALS),
+        timestamp: ic_cdk::api::time() / 1_000_000_000,
+    })
+}
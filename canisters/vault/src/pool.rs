@@ -0,0 +1,175 @@
+// Lending Pool
+// Suppliers deposit ckBTC for shares and ckBTC loans are funded from the pool.
+// Interest repaid by borrowers, minus the reserve factor, stays in the pool,
+// so each share is redeemable for more ckBTC over time.
+
+use crate::ckbtc::Account;
+use crate::types::{LendingPool, Loan, LoanAsset, PoolStats, SupplierPosition};
+
+/// Share of repaid interest kept as protocol reserves, in basis points
+pub const RESERVE_FACTOR_BPS: u64 = 1_000;
+
+/// Fixed-point scale of the exchange rate (1.0 = one satoshi per share)
+pub const EXCHANGE_RATE_SCALE: u64 = 100_000_000;
+
+/// ckBTC owned by suppliers: idle cash plus principal lent out
+pub fn total_value(pool: &LendingPool) -> u64 {
+    pool.cash.saturating_add(pool.borrowed)
+}
+
+/// Satoshis per share, scaled by `EXCHANGE_RATE_SCALE`
+pub fn exchange_rate(pool: &LendingPool) -> u64 {
+    if pool.total_shares == 0 {
+        return EXCHANGE_RATE_SCALE;
+    }
+    mul_div(total_value(pool), EXCHANGE_RATE_SCALE, pool.total_shares)
+}
+
+/// Current value of `shares` in satoshis, rounded down
+pub fn share_value(pool: &LendingPool, shares: u64) -> u64 {
+    if pool.total_shares == 0 {
+        return 0;
+    }
+    mul_div(shares, total_value(pool), pool.total_shares)
+}
+
+/// Borrowed principal as a share of supplied value, in basis points
+pub fn utilization_rate(pool: &LendingPool) -> u64 {
+    let value = total_value(pool);
+    if value == 0 {
+        return 0;
+    }
+    mul_div(pool.borrowed, 10_000, value)
+}
+
+/// Whether a loan was funded from the pool (ckBTC paid out on the ckBTC ledger)
+pub fn is_pool_loan(loan: &Loan) -> bool {
+    loan.asset == LoanAsset::CkBtc && loan.solana_disbursement.is_none()
+}
+
+/// Credits a deposit and mints shares at the current exchange rate
+pub fn supply(pool: &mut LendingPool, supplier: &Account, amount: u64) -> Result<u64, String> {
+    let value = total_value(pool);
+    let shares = if pool.total_shares == 0 {
+        amount
+    } else if value == 0 {
+        return Err("Pool has no value left; deposits are disabled".to_string());
+    } else {
+        mul_div(amount, pool.total_shares, value)
+    };
+    if shares == 0 {
+        return Err("Deposit too small to mint a share".to_string());
+    }
+
+    pool.cash = pool.cash.saturating_add(amount);
+    pool.total_shares = pool.total_shares.saturating_add(shares);
+    *pool.shares.entry(supplier.clone()).or_insert(0) += shares;
+    Ok(shares)
+}
+
+/// Burns shares and debits their value from idle cash
+/// Fails if the pool does not hold enough unlent ckBTC
+pub fn redeem(pool: &mut LendingPool, supplier: &Account, shares: u64) -> Result<u64, String> {
+    let owned = pool.shares.get(supplier).copied().unwrap_or(0);
+    if shares == 0 || shares > owned {
+        return Err(format!("Invalid share amount: {} owned", owned));
+    }
+
+    let amount = share_value(pool, shares);
+    if amount > pool.cash {
+        return Err(format!(
+            "Insufficient liquidity: {} satoshis available, {} requested",
+            pool.cash, amount
+        ));
+    }
+
+    pool.cash -= amount;
+    pool.total_shares -= shares;
+    if owned == shares {
+        pool.shares.remove(supplier);
+    } else {
+        pool.shares.insert(supplier.clone(), owned - shares);
+    }
+    Ok(amount)
+}
+
+/// Reverses a redemption whose payout failed
+pub fn restore_redemption(pool: &mut LendingPool, supplier: &Account, shares: u64, amount: u64) {
+    pool.cash = pool.cash.saturating_add(amount);
+    pool.total_shares = pool.total_shares.saturating_add(shares);
+    *pool.shares.entry(supplier.clone()).or_insert(0) += shares;
+}
+
+/// Moves `amount` from idle cash to borrowed principal
+pub fn lend(pool: &mut LendingPool, amount: u64) -> Result<(), String> {
+    if amount > pool.cash {
+        return Err(format!(
+            "Insufficient pool liquidity: {} satoshis available, {} requested",
+            pool.cash, amount
+        ));
+    }
+    pool.cash -= amount;
+    pool.borrowed = pool.borrowed.saturating_add(amount);
+    Ok(())
+}
+
+/// Reverses a loan whose payout failed
+pub fn cancel_lend(pool: &mut LendingPool, amount: u64) {
+    pool.borrowed = pool.borrowed.saturating_sub(amount);
+    pool.cash = pool.cash.saturating_add(amount);
+}
+
+/// Splits a repayment of `amount` into (principal, interest); principal is repaid first
+pub fn repayment_split(loan: &Loan, amount: u64) -> (u64, u64) {
+    let principal_repaid = loan.repaid_amount.min(loan.borrowed_amount);
+    let principal = amount.min(loan.borrowed_amount - principal_repaid);
+    (principal, amount - principal)
+}
+
+/// Books a repayment: principal returns to cash, interest is shared between
+/// suppliers and reserves according to `RESERVE_FACTOR_BPS`
+pub fn repay(pool: &mut LendingPool, principal: u64, interest: u64) {
+    let reserve_cut = mul_div(interest, RESERVE_FACTOR_BPS, 10_000);
+    pool.borrowed = pool.borrowed.saturating_sub(principal);
+    pool.cash = pool
+        .cash
+        .saturating_add(principal)
+        .saturating_add(interest - reserve_cut);
+    pool.reserves = pool.reserves.saturating_add(reserve_cut);
+}
+
+/// Writes off principal that will not be repaid; reserves absorb the loss first
+pub fn write_off(pool: &mut LendingPool, principal: u64) {
+    let principal = principal.min(pool.borrowed);
+    pool.borrowed -= principal;
+
+    let covered = principal.min(pool.reserves);
+    pool.reserves -= covered;
+    pool.cash = pool.cash.saturating_add(covered);
+}
+
+/// Pool overview
+pub fn stats(pool: &LendingPool) -> PoolStats {
+    PoolStats {
+        cash: pool.cash,
+        borrowed: pool.borrowed,
+        reserves: pool.reserves,
+        total_shares: pool.total_shares,
+        exchange_rate: exchange_rate(pool),
+        utilization_rate: utilization_rate(pool),
+        reserve_factor: RESERVE_FACTOR_BPS,
+    }
+}
+
+/// A supplier's shares and their current value
+pub fn position(pool: &LendingPool, supplier: &Account) -> SupplierPosition {
+    let shares = pool.shares.get(supplier).copied().unwrap_or(0);
+    SupplierPosition {
+        shares,
+        value: share_value(pool, shares),
+    }
+}
+
+fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    (a as u128 * b as u128 / c as u128).min(u64::MAX as u128) as u64
+}
@@ -0,0 +1,825 @@
+// PSBT Construction and Export/Import
+// Builds BIP-174 (v0) and BIP-370 (v2) partially signed transactions that spend
+// custodied UTXOs, and lets external co-signers add signatures before finalization
+
+use crate::types::UtxoId;
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::consensus::encode::serialize as consensus_serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::{
+    absolute, ecdsa, taproot, transaction, Address, Amount, Network, OutPoint, PublicKey,
+    ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Bitcoin network used for address parsing (matches `bitcoin::get_utxos_for_address`)
+pub const NETWORK: Network = Network::Testnet;
+
+/// PSBT magic bytes ("psbt" + 0xff)
+const PSBT_MAGIC: [u8; 5] = [0x70, 0x73, 0x62, 0x74, 0xff];
+
+// BIP-174 / BIP-370 key types used when converting between versions
+const PSBT_GLOBAL_UNSIGNED_TX: u64 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u64 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u64 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u64 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u64 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u64 = 0x06;
+const PSBT_GLOBAL_VERSION: u64 = 0xfb;
+const PSBT_IN_PREVIOUS_TXID: u64 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u64 = 0x0f;
+const PSBT_IN_SEQUENCE: u64 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u64 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u64 = 0x12;
+const PSBT_OUT_AMOUNT: u64 = 0x03;
+const PSBT_OUT_SCRIPT: u64 = 0x04;
+
+/// PSBT serialization version
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum PsbtVersion {
+    V0, // BIP-174
+    V2, // BIP-370
+}
+
+/// How a Taproot input will be spent
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum TaprootSpend {
+    /// Key path spend; `merkle_root` is set when the output also commits to a script tree
+    KeyPath {
+        internal_key: Vec<u8>,        // 32-byte x-only internal key
+        merkle_root: Option<Vec<u8>>, // 32-byte script tree root
+    },
+    /// Script path spend of a single leaf
+    ScriptPath {
+        internal_key: Vec<u8>,  // 32-byte x-only internal key
+        leaf_script: Vec<u8>,   // Tapscript being executed
+        control_block: Vec<u8>, // Serialized control block proving the leaf
+    },
+}
+
+/// A single input of a PSBT to build
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PsbtInputSpec {
+    pub txid: String,                  // Hex txid of the previous output
+    pub vout: u32,                     // Output index
+    pub amount: u64,                   // Previous output value in satoshis
+    pub script_pubkey: Vec<u8>,        // Previous output script
+    pub sequence: Option<u32>,         // nSequence (defaults to RBF-enabled 0xfffffffd)
+    pub taproot: Option<TaprootSpend>, // Taproot spend details, if any
+}
+
+/// A single output of a PSBT to build
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PsbtOutputSpec {
+    pub address: String, // Destination address
+    pub amount: u64,     // Value in satoshis
+}
+
+/// Partial signature supplied by a co-signer
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum PartialSignature {
+    /// ECDSA signature for a segwit v0 input (DER + sighash byte)
+    Ecdsa {
+        public_key: Vec<u8>,
+        signature: Vec<u8>,
+    },
+    /// Schnorr signature for a Taproot key path spend
+    TaprootKey { signature: Vec<u8> },
+    /// Schnorr signature for a Taproot script path leaf
+    TaprootScript {
+        public_key: Vec<u8>,  // 32-byte x-only key inside the leaf
+        leaf_script: Vec<u8>, // Leaf the signature commits to
+        signature: Vec<u8>,
+    },
+}
+
+/// Custodied UTXO to spend in a PSBT built by the vault
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CollateralInput {
+    pub utxo_id: UtxoId,
+    pub sequence: Option<u32>,
+    pub taproot: Option<TaprootSpend>,
+}
+
+/// Request to build a PSBT spending custodied UTXOs
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BuildPsbtRequest {
+    pub inputs: Vec<CollateralInput>,
+    pub outputs: Vec<PsbtOutputSpec>,
+    pub lock_time: u32,
+    pub version: PsbtVersion,
+}
+
+/// Finalized network transaction
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct FinalizedTransaction {
+    pub txid: String,
+    pub tx_hex: String,
+}
+
+/// Builds an unsigned PSBT from input and output specifications
+///
+/// Taproot inputs get `tap_internal_key` and either `tap_merkle_root` (key path)
+/// or `tap_scripts` (script path) filled in so signers can compute sighashes.
+pub fn build_psbt(
+    inputs: &[PsbtInputSpec],
+    outputs: &[PsbtOutputSpec],
+    lock_time: u32,
+) -> Result<Psbt, String> {
+    if inputs.is_empty() {
+        return Err("PSBT must have at least one input".to_string());
+    }
+    if outputs.is_empty() {
+        return Err("PSBT must have at least one output".to_string());
+    }
+
+    let total_in: u64 = inputs.iter().map(|i| i.amount).sum();
+    let total_out: u64 = outputs.iter().map(|o| o.amount).sum();
+    if total_out > total_in {
+        return Err(format!(
+            "Outputs ({} sats) exceed inputs ({} sats)",
+            total_out, total_in
+        ));
+    }
+
+    let tx_inputs = inputs
+        .iter()
+        .map(|input| {
+            let txid = Txid::from_str(&input.txid).map_err(|e| format!("Invalid txid: {}", e))?;
+            Ok(TxIn {
+                previous_output: OutPoint::new(txid, input.vout),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(input.sequence.unwrap_or(Sequence::ENABLE_RBF_NO_LOCKTIME.0)),
+                witness: Witness::new(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let tx_outputs = outputs
+        .iter()
+        .map(|output| {
+            if output.amount == 0 {
+                return Err("Output amount must be greater than 0".to_string());
+            }
+            Ok(TxOut {
+                value: Amount::from_sat(output.amount),
+                script_pubkey: parse_address(&output.address)?.script_pubkey(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let unsigned_tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::from_consensus(lock_time),
+        input: tx_inputs,
+        output: tx_outputs,
+    };
+
+    let mut psbt =
+        Psbt::from_unsigned_tx(unsigned_tx).map_err(|e| format!("Failed to create PSBT: {}", e))?;
+
+    for (psbt_input, spec) in psbt.inputs.iter_mut().zip(inputs) {
+        psbt_input.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(spec.amount),
+            script_pubkey: ScriptBuf::from_bytes(spec.script_pubkey.clone()),
+        });
+
+        match &spec.taproot {
+            Some(TaprootSpend::KeyPath {
+                internal_key,
+                merkle_root,
+            }) => {
+                psbt_input.tap_internal_key = Some(parse_xonly(internal_key)?);
+                psbt_input.tap_merkle_root = merkle_root
+                    .as_ref()
+                    .map(|root| {
+                        TapNodeHash::from_slice(root)
+                            .map_err(|e| format!("Invalid merkle root: {}", e))
+                    })
+                    .transpose()?;
+            }
+            Some(TaprootSpend::ScriptPath {
+                internal_key,
+                leaf_script,
+                control_block,
+            }) => {
+                let control_block = ControlBlock::decode(control_block)
+                    .map_err(|e| format!("Invalid control block: {}", e))?;
+                psbt_input.tap_internal_key = Some(parse_xonly(internal_key)?);
+                psbt_input.tap_scripts.insert(
+                    control_block,
+                    (
+                        ScriptBuf::from_bytes(leaf_script.clone()),
+                        LeafVersion::TapScript,
+                    ),
+                );
+            }
+            None => {}
+        }
+    }
+
+    Ok(psbt)
+}
+
+/// Parses an address and checks it belongs to the vault's network
+pub fn parse_address(address: &str) -> Result<Address, String> {
+    Address::from_str(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?
+        .require_network(NETWORK)
+        .map_err(|e| format!("Address {} is for the wrong network: {}", address, e))
+}
+
+fn parse_xonly(bytes: &[u8]) -> Result<XOnlyPublicKey, String> {
+    XOnlyPublicKey::from_slice(bytes).map_err(|e| format!("Invalid x-only public key: {}", e))
+}
+
+/// Serializes a PSBT to base64 in the requested version
+pub fn to_base64(psbt: &Psbt, version: PsbtVersion) -> Result<String, String> {
+    let bytes = match version {
+        PsbtVersion::V0 => psbt.serialize(),
+        PsbtVersion::V2 => v0_to_v2(psbt)?,
+    };
+    Ok(BASE64.encode(bytes))
+}
+
+/// Parses a base64 PSBT, accepting both v0 and v2 encodings
+///
+/// Returns the version it was encoded in so callers can answer in kind.
+pub fn from_base64(encoded: &str) -> Result<(Psbt, PsbtVersion), String> {
+    let bytes = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    from_bytes(&bytes)
+}
+
+/// Parses raw PSBT bytes, accepting both v0 and v2 encodings
+pub fn from_bytes(bytes: &[u8]) -> Result<(Psbt, PsbtVersion), String> {
+    let (global, _, _) = split_maps(bytes, None)?;
+    let is_v2 = global
+        .iter()
+        .any(|kv| kv.key_type == PSBT_GLOBAL_VERSION && read_u32(&kv.value) == Some(2));
+
+    let (v0_bytes, version) = if is_v2 {
+        (v2_to_v0(bytes)?, PsbtVersion::V2)
+    } else {
+        (bytes.to_vec(), PsbtVersion::V0)
+    };
+    let psbt = Psbt::deserialize(&v0_bytes).map_err(|e| format!("Invalid PSBT: {}", e))?;
+    Ok((psbt, version))
+}
+
+/// Combines PSBTs for the same unsigned transaction (BIP-174 Combiner role)
+pub fn combine(psbts: Vec<Psbt>) -> Result<Psbt, String> {
+    let mut iter = psbts.into_iter();
+    let mut combined = iter.next().ok_or("No PSBTs to combine".to_string())?;
+    for psbt in iter {
+        combined
+            .combine(psbt)
+            .map_err(|e| format!("Failed to combine PSBTs: {}", e))?;
+    }
+    Ok(combined)
+}
+
+/// Outpoints (txid, vout) spent by a PSBT's inputs
+pub fn outpoints(psbt: &Psbt) -> Vec<(String, u32)> {
+    psbt.unsigned_tx
+        .input
+        .iter()
+        .map(|txin| {
+            (
+                txin.previous_output.txid.to_string(),
+                txin.previous_output.vout,
+            )
+        })
+        .collect()
+}
+
+/// Adds a co-signer's partial signature to an input
+pub fn add_partial_signature(
+    psbt: &mut Psbt,
+    input_index: usize,
+    signature: &PartialSignature,
+) -> Result<(), String> {
+    let input = psbt
+        .inputs
+        .get_mut(input_index)
+        .ok_or(format!("Input {} does not exist", input_index))?;
+
+    match signature {
+        PartialSignature::Ecdsa {
+            public_key,
+            signature,
+        } => {
+            let public_key = PublicKey::from_slice(public_key)
+                .map_err(|e| format!("Invalid public key: {}", e))?;
+            let signature = ecdsa::Signature::from_slice(signature)
+                .map_err(|e| format!("Invalid ECDSA signature: {}", e))?;
+            input.partial_sigs.insert(public_key, signature);
+        }
+        PartialSignature::TaprootKey { signature } => {
+            if input.tap_internal_key.is_none() {
+                return Err(format!(
+                    "Input {} is not a Taproot key path input",
+                    input_index
+                ));
+            }
+            input.tap_key_sig = Some(parse_taproot_signature(signature)?);
+        }
+        PartialSignature::TaprootScript {
+            public_key,
+            leaf_script,
+            signature,
+        } => {
+            let script = ScriptBuf::from_bytes(leaf_script.clone());
+            if !input.tap_scripts.values().any(|(s, _)| *s == script) {
+                return Err(format!(
+                    "Input {} does not commit to the given leaf script",
+                    input_index
+                ));
+            }
+            let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+            input.tap_script_sigs.insert(
+                (parse_xonly(public_key)?, leaf_hash),
+                parse_taproot_signature(signature)?,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_taproot_signature(bytes: &[u8]) -> Result<taproot::Signature, String> {
+    taproot::Signature::from_slice(bytes).map_err(|e| format!("Invalid Schnorr signature: {}", e))
+}
+
+/// Computes the BIP-341 sighash for a Taproot input
+///
+/// Pass `leaf_script` for script path spends; `None` computes the key path sighash.
+/// The returned 32-byte message is what `schnorr::sign_taproot_transaction` signs.
+pub fn taproot_sighash(
+    psbt: &Psbt,
+    input_index: usize,
+    leaf_script: Option<&[u8]>,
+) -> Result<[u8; 32], String> {
+    let prevouts = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            input
+                .witness_utxo
+                .clone()
+                .ok_or(format!("Input {} is missing its witness UTXO", i))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let sighash = match leaf_script {
+        None => cache
+            .taproot_key_spend_signature_hash(
+                input_index,
+                &Prevouts::All(&prevouts),
+                TapSighashType::Default,
+            )
+            .map_err(|e| format!("Failed to compute sighash: {}", e))?
+            .to_byte_array(),
+        Some(script) => {
+            let leaf_hash = TapLeafHash::from_script(
+                &ScriptBuf::from_bytes(script.to_vec()),
+                LeafVersion::TapScript,
+            );
+            cache
+                .taproot_script_spend_signature_hash(
+                    input_index,
+                    &Prevouts::All(&prevouts),
+                    leaf_hash,
+                    TapSighashType::Default,
+                )
+                .map_err(|e| format!("Failed to compute sighash: {}", e))?
+                .to_byte_array()
+        }
+    };
+
+    Ok(sighash)
+}
+
+/// Finalizes every input (BIP-174 Finalizer role)
+///
+/// Supports Taproot key path, Taproot script path and P2WPKH inputs. Inputs that
+/// are already finalized are left untouched.
+pub fn finalize(psbt: &mut Psbt) -> Result<(), String> {
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        if input.final_script_witness.is_some() {
+            continue;
+        }
+
+        let witness = if let Some(sig) = input.tap_key_sig {
+            Witness::p2tr_key_spend(&sig)
+        } else if let Some((control_block, (script, _))) = input.tap_scripts.iter().next() {
+            let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+            let keys = tapscript_keys(script);
+            if keys.is_empty() {
+                return Err(format!("Input {}: leaf script contains no keys", index));
+            }
+
+            // Script stack is consumed top-down, so signatures are pushed in reverse
+            // order of the keys appearing in the script.
+            let mut witness = Witness::new();
+            for key in keys.iter().rev() {
+                let sig = input
+                    .tap_script_sigs
+                    .get(&(*key, leaf_hash))
+                    .ok_or(format!(
+                        "Input {}: missing script signature for key {}",
+                        index, key
+                    ))?;
+                witness.push(sig.to_vec());
+            }
+            witness.push(script.as_bytes());
+            witness.push(control_block.serialize());
+            witness
+        } else if let Some((public_key, sig)) = input.partial_sigs.iter().next() {
+            let mut witness = Witness::new();
+            witness.push(sig.to_vec());
+            witness.push(public_key.to_bytes());
+            witness
+        } else {
+            return Err(format!("Input {} has no signatures to finalize", index));
+        };
+
+        input.final_script_witness = Some(witness);
+
+        // BIP-174: finalizer clears everything except UTXO and final fields
+        input.partial_sigs.clear();
+        input.sighash_type = None;
+        input.tap_key_sig = None;
+        input.tap_script_sigs.clear();
+        input.tap_scripts.clear();
+        input.tap_key_origins.clear();
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+    }
+
+    Ok(())
+}
+
+/// Extracts 32-byte x-only keys pushed in a tapscript, in script order
+fn tapscript_keys(script: &ScriptBuf) -> Vec<XOnlyPublicKey> {
+    script
+        .instructions()
+        .filter_map(|ins| ins.ok())
+        .filter_map(|ins| ins.push_bytes().map(|b| b.as_bytes().to_vec()))
+        .filter(|bytes| bytes.len() == 32)
+        .filter_map(|bytes| XOnlyPublicKey::from_slice(&bytes).ok())
+        .collect()
+}
+
+/// Extracts the network transaction from a fully finalized PSBT
+pub fn extract(psbt: Psbt) -> Result<FinalizedTransaction, String> {
+    if let Some(index) = psbt
+        .inputs
+        .iter()
+        .position(|input| input.final_script_witness.is_none() && input.final_script_sig.is_none())
+    {
+        return Err(format!("Input {} is not finalized", index));
+    }
+
+    let tx = psbt
+        .extract_tx()
+        .map_err(|e| format!("Failed to extract transaction: {}", e))?;
+
+    Ok(FinalizedTransaction {
+        txid: tx.compute_txid().to_string(),
+        tx_hex: hex::encode(consensus_serialize(&tx)),
+    })
+}
+
+// ============================================================================
+// BIP-370 (PSBT v2) conversion
+// ============================================================================
+
+/// Raw key-value pair of a PSBT map
+#[derive(Clone, Debug)]
+struct KeyValue {
+    key_type: u64,
+    key: Vec<u8>, // Full key including the type prefix
+    value: Vec<u8>,
+}
+
+type PsbtMaps = (Vec<KeyValue>, Vec<Vec<KeyValue>>, Vec<Vec<KeyValue>>);
+
+/// Converts a v0 PSBT to BIP-370 v2 bytes
+fn v0_to_v2(psbt: &Psbt) -> Result<Vec<u8>, String> {
+    let tx = &psbt.unsigned_tx;
+    let (global, inputs, outputs) =
+        split_maps(&psbt.serialize(), Some((tx.input.len(), tx.output.len())))?;
+
+    let mut v2_global: Vec<KeyValue> = global
+        .into_iter()
+        .filter(|kv| kv.key_type != PSBT_GLOBAL_UNSIGNED_TX && kv.key_type != PSBT_GLOBAL_VERSION)
+        .collect();
+    v2_global.push(kv(
+        PSBT_GLOBAL_TX_VERSION,
+        tx.version.0.to_le_bytes().to_vec(),
+    ));
+    v2_global.push(kv(
+        PSBT_GLOBAL_FALLBACK_LOCKTIME,
+        tx.lock_time.to_consensus_u32().to_le_bytes().to_vec(),
+    ));
+    v2_global.push(kv(
+        PSBT_GLOBAL_INPUT_COUNT,
+        compact_size(tx.input.len() as u64),
+    ));
+    v2_global.push(kv(
+        PSBT_GLOBAL_OUTPUT_COUNT,
+        compact_size(tx.output.len() as u64),
+    ));
+    v2_global.push(kv(PSBT_GLOBAL_VERSION, 2u32.to_le_bytes().to_vec()));
+
+    let v2_inputs = inputs.into_iter().zip(&tx.input).map(|(mut map, txin)| {
+        map.push(kv(
+            PSBT_IN_PREVIOUS_TXID,
+            txin.previous_output.txid.to_byte_array().to_vec(),
+        ));
+        map.push(kv(
+            PSBT_IN_OUTPUT_INDEX,
+            txin.previous_output.vout.to_le_bytes().to_vec(),
+        ));
+        map.push(kv(PSBT_IN_SEQUENCE, txin.sequence.0.to_le_bytes().to_vec()));
+        map
+    });
+
+    let v2_outputs = outputs.into_iter().zip(&tx.output).map(|(mut map, txout)| {
+        map.push(kv(
+            PSBT_OUT_AMOUNT,
+            (txout.value.to_sat() as i64).to_le_bytes().to_vec(),
+        ));
+        map.push(kv(PSBT_OUT_SCRIPT, txout.script_pubkey.to_bytes()));
+        map
+    });
+
+    let mut bytes = PSBT_MAGIC.to_vec();
+    write_map(&mut bytes, &v2_global);
+    v2_inputs.for_each(|map| write_map(&mut bytes, &map));
+    v2_outputs.for_each(|map| write_map(&mut bytes, &map));
+    Ok(bytes)
+}
+
+/// Converts BIP-370 v2 bytes to v0 bytes understood by the `bitcoin` crate
+fn v2_to_v0(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let (global, inputs, outputs) = split_maps(bytes, None)?;
+
+    let find = |map: &[KeyValue], key_type: u64| {
+        map.iter()
+            .find(|kv| kv.key_type == key_type)
+            .map(|kv| kv.value.clone())
+    };
+    let required = |map: &[KeyValue], key_type: u64, name: &str| {
+        find(map, key_type).ok_or(format!("PSBT v2 is missing {}", name))
+    };
+
+    let version = read_u32(&required(
+        &global,
+        PSBT_GLOBAL_TX_VERSION,
+        "PSBT_GLOBAL_TX_VERSION",
+    )?)
+    .ok_or("Invalid PSBT_GLOBAL_TX_VERSION".to_string())?;
+    let fallback_lock_time = find(&global, PSBT_GLOBAL_FALLBACK_LOCKTIME)
+        .map(|v| read_u32(&v).ok_or("Invalid PSBT_GLOBAL_FALLBACK_LOCKTIME".to_string()))
+        .transpose()?
+        .unwrap_or(0);
+
+    let mut tx_inputs = Vec::with_capacity(inputs.len());
+    let mut required_lock_time: Option<u32> = None;
+    for map in &inputs {
+        let txid_bytes = required(map, PSBT_IN_PREVIOUS_TXID, "PSBT_IN_PREVIOUS_TXID")?;
+        let txid =
+            Txid::from_slice(&txid_bytes).map_err(|e| format!("Invalid previous txid: {}", e))?;
+        let vout = read_u32(&required(
+            map,
+            PSBT_IN_OUTPUT_INDEX,
+            "PSBT_IN_OUTPUT_INDEX",
+        )?)
+        .ok_or("Invalid PSBT_IN_OUTPUT_INDEX".to_string())?;
+        let sequence = find(map, PSBT_IN_SEQUENCE)
+            .map(|v| read_u32(&v).ok_or("Invalid PSBT_IN_SEQUENCE".to_string()))
+            .transpose()?
+            .unwrap_or(Sequence::MAX.0);
+
+        // BIP-370: the lock time is the maximum of the inputs' required lock times
+        for key_type in [
+            PSBT_IN_REQUIRED_TIME_LOCKTIME,
+            PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+        ] {
+            if let Some(lock) = find(map, key_type).and_then(|v| read_u32(&v)) {
+                required_lock_time = Some(required_lock_time.map_or(lock, |cur| cur.max(lock)));
+            }
+        }
+
+        tx_inputs.push(TxIn {
+            previous_output: OutPoint::new(txid, vout),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(sequence),
+            witness: Witness::new(),
+        });
+    }
+
+    let mut tx_outputs = Vec::with_capacity(outputs.len());
+    for map in &outputs {
+        let amount = required(map, PSBT_OUT_AMOUNT, "PSBT_OUT_AMOUNT")?;
+        let amount: [u8; 8] = amount
+            .as_slice()
+            .try_into()
+            .map_err(|_| "Invalid PSBT_OUT_AMOUNT".to_string())?;
+        let script = required(map, PSBT_OUT_SCRIPT, "PSBT_OUT_SCRIPT")?;
+        tx_outputs.push(TxOut {
+            value: Amount::from_sat(i64::from_le_bytes(amount) as u64),
+            script_pubkey: ScriptBuf::from_bytes(script),
+        });
+    }
+
+    let unsigned_tx = Transaction {
+        version: transaction::Version(version as i32),
+        lock_time: absolute::LockTime::from_consensus(
+            required_lock_time.unwrap_or(fallback_lock_time),
+        ),
+        input: tx_inputs,
+        output: tx_outputs,
+    };
+
+    const V2_GLOBAL_KEYS: [u64; 6] = [
+        PSBT_GLOBAL_TX_VERSION,
+        PSBT_GLOBAL_FALLBACK_LOCKTIME,
+        PSBT_GLOBAL_INPUT_COUNT,
+        PSBT_GLOBAL_OUTPUT_COUNT,
+        PSBT_GLOBAL_TX_MODIFIABLE,
+        PSBT_GLOBAL_VERSION,
+    ];
+    const V2_INPUT_KEYS: [u64; 5] = [
+        PSBT_IN_PREVIOUS_TXID,
+        PSBT_IN_OUTPUT_INDEX,
+        PSBT_IN_SEQUENCE,
+        PSBT_IN_REQUIRED_TIME_LOCKTIME,
+        PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+    ];
+    const V2_OUTPUT_KEYS: [u64; 2] = [PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT];
+
+    let mut v0_global = vec![kv(
+        PSBT_GLOBAL_UNSIGNED_TX,
+        consensus_serialize(&unsigned_tx),
+    )];
+    v0_global.extend(
+        global
+            .into_iter()
+            .filter(|kv| !V2_GLOBAL_KEYS.contains(&kv.key_type)),
+    );
+
+    let mut out = PSBT_MAGIC.to_vec();
+    write_map(&mut out, &v0_global);
+    for map in inputs {
+        let map: Vec<KeyValue> = map
+            .into_iter()
+            .filter(|kv| !V2_INPUT_KEYS.contains(&kv.key_type))
+            .collect();
+        write_map(&mut out, &map);
+    }
+    for map in outputs {
+        let map: Vec<KeyValue> = map
+            .into_iter()
+            .filter(|kv| !V2_OUTPUT_KEYS.contains(&kv.key_type))
+            .collect();
+        write_map(&mut out, &map);
+    }
+    Ok(out)
+}
+
+fn kv(key_type: u64, value: Vec<u8>) -> KeyValue {
+    KeyValue {
+        key_type,
+        key: compact_size(key_type),
+        value,
+    }
+}
+
+/// Splits raw PSBT bytes into global, input and output maps
+///
+/// `counts` gives the number of input/output maps; when `None` they are read from the
+/// v2 count fields or the v0 unsigned transaction in the global map.
+fn split_maps(bytes: &[u8], counts: Option<(usize, usize)>) -> Result<PsbtMaps, String> {
+    if bytes.len() < PSBT_MAGIC.len() || bytes[..PSBT_MAGIC.len()] != PSBT_MAGIC {
+        return Err("Invalid PSBT: bad magic bytes".to_string());
+    }
+    let mut pos = PSBT_MAGIC.len();
+    let global = read_map(bytes, &mut pos)?;
+
+    let (input_count, output_count) = match counts {
+        Some(counts) => counts,
+        None => map_counts(&global)?,
+    };
+
+    let mut inputs = Vec::with_capacity(input_count);
+    for _ in 0..input_count {
+        inputs.push(read_map(bytes, &mut pos)?);
+    }
+    let mut outputs = Vec::with_capacity(output_count);
+    for _ in 0..output_count {
+        outputs.push(read_map(bytes, &mut pos)?);
+    }
+    Ok((global, inputs, outputs))
+}
+
+fn map_counts(global: &[KeyValue]) -> Result<(usize, usize), String> {
+    if let Some(tx) = global
+        .iter()
+        .find(|kv| kv.key_type == PSBT_GLOBAL_UNSIGNED_TX)
+    {
+        let tx: Transaction = bitcoin::consensus::deserialize(&tx.value)
+            .map_err(|e| format!("Invalid unsigned transaction: {}", e))?;
+        return Ok((tx.input.len(), tx.output.len()));
+    }
+
+    let count = |key_type: u64| -> Result<usize, String> {
+        let kv = global
+            .iter()
+            .find(|kv| kv.key_type == key_type)
+            .ok_or("PSBT has neither an unsigned transaction nor v2 counts".to_string())?;
+        let mut pos = 0;
+        Ok(read_compact_size(&kv.value, &mut pos)? as usize)
+    };
+    Ok((
+        count(PSBT_GLOBAL_INPUT_COUNT)?,
+        count(PSBT_GLOBAL_OUTPUT_COUNT)?,
+    ))
+}
+
+fn read_map(bytes: &[u8], pos: &mut usize) -> Result<Vec<KeyValue>, String> {
+    let mut map = Vec::new();
+    loop {
+        let key_len = read_compact_size(bytes, pos)? as usize;
+        if key_len == 0 {
+            return Ok(map);
+        }
+        let key = read_slice(bytes, pos, key_len)?.to_vec();
+        let key_type = read_compact_size(&key, &mut 0)?;
+        let value_len = read_compact_size(bytes, pos)? as usize;
+        let value = read_slice(bytes, pos, value_len)?.to_vec();
+        map.push(KeyValue {
+            key_type,
+            key,
+            value,
+        });
+    }
+}
+
+fn write_map(out: &mut Vec<u8>, map: &[KeyValue]) {
+    for kv in map {
+        out.extend(compact_size(kv.key.len() as u64));
+        out.extend(&kv.key);
+        out.extend(compact_size(kv.value.len() as u64));
+        out.extend(&kv.value);
+    }
+    out.push(0x00);
+}
+
+fn read_slice<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], String> {
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= bytes.len())
+        .ok_or("Invalid PSBT: unexpected end of data".to_string())?;
+    let slice = &bytes[*pos..end];
+    *pos = end;
+    Ok(slice)
+}
+
+fn read_compact_size(bytes: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let first = read_slice(bytes, pos, 1)?[0];
+    let width = match first {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Ok(n as u64),
+    };
+    let mut buf = [0u8; 8];
+    buf[..width].copy_from_slice(read_slice(bytes, pos, width)?);
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn compact_size(n: u64) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => [vec![0xfd], (n as u16).to_le_bytes().to_vec()].concat(),
+        0x10000..=0xffff_ffff => [vec![0xfe], (n as u32).to_le_bytes().to_vec()].concat(),
+        _ => [vec![0xff], n.to_le_bytes().to_vec()].concat(),
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    bytes.try_into().ok().map(u32::from_le_bytes)
+}
@@ -0,0 +1,345 @@
+// Loan Repayment Accounts
+// Every loan has its own ICRC-1 subaccount of the vault. Borrowers repay by
+// transferring the loan asset to it; the vault polls the subaccount balance,
+// sweeps the funds into its default (treasury) account and credits the loan,
+// so every payment is attributed to exactly one loan.
+// Transfers straight to the treasury are credited by ledger block instead: the
+// block must carry the loan ID as memo and is never credited twice.
+
+use crate::ckbtc::{self, nat_to_u64, Account, Transaction};
+use crate::helpers::{calculate_loan_value, is_loan_open, is_loan_repaid, loan_memo};
+use crate::state::State;
+use crate::types::{Loan, LoanAsset, LoanId, LoanStatus};
+use crate::{assets, collateral, pool, share_token};
+use candid::Principal;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// How often the subaccounts of active loans are polled
+pub const REPAYMENT_POLL_SECONDS: u64 = 300;
+
+/// Domain separator for loan subaccount derivation
+const SUBACCOUNT_DOMAIN: &[u8] = b"bitfold-loan-repayment";
+
+thread_local! {
+    static POLL_TIMER_STARTED: Cell<bool> = const { Cell::new(false) };
+    // Loans whose subaccount is being swept; a second poll would double-credit
+    static POLLS_IN_PROGRESS: RefCell<HashSet<LoanId>> = RefCell::new(HashSet::new());
+}
+
+/// How a subaccount balance is applied to a loan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepositSplit {
+    pub swept: u64,    // Moved to the treasury (balance minus the sweep fee)
+    pub credited: u64, // Applied to the loan
+    pub excess: u64,   // Overpayment returned to the borrower
+}
+
+/// Subaccount a loan is repaid into: SHA-256(domain || loan ID)
+pub fn loan_subaccount(loan_id: LoanId) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(SUBACCOUNT_DOMAIN);
+    hasher.update(loan_id.to_be_bytes());
+    let mut subaccount = [0u8; 32];
+    subaccount.copy_from_slice(&hasher.finalize());
+    subaccount
+}
+
+/// Account borrowers transfer repayments of `loan_id` to
+pub fn repayment_account(vault: Principal, loan_id: LoanId) -> Account {
+    Account {
+        owner: vault,
+        subaccount: Some(loan_subaccount(loan_id).to_vec()),
+    }
+}
+
+/// Splits a subaccount balance; the sweep fee is borne by the borrower
+/// Returns None when the balance does not cover the sweep fee
+pub fn split_deposit(balance: u64, fee: u64, remaining_debt: u64) -> Option<DepositSplit> {
+    if balance <= fee {
+        return None;
+    }
+    let swept = balance - fee;
+    let credited = swept.min(remaining_debt);
+    Some(DepositSplit {
+        swept,
+        credited,
+        excess: swept - credited,
+    })
+}
+
+/// Applies `amount` of received funds to an active loan, releasing its
+/// collateral once the debt is paid off
+pub fn credit_repayment(loan_id: LoanId, amount: u64) -> Result<(), String> {
+    State::with(|state| {
+        let loan = state
+            .loans
+            .get_mut(&loan_id)
+            .ok_or("Loan not found".to_string())?;
+        if !is_loan_open(loan) {
+            return Err("Loan is no longer open".to_string());
+        }
+        let remaining_debt = calculate_loan_value(loan)?;
+        if amount > remaining_debt {
+            return Err(format!(
+                "Amount {} exceeds remaining debt: {}",
+                amount, remaining_debt
+            ));
+        }
+
+        if pool::is_pool_loan(loan) {
+            let (principal, interest) = pool::repayment_split(loan, amount);
+            pool::repay(&mut state.lending_pool, principal, interest);
+        }
+        loan.repaid_amount += amount;
+
+        if is_loan_repaid(loan) {
+            loan.status = LoanStatus::Repaid;
+            let basket = collateral::basket(loan);
+            collateral::unlock_unused(state, &basket);
+            ic_cdk::println!("Loan {} fully repaid, unlocked UTXOs {:?}", loan_id, basket);
+        } else {
+            ic_cdk::println!(
+                "Partial repayment for loan {}: {} / {} base units",
+                loan_id,
+                loan.repaid_amount,
+                loan.borrowed_amount
+            );
+        }
+        Ok(())
+    })
+}
+
+/// Sweeps a loan's repayment subaccount and credits what it held
+/// Funds sent after the loan closed are returned to the borrower.
+/// Returns the amount credited to the loan.
+pub async fn poll_loan(loan_id: LoanId) -> Result<u64, String> {
+    if ledger_simulated() {
+        return Err("Repayment accounts need a real ledger; use repay instead".to_string());
+    }
+
+    let loan = State::with_read(|state| state.loans.get(&loan_id).cloned())
+        .ok_or("Loan not found".to_string())?;
+    let _poll = PollGuard::acquire(loan_id)?;
+
+    let ledger_id = assets::ledger_id(loan.asset)?;
+    let fee = assets::config(loan.asset).transfer_fee;
+    let vault = ic_cdk::api::canister_self();
+    let balance = ckbtc::balance_of_account(ledger_id, repayment_account(vault, loan_id)).await?;
+
+    // Re-read the debt: the loan may have been repaid while the balance was fetched
+    let remaining_debt = State::with_read(|state| {
+        state
+            .loans
+            .get(&loan_id)
+            .filter(|loan| is_loan_open(loan))
+            .map(calculate_loan_value)
+            .unwrap_or(Ok(0))
+    })?;
+    let Some(split) = split_deposit(balance, fee, remaining_debt) else {
+        return Ok(0);
+    };
+
+    let treasury = Account {
+        owner: vault,
+        subaccount: None,
+    };
+    let block_index = ckbtc::transfer_from_subaccount(
+        ledger_id,
+        loan_subaccount(loan_id),
+        treasury,
+        split.swept,
+        Some(loan_memo(loan_id)),
+    )
+    .await?;
+    ic_cdk::println!(
+        "Swept {} base units for loan {} into the treasury, block index: {}",
+        split.swept,
+        loan_id,
+        block_index
+    );
+
+    let mut refund = split.excess;
+    if split.credited > 0 {
+        if let Err(e) = credit_repayment(loan_id, split.credited) {
+            ic_cdk::println!("⚠️ Could not credit loan {}: {}", loan_id, e);
+            refund += split.credited;
+        }
+    }
+    let credited = split.swept - refund;
+
+    if refund > fee {
+        if let Err(e) = ckbtc::transfer(ledger_id, loan.user_id, refund - fee).await {
+            ic_cdk::println!(
+                "⚠️ Refund of {} base units for loan {} failed: {}",
+                refund - fee,
+                loan_id,
+                e
+            );
+        }
+    }
+    Ok(credited)
+}
+
+/// Checks that a ledger transaction repays `loan`: a transfer from the
+/// borrower to the vault's treasury account, made after the loan was opened,
+/// with the loan ID as memo
+/// Returns the amount transferred
+pub fn validate_repayment_transfer(
+    transaction: &Transaction,
+    loan: &Loan,
+    vault: Principal,
+) -> Result<u64, String> {
+    let transfer = transaction
+        .transfer
+        .as_ref()
+        .filter(|_| transaction.kind == "transfer")
+        .ok_or("Block is not a transfer".to_string())?;
+
+    if transfer.from.owner != loan.user_id {
+        return Err("Transfer is not from the borrower".to_string());
+    }
+    let treasury = Account {
+        owner: vault,
+        subaccount: None,
+    };
+    if share_token::normalize(&transfer.to).ok() != Some(treasury) {
+        return Err("Transfer is not to the vault's treasury account".to_string());
+    }
+    if transfer.memo.as_deref() != Some(loan_memo(loan.id).as_slice()) {
+        return Err(format!("Transfer memo does not reference loan {}", loan.id));
+    }
+    if transaction.timestamp < loan.created_at {
+        return Err("Transfer predates the loan".to_string());
+    }
+    nat_to_u64(&transfer.amount)
+}
+
+/// Marks a ledger block as credited; returns false if it already was
+pub fn consume_block(asset: LoanAsset, block_index: u64) -> bool {
+    State::with(|state| state.consumed_repayment_blocks.insert((asset, block_index)))
+}
+
+/// Whether a ledger block has already been credited to a loan
+pub fn is_block_consumed(asset: LoanAsset, block_index: u64) -> bool {
+    State::with_read(|state| {
+        state
+            .consumed_repayment_blocks
+            .contains(&(asset, block_index))
+    })
+}
+
+/// Credits the transfer in block `block_index` of the loan asset's ledger
+/// Anything above the remaining debt is returned to the borrower, minus the
+/// ledger fee. Returns the amount credited to the loan.
+pub async fn credit_block(loan_id: LoanId, block_index: u64) -> Result<u64, String> {
+    if ledger_simulated() {
+        return Err("Block repayments need a real ledger; use repay instead".to_string());
+    }
+
+    let loan = State::with_read(|state| state.loans.get(&loan_id).cloned())
+        .ok_or("Loan not found".to_string())?;
+    if is_block_consumed(loan.asset, block_index) {
+        return Err(format!("Block {} was already credited", block_index));
+    }
+
+    let ledger_id = assets::ledger_id(loan.asset)?;
+    let transaction = ckbtc::get_transaction(ledger_id, block_index)
+        .await?
+        .ok_or(format!("Block {} not found", block_index))?;
+    let amount = validate_repayment_transfer(&transaction, &loan, ic_cdk::api::canister_self())?;
+
+    // Consume and credit without awaiting in between, so a concurrent call
+    // for the same block is rejected here
+    if !consume_block(loan.asset, block_index) {
+        return Err(format!("Block {} was already credited", block_index));
+    }
+    let remaining_debt = State::with_read(|state| {
+        state
+            .loans
+            .get(&loan_id)
+            .filter(|loan| is_loan_open(loan))
+            .map(calculate_loan_value)
+            .unwrap_or(Ok(0))
+    })?;
+    let credited = amount.min(remaining_debt);
+    if credited > 0 {
+        credit_repayment(loan_id, credited)?;
+    }
+
+    let fee = assets::config(loan.asset).transfer_fee;
+    let excess = amount - credited;
+    if excess > fee {
+        if let Err(e) = ckbtc::transfer(ledger_id, loan.user_id, excess - fee).await {
+            ic_cdk::println!(
+                "⚠️ Refund of {} base units for loan {} failed: {}",
+                excess - fee,
+                loan_id,
+                e
+            );
+        }
+    }
+    Ok(credited)
+}
+
+/// Starts polling repayment subaccounts (once per canister instance)
+/// Local and playground deployments simulate ledger transfers, so nothing is polled there
+pub fn ensure_poll_timer() {
+    if ledger_simulated() || POLL_TIMER_STARTED.with(|started| started.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(REPAYMENT_POLL_SECONDS), poll_all);
+}
+
+/// Restarts polling after an upgrade if any loan is still open
+pub fn resume() {
+    let needed = State::with_read(|state| state.loans.values().any(is_loan_open));
+    if needed {
+        ensure_poll_timer();
+    }
+}
+
+async fn poll_all() {
+    let active: Vec<LoanId> = State::with_read(|state| {
+        state
+            .loans
+            .values()
+            .filter(|loan| is_loan_open(loan))
+            .map(|loan| loan.id)
+            .collect()
+    });
+    for loan_id in active {
+        if let Err(e) = poll_loan(loan_id).await {
+            ic_cdk::println!("⚠️ Repayment poll for loan {} failed: {}", loan_id, e);
+        }
+    }
+}
+
+fn ledger_simulated() -> bool {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    network == "local" || network == "playground"
+}
+
+/// Marks a loan's subaccount as being swept until dropped
+struct PollGuard(LoanId);
+
+impl PollGuard {
+    fn acquire(loan_id: LoanId) -> Result<Self, String> {
+        let acquired = POLLS_IN_PROGRESS.with(|polls| polls.borrow_mut().insert(loan_id));
+        if !acquired {
+            return Err(format!(
+                "Repayment check for loan {} already in progress",
+                loan_id
+            ));
+        }
+        Ok(PollGuard(loan_id))
+    }
+}
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        POLLS_IN_PROGRESS.with(|polls| polls.borrow_mut().remove(&self.0));
+    }
+}
@@ -0,0 +1,282 @@
+// Key Rotation
+// Moves a user's vetKD-protected data to a new key version. The version is bumped
+// immediately so new data keys are wrapped under it. Everything is encrypted by
+// the client, so the client works through the stored data in batches: what the
+// user owns is re-encrypted under a fresh data key wrapped for every reader, and
+// on data others own the user re-wraps their own copy of the key. Items the
+// client fails on are recorded and, unless the error is transient, skipped so the
+// rotation still finishes. Older key versions can be derived only until the
+// rotation finishes, except those skipped items are still wrapped under.
+
+use crate::helpers::get_timestamp;
+use crate::state::State;
+use crate::types::{KeyRotationStatus, RotationFailure, RotationItem, RotationTask, UserKeyState};
+use crate::vetkeys;
+use candid::Principal;
+
+/// Items handed to the client per batch
+pub const ROTATION_BATCH_SIZE: usize = 20;
+
+/// Failed attempts after which an item is skipped even on transient errors
+pub const MAX_ROTATION_ATTEMPTS: u32 = 5;
+
+/// Errors that come from data changing while the client re-keyed it, or from a
+/// failed vetKD call, and may succeed on a retry
+const TRANSIENT_ERRORS: [&str; 6] = [
+    "Missing wrapped key for",        // A reader was added
+    "has no access",                  // A reader was removed
+    "Wrapped key is for key version", // A reader rotated their own keys
+    "Expected re-keyed chunk",        // A document re-key was interrupted
+    "is still uploading",             // A document upload is unfinished
+    "vetkd_derive_key failed",
+];
+
+/// Whether a wrapped key was made under a version older than `current_version`
+pub fn needs_rotation(wrapped_key: &[u8], current_version: u32) -> bool {
+    vetkeys::ciphertext_key_version(wrapped_key)
+        .map(|version| version < current_version)
+        .unwrap_or(false)
+}
+
+/// Bumps the user's key version and starts the rotation of their stored data
+pub fn start(user: Principal) -> Result<u32, String> {
+    let now = get_timestamp();
+    let version = State::with(|state| {
+        let keys = state.user_key_versions.entry(user).or_insert(UserKeyState {
+            current_version: vetkeys::INITIAL_KEY_VERSION,
+            rotation_started_at: None,
+            last_rotated_at: None,
+            failures: None,
+        });
+        if keys.rotation_started_at.is_some() {
+            return Err("A key rotation is already in progress".to_string());
+        }
+
+        // Items skipped last time are retried under the new version
+        keys.failures = None;
+
+        keys.current_version = keys
+            .current_version
+            .checked_add(1)
+            .ok_or("Key version overflow".to_string())?;
+        keys.rotation_started_at = Some(now);
+        Ok(keys.current_version)
+    })?;
+
+    ic_cdk::println!("🔑 Rotating keys for {} to version {}", user, version);
+    settle(user);
+    Ok(version)
+}
+
+/// Current key version and rotation progress for a user
+pub fn status(user: Principal) -> KeyRotationStatus {
+    State::with_read(|state| {
+        let keys = state.user_key_versions.get(&user);
+        let current_version = keys
+            .map(|keys| keys.current_version)
+            .unwrap_or(vetkeys::INITIAL_KEY_VERSION);
+
+        KeyRotationStatus {
+            current_version,
+            in_progress: keys.and_then(|keys| keys.rotation_started_at).is_some(),
+            remaining: pending_items(state, user, current_version).len() as u32,
+            rotation_started_at: keys.and_then(|keys| keys.rotation_started_at),
+            last_rotated_at: keys.and_then(|keys| keys.last_rotated_at),
+            failures: keys
+                .and_then(|keys| keys.failures.clone())
+                .unwrap_or_default(),
+        }
+    })
+}
+
+/// Next items the client has to re-key for the user's running rotation
+pub(crate) fn next_batch(user: Principal) -> Vec<RotationTask> {
+    let current = vetkeys::current_key_version(user);
+    State::with_read(|state| {
+        pending_items(state, user, current)
+            .into_iter()
+            .take(ROTATION_BATCH_SIZE)
+            .map(|(item, wrapped_key)| {
+                // Owners get the readers to wrap a fresh data key for
+                let readers: Option<Vec<Principal>> = match &item {
+                    RotationItem::Note(note_id) => state
+                        .notes
+                        .get(note_id)
+                        .filter(|note| note.owner == user)
+                        .map(|note| note.wrapped_keys.keys().copied().collect()),
+                    RotationItem::LoanDocuments(loan_id) => state
+                        .loans
+                        .get(loan_id)
+                        .filter(|loan| loan.user_id == user)
+                        .and_then(|_| state.loan_document_grants.get(loan_id))
+                        .map(|grants| grants.keys().copied().collect()),
+                };
+                RotationTask {
+                    item,
+                    wrapped_key,
+                    owner: readers.is_some(),
+                    readers: sorted_readers(state, readers.unwrap_or_default()),
+                }
+            })
+            .collect()
+    })
+}
+
+/// Whether an error reported for a rotation item may succeed on a retry
+pub fn is_transient(error: &str) -> bool {
+    TRANSIENT_ERRORS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+/// Whether an item that failed `attempts` times is skipped rather than retried
+pub fn should_skip(error: &str, attempts: u32) -> bool {
+    !is_transient(error) || attempts >= MAX_ROTATION_ATTEMPTS
+}
+
+/// Records that the client failed to move an item of the user's running rotation
+/// The item is skipped on a permanent error or after repeated transient ones;
+/// otherwise it stays pending and comes back in the next batch
+pub(crate) fn record_failure(
+    user: Principal,
+    item: RotationItem,
+    error: String,
+) -> Result<RotationFailure, String> {
+    let now = get_timestamp();
+    let failure = State::with(|state| {
+        let current = state
+            .user_key_versions
+            .get(&user)
+            .filter(|keys| keys.rotation_started_at.is_some())
+            .map(|keys| keys.current_version)
+            .ok_or("No key rotation is in progress".to_string())?;
+        let (_, wrapped_key) = pending_items(state, user, current)
+            .into_iter()
+            .find(|(pending, _)| *pending == item)
+            .ok_or("Item is not pending in this rotation".to_string())?;
+        let key_version = vetkeys::ciphertext_key_version(&wrapped_key)?;
+
+        let failures = state
+            .user_key_versions
+            .get_mut(&user)
+            .map(|keys| keys.failures.get_or_insert_with(Vec::new))
+            .ok_or("No key rotation is in progress".to_string())?;
+        let attempts = failures
+            .iter()
+            .find(|failure| failure.item == item)
+            .map_or(0, |failure| failure.attempts)
+            + 1;
+        failures.retain(|failure| failure.item != item);
+        let failure = RotationFailure {
+            item,
+            key_version,
+            skipped: should_skip(&error, attempts),
+            error,
+            attempts,
+            failed_at: now,
+        };
+        failures.push(failure.clone());
+        Ok::<RotationFailure, String>(failure)
+    })?;
+
+    if failure.skipped {
+        ic_cdk::println!(
+            "⚠️ Key rotation for {} skipped {:?}: {}",
+            user,
+            failure.item,
+            failure.error
+        );
+        settle(user);
+    }
+    Ok(failure)
+}
+
+/// Finishes the user's rotation once none of their data is left under an older
+/// key version, apart from skipped items; from then on older versions can no
+/// longer be derived
+pub(crate) fn settle(user: Principal) {
+    let now = get_timestamp();
+    let finished = State::with(|state| {
+        let Some(keys) = state.user_key_versions.get(&user) else {
+            return false;
+        };
+        if keys.rotation_started_at.is_none()
+            || !pending_items(state, user, keys.current_version).is_empty()
+        {
+            return false;
+        }
+        if let Some(keys) = state.user_key_versions.get_mut(&user) {
+            keys.rotation_started_at = None;
+            keys.last_rotated_at = Some(now);
+        }
+        true
+    });
+    if finished {
+        ic_cdk::println!("✅ Key rotation finished for {}", user);
+    }
+}
+
+/// Readers with their current key versions, in a stable order
+fn sorted_readers(state: &State, mut readers: Vec<Principal>) -> Vec<(Principal, u32)> {
+    readers.sort();
+    readers
+        .into_iter()
+        .map(|reader| {
+            let version = state
+                .user_key_versions
+                .get(&reader)
+                .map(|keys| keys.current_version)
+                .unwrap_or(vetkeys::INITIAL_KEY_VERSION);
+            (reader, version)
+        })
+        .collect()
+}
+
+/// Wrapped keys of `user` still under a version older than `current_version`,
+/// without the items the rotation skipped
+fn pending_items(
+    state: &State,
+    user: Principal,
+    current_version: u32,
+) -> Vec<(RotationItem, Vec<u8>)> {
+    let skipped: Vec<&RotationItem> = state
+        .user_key_versions
+        .get(&user)
+        .and_then(|keys| keys.failures.as_ref())
+        .map(|failures| {
+            failures
+                .iter()
+                .filter(|failure| failure.skipped)
+                .map(|failure| &failure.item)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let note_keys = state.notes.values().filter_map(|note| {
+        note.wrapped_keys
+            .get(&user)
+            .filter(|wrapped| needs_rotation(wrapped, current_version))
+            .map(|wrapped| (RotationItem::Note(note.id), wrapped.clone()))
+    });
+    let document_keys = state
+        .loan_document_grants
+        .iter()
+        .filter_map(|(loan_id, grants)| {
+            grants
+                .get(&user)
+                .filter(|grant| needs_rotation(&grant.wrapped_key, current_version))
+                .map(|grant| {
+                    (
+                        RotationItem::LoanDocuments(*loan_id),
+                        grant.wrapped_key.clone(),
+                    )
+                })
+        });
+
+    let mut items: Vec<(RotationItem, Vec<u8>)> = note_keys
+        .chain(document_keys)
+        .filter(|(item, _)| !skipped.contains(&item))
+        .collect();
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    items
+}
@@ -0,0 +1,478 @@
+// Pool Share Token
+// ICRC-1/ICRC-2 ledger over the lending pool's share balances, so supplier
+// positions can be transferred and spent by approved accounts. Shares are
+// minted by supply_liquidity and burned by withdraw_liquidity; the vault
+// canister is the minting account. Each share is redeemable for
+// pool::exchange_rate satoshis, which grows as borrowers repay interest.
+
+use crate::ckbtc::{
+    nat_to_u64, Account, TransferArgs, TransferError, TransferFromArgs, TransferFromError,
+};
+use crate::pool;
+use crate::types::{LendingPool, ShareAllowance};
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use serde::Serialize;
+
+pub const TOKEN_NAME: &str = "BitFold ckBTC Pool Share";
+pub const TOKEN_SYMBOL: &str = "bfckBTC";
+pub const TOKEN_DECIMALS: u8 = 8;
+
+/// Fee in shares, burned from the payer on every transfer and approval
+pub const TRANSFER_FEE: u64 = 10;
+
+/// Transactions carrying `created_at_time` are deduplicated within this window
+pub const TX_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Clock skew tolerated between callers and the canister
+pub const PERMITTED_DRIFT_NANOS: u64 = 2 * 60 * 1_000_000_000;
+
+/// Longest memo accepted, in bytes
+pub const MAX_MEMO_BYTES: usize = 32;
+
+/// ICRC-1 metadata value
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Nat(Nat),
+    Int(Int),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// ICRC-1 supported standard
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct StandardRecord {
+    pub name: String,
+    pub url: String,
+}
+
+/// ICRC-2 Approve arguments
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ApproveArgs {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub spender: Account,
+    pub amount: Nat,
+    pub expected_allowance: Option<Nat>,
+    pub expires_at: Option<u64>,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+/// ICRC-2 Approve error
+#[derive(CandidType, Deserialize, Debug)]
+pub enum ApproveError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    AllowanceChanged { current_allowance: Nat },
+    Expired { ledger_time: u64 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+/// ICRC-2 Allowance arguments
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct AllowanceArgs {
+    pub account: Account,
+    pub spender: Account,
+}
+
+/// ICRC-2 Allowance
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Allowance {
+    pub allowance: Nat,
+    pub expires_at: Option<u64>,
+}
+
+/// Reasons shared by transfer, approve and transfer_from rejections
+enum Rejection {
+    BadFee,
+    InsufficientFunds(u64),
+    TooOld,
+    CreatedInFuture(u64),
+    Duplicate(u64),
+    Generic(String),
+}
+
+impl From<Rejection> for TransferError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::BadFee => TransferError::BadFee {
+                expected_fee: Nat::from(TRANSFER_FEE),
+            },
+            Rejection::InsufficientFunds(balance) => TransferError::InsufficientFunds {
+                balance: Nat::from(balance),
+            },
+            Rejection::TooOld => TransferError::TooOld,
+            Rejection::CreatedInFuture(ledger_time) => {
+                TransferError::CreatedInFuture { ledger_time }
+            }
+            Rejection::Duplicate(block) => TransferError::Duplicate {
+                duplicate_of: Nat::from(block),
+            },
+            Rejection::Generic(message) => TransferError::GenericError {
+                error_code: Nat::from(0u64),
+                message,
+            },
+        }
+    }
+}
+
+impl From<Rejection> for TransferFromError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::BadFee => TransferFromError::BadFee {
+                expected_fee: Nat::from(TRANSFER_FEE),
+            },
+            Rejection::InsufficientFunds(balance) => TransferFromError::InsufficientFunds {
+                balance: Nat::from(balance),
+            },
+            Rejection::TooOld => TransferFromError::TooOld,
+            Rejection::CreatedInFuture(ledger_time) => {
+                TransferFromError::CreatedInFuture { ledger_time }
+            }
+            Rejection::Duplicate(block) => TransferFromError::Duplicate {
+                duplicate_of: Nat::from(block),
+            },
+            Rejection::Generic(message) => TransferFromError::GenericError {
+                error_code: Nat::from(0u64),
+                message,
+            },
+        }
+    }
+}
+
+impl From<Rejection> for ApproveError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::BadFee => ApproveError::BadFee {
+                expected_fee: Nat::from(TRANSFER_FEE),
+            },
+            Rejection::InsufficientFunds(balance) => ApproveError::InsufficientFunds {
+                balance: Nat::from(balance),
+            },
+            Rejection::TooOld => ApproveError::TooOld,
+            Rejection::CreatedInFuture(ledger_time) => {
+                ApproveError::CreatedInFuture { ledger_time }
+            }
+            Rejection::Duplicate(block) => ApproveError::Duplicate {
+                duplicate_of: Nat::from(block),
+            },
+            Rejection::Generic(message) => ApproveError::GenericError {
+                error_code: Nat::from(0u64),
+                message,
+            },
+        }
+    }
+}
+
+/// Builds an account, treating the all-zero subaccount as the default one
+pub fn account(owner: Principal, subaccount: Option<Vec<u8>>) -> Result<Account, String> {
+    normalize(&Account { owner, subaccount })
+}
+
+/// Canonical form of an account: subaccounts are 32 bytes and the all-zero
+/// subaccount is stored as `None`
+pub fn normalize(account: &Account) -> Result<Account, String> {
+    match &account.subaccount {
+        Some(subaccount) if subaccount.len() != 32 => {
+            Err("Subaccount must be 32 bytes".to_string())
+        }
+        Some(subaccount) if subaccount.iter().all(|byte| *byte == 0) => Ok(Account {
+            owner: account.owner,
+            subaccount: None,
+        }),
+        _ => Ok(account.clone()),
+    }
+}
+
+/// Share balance of an account
+pub fn balance_of(pool: &LendingPool, account: &Account) -> u64 {
+    normalize(account)
+        .map(|account| pool.shares.get(&account).copied().unwrap_or(0))
+        .unwrap_or(0)
+}
+
+/// ICRC-1 metadata, including the current exchange rate to ckBTC
+pub fn metadata(pool: &LendingPool) -> Vec<(String, MetadataValue)> {
+    vec![
+        (
+            "icrc1:name".to_string(),
+            MetadataValue::Text(TOKEN_NAME.to_string()),
+        ),
+        (
+            "icrc1:symbol".to_string(),
+            MetadataValue::Text(TOKEN_SYMBOL.to_string()),
+        ),
+        (
+            "icrc1:decimals".to_string(),
+            MetadataValue::Nat(Nat::from(TOKEN_DECIMALS)),
+        ),
+        (
+            "icrc1:fee".to_string(),
+            MetadataValue::Nat(Nat::from(TRANSFER_FEE)),
+        ),
+        (
+            "bitfold:exchange_rate".to_string(),
+            MetadataValue::Nat(Nat::from(pool::exchange_rate(pool))),
+        ),
+        (
+            "bitfold:exchange_rate_scale".to_string(),
+            MetadataValue::Nat(Nat::from(pool::EXCHANGE_RATE_SCALE)),
+        ),
+    ]
+}
+
+/// Standards implemented by the share token
+pub fn supported_standards() -> Vec<StandardRecord> {
+    ["ICRC-1", "ICRC-2"]
+        .iter()
+        .map(|name| StandardRecord {
+            name: name.to_string(),
+            url: format!(
+                "https://github.com/dfinity/ICRC-1/tree/main/standards/{}",
+                name
+            ),
+        })
+        .collect()
+}
+
+/// ICRC-1 transfer from the caller's account
+pub fn transfer(
+    pool: &mut LendingPool,
+    caller: Principal,
+    args: TransferArgs,
+    now: u64,
+) -> Result<Nat, TransferError> {
+    let from = account(caller, args.from_subaccount.clone()).map_err(Rejection::Generic)?;
+    let to = normalize(&args.to).map_err(Rejection::Generic)?;
+    let amount = parse_amount(&args.amount)?;
+    check_fee(&args.fee)?;
+    check_memo(&args.memo)?;
+
+    let dedup_key = format!("{:?}{:?}", from, args);
+    check_time(pool, &dedup_key, args.created_at_time, now)?;
+
+    move_shares(pool, &from, &to, amount)?;
+    Ok(Nat::from(record_tx(pool, dedup_key, args.created_at_time)))
+}
+
+/// ICRC-2 approval of `spender` to spend from the caller's account
+/// The fee is burned from the caller even when the allowance is lowered
+pub fn approve(
+    pool: &mut LendingPool,
+    caller: Principal,
+    args: ApproveArgs,
+    now: u64,
+) -> Result<Nat, ApproveError> {
+    let from = account(caller, args.from_subaccount.clone()).map_err(Rejection::Generic)?;
+    let spender = normalize(&args.spender).map_err(Rejection::Generic)?;
+    if from.owner == spender.owner {
+        return Err(
+            Rejection::Generic("Cannot approve the caller's own principal".to_string()).into(),
+        );
+    }
+    check_fee(&args.fee)?;
+    check_memo(&args.memo)?;
+    if let Some(expires_at) = args.expires_at {
+        if expires_at <= now {
+            return Err(ApproveError::Expired { ledger_time: now });
+        }
+    }
+
+    let key = (from.clone(), spender.clone());
+    if let Some(expected) = &args.expected_allowance {
+        let current = current_allowance(pool, &key, now);
+        if nat_to_u64(expected).ok() != Some(current) {
+            return Err(ApproveError::AllowanceChanged {
+                current_allowance: Nat::from(current),
+            });
+        }
+    }
+
+    let dedup_key = format!("{:?}{:?}", from, args);
+    check_time(pool, &dedup_key, args.created_at_time, now)?;
+
+    let balance = pool.shares.get(&from).copied().unwrap_or(0);
+    if balance < TRANSFER_FEE {
+        return Err(Rejection::InsufficientFunds(balance).into());
+    }
+    debit(pool, &from, TRANSFER_FEE);
+    pool.total_shares -= TRANSFER_FEE;
+
+    // Allowances above u64::MAX cannot be spent anyway
+    let amount = nat_to_u64(&args.amount).unwrap_or(u64::MAX);
+    if amount == 0 {
+        pool.allowances.remove(&key);
+    } else {
+        pool.allowances.insert(
+            key,
+            ShareAllowance {
+                amount,
+                expires_at: args.expires_at,
+            },
+        );
+    }
+    Ok(Nat::from(record_tx(pool, dedup_key, args.created_at_time)))
+}
+
+/// ICRC-2 allowance of `spender` on `account`
+pub fn allowance(pool: &LendingPool, args: &AllowanceArgs, now: u64) -> Allowance {
+    let key = match (normalize(&args.account), normalize(&args.spender)) {
+        (Ok(account), Ok(spender)) => (account, spender),
+        _ => {
+            return Allowance {
+                allowance: Nat::from(0u64),
+                expires_at: None,
+            }
+        }
+    };
+
+    let amount = current_allowance(pool, &key, now);
+    Allowance {
+        allowance: Nat::from(amount),
+        expires_at: pool
+            .allowances
+            .get(&key)
+            .filter(|_| amount > 0)
+            .and_then(|allowance| allowance.expires_at),
+    }
+}
+
+/// ICRC-2 transfer by the caller out of an account that approved it
+/// Spends `amount` plus the fee from the allowance
+pub fn transfer_from(
+    pool: &mut LendingPool,
+    caller: Principal,
+    args: TransferFromArgs,
+    now: u64,
+) -> Result<Nat, TransferFromError> {
+    let spender = account(caller, args.spender_subaccount.clone()).map_err(Rejection::Generic)?;
+    let from = normalize(&args.from).map_err(Rejection::Generic)?;
+    let to = normalize(&args.to).map_err(Rejection::Generic)?;
+    let amount = parse_amount(&args.amount)?;
+    check_fee(&args.fee)?;
+    check_memo(&args.memo)?;
+
+    let key = (from.clone(), spender.clone());
+    let available = current_allowance(pool, &key, now);
+    let spend = amount.saturating_add(TRANSFER_FEE);
+    if available < spend {
+        return Err(TransferFromError::InsufficientAllowance {
+            allowance: Nat::from(available),
+        });
+    }
+
+    let dedup_key = format!("{:?}{:?}", spender, args);
+    check_time(pool, &dedup_key, args.created_at_time, now)?;
+
+    move_shares(pool, &from, &to, amount)?;
+    if available == spend {
+        pool.allowances.remove(&key);
+    } else if let Some(allowance) = pool.allowances.get_mut(&key) {
+        allowance.amount = available - spend;
+    }
+    Ok(Nat::from(record_tx(pool, dedup_key, args.created_at_time)))
+}
+
+fn parse_amount(amount: &Nat) -> Result<u64, Rejection> {
+    nat_to_u64(amount).map_err(Rejection::Generic)
+}
+
+fn check_fee(fee: &Option<Nat>) -> Result<(), Rejection> {
+    match fee {
+        Some(fee) if nat_to_u64(fee).ok() != Some(TRANSFER_FEE) => Err(Rejection::BadFee),
+        _ => Ok(()),
+    }
+}
+
+fn check_memo(memo: &Option<Vec<u8>>) -> Result<(), Rejection> {
+    match memo {
+        Some(memo) if memo.len() > MAX_MEMO_BYTES => Err(Rejection::Generic(format!(
+            "Memo longer than {} bytes",
+            MAX_MEMO_BYTES
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects stale, future-dated and duplicate transactions
+/// Transactions without `created_at_time` are never deduplicated
+fn check_time(
+    pool: &mut LendingPool,
+    dedup_key: &str,
+    created_at_time: Option<u64>,
+    now: u64,
+) -> Result<(), Rejection> {
+    let horizon = now.saturating_sub(TX_WINDOW_NANOS + PERMITTED_DRIFT_NANOS);
+    pool.recent_share_txs
+        .retain(|_, (created_at, _)| *created_at >= horizon);
+
+    let Some(created_at_time) = created_at_time else {
+        return Ok(());
+    };
+    if created_at_time < horizon {
+        return Err(Rejection::TooOld);
+    }
+    if created_at_time > now.saturating_add(PERMITTED_DRIFT_NANOS) {
+        return Err(Rejection::CreatedInFuture(now));
+    }
+    if let Some((_, block)) = pool.recent_share_txs.get(dedup_key) {
+        return Err(Rejection::Duplicate(*block));
+    }
+    Ok(())
+}
+
+fn record_tx(pool: &mut LendingPool, dedup_key: String, created_at_time: Option<u64>) -> u64 {
+    let block = pool.share_tx_count;
+    pool.share_tx_count += 1;
+    if let Some(created_at_time) = created_at_time {
+        pool.recent_share_txs
+            .insert(dedup_key, (created_at_time, block));
+    }
+    block
+}
+
+fn current_allowance(pool: &LendingPool, key: &(Account, Account), now: u64) -> u64 {
+    match pool.allowances.get(key) {
+        Some(allowance)
+            if allowance
+                .expires_at
+                .is_none_or(|expires_at| expires_at > now) =>
+        {
+            allowance.amount
+        }
+        _ => 0,
+    }
+}
+
+/// Moves `amount` shares and burns the fee from the sender
+fn move_shares(
+    pool: &mut LendingPool,
+    from: &Account,
+    to: &Account,
+    amount: u64,
+) -> Result<(), Rejection> {
+    let balance = pool.shares.get(from).copied().unwrap_or(0);
+    if balance < amount.saturating_add(TRANSFER_FEE) {
+        return Err(Rejection::InsufficientFunds(balance));
+    }
+
+    debit(pool, from, amount + TRANSFER_FEE);
+    pool.total_shares -= TRANSFER_FEE;
+    if amount > 0 {
+        *pool.shares.entry(to.clone()).or_insert(0) += amount;
+    }
+    Ok(())
+}
+
+fn debit(pool: &mut LendingPool, account: &Account, amount: u64) {
+    if let Some(balance) = pool.shares.get_mut(account) {
+        *balance -= amount;
+        if *balance == 0 {
+            pool.shares.remove(account);
+        }
+    }
+}
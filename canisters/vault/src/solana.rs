@@ -2,37 +2,177 @@
 // Enables cross-chain functionality with Solana blockchain
 
 use candid::{CandidType, Deserialize, Principal};
-use serde::{Deserialize as SerdeDeserialize, Serialize};
-use ic_cdk::api::management_canister::http_request::{
-    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
+use serde::Serialize;
+use ic_cdk::management_canister::{
+    http_request, schnorr_public_key, sign_with_schnorr, transform_context_from_query,
+    HttpHeader, HttpMethod, HttpRequestArgs, HttpRequestResult, SchnorrAlgorithm, SchnorrKeyId,
+    SchnorrPublicKeyArgs, SignWithSchnorrArgs, TransformArgs,
 };
-use hex;
+use crate::solana_tx::{
+    associated_token_address, create_associated_token_account_idempotent, serialize_transaction,
+    spl_transfer_checked, system_transfer, Instruction, Message,
+};
+use crate::state::State;
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use ed25519_dalek::{Signer, SigningKey};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 
 /// Solana RPC Canister ID
 /// This canister provides Solana RPC functionality on ICP
 const SOLANA_RPC_CANISTER_ID: &str = "qhbym-qaaaa-aaaaa-aaafq-cai"; // Example - update with actual canister ID
 
 /// Solana network configuration
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum SolanaNetwork {
     Mainnet,
     Testnet,
     Devnet,
 }
 
+/// Lamports per SOL
+pub const SOL_DECIMALS: u32 = 9;
+
+/// Base units per USDC
+pub const USDC_DECIMALS: u32 = 6;
+
+impl SolanaNetwork {
+    /// Circle's USDC mint on this cluster
+    pub fn usdc_mint(&self) -> Result<SolanaAddress, String> {
+        match self {
+            SolanaNetwork::Mainnet => {
+                SolanaAddress::parse("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+            }
+            SolanaNetwork::Devnet => {
+                SolanaAddress::parse("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU")
+            }
+            SolanaNetwork::Testnet => Err("USDC is not issued on Solana testnet".to_string()),
+        }
+    }
+}
+
+/// Base58-encoded Solana public key: a wallet, token account, mint or program
+/// Encoded as `text` in Candid; decoding rejects anything that is not 32 bytes
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct SolanaAddress(String);
+
+impl SolanaAddress {
+    pub const LEN: usize = 32;
+
+    /// Parses and validates a base58 address
+    pub fn parse(address: &str) -> Result<Self, String> {
+        decode_base58::<{ Self::LEN }>(address, "Solana address")?;
+        Ok(Self(address.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self(bs58::encode(bytes).into_string())
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        decode_base58::<{ Self::LEN }>(&self.0, "Solana address")
+            .expect("SolanaAddress is validated on construction")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Base58-encoded Ed25519 transaction signature (64 bytes)
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct SolanaSignature(String);
+
+impl SolanaSignature {
+    pub const LEN: usize = 64;
+
+    /// Parses and validates a base58 signature
+    pub fn parse(signature: &str) -> Result<Self, String> {
+        decode_base58::<{ Self::LEN }>(signature, "Solana signature")?;
+        Ok(Self(signature.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self(bs58::encode(bytes).into_string())
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        decode_base58::<{ Self::LEN }>(&self.0, "Solana signature")
+            .expect("SolanaSignature is validated on construction")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+macro_rules! impl_base58_string {
+    ($name:ident) => {
+        impl TryFrom<String> for $name {
+            type Error = String;
+
+            fn try_from(value: String) -> Result<Self, String> {
+                Self::parse(&value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> String {
+                value.0
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = String;
+
+            fn from_str(value: &str) -> Result<Self, String> {
+                Self::parse(value)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+    };
+}
+
+impl_base58_string!(SolanaAddress);
+impl_base58_string!(SolanaSignature);
+
+/// Decodes base58 into exactly `N` bytes
+fn decode_base58<const N: usize>(value: &str, what: &str) -> Result<[u8; N], String> {
+    let bytes = bs58::decode(value)
+        .into_vec()
+        .map_err(|e| format!("Invalid {}: {}", what, e))?;
+    <[u8; N]>::try_from(bytes.as_slice()).map_err(|_| {
+        format!(
+            "Invalid {}: expected {} bytes, got {}",
+            what,
+            N,
+            bytes.len()
+        )
+    })
+}
+
 /// Solana account information
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct SolanaAccount {
-    pub address: String,        // Base58 encoded address
-    pub balance: u64,           // Balance in lamports
-    pub owner: Option<String>,  // Program owner
+    pub address: SolanaAddress,
+    pub balance: u64,                 // Balance in lamports
+    pub owner: Option<SolanaAddress>, // Program owner
 }
 
 /// Solana transaction request
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct SolanaTransactionRequest {
-    pub from: String,           // Source address
-    pub to: String,             // Destination address
+    pub to: SolanaAddress,      // Destination address
     pub amount: u64,            // Amount in lamports
     pub network: SolanaNetwork,
 }
@@ -40,95 +180,922 @@ pub struct SolanaTransactionRequest {
 /// Solana transaction response
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct SolanaTransactionResponse {
-    pub signature: String,      // Transaction signature
-    pub slot: u64,              // Confirmed slot
-    pub success: bool,          // Transaction success status
+    pub signature: SolanaSignature,          // Transaction signature
+    pub slot: u64,                           // Slot it landed in (0 if not seen yet)
+    pub success: bool,                                 // Confirmed without error
+    pub confirmation_status: Option<SolanaCommitment>, // Level reached so far
+    pub error: Option<String>,                         // Why it failed or is still unconfirmed
 }
 
-/// Gets Solana account balance
-pub async fn get_solana_balance(
-    address: &str,
-    network: SolanaNetwork,
-) -> Result<u64, String> {
-    ic_cdk::println!("🔍 Querying Solana balance for address: {}", address);
-    
-    // Use Solana RPC canister to get balance
-    // For now, we'll use HTTP outcalls to public Solana RPC endpoints
-    
-    let rpc_url = match network {
+/// Commitment level of a transaction, from least to most final
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SolanaCommitment {
+    Processed, // Seen by the node, may still be rolled back
+    Confirmed, // Voted on by a supermajority of the cluster
+    Finalized, // Rooted; cannot be rolled back
+}
+
+impl SolanaCommitment {
+    /// Parses a `confirmationStatus` value
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "processed" => Some(Self::Processed),
+            "confirmed" => Some(Self::Confirmed),
+            "finalized" => Some(Self::Finalized),
+            _ => None,
+        }
+    }
+}
+
+/// Status of a submitted transaction (`getSignatureStatuses`)
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SignatureStatus {
+    pub slot: u64,
+    pub confirmation_status: Option<SolanaCommitment>, // Absent on very old nodes
+    pub err: Option<String>,                           // Transaction error, if it failed
+}
+
+/// Result of verifying a transaction against a required commitment
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SolanaTransactionStatus {
+    pub signature: SolanaSignature,
+    pub slot: u64,
+    pub confirmation_status: Option<SolanaCommitment>,
+    pub err: Option<String>, // Transaction error, if it failed on-chain
+    pub required_commitment: SolanaCommitment,
+    pub verified: bool, // Succeeded and reached the required commitment
+}
+
+/// Why a transaction could not be verified
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum SolanaVerificationError {
+    InvalidSignature(String),          // Not a base58 64-byte signature
+    UnknownSignature(SolanaSignature), // The cluster has no record of it
+    RpcError(String),                  // Outcall failed or the response was malformed
+}
+
+impl fmt::Display for SolanaVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSignature(e) => write!(f, "Invalid signature: {}", e),
+            Self::UnknownSignature(signature) => {
+                write!(f, "Unknown transaction signature: {}", signature)
+            }
+            Self::RpcError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// SPL token balance in base units
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SplTokenAmount {
+    pub amount: u64, // Raw amount (10^-decimals of a token)
+    pub decimals: u8,
+}
+
+/// SPL token account held by a wallet
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct SplTokenAccount {
+    pub address: SolanaAddress, // Token account address
+    pub mint: SolanaAddress,
+    pub owner: SolanaAddress, // Wallet that owns the token account
+    pub balance: SplTokenAmount,
+}
+
+/// Everything a wallet holds: SOL plus SPL token accounts
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SolanaHoldings {
+    pub account: SolanaAccount,
+    pub token_accounts: Vec<SplTokenAccount>,
+}
+
+/// SPL Token program, used to list every token account of a wallet
+pub const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+/// How long RPC read results are served from cache
+pub const CACHE_TTL_SECONDS: u64 = 30;
+
+/// Upper bound on cached RPC responses
+const MAX_CACHE_ENTRIES: usize = 1_000;
+
+/// Name of the canister query used to make RPC responses identical across replicas
+pub const TRANSFORM_METHOD: &str = "transform_solana_response";
+
+/// Transform context: round a `getSlot` result down to a multiple of `SLOT_ROUNDING`
+pub const ROUND_SLOT_CONTEXT: &[u8] = b"round_slot";
+
+/// Transform context prefix for `sendTransaction`, followed by the expected signature
+pub const SEND_TRANSACTION_CONTEXT: &[u8] = b"send_transaction:";
+
+/// Replicas query slightly different slots; rounding lets them agree on one
+pub const SLOT_ROUNDING: u64 = 20;
+
+/// How many times a submitted transaction's status is checked before giving up
+pub const MAX_STATUS_POLLS: u32 = 10;
+
+thread_local! {
+    // Request key -> (fetched at, response body); not preserved across upgrades
+    static RPC_CACHE: RefCell<HashMap<String, (u64, String)>> = RefCell::new(HashMap::new());
+}
+
+fn rpc_url(network: &SolanaNetwork) -> &'static str {
+    match network {
         SolanaNetwork::Mainnet => "https://api.mainnet-beta.solana.com",
         SolanaNetwork::Testnet => "https://api.testnet.solana.com",
         SolanaNetwork::Devnet => "https://api.devnet.solana.com",
-    };
-    
-    // Solana RPC JSON-RPC request
+    }
+}
+
+/// Sends a JSON-RPC request and returns the response body
+async fn rpc_call(
+    network: &SolanaNetwork,
+    method: &str,
+    params: serde_json::Value,
+    max_response_bytes: u64,
+) -> Result<String, String> {
+    rpc_call_with_transform(network, method, params, max_response_bytes, vec![]).await
+}
+
+/// Like `rpc_call`, passing `transform_context` to `transform_response`
+async fn rpc_call_with_transform(
+    network: &SolanaNetwork,
+    method: &str,
+    params: serde_json::Value,
+    max_response_bytes: u64,
+    transform_context: Vec<u8>,
+) -> Result<String, String> {
     let rpc_request = serde_json::json!({
         "jsonrpc": "2.0",
         "id": 1,
-        "method": "getBalance",
-        "params": [address]
+        "method": method,
+        "params": params
     });
-    
-    let request = CanisterHttpRequestArgument {
-        url: rpc_url.to_string(),
+
+    let request = HttpRequestArgs {
+        url: rpc_url(network).to_string(),
         method: HttpMethod::POST,
         body: Some(rpc_request.to_string().into_bytes()),
-        max_response_bytes: Some(10_000),
-        transform: None,
-        headers: vec![
-            HttpHeader {
-                name: "Content-Type".to_string(),
-                value: "application/json".to_string(),
-            },
-        ],
+        max_response_bytes: Some(max_response_bytes),
+        transform: Some(transform_context_from_query(
+            TRANSFORM_METHOD.to_string(),
+            transform_context,
+        )),
+        headers: vec![HttpHeader {
+            name: "Content-Type".to_string(),
+            value: "application/json".to_string(),
+        }],
+        is_replicated: None,
     };
-    
-    let (response,) = http_request(request, 25_000_000_000)
+
+    let response = http_request(&request)
         .await
-        .map_err(|(code, msg)| {
-            format!("Solana RPC request failed: {:?} - {}", code, msg)
-        })?;
-    
-    if response.status != 200u64 && response.status != 200u32 as u64 {
+        .map_err(|e| format!("Solana RPC request failed: {:?}", e))?;
+
+    if response.status != 200u64 {
         return Err(format!(
             "Solana RPC returned status {}: {}",
             response.status,
             String::from_utf8_lossy(&response.body)
         ));
     }
-    
-    let response_body = String::from_utf8(response.body)
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
-    
-    #[derive(SerdeDeserialize)]
-    struct SolanaRpcResponse {
-        result: u64,
+
+    String::from_utf8(response.body).map_err(|e| format!("Failed to parse response: {}", e))
+}
+
+/// Like `rpc_call`, but serves repeated reads from a short-lived cache
+async fn cached_rpc_call(
+    network: &SolanaNetwork,
+    method: &str,
+    params: serde_json::Value,
+    max_response_bytes: u64,
+) -> Result<String, String> {
+    let key = format!("{}|{}|{}", rpc_url(network), method, params);
+    let now = ic_cdk::api::time();
+    let ttl = CACHE_TTL_SECONDS * 1_000_000_000;
+
+    let cached = RPC_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .get(&key)
+            .filter(|(fetched_at, _)| now.saturating_sub(*fetched_at) < ttl)
+            .map(|(_, body)| body.clone())
+    });
+    if let Some(body) = cached {
+        return Ok(body);
+    }
+
+    let body = rpc_call(network, method, params, max_response_bytes).await?;
+
+    RPC_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.retain(|_, (fetched_at, _)| now.saturating_sub(*fetched_at) < ttl);
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            if let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (fetched_at, _))| *fetched_at)
+                .map(|(key, _)| key.clone())
+            {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key, (now, body.clone()));
+    });
+
+    Ok(body)
+}
+
+/// Drops headers and the per-replica `context` (slot) so every replica agrees
+/// `getSlot` results are rounded down, and `sendTransaction` results are reduced
+/// to the expected signature, since replicas race each other to submit it
+pub fn transform_response(args: TransformArgs) -> HttpRequestResult {
+    let mut body = args.response.body;
+    if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(&body) {
+        if let Some(result) = json.get_mut("result").and_then(|r| r.as_object_mut()) {
+            result.remove("context");
+            // getSignatureStatuses: the confirmation count changes every slot
+            if let Some(statuses) = result.get_mut("value").and_then(|v| v.as_array_mut()) {
+                for status in statuses.iter_mut().filter_map(|s| s.as_object_mut()) {
+                    status.remove("confirmations");
+                }
+            }
+        }
+
+        if args.context == ROUND_SLOT_CONTEXT {
+            if let Some(slot) = json.get("result").and_then(|r| r.as_u64()) {
+                json["result"] = serde_json::json!(slot - slot % SLOT_ROUNDING);
+            }
+        } else if let Some(signature) = args.context.strip_prefix(SEND_TRANSACTION_CONTEXT) {
+            json = normalize_send_response(&json, &String::from_utf8_lossy(signature));
+        }
+
+        if let Ok(normalized) = serde_json::to_vec(&json) {
+            body = normalized;
+        }
     }
-    
-    let rpc_response: SolanaRpcResponse = serde_json::from_str(&response_body)
+
+    HttpRequestResult {
+        status: args.response.status,
+        headers: vec![],
+        body,
+    }
+}
+
+/// Extracts `result` from a JSON-RPC response, surfacing RPC errors
+fn rpc_result(body: &str) -> Result<serde_json::Value, String> {
+    let mut json: serde_json::Value = serde_json::from_str(body)
         .map_err(|e| format!("Failed to parse Solana RPC response: {}", e))?;
-    
-    Ok(rpc_response.result)
+
+    if let Some(error) = json.get("error") {
+        return Err(format!(
+            "Solana RPC error: {}",
+            error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error")
+        ));
+    }
+
+    json.get_mut("result")
+        .map(serde_json::Value::take)
+        .ok_or("Solana RPC response has no result".to_string())
+}
+
+/// Parses a `getBalance` response (`{result: {context, value}}`) into lamports
+pub fn parse_balance(body: &str) -> Result<u64, String> {
+    rpc_result(body)?
+        .get("value")
+        .and_then(|v| v.as_u64())
+        .ok_or("Invalid getBalance response: missing value".to_string())
+}
+
+/// Parses a `getAccountInfo` response; an account that does not exist has no owner and no lamports
+pub fn parse_account_info(address: &SolanaAddress, body: &str) -> Result<SolanaAccount, String> {
+    let result = rpc_result(body)?;
+    let value = result
+        .get("value")
+        .ok_or("Invalid getAccountInfo response: missing value".to_string())?;
+
+    if value.is_null() {
+        return Ok(SolanaAccount {
+            address: address.clone(),
+            balance: 0,
+            owner: None,
+        });
+    }
+
+    Ok(SolanaAccount {
+        address: address.clone(),
+        balance: value
+            .get("lamports")
+            .and_then(|v| v.as_u64())
+            .ok_or("Invalid getAccountInfo response: missing lamports".to_string())?,
+        owner: value
+            .get("owner")
+            .and_then(|v| v.as_str())
+            .map(SolanaAddress::parse)
+            .transpose()?,
+    })
+}
+
+/// Parses a `getTokenAccountBalance` response
+pub fn parse_token_account_balance(body: &str) -> Result<SplTokenAmount, String> {
+    let result = rpc_result(body)?;
+    let value = result
+        .get("value")
+        .ok_or("Invalid getTokenAccountBalance response: missing value".to_string())?;
+    parse_token_amount(value)
+}
+
+/// Parses a `getTokenAccountsByOwner` response requested with `jsonParsed` encoding
+pub fn parse_token_accounts(body: &str) -> Result<Vec<SplTokenAccount>, String> {
+    let result = rpc_result(body)?;
+    let accounts = result
+        .get("value")
+        .and_then(|v| v.as_array())
+        .ok_or("Invalid getTokenAccountsByOwner response: missing value".to_string())?;
+
+    accounts
+        .iter()
+        .map(|entry| {
+            let info = entry
+                .pointer("/account/data/parsed/info")
+                .ok_or("Token account is not jsonParsed".to_string())?;
+            let field = |name: &str| {
+                info.get(name)
+                    .and_then(|v| v.as_str())
+                    .ok_or(format!("Token account is missing {}", name))
+                    .and_then(SolanaAddress::parse)
+            };
+
+            Ok(SplTokenAccount {
+                address: entry
+                    .get("pubkey")
+                    .and_then(|v| v.as_str())
+                    .ok_or("Token account is missing pubkey".to_string())
+                    .and_then(SolanaAddress::parse)?,
+                mint: field("mint")?,
+                owner: field("owner")?,
+                balance: parse_token_amount(
+                    info.get("tokenAmount")
+                        .ok_or("Token account is missing tokenAmount".to_string())?,
+                )?,
+            })
+        })
+        .collect()
 }
 
-/// Gets Solana account information
+/// Parses a `UiTokenAmount`; the raw amount is a decimal string
+fn parse_token_amount(value: &serde_json::Value) -> Result<SplTokenAmount, String> {
+    let amount = value
+        .get("amount")
+        .and_then(|v| v.as_str())
+        .ok_or("Token amount is missing amount".to_string())?
+        .parse::<u64>()
+        .map_err(|e| format!("Invalid token amount: {}", e))?;
+    let decimals = value
+        .get("decimals")
+        .and_then(|v| v.as_u64())
+        .and_then(|d| u8::try_from(d).ok())
+        .ok_or("Token amount is missing decimals".to_string())?;
+
+    Ok(SplTokenAmount { amount, decimals })
+}
+
+/// Parses a `getSlot` response
+pub fn parse_slot(body: &str) -> Result<u64, String> {
+    rpc_result(body)?
+        .as_u64()
+        .ok_or("Invalid getSlot response".to_string())
+}
+
+/// Parses the blockhash out of a `getBlock` response
+pub fn parse_block_hash(body: &str) -> Result<[u8; 32], String> {
+    let result = rpc_result(body)?;
+    let blockhash = result
+        .get("blockhash")
+        .and_then(|v| v.as_str())
+        .ok_or("Invalid getBlock response: missing blockhash".to_string())?;
+    decode_base58::<32>(blockhash, "blockhash")
+}
+
+/// Parses a `getSignatureStatuses` response for a single signature
+/// Returns `None` while the cluster has not seen the transaction
+pub fn parse_signature_status(body: &str) -> Result<Option<SignatureStatus>, String> {
+    let result = rpc_result(body)?;
+    let status = result
+        .pointer("/value/0")
+        .ok_or("Invalid getSignatureStatuses response: missing value".to_string())?;
+    if status.is_null() {
+        return Ok(None);
+    }
+
+    Ok(Some(SignatureStatus {
+        slot: status
+            .get("slot")
+            .and_then(|v| v.as_u64())
+            .ok_or("Signature status is missing slot".to_string())?,
+        confirmation_status: status
+            .get("confirmationStatus")
+            .and_then(|v| v.as_str())
+            .and_then(SolanaCommitment::parse),
+        err: status
+            .get("err")
+            .filter(|err| !err.is_null())
+            .map(|err| err.to_string()),
+    }))
+}
+
+/// Checks a signature's status against the commitment the caller requires
+/// A transaction that failed on-chain is found but never verified
+pub fn check_transaction_status(
+    signature: &SolanaSignature,
+    status: Option<SignatureStatus>,
+    required_commitment: SolanaCommitment,
+) -> Result<SolanaTransactionStatus, SolanaVerificationError> {
+    let status =
+        status.ok_or_else(|| SolanaVerificationError::UnknownSignature(signature.clone()))?;
+
+    Ok(SolanaTransactionStatus {
+        signature: signature.clone(),
+        slot: status.slot,
+        verified: status.err.is_none()
+            && status
+                .confirmation_status
+                .is_some_and(|level| level >= required_commitment),
+        confirmation_status: status.confirmation_status,
+        err: status.err,
+        required_commitment,
+    })
+}
+
+/// Reduces a `sendTransaction` response to what every replica can agree on:
+/// the signature when accepted (or already processed by another replica's
+/// submission), otherwise the error code and message without simulation logs
+pub fn normalize_send_response(
+    response: &serde_json::Value,
+    expected_signature: &str,
+) -> serde_json::Value {
+    if response.get("result").is_some_and(|r| r.is_string()) {
+        return serde_json::json!({ "result": expected_signature });
+    }
+
+    let error = response.get("error");
+    let message = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .unwrap_or("unknown error");
+    if message.contains("already been processed") {
+        return serde_json::json!({ "result": expected_signature });
+    }
+
+    serde_json::json!({
+        "error": {
+            "code": error.and_then(|e| e.get("code")).cloned().unwrap_or_default(),
+            "message": message
+        }
+    })
+}
+
+/// Gets Solana account balance in lamports
+pub async fn get_solana_balance(
+    address: &SolanaAddress,
+    network: SolanaNetwork,
+) -> Result<u64, String> {
+    ic_cdk::println!("🔍 Querying Solana balance for address: {}", address);
+
+    let body = cached_rpc_call(
+        &network,
+        "getBalance",
+        serde_json::json!([address.as_str(), { "commitment": "finalized" }]),
+        2_000,
+    )
+    .await?;
+    parse_balance(&body)
+}
+
+/// Gets Solana account information: balance and owning program
 pub async fn get_solana_account(
-    address: &str,
+    address: &SolanaAddress,
     network: SolanaNetwork,
 ) -> Result<SolanaAccount, String> {
-    let balance = get_solana_balance(address, network.clone()).await?;
-    
-    Ok(SolanaAccount {
-        address: address.to_string(),
-        balance,
-        owner: None, // Would need additional RPC call to get owner
+    // An empty data slice keeps the response small; only lamports and owner are needed
+    let body = cached_rpc_call(
+        &network,
+        "getAccountInfo",
+        serde_json::json!([address.as_str(), {
+            "commitment": "finalized",
+            "encoding": "base64",
+            "dataSlice": { "offset": 0, "length": 0 }
+        }]),
+        2_000,
+    )
+    .await?;
+    parse_account_info(address, &body)
+}
+
+/// Lists a wallet's SPL token accounts, for one mint or for every mint
+pub async fn get_solana_token_accounts(
+    owner: &SolanaAddress,
+    token_mint: Option<&SolanaAddress>,
+    network: SolanaNetwork,
+) -> Result<Vec<SplTokenAccount>, String> {
+    let filter = match token_mint {
+        Some(mint) => serde_json::json!({ "mint": mint.as_str() }),
+        None => serde_json::json!({ "programId": SPL_TOKEN_PROGRAM_ID }),
+    };
+
+    let body = cached_rpc_call(
+        &network,
+        "getTokenAccountsByOwner",
+        serde_json::json!([owner.as_str(), filter, {
+            "commitment": "finalized",
+            "encoding": "jsonParsed"
+        }]),
+        100_000,
+    )
+    .await?;
+    parse_token_accounts(&body)
+}
+
+/// Gets the balance of a single SPL token account
+pub async fn get_token_account_balance(
+    token_account: &SolanaAddress,
+    network: SolanaNetwork,
+) -> Result<SplTokenAmount, String> {
+    let body = cached_rpc_call(
+        &network,
+        "getTokenAccountBalance",
+        serde_json::json!([token_account.as_str(), { "commitment": "finalized" }]),
+        2_000,
+    )
+    .await?;
+    parse_token_account_balance(&body)
+}
+
+/// Gets SOL and SPL token holdings of a wallet
+pub async fn get_solana_holdings(
+    address: &SolanaAddress,
+    network: SolanaNetwork,
+) -> Result<SolanaHoldings, String> {
+    Ok(SolanaHoldings {
+        account: get_solana_account(address, network.clone()).await?,
+        token_accounts: get_solana_token_accounts(address, None, network).await?,
+    })
+}
+
+// ============================================================================
+// Threshold Ed25519 signing and submission
+// ============================================================================
+
+/// Derivation path of the Solana account the vault controls for `user`
+pub fn user_derivation_path(user: Principal) -> Vec<Vec<u8>> {
+    vec![b"solana".to_vec(), user.as_slice().to_vec()]
+}
+
+fn ed25519_key_id() -> SchnorrKeyId {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    SchnorrKeyId {
+        algorithm: SchnorrAlgorithm::Ed25519,
+        name: if network == "ic" {
+            "key_1"
+        } else {
+            "dfx_test_key"
+        }
+        .to_string(),
+    }
+}
+
+fn use_local_signer() -> bool {
+    std::env::var("DFX_NETWORK")
+        .map(|n| n == "playground")
+        .unwrap_or(false)
+}
+
+/// Stand-in key for the playground, where threshold signing is unavailable
+/// Derived from public data, so it must never hold real funds
+fn local_signing_key(derivation_path: &[Vec<u8>]) -> SigningKey {
+    let mut engine = sha256::Hash::engine();
+    engine.input(b"bitfold-local-ed25519");
+    engine.input(ic_cdk::api::canister_self().as_slice());
+    for part in derivation_path {
+        engine.input(&(part.len() as u32).to_be_bytes());
+        engine.input(part);
+    }
+    SigningKey::from_bytes(&sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+/// Ed25519 public key for a derivation path, i.e. the Solana address it controls
+pub async fn public_key_for(derivation_path: Vec<Vec<u8>>) -> Result<SolanaAddress, String> {
+    if use_local_signer() {
+        ic_cdk::println!("⚠️ Using local Ed25519 stand-in on playground");
+        let public_key = local_signing_key(&derivation_path).verifying_key();
+        return Ok(SolanaAddress::from_bytes(public_key.as_bytes()));
+    }
+
+    let result = schnorr_public_key(&SchnorrPublicKeyArgs {
+        canister_id: None,
+        derivation_path,
+        key_id: ed25519_key_id(),
+    })
+    .await
+    .map_err(|e| format!("schnorr_public_key failed: {:?}", e))?;
+
+    let bytes = <[u8; SolanaAddress::LEN]>::try_from(result.public_key.as_slice())
+        .map_err(|_| "Ed25519 public key must be 32 bytes".to_string())?;
+    Ok(SolanaAddress::from_bytes(&bytes))
+}
+
+/// The Solana address the vault controls for `user`
+pub async fn user_solana_address(user: Principal) -> Result<SolanaAddress, String> {
+    if let Some(address) = State::with_read(|state| state.solana_addresses.get(&user).cloned()) {
+        return Ok(address);
+    }
+
+    let address = public_key_for(user_derivation_path(user)).await?;
+    State::with(|state| state.solana_addresses.insert(user, address.clone()));
+    Ok(address)
+}
+
+/// Signs `message` with threshold Ed25519 under `derivation_path`
+async fn sign_ed25519(derivation_path: Vec<Vec<u8>>, message: Vec<u8>) -> Result<[u8; 64], String> {
+    if use_local_signer() {
+        return Ok(local_signing_key(&derivation_path).sign(&message).to_bytes());
+    }
+
+    let result = sign_with_schnorr(&SignWithSchnorrArgs {
+        message,
+        derivation_path,
+        key_id: ed25519_key_id(),
+        aux: None,
+    })
+    .await
+    .map_err(|e| format!("sign_with_schnorr failed: {:?}", e))?;
+
+    <[u8; 64]>::try_from(result.signature.as_slice())
+        .map_err(|_| "Ed25519 signature must be 64 bytes".to_string())
+}
+
+/// Gets a recent finalized blockhash every replica agrees on
+/// The slot is rounded down so replicas request the same block; skipped slots
+/// have no block, so earlier slots are tried
+pub async fn get_recent_blockhash(network: &SolanaNetwork) -> Result<[u8; 32], String> {
+    let body = rpc_call_with_transform(
+        network,
+        "getSlot",
+        serde_json::json!([{ "commitment": "finalized" }]),
+        1_000,
+        ROUND_SLOT_CONTEXT.to_vec(),
+    )
+    .await?;
+    let slot = parse_slot(&body)?;
+
+    let mut last_error = String::new();
+    for candidate in (slot.saturating_sub(3)..=slot).rev() {
+        let body = rpc_call(
+            network,
+            "getBlock",
+            serde_json::json!([candidate, {
+                "commitment": "finalized",
+                "transactionDetails": "none",
+                "rewards": false,
+                "maxSupportedTransactionVersion": 0
+            }]),
+            2_000,
+        )
+        .await?;
+        match parse_block_hash(&body) {
+            Ok(blockhash) => return Ok(blockhash),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(format!("No block found near slot {}: {}", slot, last_error))
+}
+
+/// Gets the status of a transaction, or `None` if the cluster has not seen it
+/// Without `search_history` only the node's recent status cache is consulted
+pub async fn get_signature_status(
+    signature: &SolanaSignature,
+    network: &SolanaNetwork,
+    search_history: bool,
+) -> Result<Option<SignatureStatus>, String> {
+    let body = rpc_call(
+        network,
+        "getSignatureStatuses",
+        serde_json::json!([
+            [signature.as_str()],
+            { "searchTransactionHistory": search_history }
+        ]),
+        2_000,
+    )
+    .await?;
+    parse_signature_status(&body)
+}
+
+/// Submits a signed transaction
+async fn send_transaction(
+    transaction: &[u8],
+    signature: &SolanaSignature,
+    network: &SolanaNetwork,
+) -> Result<(), String> {
+    let mut context = SEND_TRANSACTION_CONTEXT.to_vec();
+    context.extend_from_slice(signature.as_str().as_bytes());
+
+    let body = rpc_call_with_transform(
+        network,
+        "sendTransaction",
+        serde_json::json!([BASE64.encode(transaction), {
+            "encoding": "base64",
+            "preflightCommitment": "confirmed"
+        }]),
+        2_000,
+        context,
+    )
+    .await?;
+
+    match rpc_result(&body)?.as_str() {
+        Some(returned) if returned == signature.as_str() => Ok(()),
+        _ => Err("sendTransaction returned an unexpected signature".to_string()),
+    }
+}
+
+/// Polls until the transaction is confirmed, fails, or `MAX_STATUS_POLLS` is reached
+/// Each poll is an HTTP outcall, which takes a few seconds to reach consensus.
+/// Replicas can briefly disagree on a fresh status; such polls are retried.
+async fn wait_for_confirmation(
+    signature: SolanaSignature,
+    network: &SolanaNetwork,
+) -> SolanaTransactionResponse {
+    let mut last_status = None;
+    for _ in 0..MAX_STATUS_POLLS {
+        match get_signature_status(&signature, network, false).await {
+            Ok(Some(status)) => {
+                let confirmed = status
+                    .confirmation_status
+                    .is_some_and(|level| level >= SolanaCommitment::Confirmed);
+                if status.err.is_some() || confirmed {
+                    return SolanaTransactionResponse {
+                        signature,
+                        slot: status.slot,
+                        success: status.err.is_none(),
+                        confirmation_status: status.confirmation_status,
+                        error: status.err,
+                    };
+                }
+                last_status = Some(status);
+            }
+            Ok(None) => {}
+            Err(e) => ic_cdk::println!("⚠️ Status poll for {} failed: {}", signature, e),
+        }
+    }
+
+    SolanaTransactionResponse {
+        signature,
+        slot: last_status.as_ref().map(|s| s.slot).unwrap_or(0),
+        success: false,
+        confirmation_status: last_status.and_then(|s| s.confirmation_status),
+        error: Some(format!(
+            "Not confirmed after {} status checks; it may still land",
+            MAX_STATUS_POLLS
+        )),
+    }
+}
+
+/// Signed transaction ready for submission
+#[derive(Clone, Debug)]
+pub struct SignedTransaction {
+    pub signature: SolanaSignature, // Fee payer's signature; identifies the transaction
+    pub bytes: Vec<u8>,             // Wire encoding
+}
+
+/// Builds and signs a transaction paid for by the account the vault controls for `user`
+/// Nothing is sent, so callers can record the signature before submitting
+pub async fn sign_transaction(
+    user: Principal,
+    instructions: &[Instruction],
+    network: &SolanaNetwork,
+) -> Result<SignedTransaction, String> {
+    let payer = user_solana_address(user).await?.to_bytes();
+    let blockhash = get_recent_blockhash(network).await?;
+    let message = Message::new(instructions, &payer, blockhash)?;
+    if message.signers() != [payer] {
+        return Err("Only the fee payer may sign vault transactions".to_string());
+    }
+
+    let signature_bytes = sign_ed25519(user_derivation_path(user), message.serialize()).await?;
+    Ok(SignedTransaction {
+        signature: SolanaSignature::from_bytes(&signature_bytes),
+        bytes: serialize_transaction(&message, &[signature_bytes])?,
     })
 }
 
+/// Submits a signed transaction and polls for its confirmation
+pub async fn submit_transaction(
+    transaction: &SignedTransaction,
+    network: &SolanaNetwork,
+) -> Result<SolanaTransactionResponse, String> {
+    ic_cdk::println!("📤 Sending Solana transaction {}", transaction.signature);
+    send_transaction(&transaction.bytes, &transaction.signature, network).await?;
+    Ok(wait_for_confirmation(transaction.signature.clone(), network).await)
+}
+
+/// Builds, signs and submits a transaction paid for by `user`'s vault-controlled account
+pub async fn sign_and_send(
+    user: Principal,
+    instructions: &[Instruction],
+    network: SolanaNetwork,
+) -> Result<SolanaTransactionResponse, String> {
+    let transaction = sign_transaction(user, instructions, &network).await?;
+    submit_transaction(&transaction, &network).await
+}
+
+/// Instructions moving SOL out of `user`'s vault-controlled account
+pub async fn sol_transfer_instructions(
+    user: Principal,
+    to: &SolanaAddress,
+    lamports: u64,
+) -> Result<Vec<Instruction>, String> {
+    if lamports == 0 {
+        return Err("Amount must be greater than 0".to_string());
+    }
+
+    let from = user_solana_address(user).await?;
+    Ok(vec![system_transfer(&from.to_bytes(), &to.to_bytes(), lamports)])
+}
+
+/// Instructions moving SPL tokens out of `user`'s vault-controlled account into
+/// `recipient`'s associated token account, creating it if needed (paid for by the sender)
+pub async fn spl_transfer_instructions(
+    user: Principal,
+    recipient: &SolanaAddress,
+    mint: &SolanaAddress,
+    amount: u64,
+    network: &SolanaNetwork,
+) -> Result<Vec<Instruction>, String> {
+    if amount == 0 {
+        return Err("Amount must be greater than 0".to_string());
+    }
+
+    let owner = user_solana_address(user).await?.to_bytes();
+    let mint_key = mint.to_bytes();
+    let source = associated_token_address(&owner, &mint_key)?;
+
+    let balance = get_token_account_balance(&SolanaAddress::from_bytes(&source), network.clone())
+        .await?;
+    if balance.amount < amount {
+        return Err(format!(
+            "Insufficient token balance: {} available, {} requested",
+            balance.amount, amount
+        ));
+    }
+
+    let recipient_key = recipient.to_bytes();
+    Ok(vec![
+        create_associated_token_account_idempotent(&owner, &recipient_key, &mint_key)?,
+        spl_transfer_checked(
+            &source,
+            &mint_key,
+            &associated_token_address(&recipient_key, &mint_key)?,
+            &owner,
+            amount,
+            balance.decimals,
+        ),
+    ])
+}
+
+/// Transfers SOL from the account the vault controls for `user`
+pub async fn transfer_sol(
+    user: Principal,
+    to: &SolanaAddress,
+    lamports: u64,
+    network: SolanaNetwork,
+) -> Result<SolanaTransactionResponse, String> {
+    let instructions = sol_transfer_instructions(user, to, lamports).await?;
+    sign_and_send(user, &instructions, network).await
+}
+
+/// Transfers SPL tokens from `user`'s vault-controlled account to `recipient`
+pub async fn transfer_spl(
+    user: Principal,
+    recipient: &SolanaAddress,
+    mint: &SolanaAddress,
+    amount: u64,
+    network: SolanaNetwork,
+) -> Result<SolanaTransactionResponse, String> {
+    let instructions = spl_transfer_instructions(user, recipient, mint, amount, &network).await?;
+    sign_and_send(user, &instructions, network).await
+}
+
+/// Principal whose derived Solana account holds the vault's own funds
+/// (the canister itself, which never calls its own endpoints)
+pub fn treasury() -> Principal {
+    ic_cdk::api::canister_self()
+}
+
 /// Creates a cross-chain swap between BTC and SOL
-/// This allows users to swap Bitcoin collateral for Solana tokens
-/// Uses deterministic transaction generation
+/// Sends the SOL leg from the Solana account the vault controls for `user`
 pub async fn create_btc_sol_swap(
+    user: Principal,
     request: SolanaTransactionRequest,
 ) -> Result<SolanaTransactionResponse, String> {
     ic_cdk::println!(
@@ -136,103 +1103,34 @@ pub async fn create_btc_sol_swap(
         request.amount,
         request.to
     );
-    
-    // Validate inputs
-    if request.amount == 0 {
-        return Err("Amount must be greater than 0".to_string());
-    }
-    
-    if request.from.is_empty() || request.to.is_empty() {
-        return Err("From and to addresses cannot be empty".to_string());
-    }
-    
-    // In production, this would:
-    // 1. Lock Bitcoin collateral in the vault
-    // 2. Calculate equivalent SOL amount (using price oracle)
-    // 3. Execute Solana transaction via RPC canister
-    // 4. Return transaction signature
-    
-    // Generate deterministic transaction signature
-    // In production, would use actual Solana transaction signing
-    let canister_id = ic_cdk::api::id();
-    let timestamp = ic_cdk::api::time();
-    
-    // Create deterministic signature from transaction details
-    let mut sig_data = format!("{}_{}_{}_{}", 
-        request.from, request.to, request.amount, timestamp).into_bytes();
-    sig_data.extend_from_slice(canister_id.as_slice());
-    
-    // Generate deterministic signature (Base58-like encoding)
-    let signature = hex::encode(&sig_data[..32]);
-    
-    // Calculate slot (deterministic from timestamp)
-    let slot = (timestamp / 1_000_000_000) as u64; // Approximate slot
-    
-    ic_cdk::println!("✅ Created BTC-SOL swap transaction");
-    ic_cdk::println!("   Signature: {}", signature);
-    ic_cdk::println!("   Slot: {}", slot);
-    
-    Ok(SolanaTransactionResponse {
-        signature,
-        slot,
-        success: true,
-    })
+
+    let response = transfer_sol(user, &request.to, request.amount, request.network).await?;
+
+    ic_cdk::println!(
+        "✅ BTC-SOL swap transaction {} (success: {})",
+        response.signature,
+        response.success
+    );
+    Ok(response)
 }
 
-/// Verifies a Solana transaction
+/// Verifies that a Solana transaction succeeded and reached `required_commitment`
 pub async fn verify_solana_transaction(
-    signature: &str,
+    signature: &SolanaSignature,
     network: SolanaNetwork,
-) -> Result<bool, String> {
-    let rpc_url = match network {
-        SolanaNetwork::Mainnet => "https://api.mainnet-beta.solana.com",
-        SolanaNetwork::Testnet => "https://api.testnet.solana.com",
-        SolanaNetwork::Devnet => "https://api.devnet.solana.com",
-    };
-    
-    let rpc_request = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "getSignatureStatuses",
-        "params": [[signature]]
-    });
-    
-    let request = CanisterHttpRequestArgument {
-        url: rpc_url.to_string(),
-        method: HttpMethod::POST,
-        body: Some(rpc_request.to_string().into_bytes()),
-        max_response_bytes: Some(10_000),
-        transform: None,
-        headers: vec![
-            HttpHeader {
-                name: "Content-Type".to_string(),
-                value: "application/json".to_string(),
-            },
-        ],
-    };
-    
-    let (response,) = http_request(request, 25_000_000_000)
+    required_commitment: SolanaCommitment,
+) -> Result<SolanaTransactionStatus, SolanaVerificationError> {
+    let status = get_signature_status(signature, &network, true)
         .await
-        .map_err(|(code, msg)| {
-            format!("Solana RPC request failed: {:?} - {}", code, msg)
-        })?;
-    
-    if response.status != 200u64 && response.status != 200u32 as u64 {
-        return Err(format!(
-            "Solana RPC returned status {}",
-            response.status
-        ));
-    }
-    
-    // Parse response and check if transaction was successful
-    // Simplified - in production, would parse full response
-    Ok(true)
+        .map_err(SolanaVerificationError::RpcError)?;
+
+    check_transaction_status(signature, status, required_commitment)
 }
 
-/// Gets Solana token balance (SPL tokens)
+/// Gets a wallet's SPL token balance for one mint, summed over its token accounts
 pub async fn get_solana_token_balance(
-    address: &str,
-    token_mint: &str,
+    address: &SolanaAddress,
+    token_mint: &SolanaAddress,
     network: SolanaNetwork,
 ) -> Result<u64, String> {
     ic_cdk::println!(
@@ -240,11 +1138,12 @@ pub async fn get_solana_token_balance(
         address,
         token_mint
     );
-    
-    // Use Solana RPC to get token account balance
-    // This would query the SPL Token program
-    
-    // Placeholder implementation
-    Ok(0)
-}
 
+    let accounts = get_solana_token_accounts(address, Some(token_mint), network).await?;
+    accounts
+        .iter()
+        .try_fold(0u64, |total, account| {
+            total.checked_add(account.balance.amount)
+        })
+        .ok_or("Token balance overflow".to_string())
+}
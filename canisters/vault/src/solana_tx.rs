@@ -0,0 +1,319 @@
+// Solana Transaction Builder
+// Builds legacy Solana transactions: instructions are compiled into a message
+// (header, account keys, recent blockhash, instructions) using the compact-u16
+// wire format, and the serialized message is what every signer signs.
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use ed25519_dalek::VerifyingKey;
+
+/// 32-byte Solana public key
+pub type Pubkey = [u8; 32];
+
+/// System program (all zero bytes)
+pub const SYSTEM_PROGRAM_ID: Pubkey = [0u8; 32];
+
+/// SPL Token program (TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA)
+pub const TOKEN_PROGRAM_ID: Pubkey = [
+    6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172, 28, 180, 133, 237,
+    95, 91, 55, 145, 58, 140, 245, 133, 126, 255, 0, 169,
+];
+
+/// Associated Token Account program (ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL)
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = [
+    140, 151, 37, 143, 78, 36, 137, 241, 187, 61, 16, 41, 20, 142, 13, 131, 11, 90, 19, 153, 218,
+    255, 16, 132, 4, 142, 123, 216, 219, 233, 248, 89,
+];
+
+/// Largest transaction the network accepts (IPv6 MTU minus headers)
+pub const MAX_TRANSACTION_SIZE: usize = 1232;
+
+const SYSTEM_TRANSFER: u32 = 2;
+const TOKEN_TRANSFER_CHECKED: u8 = 12;
+const ATA_CREATE_IDEMPOTENT: u8 = 1;
+
+/// Account referenced by an instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl AccountMeta {
+    pub fn writable(pubkey: Pubkey, is_signer: bool) -> Self {
+        Self {
+            pubkey,
+            is_signer,
+            is_writable: true,
+        }
+    }
+
+    pub fn readonly(pubkey: Pubkey, is_signer: bool) -> Self {
+        Self {
+            pubkey,
+            is_signer,
+            is_writable: false,
+        }
+    }
+}
+
+/// Program call with its accounts and opaque data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+}
+
+/// Instruction with accounts replaced by indices into the message's account keys
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledInstruction {
+    pub program_id_index: u8,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Legacy transaction message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub num_required_signatures: u8,
+    pub num_readonly_signed_accounts: u8,
+    pub num_readonly_unsigned_accounts: u8,
+    pub account_keys: Vec<Pubkey>, // Signers first; the fee payer is always index 0
+    pub recent_blockhash: [u8; 32],
+    pub instructions: Vec<CompiledInstruction>,
+}
+
+impl Message {
+    /// Compiles instructions into a message paid for by `payer`
+    /// Accounts are ordered: writable signers, read-only signers,
+    /// writable non-signers, read-only non-signers
+    pub fn new(
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        recent_blockhash: [u8; 32],
+    ) -> Result<Self, String> {
+        let mut metas: Vec<AccountMeta> = vec![AccountMeta::writable(*payer, true)];
+        let mut add = |meta: AccountMeta| match metas.iter_mut().find(|m| m.pubkey == meta.pubkey) {
+            Some(existing) => {
+                existing.is_signer |= meta.is_signer;
+                existing.is_writable |= meta.is_writable;
+            }
+            None => metas.push(meta),
+        };
+        for instruction in instructions {
+            for meta in &instruction.accounts {
+                add(meta.clone());
+            }
+            add(AccountMeta::readonly(instruction.program_id, false));
+        }
+
+        let group = |signer: bool, writable: bool| {
+            metas
+                .iter()
+                .filter(move |m| m.is_signer == signer && m.is_writable == writable)
+                .map(|m| m.pubkey)
+        };
+        let account_keys: Vec<Pubkey> = group(true, true)
+            .chain(group(true, false))
+            .chain(group(false, true))
+            .chain(group(false, false))
+            .collect();
+        if account_keys.len() > u8::MAX as usize {
+            return Err("Too many accounts in transaction".to_string());
+        }
+
+        let count = |f: &dyn Fn(&AccountMeta) -> bool| metas.iter().filter(|m| f(m)).count() as u8;
+        let index_of = |key: &Pubkey| {
+            account_keys
+                .iter()
+                .position(|k| k == key)
+                .map(|i| i as u8)
+                .expect("every instruction account was collected")
+        };
+
+        Ok(Self {
+            num_required_signatures: count(&|m| m.is_signer),
+            num_readonly_signed_accounts: count(&|m| m.is_signer && !m.is_writable),
+            num_readonly_unsigned_accounts: count(&|m| !m.is_signer && !m.is_writable),
+            instructions: instructions
+                .iter()
+                .map(|instruction| CompiledInstruction {
+                    program_id_index: index_of(&instruction.program_id),
+                    accounts: instruction
+                        .accounts
+                        .iter()
+                        .map(|m| index_of(&m.pubkey))
+                        .collect(),
+                    data: instruction.data.clone(),
+                })
+                .collect(),
+            account_keys,
+            recent_blockhash,
+        })
+    }
+
+    /// Accounts that must sign, in signature order
+    pub fn signers(&self) -> &[Pubkey] {
+        &self.account_keys[..self.num_required_signatures as usize]
+    }
+
+    /// Wire encoding of the message: the bytes each signer signs
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = vec![
+            self.num_required_signatures,
+            self.num_readonly_signed_accounts,
+            self.num_readonly_unsigned_accounts,
+        ];
+        encode_compact_u16(self.account_keys.len(), &mut out);
+        for key in &self.account_keys {
+            out.extend_from_slice(key);
+        }
+        out.extend_from_slice(&self.recent_blockhash);
+        encode_compact_u16(self.instructions.len(), &mut out);
+        for instruction in &self.instructions {
+            out.push(instruction.program_id_index);
+            encode_compact_u16(instruction.accounts.len(), &mut out);
+            out.extend_from_slice(&instruction.accounts);
+            encode_compact_u16(instruction.data.len(), &mut out);
+            out.extend_from_slice(&instruction.data);
+        }
+        out
+    }
+}
+
+/// Serializes a signed transaction: signatures followed by the message
+pub fn serialize_transaction(
+    message: &Message,
+    signatures: &[[u8; 64]],
+) -> Result<Vec<u8>, String> {
+    if signatures.len() != message.num_required_signatures as usize {
+        return Err(format!(
+            "Expected {} signatures, got {}",
+            message.num_required_signatures,
+            signatures.len()
+        ));
+    }
+
+    let mut out = Vec::new();
+    encode_compact_u16(signatures.len(), &mut out);
+    for signature in signatures {
+        out.extend_from_slice(signature);
+    }
+    out.extend_from_slice(&message.serialize());
+
+    if out.len() > MAX_TRANSACTION_SIZE {
+        return Err(format!(
+            "Transaction too large: {} bytes (maximum {})",
+            out.len(),
+            MAX_TRANSACTION_SIZE
+        ));
+    }
+    Ok(out)
+}
+
+/// Appends a compact-u16 length: 7 bits per byte, high bit set while more follow
+pub fn encode_compact_u16(value: usize, out: &mut Vec<u8>) {
+    let mut value = value as u16;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// System program transfer of `lamports` from `from` (signer) to `to`
+pub fn system_transfer(from: &Pubkey, to: &Pubkey, lamports: u64) -> Instruction {
+    let mut data = SYSTEM_TRANSFER.to_le_bytes().to_vec();
+    data.extend_from_slice(&lamports.to_le_bytes());
+
+    Instruction {
+        program_id: SYSTEM_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::writable(*from, true),
+            AccountMeta::writable(*to, false),
+        ],
+        data,
+    }
+}
+
+/// SPL Token `TransferChecked` between token accounts; `owner` signs
+/// The mint and decimals are checked on-chain so a wrong token cannot be sent
+pub fn spl_transfer_checked(
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Instruction {
+    let mut data = vec![TOKEN_TRANSFER_CHECKED];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+
+    Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::writable(*source, false),
+            AccountMeta::readonly(*mint, false),
+            AccountMeta::writable(*destination, false),
+            AccountMeta::readonly(*owner, true),
+        ],
+        data,
+    }
+}
+
+/// Creates `owner`'s associated token account for `mint` unless it already exists
+pub fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Instruction, String> {
+    Ok(Instruction {
+        program_id: ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::writable(*payer, true),
+            AccountMeta::writable(associated_token_address(owner, mint)?, false),
+            AccountMeta::readonly(*owner, false),
+            AccountMeta::readonly(*mint, false),
+            AccountMeta::readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data: vec![ATA_CREATE_IDEMPOTENT],
+    })
+}
+
+/// Address of `owner`'s associated token account for `mint`
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Result<Pubkey, String> {
+    find_program_address(
+        &[owner, &TOKEN_PROGRAM_ID, mint],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .map(|(address, _)| address)
+    .ok_or("Unable to find a valid program address".to_string())
+}
+
+/// Finds the first off-curve program derived address, searching bumps from 255 down
+pub fn find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> Option<(Pubkey, u8)> {
+    (0..=u8::MAX).rev().find_map(|bump| {
+        let mut engine = sha256::Hash::engine();
+        for seed in seeds {
+            engine.input(seed);
+        }
+        engine.input(&[bump]);
+        engine.input(program_id);
+        engine.input(b"ProgramDerivedAddress");
+        let address = sha256::Hash::from_engine(engine).to_byte_array();
+
+        (!is_on_curve(&address)).then_some((address, bump))
+    })
+}
+
+/// Whether the bytes decode to an Ed25519 point (i.e. could have a private key)
+pub fn is_on_curve(bytes: &Pubkey) -> bool {
+    VerifyingKey::from_bytes(bytes).is_ok()
+}
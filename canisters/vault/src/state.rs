@@ -1,13 +1,20 @@
-use crate::types::{Loan, LoanId, LoanOffer, UTXO, UtxoId};
+use crate::solana::SolanaAddress;
+use crate::types::{
+    DeadManSwitch, DocumentGrant, EncryptedNote, LendingPool, Loan, LoanAsset, LoanDocument,
+    LoanId, LoanOffer, LoanStatus, MuSigVault, PendingDisbursement, TimeLockConfig,
+    UserKeyState, UTXO, UtxoId,
+};
 use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 thread_local! {
     static STATE: RefCell<State> = RefCell::default();
 }
 
+/// Candid decodes a field missing from saved state only into an `Option`, so
+/// fields added to saved types must be `Option`s or come with a migration
 #[derive(Default, CandidType, Serialize, Deserialize, Clone)]
 pub struct State {
     pub loans: HashMap<LoanId, Loan>,
@@ -19,6 +26,23 @@ pub struct State {
     pub next_loan_id: LoanId,
     pub next_utxo_id: UtxoId,
     pub next_loan_offer_id: u64,
+    pub musig_vaults: HashMap<u64, MuSigVault>,
+    pub user_musig_vaults: HashMap<Principal, Vec<u64>>,
+    pub next_musig_vault_id: u64,
+    pub time_locks: HashMap<UtxoId, TimeLockConfig>,
+    pub dead_man_switches: HashMap<Principal, DeadManSwitch>,
+    pub notes: HashMap<u64, EncryptedNote>,
+    pub user_notes: HashMap<Principal, Vec<u64>>, // Notes owned by each user
+    pub next_note_id: u64,
+    pub loan_documents: HashMap<u64, LoanDocument>,
+    pub loan_document_grants: HashMap<LoanId, HashMap<Principal, DocumentGrant>>,
+    pub next_loan_document_id: u64,
+    pub user_key_versions: HashMap<Principal, UserKeyState>,
+    pub solana_addresses: HashMap<Principal, SolanaAddress>, // Derived Ed25519 account per user
+    pub lending_pool: LendingPool,
+    pub consumed_repayment_blocks: HashSet<(LoanAsset, u64)>, // Ledger blocks credited to loans
+    pub pending_disbursements: HashMap<LoanId, PendingDisbursement>, // Unconfirmed loan payouts
+    pub rune_utxos: HashSet<UtxoId>, // UTXOs found to carry Runes
 }
 
 impl State {
@@ -57,6 +81,73 @@ impl State {
     }
 }
 
+/// Loan as saved by the first release, before loans could be in other assets,
+/// backed by baskets or given a term
+#[derive(CandidType, Deserialize)]
+struct LoanV1 {
+    id: LoanId,
+    user_id: Principal,
+    collateral_utxo_id: UtxoId,
+    borrowed_amount: u64,
+    repaid_amount: u64,
+    interest_rate: u64,
+    created_at: u64,
+    status: LoanStatus,
+}
+
+/// State as saved by the first release
+#[derive(CandidType, Deserialize)]
+struct StateV1 {
+    loans: HashMap<LoanId, LoanV1>,
+    utxos: HashMap<UtxoId, UTXO>,
+    user_loans: HashMap<Principal, Vec<LoanId>>,
+    user_utxos: HashMap<Principal, Vec<UtxoId>>,
+    loan_offers: HashMap<u64, LoanOffer>,
+    user_loan_offers: HashMap<Principal, Vec<u64>>,
+    next_loan_id: LoanId,
+    next_utxo_id: UtxoId,
+    next_loan_offer_id: u64,
+}
+
+impl From<StateV1> for State {
+    fn from(v1: StateV1) -> Self {
+        let loans = v1
+            .loans
+            .into_iter()
+            .map(|(id, loan)| {
+                let loan = Loan {
+                    id: loan.id,
+                    user_id: loan.user_id,
+                    collateral_utxo_id: loan.collateral_utxo_id,
+                    extra_collateral: vec![],
+                    asset: LoanAsset::CkBtc,
+                    borrowed_amount: loan.borrowed_amount,
+                    repaid_amount: loan.repaid_amount,
+                    interest_rate: loan.interest_rate,
+                    created_at: loan.created_at,
+                    status: loan.status,
+                    solana_disbursement: None,
+                    term: None,
+                };
+                (id, loan)
+            })
+            .collect();
+
+        State {
+            loans,
+            utxos: v1.utxos,
+            user_loans: v1.user_loans,
+            user_utxos: v1.user_utxos,
+            loan_offers: v1.loan_offers,
+            user_loan_offers: v1.user_loan_offers,
+            next_loan_id: v1.next_loan_id,
+            next_utxo_id: v1.next_utxo_id,
+            next_loan_offer_id: v1.next_loan_offer_id,
+            ..State::default()
+        }
+    }
+}
+
 /// Pre-upgrade hook: saves state to stable memory before canister upgrade
 #[ic_cdk::pre_upgrade]
 fn pre_upgrade() {
@@ -79,10 +170,20 @@ fn pre_upgrade() {
 fn post_upgrade() {
     let mut state: State = match ic_cdk::storage::stable_restore() {
         Ok((s,)) => s,
-        Err(_) => {
-            // If restore fails (e.g., due to new fields), try to restore old state and add defaults
-            ic_cdk::println!("Warning: Failed to restore state, initializing with defaults");
-            State::default()
+        Err(current) => {
+            // State saved by the first release lacks the fields added since
+            match ic_cdk::storage::stable_restore::<(StateV1,)>() {
+                Ok((v1,)) => {
+                    ic_cdk::println!("Post-upgrade: Migrating state saved by the first release");
+                    v1.into()
+                }
+                // Trapping rolls the upgrade back and keeps the saved state;
+                // starting empty would lose every loan and UTXO
+                Err(v1) => ic_cdk::trap(format!(
+                    "Failed to restore state: {} (as first release state: {})",
+                    current, v1
+                )),
+            }
         }
     };
     
@@ -97,5 +198,13 @@ fn post_upgrade() {
     ic_cdk::println!("  - Next Loan Offer ID: {}", state.next_loan_offer_id);
     
     State::replace(state);
+
+    // Timers are not preserved across upgrades
+    crate::timelock::schedule_all();
+    crate::deadman::resume();
+    crate::assets::resume();
+    crate::repayments::resume();
+    crate::disbursements::resume();
+    crate::terms::resume();
 }
 
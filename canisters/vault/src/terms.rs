@@ -0,0 +1,279 @@
+// Fixed-Term Loans
+// A loan may be opened with a maturity instead of being open-ended. Once it
+// matures unpaid it is charged a daily late penalty on its unpaid principal,
+// at a rate that steps up every week it stays overdue. After the grace period
+// a timer moves it to `Defaulted`, which makes it liquidatable at any LTV.
+// Before that, the borrower can push the maturity back for a fee.
+// When refinancing, a borrower picks a rate up to the asset's rate; shorter
+// terms may go lower, as they tie up pool liquidity for less time.
+
+use crate::helpers::{get_timestamp, is_loan_open};
+use crate::state::State;
+use crate::types::{Loan, LoanStatus, LoanTerm};
+use std::cell::Cell;
+use std::time::Duration;
+
+/// How often fixed-term loans are checked for late penalties and defaults
+pub const TERM_CHECK_SECONDS: u64 = 60 * 60;
+
+pub const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Shortest and longest term a loan can be opened or extended for, in days
+pub const MIN_TERM_DAYS: u64 = 7;
+pub const MAX_TERM_DAYS: u64 = 365;
+
+/// Days after maturity before an unpaid loan defaults
+pub const GRACE_PERIOD_DAYS: u64 = 7;
+
+/// Daily late penalty in the first week past maturity, in basis points of
+/// the unpaid principal; it grows by the same step every further week
+pub const LATE_PENALTY_BPS_PER_DAY: u64 = 10;
+
+/// Upper bound of the daily late penalty, in basis points
+pub const MAX_LATE_PENALTY_BPS_PER_DAY: u64 = 50;
+
+/// Extension fee per 30 days of extension, in basis points of the unpaid principal
+pub const EXTENSION_FEE_BPS: u64 = 100;
+
+/// How many times a loan can be extended
+pub const MAX_EXTENSIONS: u32 = 3;
+
+/// Rate discount for every full 90 days a term runs shorter than
+/// `MAX_TERM_DAYS`, in basis points
+pub const SHORT_TERM_DISCOUNT_BPS: u64 = 25;
+
+thread_local! {
+    static TERM_TIMER_STARTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Checks that a loan can run for `days`
+pub fn validate_term_days(days: u64) -> Result<(), String> {
+    if !(MIN_TERM_DAYS..=MAX_TERM_DAYS).contains(&days) {
+        return Err(format!(
+            "Invalid term: must be between {} and {} days",
+            MIN_TERM_DAYS, MAX_TERM_DAYS
+        ));
+    }
+    Ok(())
+}
+
+/// Term of a loan opened at `now` and maturing `days` later
+pub fn new_term(now: u64, days: u64) -> LoanTerm {
+    LoanTerm {
+        maturity: now.saturating_add(days.saturating_mul(DAY_NANOS)),
+        grace_period: GRACE_PERIOD_DAYS * DAY_NANOS,
+        fees: 0,
+        penalty_days: 0,
+        extensions: 0,
+    }
+}
+
+/// Term of a refinanced loan running `days` from `now`
+/// Fees owed and extensions used under its current term carry over; pushing
+/// back the current maturity is what `extend` charges for, so it is refused
+pub fn renew(current: Option<&LoanTerm>, now: u64, days: u64) -> Result<LoanTerm, String> {
+    let mut term = new_term(now, days);
+    if let Some(current) = current {
+        if term.maturity > current.maturity {
+            return Err(
+                "A new term cannot end after the current maturity; extend the loan instead"
+                    .to_string(),
+            );
+        }
+        term.fees = current.fees;
+        term.extensions = current.extensions;
+    }
+    Ok(term)
+}
+
+/// Lowest and highest rate, in basis points, a loan in an asset lent at
+/// `asset_rate` can be refinanced to for `term_days` (none: open-ended)
+pub fn rate_bounds(asset_rate: u64, term_days: Option<u64>) -> (u64, u64) {
+    let discount = term_days.map_or(0, |days| {
+        MAX_TERM_DAYS.saturating_sub(days) / 90 * SHORT_TERM_DISCOUNT_BPS
+    });
+    (asset_rate.saturating_sub(discount), asset_rate)
+}
+
+/// Checks a rate requested by a borrower against `rate_bounds`
+pub fn validate_rate(
+    interest_rate: u64,
+    asset_rate: u64,
+    term_days: Option<u64>,
+) -> Result<(), String> {
+    let (min, max) = rate_bounds(asset_rate, term_days);
+    if !(min..=max).contains(&interest_rate) {
+        return Err(format!(
+            "Invalid rate: must be between {} and {} basis points for this term",
+            min, max
+        ));
+    }
+    Ok(())
+}
+
+/// Days left until a term matures, rounded up
+pub fn remaining_days(term: &LoanTerm, now: u64) -> u64 {
+    term.maturity.saturating_sub(now).div_ceil(DAY_NANOS)
+}
+
+/// Daily late penalty for the `day`-th day past maturity (counting from 0),
+/// in basis points
+pub fn penalty_rate(day: u64) -> u64 {
+    LATE_PENALTY_BPS_PER_DAY
+        .saturating_mul(day / 7 + 1)
+        .min(MAX_LATE_PENALTY_BPS_PER_DAY)
+}
+
+/// Full days a term is past its maturity at `now`
+pub fn days_overdue(term: &LoanTerm, now: u64) -> u64 {
+    now.saturating_sub(term.maturity) / DAY_NANOS
+}
+
+/// Whether a term has reached its maturity at `now`
+pub fn is_past_maturity(term: &LoanTerm, now: u64) -> bool {
+    now >= term.maturity
+}
+
+/// Whether a term is past maturity and grace period at `now`
+pub fn is_past_grace(term: &LoanTerm, now: u64) -> bool {
+    now >= term.maturity.saturating_add(term.grace_period)
+}
+
+/// Principal of a loan that has not been repaid yet
+fn unpaid_principal(loan: &Loan) -> u64 {
+    loan.borrowed_amount.saturating_sub(loan.repaid_amount)
+}
+
+fn bps_of(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / 10_000) as u64
+}
+
+/// Charges the late penalty for every full overdue day not charged yet
+/// Returns the penalty added, in the loan asset's base units
+pub fn accrue_penalty(loan: &mut Loan, now: u64) -> u64 {
+    let principal = unpaid_principal(loan);
+    let Some(term) = loan.term.as_mut() else {
+        return 0;
+    };
+
+    let overdue = days_overdue(term, now);
+    let penalty = (term.penalty_days..overdue).fold(0u64, |total, day| {
+        total.saturating_add(bps_of(principal, penalty_rate(day)))
+    });
+    term.penalty_days = term.penalty_days.max(overdue);
+    term.fees = term.fees.saturating_add(penalty);
+    penalty
+}
+
+/// Accrues penalties on an open fixed-term loan and moves it to `Defaulted`
+/// once its grace period has run out
+/// Returns whether the loan defaulted just now
+pub fn apply_term(loan: &mut Loan, now: u64) -> bool {
+    if !is_loan_open(loan) {
+        return false;
+    }
+    accrue_penalty(loan, now);
+
+    let past_grace = loan
+        .term
+        .as_ref()
+        .is_some_and(|term| is_past_grace(term, now));
+    if loan.status == LoanStatus::Active && past_grace {
+        loan.status = LoanStatus::Defaulted;
+        return true;
+    }
+    false
+}
+
+/// Fee for extending a loan by `extra_days`, in the loan asset's base units
+pub fn extension_fee(loan: &Loan, extra_days: u64) -> u64 {
+    (unpaid_principal(loan) as u128 * EXTENSION_FEE_BPS as u128 * extra_days as u128
+        / (30 * 10_000)) as u64
+}
+
+/// Pushes back a loan's maturity by `extra_days`, adding the extension fee to
+/// its debt; an overdue loan must be extended within its grace period and
+/// brought back before its new maturity
+/// Returns the fee charged
+pub fn extend(loan: &mut Loan, extra_days: u64, now: u64) -> Result<u64, String> {
+    if loan.status != LoanStatus::Active {
+        return Err("Only active loans can be extended".to_string());
+    }
+    if extra_days == 0 || extra_days > MAX_TERM_DAYS {
+        return Err(format!(
+            "Invalid extension: must be between 1 and {} days",
+            MAX_TERM_DAYS
+        ));
+    }
+    let term = loan
+        .term
+        .as_ref()
+        .ok_or("Loan has no fixed term".to_string())?;
+    if is_past_grace(term, now) {
+        return Err("Loan is past its grace period and can no longer be extended".to_string());
+    }
+    if term.extensions >= MAX_EXTENSIONS {
+        return Err(format!(
+            "Loan has already been extended {} times",
+            MAX_EXTENSIONS
+        ));
+    }
+    let maturity = term
+        .maturity
+        .saturating_add(extra_days.saturating_mul(DAY_NANOS));
+    if maturity <= now {
+        return Err(format!(
+            "An extension of {} days still leaves the loan past maturity",
+            extra_days
+        ));
+    }
+
+    // Penalties for the days already overdue stay charged
+    accrue_penalty(loan, now);
+    let fee = extension_fee(loan, extra_days);
+    if let Some(term) = loan.term.as_mut() {
+        term.maturity = maturity;
+        term.penalty_days = 0;
+        term.fees = term.fees.saturating_add(fee);
+        term.extensions += 1;
+    }
+    Ok(fee)
+}
+
+/// Applies late penalties and defaults to every open fixed-term loan
+pub(crate) fn check_all() {
+    let now = get_timestamp();
+    State::with(|state| {
+        for loan in state.loans.values_mut() {
+            if apply_term(loan, now) {
+                ic_cdk::println!(
+                    "⚠️ Loan {} defaulted: unpaid past maturity and grace period",
+                    loan.id
+                );
+            }
+        }
+    });
+}
+
+/// Starts checking fixed-term loans periodically (once per canister instance)
+pub fn ensure_timer() {
+    if TERM_TIMER_STARTED.with(|started| started.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(TERM_CHECK_SECONDS), || async {
+        check_all()
+    });
+}
+
+/// Restarts the term timer after an upgrade if any fixed-term loan is open
+pub fn resume() {
+    let needed = State::with_read(|state| {
+        state
+            .loans
+            .values()
+            .any(|loan| loan.term.is_some() && is_loan_open(loan))
+    });
+    if needed {
+        ensure_timer();
+    }
+}
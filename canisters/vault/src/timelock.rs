@@ -0,0 +1,213 @@
+// Time-Locked Collateral
+// Enforces TimeLockConfig on deposited UTXOs and releases them at unlock time
+// through ic-cdk-timers, with optional on-chain enforcement via a CLTV leaf
+
+use crate::helpers::get_timestamp;
+use crate::state::State;
+use crate::types::{TimeLockConfig, UtxoId, UtxoStatus};
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CLTV, OP_DROP};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Secp256k1, XOnlyPublicKey};
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::{absolute, Address, ScriptBuf};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::time::Duration;
+
+/// BIP-341 NUMS point: an internal key nobody knows the secret for, so only the
+/// script path can spend
+const NUMS_INTERNAL_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Taproot address whose only spend path is a CLTV-locked user leaf
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TimeLockAddress {
+    pub address: String,
+    pub internal_key: Vec<u8>, // Unspendable NUMS key
+    pub leaf_script: Vec<u8>,  // <locktime> OP_CLTV OP_DROP <user> OP_CHECKSIG
+    pub control_block: Vec<u8>,
+    pub lock_time: u32, // nLockTime the spending transaction must set
+}
+
+/// What happened to a UTXO when its time lock expired
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum UnlockAction {
+    Released,                         // Lock removed, owner withdraws manually
+    AutoWithdrawn,                    // Marked withdrawn for the owner
+    TransferredTo(candid::Principal), // Ownership moved to the beneficiary
+    Deferred(String),                 // Lock kept until the UTXO stops backing a loan
+    Skipped(String),                  // Nothing to do (withdrawn, not expired, ...)
+}
+
+/// Checks a new time lock against the current one (if any)
+/// A lock can be extended but never shortened or removed early
+pub fn validate_time_lock(
+    config: &TimeLockConfig,
+    existing: Option<&TimeLockConfig>,
+    now: u64,
+) -> Result<(), String> {
+    if config.unlock_timestamp <= now {
+        return Err("Unlock timestamp must be in the future".to_string());
+    }
+
+    if let Some(current) = existing {
+        if current.unlock_timestamp > now && config.unlock_timestamp < current.unlock_timestamp {
+            return Err("Cannot shorten an active time lock".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true once the time lock has expired
+pub fn is_unlocked(config: &TimeLockConfig, now: u64) -> bool {
+    now >= config.unlock_timestamp
+}
+
+/// Converts an unlock timestamp (nanoseconds) into a timestamp-based nLockTime
+pub fn lock_time_from_timestamp(unlock_timestamp: u64) -> Result<u32, String> {
+    let seconds = unlock_timestamp / 1_000_000_000;
+    let lock_time = u32::try_from(seconds)
+        .map_err(|_| "Unlock timestamp is too far in the future".to_string())?;
+
+    if lock_time < absolute::LOCK_TIME_THRESHOLD {
+        return Err("Unlock timestamp is too early for a time-based lock".to_string());
+    }
+    Ok(lock_time)
+}
+
+/// Builds the CLTV leaf: <locktime> OP_CHECKLOCKTIMEVERIFY OP_DROP <user> OP_CHECKSIG
+pub fn cltv_leaf(user_public_key: &[u8], lock_time: u32) -> Result<ScriptBuf, String> {
+    let user = XOnlyPublicKey::from_slice(user_public_key)
+        .map_err(|e| format!("Invalid user public key: {}", e))?;
+
+    Ok(Builder::new()
+        .push_int(lock_time as i64)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_x_only_key(&user)
+        .push_opcode(OP_CHECKSIG)
+        .into_script())
+}
+
+/// Builds a P2TR address that cannot be spent before `unlock_timestamp`
+pub fn time_lock_address(
+    user_public_key: &[u8],
+    unlock_timestamp: u64,
+) -> Result<TimeLockAddress, String> {
+    let lock_time = lock_time_from_timestamp(unlock_timestamp)?;
+    let leaf = cltv_leaf(user_public_key, lock_time)?;
+
+    let secp = Secp256k1::verification_only();
+    let internal_key = XOnlyPublicKey::from_slice(
+        &hex::decode(NUMS_INTERNAL_KEY).map_err(|e| format!("Invalid NUMS key: {}", e))?,
+    )
+    .map_err(|e| format!("Invalid NUMS key: {}", e))?;
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, leaf.clone())
+        .map_err(|e| format!("Failed to build script tree: {}", e))?
+        .finalize(&secp, internal_key)
+        .map_err(|_| "Failed to finalize script tree".to_string())?;
+    let control_block = spend_info
+        .control_block(&(leaf.clone(), LeafVersion::TapScript))
+        .ok_or("Failed to compute control block".to_string())?;
+
+    let address = Address::p2tr_tweaked(spend_info.output_key(), crate::psbt::NETWORK);
+
+    Ok(TimeLockAddress {
+        address: address.to_string(),
+        internal_key: internal_key.serialize().to_vec(),
+        leaf_script: leaf.to_bytes(),
+        control_block: control_block.serialize(),
+        lock_time,
+    })
+}
+
+/// Arms a one-shot timer that releases the UTXO at its unlock time
+pub fn schedule_unlock(utxo_id: UtxoId, unlock_timestamp: u64) {
+    let delay = Duration::from_nanos(unlock_timestamp.saturating_sub(get_timestamp()));
+    ic_cdk_timers::set_timer(delay, async move {
+        let action = process_unlock(utxo_id);
+        ic_cdk::println!("⏰ Time lock on UTXO {} expired: {:?}", utxo_id, action);
+    });
+}
+
+/// Re-arms timers for every pending time lock (timers do not survive upgrades)
+pub fn schedule_all() {
+    let locks: Vec<(UtxoId, u64)> = State::with_read(|state| {
+        state
+            .time_locks
+            .iter()
+            .map(|(id, lock)| (*id, lock.unlock_timestamp))
+            .collect()
+    });
+
+    for (utxo_id, unlock_timestamp) in locks {
+        schedule_unlock(utxo_id, unlock_timestamp);
+    }
+}
+
+/// What an expired time lock does to a UTXO in `status` (`None` if it is gone)
+/// A UTXO backing a loan keeps its lock until the loan releases it
+pub fn unlock_action(lock: &TimeLockConfig, status: Option<&UtxoStatus>, now: u64) -> UnlockAction {
+    if !is_unlocked(lock, now) {
+        return UnlockAction::Skipped("Time lock has not expired".to_string());
+    }
+
+    match status {
+        None => UnlockAction::Skipped("UTXO not found".to_string()),
+        Some(UtxoStatus::Withdrawn) => UnlockAction::Skipped("UTXO was withdrawn".to_string()),
+        Some(UtxoStatus::Locked) => {
+            UnlockAction::Deferred("UTXO backs a loan; applied once it is released".to_string())
+        }
+        Some(UtxoStatus::Deposited) => {
+            if lock.auto_withdraw {
+                UnlockAction::AutoWithdrawn
+            } else if let Some(beneficiary) = lock.beneficiary {
+                UnlockAction::TransferredTo(beneficiary)
+            } else {
+                UnlockAction::Released
+            }
+        }
+    }
+}
+
+/// Applies the unlock behaviour of an expired time lock
+pub fn process_unlock(utxo_id: UtxoId) -> UnlockAction {
+    let now = get_timestamp();
+    State::with(|state| apply_unlock(state, utxo_id, now))
+}
+
+/// Applies `unlock_action` to state; the lock is removed only once it has run
+pub(crate) fn apply_unlock(state: &mut State, utxo_id: UtxoId, now: u64) -> UnlockAction {
+    let lock = match state.time_locks.get(&utxo_id) {
+        Some(lock) => lock.clone(),
+        None => return UnlockAction::Skipped("No time lock".to_string()),
+    };
+
+    // The lock may also have been extended after this timer was armed
+    let action = unlock_action(&lock, state.utxos.get(&utxo_id).map(|u| &u.status), now);
+    if matches!(action, UnlockAction::Deferred(_)) || !is_unlocked(&lock, now) {
+        return action;
+    }
+
+    match &action {
+        UnlockAction::AutoWithdrawn => {
+            if let Some(utxo) = state.utxos.get_mut(&utxo_id) {
+                utxo.status = UtxoStatus::Withdrawn;
+            }
+        }
+        UnlockAction::TransferredTo(beneficiary) => {
+            for utxo_ids in state.user_utxos.values_mut() {
+                utxo_ids.retain(|id| *id != utxo_id);
+            }
+            state
+                .user_utxos
+                .entry(*beneficiary)
+                .or_default()
+                .push(utxo_id);
+        }
+        _ => {}
+    }
+    state.time_locks.remove(&utxo_id);
+    action
+}
@@ -1,5 +1,8 @@
+use crate::ckbtc::Account;
+use crate::solana::{SolanaAddress, SolanaNetwork, SolanaSignature};
 use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
+use std::collections::HashMap;
 
 pub type LoanId = u64;
 pub type UtxoId = u64;
@@ -9,11 +12,25 @@ pub struct Loan {
     pub id: LoanId,
     pub user_id: Principal,
     pub collateral_utxo_id: UtxoId,
-    pub borrowed_amount: u64, // in satoshis
-    pub repaid_amount: u64,   // in satoshis
-    pub interest_rate: u64,   // basis points (e.g., 500 = 5%)
-    pub created_at: u64,      // timestamp in nanoseconds
+    pub extra_collateral: Vec<UtxoId>, // Further UTXOs in the loan's collateral basket
+    pub asset: LoanAsset,              // Asset lent and repaid
+    pub borrowed_amount: u64,          // in the asset's base units (satoshis for ckBTC)
+    pub repaid_amount: u64,            // in the asset's base units
+    pub interest_rate: u64,            // basis points (e.g., 500 = 5%)
+    pub created_at: u64,               // timestamp in nanoseconds
     pub status: LoanStatus,
+    pub solana_disbursement: Option<SolanaDisbursement>, // Proceeds paid out on Solana
+    pub term: Option<LoanTerm>, // Maturity of fixed-term loans; open-ended if None
+}
+
+/// Schedule and late charges of a fixed-term loan
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct LoanTerm {
+    pub maturity: u64,     // timestamp in nanoseconds
+    pub grace_period: u64, // nanoseconds after maturity before the loan defaults
+    pub fees: u64,         // Late penalties and extension fees, in the asset's base units
+    pub penalty_days: u64, // Days past maturity already charged a penalty
+    pub extensions: u32,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
@@ -21,6 +38,68 @@ pub enum LoanStatus {
     Active,
     Repaid,
     Liquidated,
+    Cancelled, // Proceeds were never delivered
+    Defaulted, // Unpaid past maturity and grace period; can be liquidated at any LTV
+}
+
+/// ICRC-1 asset a loan is denominated in
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LoanAsset {
+    CkBtc,
+    CkUsdc,
+    CkEth,
+}
+
+/// Ledger and terms of a loan asset
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LoanAssetInfo {
+    pub asset: LoanAsset,
+    pub symbol: String,
+    pub ledger_id: Principal,
+    pub decimals: u32,
+    pub transfer_fee: u64,
+    pub interest_rate: u64, // basis points
+}
+
+/// Liquidity and interest of one loan asset, in its base units
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct AssetMarket {
+    pub asset: LoanAsset,
+    pub interest_rate: u64,       // basis points
+    pub available_liquidity: u64, // Vault balance on the asset's ledger
+    pub active_loans: u64,
+    pub outstanding_principal: u64, // Borrowed minus repaid on active loans
+    pub outstanding_debt: u64,      // Including interest
+    pub total_borrowed: u64,        // All loans ever disbursed
+    pub total_interest: u64,        // Interest charged on those loans
+}
+
+/// Asset a loan's proceeds are paid out in on Solana
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SolanaLoanAsset {
+    Sol,
+    UsdcSpl,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum DisbursementStatus {
+    Pending,   // Signed and possibly submitted, not yet confirmed
+    Confirmed, // Landed without error
+    Failed,    // Failed on-chain or expired; the loan was cancelled
+}
+
+/// Solana payout of a loan's proceeds; the debt itself stays in satoshis
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SolanaDisbursement {
+    pub asset: SolanaLoanAsset,
+    pub network: SolanaNetwork,
+    pub recipient: SolanaAddress,
+    pub amount: u64,       // Lamports or USDC base units
+    pub btc_usd_rate: u64, // Oracle prices used, scaled by 10^oracle::PRICE_DECIMALS
+    pub asset_usd_rate: u64,
+    pub signature: SolanaSignature,
+    pub signed_at: u64, // timestamp in nanoseconds
+    pub status: DisbursementStatus,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -35,6 +114,14 @@ pub struct UTXO {
     pub deposited_at: u64, // timestamp
 }
 
+/// Kind of collateral a UTXO is, which sets the LTV it can be borrowed against
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollateralClass {
+    Plain,
+    RuneBearing,
+    Inscribed,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum UtxoStatus {
     Deposited,
@@ -62,7 +149,41 @@ pub struct DepositUtxoRequest {
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct BorrowRequest {
     pub utxo_id: UtxoId,
-    pub amount: u64, // in satoshis
+    pub amount: u64,                              // in the asset's base units
+    pub asset: Option<LoanAsset>,                 // ckBTC if omitted
+    pub additional_utxo_ids: Option<Vec<UtxoId>>, // Further collateral for the basket
+    pub term_days: Option<u64>,                   // Fixed-term loan maturing after this many days
+}
+
+/// Draws additional funds on an existing loan against the same collateral
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct IncreaseLoanRequest {
+    pub loan_id: LoanId,
+    pub amount: u64, // in the loan asset's base units
+}
+
+/// Terms a borrower requests when refinancing a loan
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RateTerms {
+    pub interest_rate: u64, // basis points; within `terms::rate_bounds` for the term
+    pub term_days: Option<u64>, // New term from now; the current term is kept if omitted
+}
+
+/// Pushes back the maturity of a fixed-term loan for a fee
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ExtendLoanRequest {
+    pub loan_id: LoanId,
+    pub extra_days: u64,
+}
+
+/// Borrows against BTC collateral with the proceeds sent to a Solana address
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SolanaBorrowRequest {
+    pub utxo_id: UtxoId,
+    pub amount: u64, // Debt in satoshis; the payout is its value in `asset`
+    pub asset: SolanaLoanAsset,
+    pub recipient: String, // Solana address receiving the proceeds
+    pub network: SolanaNetwork,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -71,6 +192,13 @@ pub struct RepayRequest {
     pub amount: u64, // in satoshis
 }
 
+/// Repayment made by a plain ICRC-1 transfer to the vault, identified by its block
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BlockRepaymentRequest {
+    pub loan_id: LoanId,
+    pub block_index: u64, // Block of the loan asset's ledger
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct LoanOffer {
     pub id: u64,
@@ -104,6 +232,30 @@ pub struct LoanHealth {
     pub can_be_liquidated: bool,    // Whether loan can be liquidated
     pub collateral_value: u64,      // Collateral value in satoshis
     pub loan_value: u64,            // Current loan value in satoshis
+    pub shared_with: Vec<LoanId>,   // Other loans on the same collateral, counted in both values
+}
+
+/// Debt outstanding against one collateral UTXO, across all of its loans
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollateralDebt {
+    pub utxo_id: UtxoId,
+    pub collateral_value: u64,    // in satoshis
+    pub max_borrowable: u64,      // in satoshis, at the UTXO's class LTV (50/40/30%)
+    pub outstanding_debt: u64,    // in satoshis, including interest
+    pub available_to_borrow: u64, // in satoshis
+    pub active_loans: u32,
+}
+
+/// UTXOs backing a loan and what they can be borrowed against
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollateralBasket {
+    pub loan_id: LoanId,
+    pub utxo_ids: Vec<UtxoId>,
+    pub collateral_value: u64, // in satoshis
+    pub max_borrowable: u64,   // in satoshis, at each UTXO's LTV
+    pub weighted_ltv: u64,     // Value-weighted maximum LTV in basis points
+    pub loan_value: u64,       // Remaining debt in satoshis
+    pub current_ltv: u64,      // in basis points
 }
 
 /// User statistics
@@ -117,6 +269,21 @@ pub struct UserStats {
     pub average_ltv: u64,            // Average LTV in basis points
 }
 
+/// Ledger payout of a loan, journaled before the transfer is attempted
+/// The entry is removed once the ledger confirms or rejects the transfer
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct PendingDisbursement {
+    pub loan_id: LoanId,
+    pub asset: LoanAsset,
+    pub to: Principal,
+    pub amount: u64,          // in the asset's base units
+    pub created_at_time: u64, // Ledger dedup key, together with the memo
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub memo: Option<Vec<u8>>, // Draw memo for draws on an existing loan; loan memo if unset
+    pub fee: Option<u64>,      // Ledger fee deducted from the payout; none for older entries
+}
+
 /// Vault statistics
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct VaultStats {
@@ -128,6 +295,46 @@ pub struct VaultStats {
     pub utilization_rate: u64,        // Utilization rate in basis points
 }
 
+/// ckBTC supplied by lenders; the source of ckBTC loans
+/// Suppliers own `cash + borrowed` in proportion to their shares
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct LendingPool {
+    pub cash: u64,     // ckBTC held for suppliers, in satoshis
+    pub borrowed: u64, // Principal lent out and not yet repaid
+    pub reserves: u64, // Protocol's cut of interest; absorbs losses first
+    pub total_shares: u64,
+    pub shares: HashMap<Account, u64>, // Share token balances (see share_token)
+    pub allowances: HashMap<(Account, Account), ShareAllowance>, // (owner, spender)
+    pub share_tx_count: u64,           // Block index of the next share token transaction
+    pub recent_share_txs: HashMap<String, (u64, u64)>, // Dedup key -> (created_at_time, block)
+}
+
+/// ICRC-2 allowance on pool shares
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ShareAllowance {
+    pub amount: u64,
+    pub expires_at: Option<u64>, // nanoseconds
+}
+
+/// Lending pool overview
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PoolStats {
+    pub cash: u64,
+    pub borrowed: u64,
+    pub reserves: u64,
+    pub total_shares: u64,
+    pub exchange_rate: u64, // Satoshis per share, scaled by pool::EXCHANGE_RATE_SCALE
+    pub utilization_rate: u64, // basis points
+    pub reserve_factor: u64, // basis points of interest kept as reserves
+}
+
+/// A supplier's stake in the lending pool
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SupplierPosition {
+    pub shares: u64,
+    pub value: u64, // Current value in satoshis
+}
+
 /// Paginated loans response
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct LoansPage {
@@ -153,7 +360,7 @@ pub struct MultiSigConfig {
 /// Time-lock configuration
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct TimeLockConfig {
-    pub unlock_timestamp: u64,          // Unix timestamp when funds unlock
+    pub unlock_timestamp: u64,          // Unix timestamp (ns) when funds unlock
     pub auto_withdraw: bool,            // Automatically withdraw when unlocked
     pub beneficiary: Option<Principal>, // Optional beneficiary if user doesn't withdraw
 }
@@ -176,8 +383,148 @@ pub struct EnhancedUTXO {
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct DeadManSwitch {
     pub user_id: Principal,
-    pub last_activity: u64,        // Last activity timestamp
-    pub inactivity_threshold: u64, // Seconds of inactivity before trigger
-    pub beneficiary: Principal,    // Who receives funds if triggered
-    pub enabled: bool,             // Whether dead man switch is enabled
+    pub last_activity: u64,              // Last activity timestamp
+    pub inactivity_threshold: u64,       // Nanoseconds of inactivity before warning
+    pub beneficiary: Principal,          // Who receives funds if triggered
+    pub enabled: bool,                   // Whether dead man switch is enabled
+    pub warning_period: u64,             // Nanoseconds between warning and transfer
+    pub warning_started_at: Option<u64>, // Cleared by any user activity
+    pub triggered_at: Option<u64>,       // Set once funds were transferred
+}
+
+// ============================================================================
+// Encrypted Notes
+// ============================================================================
+
+/// Note stored encrypted under its own data key
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptedNote {
+    pub id: u64,
+    pub owner: Principal,
+    pub ciphertext: Vec<u8>, // Content under the note's data key
+    pub wrapped_keys: HashMap<Principal, Vec<u8>>, // Data key wrapped for each reader
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// ============================================================================
+// Loan Documents
+// ============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DocumentRole {
+    Borrower,     // Owns the loan, manages grants
+    Auditor,      // Read-only access for review
+    Counterparty, // Read-only access for the other side of the deal
+}
+
+/// A principal's access to a loan's documents
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DocumentGrant {
+    pub role: DocumentRole,
+    pub wrapped_key: Vec<u8>, // Loan document key wrapped for this principal by the client
+    pub granted_at: u64,
+}
+
+/// Encrypted document attached to a loan
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LoanDocument {
+    pub id: u64,
+    pub loan_id: LoanId,
+    pub name: String,
+    pub content_type: String,
+    pub total_size: u64,      // Declared ciphertext size
+    pub uploaded_size: u64,   // Ciphertext bytes received so far
+    pub chunks: Vec<Vec<u8>>, // Chunks encrypted by the client, in order
+    pub complete: bool,       // Set once every chunk is uploaded
+    pub created_at: u64,
+    pub rekeyed_chunks: Option<Vec<Vec<u8>>>, // Chunks re-encrypted under a fresh loan key, until committed
+}
+
+/// Document metadata without content
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LoanDocumentInfo {
+    pub id: u64,
+    pub loan_id: LoanId,
+    pub name: String,
+    pub content_type: String,
+    pub total_size: u64,
+    pub chunk_count: u32,
+    pub complete: bool,
+    pub created_at: u64,
+}
+
+// ============================================================================
+// Key Rotation
+// ============================================================================
+
+/// vetKD key version protecting a user's encrypted data
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct UserKeyState {
+    pub current_version: u32,             // Version new data is encrypted under
+    pub rotation_started_at: Option<u64>, // Set while older ciphertexts are re-encrypted
+    pub last_rotated_at: Option<u64>,     // When the last rotation finished
+    pub failures: Option<Vec<RotationFailure>>, // Items the last rotation failed on
+}
+
+/// Item a key rotation failed to move to the new key version
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RotationFailure {
+    pub item: RotationItem,
+    pub key_version: u32, // Version the item is still wrapped under
+    pub error: String,    // Last error the client reported
+    pub attempts: u32,
+    pub skipped: bool, // Left behind; the rotation finishes without it
+    pub failed_at: u64,
+}
+
+/// Stored data whose key a user has to move to their new key version
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RotationItem {
+    Note(u64),             // A note the user owns or can read
+    LoanDocuments(LoanId), // The documents of a loan the user borrowed or can read
+}
+
+/// One item of a key rotation batch
+/// Owners re-encrypt the content under a fresh data key and wrap it for every
+/// reader; other readers re-wrap their own copy of the key
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RotationTask {
+    pub item: RotationItem,
+    pub wrapped_key: Vec<u8>, // User's copy of the data key, under an older version
+    pub owner: bool,
+    pub readers: Vec<(Principal, u32)>, // Readers and their key versions, for owners
+}
+
+/// Progress of a user's key rotation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct KeyRotationStatus {
+    pub current_version: u32,
+    pub in_progress: bool,
+    pub remaining: u32, // Wrapped keys still under an older version
+    pub rotation_started_at: Option<u64>,
+    pub last_rotated_at: Option<u64>,
+    pub failures: Vec<RotationFailure>,
+}
+
+// ============================================================================
+// MuSig2 Vaults
+// ============================================================================
+
+/// 2-of-2 Taproot vault shared between a user and the canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MuSigVault {
+    pub id: u64,
+    pub user_id: Principal,
+    pub user_public_key: Vec<u8>,      // 33-byte compressed user key
+    pub canister_public_key: Vec<u8>,  // 33-byte compressed threshold Schnorr key
+    pub internal_key: Vec<u8>,         // 32-byte x-only MuSig2 aggregate key
+    pub cosign_script: Vec<u8>,        // <user> OP_CHECKSIGVERIFY <canister> OP_CHECKSIG
+    pub cosign_control_block: Vec<u8>, // Proof for spending the 2-of-2 leaf
+    pub refund_script: Vec<u8>,        // <delay> OP_CSV OP_DROP <user> OP_CHECKSIG
+    pub refund_control_block: Vec<u8>,
+    pub merkle_root: Vec<u8>, // Script tree root committed in the output key
+    pub refund_delay_blocks: u16, // Blocks before the user can refund alone
+    pub address: String,      // P2TR address for deposits
+    pub created_at: u64,
 }
@@ -1,248 +1,554 @@
 // vetKeys Integration
-// Provides secure encryption, threshold decryption, and privacy-preserving features
+// Derives per-identity keys with vetKD (BLS12-381) and encrypts data with AES-256-GCM
+//
+// Keys are always requested under a transport key, so the management canister
+// only ever returns them encrypted. A user's own key is derived under a transport
+// key the client supplies and handed back still encrypted: only the client can
+// decrypt it, and it encrypts and decrypts its data locally.
+// Notes and loan documents are encrypted by the client under their own data
+// keys. The client wraps a data key for another principal with vetKD
+// identity-based encryption to that principal's identity (`versioned_key_id` at
+// their current key version); the canister only stores ciphertexts and wrapped keys.
 
+use crate::state::State;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, Scalar};
 use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::management_canister::{VetKDCurve, VetKDDeriveKeyArgs, VetKDKeyId, VetKDPublicKeyArgs};
 use serde::Serialize;
-use std::convert::TryInto;
+use std::collections::HashMap;
 
-/// vetKeys Canister ID
-/// vetKeys provides threshold encryption/decryption services
-const VETKEYS_CANISTER_ID: &str = "qjdve-lqaaa-aaaaa-aaaeq-cai"; // Example - update with actual canister ID
+/// Derivation context shared by every key this canister derives
+pub const CONTEXT: &[u8] = b"bitfold-vault-encryption";
 
-/// Encryption request
-#[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct EncryptRequest {
-    pub plaintext: Vec<u8>,           // Data to encrypt
-    pub public_key_id: String,        // vetKeys public key ID
-    pub associated_data: Option<Vec<u8>>, // Optional associated data
-}
+/// Domain separator for turning a vetKey into an AES-256-GCM key
+pub const SYMMETRIC_KEY_DOMAIN: &[u8] = b"bitfold-aes-256-gcm";
 
-/// Encryption response
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
-pub struct EncryptResponse {
-    pub ciphertext: Vec<u8>,          // Encrypted data
-    pub public_key_id: String,        // Public key used
-}
+/// Ciphertext format: version (1) || nonce (12) || AES-GCM ciphertext and tag
+pub const CIPHERTEXT_VERSION: u8 = 1;
+pub const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
 
-/// Decryption request
-#[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct DecryptRequest {
-    pub ciphertext: Vec<u8>,          // Encrypted data
-    pub public_key_id: String,        // vetKeys public key ID
-    pub associated_data: Option<Vec<u8>>, // Optional associated data
-}
+/// Ciphertext format for data under a versioned identity key:
+/// version (2) || key version (4, big-endian) || nonce (12) || AES-GCM ciphertext and tag
+/// The header is authenticated together with the associated data.
+pub const KEYED_CIPHERTEXT_VERSION: u8 = 2;
+const KEY_VERSION_LEN: usize = 4;
 
-/// Decryption response
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
-pub struct DecryptResponse {
-    pub plaintext: Vec<u8>,           // Decrypted data
-}
+/// First key version of every user; its derivation input predates versioning
+pub const INITIAL_KEY_VERSION: u32 = 1;
+
+/// Maximum size of a data key the client wrapped for one principal
+pub const MAX_WRAPPED_KEY_SIZE: usize = 1024;
+
+const G1_LEN: usize = 48;
+const G2_LEN: usize = 96;
+const ENCRYPTED_KEY_LEN: usize = G1_LEN + G2_LEN + G1_LEN;
+
+/// Hash-to-curve DST used by vetKD for the augmented BLS signature
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_AUG_";
 
 /// Public key information
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct VetKeyInfo {
     pub public_key_id: String,
-    pub public_key: Vec<u8>,
-    pub algorithm: String,            // e.g., "BLS12_381"
-}
-
-/// Encrypts sensitive data using vetKeys
-/// This is useful for storing private keys, user data, etc.
-/// Uses deterministic encryption based on public key ID and associated data
-pub async fn encrypt_data(request: EncryptRequest) -> Result<EncryptResponse, String> {
-    ic_cdk::println!("🔐 Encrypting data with vetKeys (public key: {})", request.public_key_id);
-    
-    // In production, this would call the vetKeys canister
-    // For now, we'll use deterministic encryption based on key ID
-    
-    // Create encryption key from public key ID and associated data
-    let mut key_material = request.public_key_id.as_bytes().to_vec();
-    if let Some(ad) = &request.associated_data {
-        key_material.extend_from_slice(ad);
-    }
-    
-    // Generate deterministic encryption key (simplified XOR cipher)
-    // In production, would use proper encryption with vetKeys canister
-    let key = generate_key_from_material(&key_material);
-    
-    // Encrypt using XOR (simplified - in production would use proper encryption)
-    let mut ciphertext = Vec::with_capacity(request.plaintext.len());
-    for (i, byte) in request.plaintext.iter().enumerate() {
-        ciphertext.push(byte ^ key[i % key.len()]);
-    }
-    
-    // Add metadata: length prefix for proper decryption
-    let mut encrypted = vec![(request.plaintext.len() as u32).to_le_bytes().to_vec()];
-    encrypted.push(ciphertext);
-    
-    let final_ciphertext = encrypted.concat();
-    
-    ic_cdk::println!("✅ Encrypted {} bytes to {} bytes", 
-        request.plaintext.len(), final_ciphertext.len());
-    
-    Ok(EncryptResponse {
-        ciphertext: final_ciphertext,
-        public_key_id: request.public_key_id,
-    })
+    pub public_key: Vec<u8>, // 96-byte derived public key (G2)
+    pub algorithm: String,   // e.g., "BLS12_381_G2"
 }
 
-/// Generates a deterministic key from key material
-fn generate_key_from_material(material: &[u8]) -> Vec<u8> {
-    // Simple hash-based key generation
-    // In production, would use proper key derivation
-    let mut key = vec![0u8; 32];
-    for (i, byte) in material.iter().enumerate() {
-        key[i % 32] ^= byte;
+// ============================================================================
+// Transport keys and vetKeys
+// ============================================================================
+
+/// Ephemeral key the derived vetKey is encrypted to
+pub struct TransportSecretKey {
+    secret: Scalar,
+}
+
+impl TransportSecretKey {
+    /// Builds a transport key from 32 bytes of fresh randomness
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Self {
+            secret: scalar_from_seed(b"bitfold-transport-key", seed),
+        }
     }
-    key
+
+    /// 48-byte compressed G1 public key to send with `vetkd_derive_key`
+    pub fn public_key(&self) -> Vec<u8> {
+        G1Affine::from(G1Affine::generator() * self.secret)
+            .to_compressed()
+            .to_vec()
+    }
+
+    /// Decrypts an encrypted vetKey and checks it is a valid BLS signature on `input`
+    pub fn decrypt_and_verify(
+        &self,
+        encrypted_key: &[u8],
+        derived_public_key: &[u8],
+        input: &[u8],
+    ) -> Result<VetKey, String> {
+        if encrypted_key.len() != ENCRYPTED_KEY_LEN {
+            return Err(format!("Encrypted key must be {} bytes", ENCRYPTED_KEY_LEN));
+        }
+        let dpk = g2_from_bytes(derived_public_key)?;
+        let c1 = g1_from_bytes(&encrypted_key[..G1_LEN])?;
+        let c2 = g2_from_bytes(&encrypted_key[G1_LEN..G1_LEN + G2_LEN])?;
+        let c3 = g1_from_bytes(&encrypted_key[G1_LEN + G2_LEN..])?;
+
+        // c1 and c2 must share the same randomness
+        if pairing(&c1, &G2Affine::generator()) != pairing(&G1Affine::generator(), &c2) {
+            return Err("Invalid encrypted key".to_string());
+        }
+
+        let key = G1Affine::from(G1Projective::from(c3) - c1 * self.secret);
+        let msg = augmented_hash(&dpk, input);
+        if pairing(&key, &G2Affine::generator()) != pairing(&msg, &dpk) {
+            return Err("vetKey verification failed".to_string());
+        }
+
+        Ok(VetKey { key })
+    }
+}
+
+/// Decrypted vetKey: a BLS signature on the derivation input
+pub struct VetKey {
+    key: G1Affine,
 }
 
-/// Decrypts data using vetKeys threshold decryption
-/// Requires threshold number of nodes to decrypt
-/// Uses deterministic decryption matching the encryption
-pub async fn decrypt_data(request: DecryptRequest) -> Result<DecryptResponse, String> {
-    ic_cdk::println!("🔓 Decrypting data with vetKeys (public key: {})", request.public_key_id);
-    
-    if request.ciphertext.len() < 4 {
+impl VetKey {
+    pub fn to_bytes(&self) -> [u8; G1_LEN] {
+        self.key.to_compressed()
+    }
+
+    /// Derives a 32-byte symmetric key for the given domain (HKDF-SHA256)
+    pub fn derive_symmetric_key(&self, domain: &[u8]) -> [u8; 32] {
+        hkdf_sha256(&self.to_bytes(), domain)
+    }
+}
+
+/// In-process stand-in for the management canister's vetKD API
+/// Used for tests and on the playground network, where vetKD is unavailable.
+/// Anyone who knows the seed can derive every key, so it must never hold real data.
+pub struct LocalVetKd {
+    master_secret: Scalar,
+}
+
+impl LocalVetKd {
+    pub fn new(seed: &[u8; 32]) -> Self {
+        Self {
+            master_secret: scalar_from_seed(b"bitfold-local-vetkd", seed),
+        }
+    }
+
+    fn derived_secret(&self, context: &[u8]) -> Scalar {
+        self.master_secret
+            + scalar_from_seed(
+                b"bitfold-local-vetkd-context",
+                &sha256::Hash::hash(context).to_byte_array(),
+            )
+    }
+
+    /// Equivalent of `vetkd_public_key` for this canister and context
+    pub fn public_key(&self, context: &[u8]) -> Vec<u8> {
+        G2Affine::from(G2Affine::generator() * self.derived_secret(context))
+            .to_compressed()
+            .to_vec()
+    }
+
+    /// Equivalent of `vetkd_derive_key`: returns the key encrypted to the transport key
+    pub fn derive_key(
+        &self,
+        input: &[u8],
+        context: &[u8],
+        transport_public_key: &[u8],
+        rand: &[u8; 32],
+    ) -> Result<Vec<u8>, String> {
+        let tpk = g1_from_bytes(transport_public_key)?;
+        let secret = self.derived_secret(context);
+        let dpk = G2Affine::from(G2Affine::generator() * secret);
+        let key = G1Projective::from(augmented_hash(&dpk, input)) * secret;
+
+        let r = scalar_from_seed(b"bitfold-local-vetkd-encrypt", rand);
+        let c1 = G1Affine::from(G1Affine::generator() * r);
+        let c2 = G2Affine::from(G2Affine::generator() * r);
+        let c3 = G1Affine::from(key + tpk * r);
+
+        let mut encrypted = c1.to_compressed().to_vec();
+        encrypted.extend_from_slice(&c2.to_compressed());
+        encrypted.extend_from_slice(&c3.to_compressed());
+        Ok(encrypted)
+    }
+}
+
+// ============================================================================
+// Authenticated encryption
+// ============================================================================
+
+/// Encrypts with AES-256-GCM under a fresh 12-byte nonce
+pub fn encrypt_with_key(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_LEN],
+    plaintext: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| format!("Invalid key: {}", e))?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    let mut out = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+    out.push(CIPHERTEXT_VERSION);
+    out.extend_from_slice(nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Encrypts like `encrypt_with_key`, recording which key version was used
+pub fn encrypt_with_key_version(
+    key: &[u8; 32],
+    key_version: u32,
+    nonce: &[u8; NONCE_LEN],
+    plaintext: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let header = keyed_header(key_version);
+    let mut aad = header.clone();
+    aad.extend_from_slice(associated_data);
+
+    let mut out = encrypt_with_key(key, nonce, plaintext, &aad)?;
+    out.splice(..1, header);
+    Ok(out)
+}
+
+/// Reads the key version from a ciphertext header
+/// Ciphertexts without a key version were made with the initial key
+pub fn ciphertext_key_version(ciphertext: &[u8]) -> Result<u32, String> {
+    match ciphertext.first() {
+        Some(&CIPHERTEXT_VERSION) => Ok(INITIAL_KEY_VERSION),
+        Some(&KEYED_CIPHERTEXT_VERSION) => ciphertext
+            .get(1..1 + KEY_VERSION_LEN)
+            .and_then(|b| <[u8; KEY_VERSION_LEN]>::try_from(b).ok())
+            .map(u32::from_be_bytes)
+            .ok_or("Invalid ciphertext: too short".to_string()),
+        Some(version) => Err(format!("Unsupported ciphertext version: {}", version)),
+        None => Err("Invalid ciphertext: too short".to_string()),
+    }
+}
+
+/// Decrypts and authenticates a ciphertext produced by `encrypt_with_key`
+/// or `encrypt_with_key_version`
+pub fn decrypt_with_key(
+    key: &[u8; 32],
+    ciphertext: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let (header_len, aad) = match ciphertext.first() {
+        Some(&KEYED_CIPHERTEXT_VERSION) => {
+            let header = keyed_header(ciphertext_key_version(ciphertext)?);
+            let mut aad = header.clone();
+            aad.extend_from_slice(associated_data);
+            (header.len(), aad)
+        }
+        Some(&CIPHERTEXT_VERSION) => (1, associated_data.to_vec()),
+        Some(version) => return Err(format!("Unsupported ciphertext version: {}", version)),
+        None => return Err("Invalid ciphertext: too short".to_string()),
+    };
+    if ciphertext.len() < header_len + NONCE_LEN + TAG_LEN {
         return Err("Invalid ciphertext: too short".to_string());
     }
-    
-    // Extract length prefix
-    let length_bytes: [u8; 4] = request.ciphertext[..4].try_into()
-        .map_err(|_| "Failed to extract length".to_string())?;
-    let plaintext_len = u32::from_le_bytes(length_bytes) as usize;
-    
-    if request.ciphertext.len() < 4 + plaintext_len {
-        return Err("Invalid ciphertext: length mismatch".to_string());
-    }
-    
-    let encrypted_data = &request.ciphertext[4..4 + plaintext_len];
-    
-    // Generate decryption key (same as encryption)
-    let mut key_material = request.public_key_id.as_bytes().to_vec();
-    if let Some(ad) = &request.associated_data {
-        key_material.extend_from_slice(ad);
-    }
-    
-    let key = generate_key_from_material(&key_material);
-    
-    // Decrypt using XOR (same as encryption - XOR is symmetric)
-    let mut plaintext = Vec::with_capacity(plaintext_len);
-    for (i, byte) in encrypted_data.iter().enumerate() {
-        plaintext.push(byte ^ key[i % key.len()]);
-    }
-    
-    ic_cdk::println!("✅ Decrypted {} bytes from {} bytes", 
-        plaintext.len(), request.ciphertext.len());
-    
-    Ok(DecryptResponse {
-        plaintext,
-    })
+
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| format!("Invalid key: {}", e))?;
+    cipher
+        .decrypt(
+            Nonce::from_slice(&ciphertext[header_len..header_len + NONCE_LEN]),
+            Payload {
+                msg: &ciphertext[header_len + NONCE_LEN..],
+                aad: &aad,
+            },
+        )
+        .map_err(|_| "Decryption failed: wrong key or tampered ciphertext".to_string())
+}
+
+fn keyed_header(key_version: u32) -> Vec<u8> {
+    let mut header = vec![KEYED_CIPHERTEXT_VERSION];
+    header.extend_from_slice(&key_version.to_be_bytes());
+    header
 }
 
-/// Creates a new vetKeys public key
-/// This generates a new threshold encryption key
-/// Uses deterministic key generation based on canister ID and timestamp
-pub async fn create_vetkey() -> Result<VetKeyInfo, String> {
-    ic_cdk::println!("🔑 Creating new vetKeys public key");
-    
-    // Generate deterministic public key from canister ID and timestamp
-    // In production, would call vetKeys canister for actual key generation
-    let canister_id = ic_cdk::api::id();
-    let timestamp = ic_cdk::api::time();
-    
-    let key_id = format!("vetkey_{}_{}", canister_id, timestamp);
-    
-    // Generate deterministic public key (48 bytes for BLS12_381)
-    let mut public_key = vec![0u8; 48];
-    let canister_bytes = canister_id.as_slice();
-    let timestamp_bytes = timestamp.to_le_bytes();
-    
-    // Combine canister ID and timestamp for deterministic key
-    for i in 0..48 {
-        if i < canister_bytes.len() {
-            public_key[i] = canister_bytes[i];
+// ============================================================================
+// Client-wrapped keys
+// ============================================================================
+
+/// Checks a data key the client wrapped for a principal
+/// It must start with the keyed ciphertext header naming `key_version`, the
+/// principal's current key version, so a rotation can find it later
+pub fn validate_wrapped_key(wrapped_key: &[u8], key_version: u32) -> Result<(), String> {
+    if wrapped_key.first() != Some(&KEYED_CIPHERTEXT_VERSION) {
+        return Err("Wrapped key must start with a key version header".to_string());
+    }
+    if wrapped_key.len() <= 1 + KEY_VERSION_LEN || wrapped_key.len() > MAX_WRAPPED_KEY_SIZE {
+        return Err(format!(
+            "Wrapped key must be {}-{} bytes",
+            2 + KEY_VERSION_LEN,
+            MAX_WRAPPED_KEY_SIZE
+        ));
+    }
+    let version = ciphertext_key_version(wrapped_key)?;
+    if version != key_version {
+        return Err(format!(
+            "Wrapped key is for key version {}, expected {}",
+            version, key_version
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `wrapped_keys` holds exactly one valid wrapped key for each of
+/// `readers`, under that reader's current key version
+pub fn validate_wrapped_keys(
+    readers: &[Principal],
+    wrapped_keys: Vec<(Principal, Vec<u8>)>,
+    key_version: impl Fn(Principal) -> u32,
+) -> Result<HashMap<Principal, Vec<u8>>, String> {
+    let mut keys = HashMap::new();
+    for (principal, wrapped_key) in wrapped_keys {
+        if !readers.contains(&principal) {
+            return Err(format!("{} has no access", principal));
         }
-        if i < 8 {
-            public_key[i] ^= timestamp_bytes[i];
+        validate_wrapped_key(&wrapped_key, key_version(principal))?;
+        if keys.insert(principal, wrapped_key).is_some() {
+            return Err(format!("Duplicate wrapped key for {}", principal));
         }
     }
-    
-    ic_cdk::println!("✅ Created vetKeys public key: {}", key_id);
-    
-    Ok(VetKeyInfo {
-        public_key_id: key_id,
-        public_key,
-        algorithm: "BLS12_381".to_string(),
+    if let Some(missing) = readers.iter().find(|reader| !keys.contains_key(reader)) {
+        return Err(format!("Missing wrapped key for {}", missing));
+    }
+    Ok(keys)
+}
+
+// ============================================================================
+// Management canister calls
+// ============================================================================
+
+fn key_id() -> VetKDKeyId {
+    let network = std::env::var("DFX_NETWORK").unwrap_or_else(|_| "local".to_string());
+    VetKDKeyId {
+        curve: VetKDCurve::Bls12_381_G2,
+        name: if network == "ic" {
+            "key_1"
+        } else {
+            "dfx_test_key"
+        }
+        .to_string(),
+    }
+}
+
+fn use_local_vetkd() -> bool {
+    std::env::var("DFX_NETWORK")
+        .map(|n| n == "playground")
+        .unwrap_or(false)
+}
+
+fn local_vetkd() -> LocalVetKd {
+    let canister = ic_cdk::api::canister_self();
+    LocalVetKd::new(&sha256::Hash::hash(canister.as_slice()).to_byte_array())
+}
+
+/// Gets the derived public key for `context` (`vetkd_public_key`)
+pub async fn vetkd_public_key(context: &[u8]) -> Result<Vec<u8>, String> {
+    if use_local_vetkd() {
+        ic_cdk::println!("⚠️ Using local vetKD stand-in on playground");
+        return Ok(local_vetkd().public_key(context));
+    }
+
+    let result = ic_cdk::management_canister::vetkd_public_key(&VetKDPublicKeyArgs {
+        canister_id: None,
+        context: context.to_vec(),
+        key_id: key_id(),
     })
+    .await
+    .map_err(|e| format!("vetkd_public_key failed: {:?}", e))?;
+
+    Ok(result.public_key)
 }
 
-/// Gets vetKeys public key information
+/// Derives the key for `input`, encrypted to `transport_public_key` (`vetkd_derive_key`)
+pub async fn vetkd_derive_key(
+    input: &[u8],
+    context: &[u8],
+    transport_public_key: &[u8],
+) -> Result<Vec<u8>, String> {
+    if use_local_vetkd() {
+        ic_cdk::println!("⚠️ Using local vetKD stand-in on playground");
+        let rand = random_bytes::<32>().await?;
+        return local_vetkd().derive_key(input, context, transport_public_key, &rand);
+    }
+
+    let result = ic_cdk::management_canister::vetkd_derive_key(&VetKDDeriveKeyArgs {
+        input: input.to_vec(),
+        context: context.to_vec(),
+        transport_public_key: transport_public_key.to_vec(),
+        key_id: key_id(),
+    })
+    .await
+    .map_err(|e| format!("vetkd_derive_key failed: {:?}", e))?;
+
+    Ok(result.encrypted_key)
+}
+
+/// Fresh randomness from the management canister
+pub async fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let bytes = ic_cdk::management_canister::raw_rand()
+        .await
+        .map_err(|e| format!("raw_rand failed: {:?}", e))?;
+    bytes
+        .get(..N)
+        .and_then(|b| <[u8; N]>::try_from(b).ok())
+        .ok_or("raw_rand returned too few bytes".to_string())
+}
+
+// ============================================================================
+// Client keys
+// ============================================================================
+
+/// Gets the derived vetKD public key used for all identities of this canister
 pub async fn get_vetkey(public_key_id: &str) -> Result<VetKeyInfo, String> {
     ic_cdk::println!("🔍 Getting vetKeys public key: {}", public_key_id);
-    
-    // In production, this would query the vetKeys canister
-    
-    // Placeholder
+
     Ok(VetKeyInfo {
         public_key_id: public_key_id.to_string(),
-        public_key: vec![0u8; 48],
-        algorithm: "BLS12_381".to_string(),
+        public_key: vetkd_public_key(CONTEXT).await?,
+        algorithm: "BLS12_381_G2".to_string(),
     })
 }
 
-/// Encrypts user's private keys for secure storage
-/// This is useful for password managers or secure note applications
-pub async fn encrypt_user_secrets(
-    user_id: Principal,
-    secrets: Vec<u8>,
-) -> Result<EncryptResponse, String> {
-    ic_cdk::println!("🔐 Encrypting user secrets for: {}", user_id);
-    
-    // Create or get user's vetKeys public key
-    let key_id = format!("user_{}", user_id);
-    
-    // Encrypt the secrets
-    encrypt_data(EncryptRequest {
-        plaintext: secrets,
-        public_key_id: key_id.clone(),
-        associated_data: Some(user_id.as_slice().to_vec()),
-    })
-    .await
+/// vetKD derivation input for a user's own data
+pub fn user_key_id(user_id: Principal) -> String {
+    format!("user_{}", user_id)
 }
 
-/// Decrypts user's private keys
-pub async fn decrypt_user_secrets(
-    user_id: Principal,
-    ciphertext: Vec<u8>,
-) -> Result<DecryptResponse, String> {
-    ic_cdk::println!("🔓 Decrypting user secrets for: {}", user_id);
-    
-    let key_id = format!("user_{}", user_id);
-    
-    decrypt_data(DecryptRequest {
-        ciphertext,
-        public_key_id: key_id,
-        associated_data: Some(user_id.as_slice().to_vec()),
+/// vetKD derivation input for a specific version of a user's key
+/// The initial version keeps the unversioned input so existing data stays readable
+pub fn versioned_key_id(user_id: Principal, key_version: u32) -> String {
+    if key_version <= INITIAL_KEY_VERSION {
+        user_key_id(user_id)
+    } else {
+        format!("user_{}_v{}", user_id, key_version)
+    }
+}
+
+/// Key version new data for `user_id` is encrypted under
+pub fn current_key_version(user_id: Principal) -> u32 {
+    State::with_read(|state| {
+        state
+            .user_key_versions
+            .get(&user_id)
+            .map(|keys| keys.current_version)
+            .unwrap_or(INITIAL_KEY_VERSION)
     })
-    .await
 }
 
-/// Creates an encrypted note (for secure note applications)
-pub async fn create_encrypted_note(
-    user_id: Principal,
-    note_content: String,
-) -> Result<EncryptResponse, String> {
-    encrypt_user_secrets(user_id, note_content.into_bytes()).await
+/// Checks that `user_id` may still derive `key_version`
+/// Older versions stay available only while a rotation moves data off them, or
+/// while an item the rotation skipped is still wrapped under them
+pub fn ensure_key_version(user_id: Principal, key_version: u32) -> Result<(), String> {
+    let (current, rotating, retained) = State::with_read(|state| {
+        state
+            .user_key_versions
+            .get(&user_id)
+            .map(|keys| {
+                let retained: Vec<u32> = keys
+                    .failures
+                    .iter()
+                    .flatten()
+                    .filter(|failure| failure.skipped)
+                    .map(|failure| failure.key_version)
+                    .collect();
+                (
+                    keys.current_version,
+                    keys.rotation_started_at.is_some(),
+                    retained,
+                )
+            })
+            .unwrap_or((INITIAL_KEY_VERSION, false, Vec::new()))
+    });
+    is_derivable(key_version, current, rotating, &retained)
 }
 
-/// Retrieves and decrypts an encrypted note
-pub async fn get_encrypted_note(
-    user_id: Principal,
-    ciphertext: Vec<u8>,
-) -> Result<String, String> {
-    let decrypted = decrypt_user_secrets(user_id, ciphertext).await?;
-    String::from_utf8(decrypted.plaintext)
-        .map_err(|e| format!("Failed to decode note: {}", e))
+/// Whether `key_version` can be derived for a user at `current_version`
+/// `retained_versions` are older versions skipped rotation items still use
+pub fn is_derivable(
+    key_version: u32,
+    current_version: u32,
+    rotating: bool,
+    retained_versions: &[u32],
+) -> Result<(), String> {
+    if key_version < INITIAL_KEY_VERSION || key_version > current_version {
+        return Err(format!("Unknown key version: {}", key_version));
+    }
+    if key_version < current_version && !rotating && !retained_versions.contains(&key_version) {
+        return Err(format!(
+            "Key version {} was retired by a key rotation",
+            key_version
+        ));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// H(dpk || input) on G1, as signed by vetKD
+fn augmented_hash(derived_public_key: &G2Affine, input: &[u8]) -> G1Affine {
+    let mut msg = derived_public_key.to_compressed().to_vec();
+    msg.extend_from_slice(input);
+    G1Affine::from(
+        <G1Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(&msg, BLS_DST),
+    )
+}
+
+fn scalar_from_seed(domain: &[u8], seed: &[u8; 32]) -> Scalar {
+    let mut wide = [0u8; 64];
+    for (i, chunk) in wide.chunks_mut(32).enumerate() {
+        let mut engine = sha256::Hash::engine();
+        engine.input(domain);
+        engine.input(seed);
+        engine.input(&[i as u8]);
+        chunk.copy_from_slice(&sha256::Hash::from_engine(engine).to_byte_array());
+    }
+    Scalar::from_bytes_wide(&wide)
+}
+
+fn g1_from_bytes(bytes: &[u8]) -> Result<G1Affine, String> {
+    let bytes = <[u8; G1_LEN]>::try_from(bytes)
+        .map_err(|_| format!("G1 point must be {} bytes", G1_LEN))?;
+    Option::from(G1Affine::from_compressed(&bytes)).ok_or("Invalid G1 point".to_string())
 }
 
+fn g2_from_bytes(bytes: &[u8]) -> Result<G2Affine, String> {
+    let bytes = <[u8; G2_LEN]>::try_from(bytes)
+        .map_err(|_| format!("G2 point must be {} bytes", G2_LEN))?;
+    Option::from(G2Affine::from_compressed(&bytes)).ok_or("Invalid G2 point".to_string())
+}
+
+/// HKDF-SHA256 with an empty salt, producing a single 32-byte block
+fn hkdf_sha256(ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut extract = hmac::HmacEngine::<sha256::Hash>::new(&[0u8; 32]);
+    extract.input(ikm);
+    let prk = hmac::Hmac::<sha256::Hash>::from_engine(extract);
+
+    let mut expand = hmac::HmacEngine::<sha256::Hash>::new(&prk.to_byte_array());
+    expand.input(info);
+    expand.input(&[1u8]);
+    hmac::Hmac::<sha256::Hash>::from_engine(expand).to_byte_array()
+}
@@ -111,7 +111,7 @@ mod deposit_utxo_tests {
 mod borrow_tests {
     use super::*;
     use vault::helpers::calculate_max_borrowable;
-    use vault::types::{UTXO, UtxoStatus, Loan, LoanStatus};
+    use vault::types::{UTXO, UtxoStatus, Loan, LoanAsset, LoanStatus};
     use candid::Principal;
 
     proptest! {
@@ -223,11 +223,15 @@ mod borrow_tests {
                 id: loan_id,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: utxo_id,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount: borrow_amount,
                 repaid_amount: 0,
                 interest_rate: 500,
                 created_at: 0,
                 status: LoanStatus::Active,
+                solana_disbursement: None,
+                term: None,
             };
 
             // Lock the UTXO
@@ -362,11 +366,20 @@ mod borrow_tests {
 #[cfg(test)]
 mod repay_tests {
     use super::*;
-    use vault::helpers::{calculate_loan_value, is_loan_repaid};
-    use vault::types::{Loan, LoanStatus};
+    use vault::helpers::{calculate_loan_value, is_loan_repaid, loan_memo};
+    use vault::types::{Loan, LoanAsset, LoanStatus};
     use candid::Principal;
 
     proptest! {
+        #[test]
+        // Repayments are pulled with icrc2_transfer_from and tagged with the loan ID
+        fn prop_loan_memo_identifies_loan(loan_id in any::<u64>(), other in any::<u64>()) {
+            let memo = loan_memo(loan_id);
+            prop_assert_eq!(memo.len(), 8);
+            prop_assert_eq!(u64::from_be_bytes(memo.clone().try_into().unwrap()), loan_id);
+            prop_assert_eq!(memo == loan_memo(other), loan_id == other);
+        }
+
         #[test]
         // Feature: bitfold-vault-integration, Property 13: Full repayment unlocks collateral
         // Validates: Requirements 5.3
@@ -379,14 +392,18 @@ mod repay_tests {
                 id: 1,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: 1,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount,
                 repaid_amount: borrowed_amount, // Fully repaid (ignoring interest for simplicity)
                 interest_rate,
                 created_at: 0,
                 status: LoanStatus::Active,
+                solana_disbursement: None,
+                term: None,
             };
 
-            let loan_value = calculate_loan_value(&loan);
+            let loan_value = calculate_loan_value(&loan).unwrap();
             
             // If repaid amount equals borrowed amount (simple interest = 0 for this test)
             if loan.repaid_amount >= borrowed_amount {
@@ -408,14 +425,18 @@ mod repay_tests {
                 id: 1,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: 1,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount,
                 repaid_amount,
                 interest_rate,
                 created_at: 0,
                 status: LoanStatus::Active,
+                solana_disbursement: None,
+                term: None,
             };
 
-            let loan_value = calculate_loan_value(&loan);
+            let loan_value = calculate_loan_value(&loan).unwrap();
             
             // If repaid amount is less than borrowed amount
             if repaid_amount < borrowed_amount {
@@ -439,14 +460,18 @@ mod repay_tests {
                 id: 1,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: 1,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount,
                 repaid_amount,
                 interest_rate,
                 created_at: 0,
                 status: LoanStatus::Active,
+                solana_disbursement: None,
+                term: None,
             };
 
-            let loan_value = calculate_loan_value(&loan);
+            let loan_value = calculate_loan_value(&loan).unwrap();
             let interest = (borrowed_amount * interest_rate) / 10000;
             let expected = borrowed_amount + interest - repaid_amount;
             
@@ -467,7 +492,7 @@ mod repay_tests {
 #[cfg(test)]
 mod withdraw_tests {
     use super::*;
-    use vault::types::{UTXO, UtxoStatus, Loan, LoanStatus};
+    use vault::types::{UTXO, UtxoStatus, Loan, LoanAsset, LoanStatus};
     use candid::Principal;
 
     proptest! {
@@ -494,11 +519,15 @@ mod withdraw_tests {
                 id: 1,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: utxo.id,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount,
                 repaid_amount: 0,
                 interest_rate: 500,
                 created_at: 0,
                 status: LoanStatus::Active,
+                solana_disbursement: None,
+                term: None,
             };
 
             // Property: If loan is active and collateral_utxo_id matches, withdrawal should fail
@@ -558,11 +587,15 @@ mod withdraw_tests {
                 id: 1,
                 user_id: Principal::anonymous(),
                 collateral_utxo_id: utxo.id,
+                extra_collateral: vec![],
+                asset: LoanAsset::CkBtc,
                 borrowed_amount,
                 repaid_amount: borrowed_amount,
                 interest_rate: 0, // No interest for simplicity
                 created_at: 0,
                 status: LoanStatus::Repaid,
+                solana_disbursement: None,
+                term: None,
             };
 
             // Property: If loan is repaid, withdrawal should be allowed
@@ -0,0 +1,172 @@
+// Unit tests for the loan asset registry and cross-asset valuation
+
+use candid::Principal;
+use vault::assets;
+use vault::oracle::{UsdPrice, PRICE_DECIMALS};
+use vault::types::{Loan, LoanAsset, LoanStatus};
+
+fn price(dollars: u64) -> UsdPrice {
+    UsdPrice {
+        rate: dollars * 10u64.pow(PRICE_DECIMALS),
+        timestamp: 0,
+    }
+}
+
+fn loan(id: u64, asset: LoanAsset, borrowed: u64, repaid: u64, status: LoanStatus) -> Loan {
+    Loan {
+        id,
+        user_id: Principal::anonymous(),
+        collateral_utxo_id: 1,
+        extra_collateral: vec![],
+        asset,
+        borrowed_amount: borrowed,
+        repaid_amount: repaid,
+        interest_rate: assets::config(asset).interest_rate,
+        created_at: 0,
+        status,
+        solana_disbursement: None,
+        term: None,
+    }
+}
+
+#[test]
+fn test_registry_covers_every_asset() {
+    for asset in [LoanAsset::CkBtc, LoanAsset::CkUsdc, LoanAsset::CkEth] {
+        let info = assets::info(asset).unwrap();
+        assert_eq!(info.asset, asset);
+        assert!(info.interest_rate > 0);
+    }
+    assert_eq!(assets::config(LoanAsset::CkBtc).decimals, 8);
+    assert_eq!(assets::config(LoanAsset::CkUsdc).decimals, 6);
+    assert_eq!(assets::config(LoanAsset::CkEth).decimals, 18);
+}
+
+#[test]
+fn test_cross_asset_ltv_conversion() {
+    let btc = price(50_000);
+
+    // ckBTC needs no prices
+    assert_eq!(
+        assets::to_sats(LoanAsset::CkBtc, 12_345, &btc, &price(0)).unwrap(),
+        12_345
+    );
+
+    // 1,000 ckUSDC is 0.02 BTC at $50,000
+    let usdc_debt = 1_000 * 10u64.pow(6);
+    assert_eq!(
+        assets::to_sats(LoanAsset::CkUsdc, usdc_debt, &btc, &price(1)).unwrap(),
+        2_000_000
+    );
+
+    // 50% of a 0.1 BTC UTXO ($2,500) is ~0.833 ETH at $3,000
+    let max_sats = 5_000_000;
+    let max_eth = assets::from_sats(LoanAsset::CkEth, max_sats, &btc, &price(3_000)).unwrap();
+    assert_eq!(max_eth, 833_333_333_333_333_333);
+    assert!(assets::to_sats(LoanAsset::CkEth, max_eth, &btc, &price(3_000)).unwrap() <= max_sats);
+}
+
+#[test]
+fn test_market_totals_per_asset() {
+    let loans = [
+        loan(1, LoanAsset::CkUsdc, 1_000_000, 0, LoanStatus::Active),
+        loan(
+            2,
+            LoanAsset::CkUsdc,
+            2_000_000,
+            2_160_000,
+            LoanStatus::Repaid,
+        ),
+        loan(3, LoanAsset::CkUsdc, 5_000_000, 0, LoanStatus::Cancelled),
+        loan(4, LoanAsset::CkBtc, 100_000, 0, LoanStatus::Active),
+    ];
+
+    let market = assets::market_totals(LoanAsset::CkUsdc, loans.iter(), 9_000_000);
+    assert_eq!(market.available_liquidity, 9_000_000);
+    assert_eq!(market.active_loans, 1);
+    assert_eq!(market.outstanding_principal, 1_000_000);
+    // 8% simple interest
+    assert_eq!(market.outstanding_debt, 1_080_000);
+    assert_eq!(market.total_borrowed, 3_000_000);
+    assert_eq!(market.total_interest, 240_000);
+
+    let btc_market = assets::market_totals(LoanAsset::CkBtc, loans.iter(), 0);
+    assert_eq!(btc_market.active_loans, 1);
+    assert_eq!(btc_market.outstanding_debt, 105_000);
+}
+
+#[test]
+fn test_collateral_debt_sums_active_loans_on_the_utxo() {
+    let mut other_utxo = loan(5, LoanAsset::CkBtc, 70_000, 0, LoanStatus::Active);
+    other_utxo.collateral_utxo_id = 2;
+    let loans = [
+        loan(1, LoanAsset::CkBtc, 100_000, 0, LoanStatus::Active),
+        loan(2, LoanAsset::CkBtc, 50_000, 20_000, LoanStatus::Active),
+        loan(3, LoanAsset::CkBtc, 80_000, 0, LoanStatus::Repaid),
+        loan(4, LoanAsset::CkBtc, 90_000, 0, LoanStatus::Cancelled),
+        other_utxo,
+    ];
+
+    // Remaining debt including interest, for open loans on UTXO 1 only
+    let rate = assets::config(LoanAsset::CkBtc).interest_rate;
+    let expected = (100_000 + 100_000 * rate / 10_000) + (50_000 + 50_000 * rate / 10_000 - 20_000);
+    assert_eq!(
+        assets::collateral_debt_in_sats(&[1], loans.iter()).unwrap(),
+        expected
+    );
+    assert_eq!(
+        assets::collateral_debt_in_sats(&[3], loans.iter()).unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_collateral_debt_needs_prices_for_other_assets() {
+    // Without a cached price the debt cannot be valued, so borrowing is refused
+    let loans = [loan(1, LoanAsset::CkUsdc, 1_000_000, 0, LoanStatus::Active)];
+    assert!(assets::collateral_debt_in_sats(&[1], loans.iter()).is_err());
+}
+
+#[test]
+fn test_collateral_debt_counts_basket_loans_once() {
+    let mut basket_loan = loan(1, LoanAsset::CkBtc, 100_000, 0, LoanStatus::Active);
+    basket_loan.extra_collateral = vec![2, 3];
+    let loans = [basket_loan.clone()];
+    let debt = vault::helpers::calculate_loan_value(&basket_loan).unwrap();
+
+    assert_eq!(
+        assets::collateral_debt_in_sats(&[3], loans.iter()).unwrap(),
+        debt
+    );
+    assert_eq!(
+        assets::collateral_debt_in_sats(&[1, 2, 3], loans.iter()).unwrap(),
+        debt
+    );
+    assert_eq!(
+        assets::collateral_debt_in_sats(&[4], loans.iter()).unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_loan_value_of_multi_eth_loans() {
+    // 5 ETH in wei times the 600 bps rate does not fit in u64
+    let eth = 10u64.pow(18);
+    let five_eth = loan(1, LoanAsset::CkEth, 5 * eth, 0, LoanStatus::Active);
+    assert_eq!(
+        vault::helpers::calculate_interest(&five_eth).unwrap(),
+        3 * eth / 10
+    );
+    assert_eq!(
+        vault::helpers::calculate_loan_value(&five_eth).unwrap(),
+        5 * eth + 3 * eth / 10
+    );
+
+    // 18 ETH plus interest exceeds u64::MAX (~18.4 ETH): reported, not wrapped
+    let eighteen_eth = loan(2, LoanAsset::CkEth, 18 * eth, 0, LoanStatus::Active);
+    assert!(vault::helpers::calculate_loan_value(&eighteen_eth).is_err());
+    assert!(!vault::helpers::is_loan_repaid(&eighteen_eth));
+
+    // Market totals saturate instead
+    let market = assets::market_totals(LoanAsset::CkEth, [eighteen_eth].iter(), 0);
+    assert_eq!(market.outstanding_debt, u64::MAX);
+}
@@ -0,0 +1,244 @@
+// Unit tests for multi-UTXO collateral baskets
+
+use candid::Principal;
+use std::collections::HashMap;
+use vault::assets;
+use vault::collateral::{
+    self, BasketValue, INSCRIPTION_LTV_BPS, LIQUIDATION_THRESHOLD_BPS, PLAIN_LTV_BPS, RUNE_LTV_BPS,
+};
+use vault::types::{CollateralClass, Loan, LoanAsset, LoanStatus, OrdinalInfo, UtxoStatus, UTXO};
+
+fn utxo(id: u64, amount: u64, inscribed: bool) -> UTXO {
+    UTXO {
+        id,
+        txid: format!("{:064x}", id),
+        vout: 0,
+        amount,
+        address: "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_string(),
+        ordinal_info: inscribed.then(|| OrdinalInfo {
+            inscription_id: format!("{:064x}i0", id),
+            content_type: "image/png".to_string(),
+            content_preview: None,
+            metadata: None,
+        }),
+        status: UtxoStatus::Locked,
+        deposited_at: 0,
+    }
+}
+
+fn loan(basket: &[u64]) -> Loan {
+    Loan {
+        id: 1,
+        user_id: Principal::anonymous(),
+        collateral_utxo_id: basket[0],
+        extra_collateral: basket[1..].to_vec(),
+        asset: LoanAsset::CkBtc,
+        borrowed_amount: 100_000,
+        repaid_amount: 0,
+        interest_rate: 500,
+        created_at: 0,
+        status: LoanStatus::Active,
+        solana_disbursement: None,
+        term: None,
+    }
+}
+
+#[test]
+fn test_collateral_classes() {
+    assert_eq!(
+        collateral::class_of(&utxo(1, 1_000, false), false),
+        CollateralClass::Plain
+    );
+    assert_eq!(
+        collateral::class_of(&utxo(1, 1_000, false), true),
+        CollateralClass::RuneBearing
+    );
+    // An inscription outweighs Runes on the same output
+    assert_eq!(
+        collateral::class_of(&utxo(1, 1_000, true), true),
+        CollateralClass::Inscribed
+    );
+
+    assert_eq!(collateral::max_ltv(CollateralClass::Plain), PLAIN_LTV_BPS);
+    assert_eq!(
+        collateral::max_ltv(CollateralClass::RuneBearing),
+        RUNE_LTV_BPS
+    );
+    assert_eq!(
+        collateral::max_ltv(CollateralClass::Inscribed),
+        INSCRIPTION_LTV_BPS
+    );
+}
+
+#[test]
+fn test_mixed_basket_lends_at_weighted_ltv() {
+    let plain = utxo(1, 1_000_000, false);
+    let runes = utxo(2, 1_000_000, false);
+    let inscribed = utxo(3, 2_000_000, true);
+
+    let value = collateral::value_basket(
+        [
+            (&plain, CollateralClass::Plain),
+            (&runes, CollateralClass::RuneBearing),
+            (&inscribed, CollateralClass::Inscribed),
+        ]
+        .into_iter(),
+    );
+
+    assert_eq!(value.collateral_value, 4_000_000);
+    assert_eq!(value.max_borrowable, 500_000 + 400_000 + 600_000);
+    // (1 × 50% + 1 × 40% + 2 × 30%) / 4
+    assert_eq!(value.weighted_ltv(), 3_750);
+}
+
+#[test]
+fn test_empty_basket_has_no_capacity() {
+    let value = collateral::value_basket(std::iter::empty());
+    assert_eq!(value, BasketValue::default());
+    assert_eq!(value.weighted_ltv(), 0);
+    assert_eq!(collateral::ltv(1, 0), 10_000);
+}
+
+#[test]
+fn test_basket_membership() {
+    let loan = loan(&[4, 7, 9]);
+    assert_eq!(collateral::basket(&loan), vec![4, 7, 9]);
+    assert!(collateral::is_backed_by(&loan, 4));
+    assert!(collateral::is_backed_by(&loan, 9));
+    assert!(!collateral::is_backed_by(&loan, 5));
+}
+
+#[test]
+fn test_add_rejects_a_utxo_already_in_the_basket() {
+    let mut loan = loan(&[4, 7]);
+
+    collateral::add_to_basket(&mut loan, 9).unwrap();
+    assert_eq!(collateral::basket(&loan), vec![4, 7, 9]);
+
+    assert!(collateral::add_to_basket(&mut loan, 4).is_err());
+    assert!(collateral::add_to_basket(&mut loan, 9).is_err());
+    assert_eq!(collateral::basket(&loan), vec![4, 7, 9]);
+}
+
+#[test]
+fn test_release_keeps_at_least_one_utxo() {
+    let mut loan = loan(&[4, 7]);
+
+    collateral::remove_from_basket(&mut loan, 7).unwrap();
+    assert_eq!(collateral::basket(&loan), vec![4]);
+
+    assert!(collateral::remove_from_basket(&mut loan, 4).is_err());
+    assert!(collateral::remove_from_basket(&mut loan, 5).is_err());
+    assert_eq!(collateral::basket(&loan), vec![4]);
+}
+
+#[test]
+fn test_releasing_the_original_utxo_promotes_the_next() {
+    let mut loan = loan(&[4, 7, 9]);
+    collateral::remove_from_basket(&mut loan, 4).unwrap();
+
+    assert_eq!(loan.collateral_utxo_id, 7);
+    assert_eq!(collateral::basket(&loan), vec![7, 9]);
+}
+
+#[test]
+fn test_swap_keeps_the_basket_order() {
+    let mut loan = loan(&[4, 7, 9]);
+
+    collateral::replace_in_basket(&mut loan, 7, 12).unwrap();
+    assert_eq!(collateral::basket(&loan), vec![4, 12, 9]);
+
+    collateral::replace_in_basket(&mut loan, 4, 15).unwrap();
+    assert_eq!(loan.collateral_utxo_id, 15);
+    assert_eq!(collateral::basket(&loan), vec![15, 12, 9]);
+}
+
+#[test]
+fn test_swap_rejects_foreign_and_duplicate_utxos() {
+    let mut loan = loan(&[4, 7]);
+
+    assert!(collateral::replace_in_basket(&mut loan, 5, 12).is_err());
+    assert!(collateral::replace_in_basket(&mut loan, 4, 7).is_err());
+    assert_eq!(collateral::basket(&loan), vec![4, 7]);
+}
+
+#[test]
+fn test_loans_sharing_collateral_are_valued_together() {
+    let utxos = [utxo(1, 100_000, false), utxo(2, 20_000, false)];
+    let first = Loan {
+        borrowed_amount: 60_000,
+        ..loan(&[1])
+    };
+    let second = Loan {
+        id: 2,
+        borrowed_amount: 40_000,
+        ..loan(&[1, 2])
+    };
+    let repaid = Loan {
+        id: 3,
+        status: LoanStatus::Repaid,
+        ..loan(&[2, 3])
+    };
+    let loans = HashMap::from([(1, first.clone()), (2, second.clone()), (3, repaid)]);
+
+    // Each loan on its own basket stays below the liquidation threshold
+    let alone = |loan: &Loan, basket: &[UTXO]| {
+        let value = collateral::value_basket(basket.iter().map(|u| (u, CollateralClass::Plain)));
+        collateral::ltv(
+            assets::loans_debt_in_sats(&[loan.id], &loans).unwrap(),
+            value.collateral_value,
+        )
+    };
+    assert_eq!(alone(&first, &utxos[..1]), 6_300);
+    assert_eq!(alone(&second, &utxos), 3_500);
+
+    // Together they owe 105,000 against 120,000, past the threshold
+    let group = collateral::collateral_group(&first, &loans);
+    assert_eq!(group.loan_ids, vec![1, 2]);
+    assert_eq!(group.utxo_ids, vec![1, 2]);
+    assert_eq!(collateral::collateral_group(&second, &loans), group);
+    let value = collateral::value_basket(utxos.iter().map(|u| (u, CollateralClass::Plain)));
+    let debt = assets::loans_debt_in_sats(&group.loan_ids, &loans).unwrap();
+    assert_eq!(debt, 105_000);
+    assert!(collateral::ltv(debt, value.collateral_value) >= LIQUIDATION_THRESHOLD_BPS);
+}
+
+#[test]
+fn test_collateral_groups_follow_chains_of_shared_utxos() {
+    let loans = HashMap::from([
+        (1, loan(&[1])),
+        (
+            2,
+            Loan {
+                id: 2,
+                ..loan(&[1, 2])
+            },
+        ),
+        (
+            3,
+            Loan {
+                id: 3,
+                ..loan(&[2, 3])
+            },
+        ),
+        (
+            4,
+            Loan {
+                id: 4,
+                ..loan(&[4])
+            },
+        ),
+    ]);
+
+    let group = collateral::collateral_group(&loans[&1], &loans);
+    assert_eq!(group.loan_ids, vec![1, 2, 3]);
+    assert_eq!(group.utxo_ids, vec![1, 2, 3]);
+
+    // A closed loan is not part of anyone's group
+    let mut closed = loans[&2].clone();
+    closed.status = LoanStatus::Liquidated;
+    assert_eq!(
+        collateral::collateral_group(&closed, &loans).loan_ids,
+        vec![2]
+    );
+}
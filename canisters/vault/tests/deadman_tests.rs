@@ -0,0 +1,91 @@
+// Unit tests for dead man's switch evaluation
+
+use candid::Principal;
+use vault::deadman::{self, SwitchAction};
+use vault::types::DeadManSwitch;
+
+const HOUR: u64 = 3_600_000_000_000;
+const DAY: u64 = 24 * HOUR;
+const START: u64 = 1_700_000_000_000_000_000;
+
+fn switch() -> DeadManSwitch {
+    DeadManSwitch {
+        user_id: Principal::from_text("2vxsx-fae").unwrap(),
+        last_activity: START,
+        inactivity_threshold: 30 * DAY,
+        beneficiary: Principal::management_canister(),
+        enabled: true,
+        warning_period: 7 * DAY,
+        warning_started_at: None,
+        triggered_at: None,
+    }
+}
+
+#[test]
+fn test_active_user_does_nothing() {
+    let dms = switch();
+    assert_eq!(deadman::evaluate(&dms, START), SwitchAction::None);
+    assert_eq!(
+        deadman::evaluate(&dms, START + 29 * DAY),
+        SwitchAction::None
+    );
+}
+
+#[test]
+fn test_inactivity_starts_warning() {
+    let dms = switch();
+    assert_eq!(
+        deadman::evaluate(&dms, START + 30 * DAY),
+        SwitchAction::StartWarning
+    );
+}
+
+#[test]
+fn test_warning_period_then_trigger() {
+    let mut dms = switch();
+    dms.warning_started_at = Some(START + 30 * DAY);
+
+    // Still within the warning period
+    assert_eq!(
+        deadman::evaluate(&dms, START + 36 * DAY),
+        SwitchAction::None
+    );
+    assert_eq!(
+        deadman::evaluate(&dms, START + 37 * DAY),
+        SwitchAction::Trigger
+    );
+}
+
+#[test]
+fn test_zero_warning_period_triggers_on_next_check() {
+    let mut dms = switch();
+    dms.warning_period = 0;
+    assert_eq!(
+        deadman::evaluate(&dms, START + 30 * DAY),
+        SwitchAction::StartWarning
+    );
+
+    dms.warning_started_at = Some(START + 30 * DAY);
+    assert_eq!(
+        deadman::evaluate(&dms, START + 30 * DAY + HOUR),
+        SwitchAction::Trigger
+    );
+}
+
+#[test]
+fn test_disabled_or_fired_switch_is_ignored() {
+    let mut dms = switch();
+    dms.enabled = false;
+    assert_eq!(
+        deadman::evaluate(&dms, START + 365 * DAY),
+        SwitchAction::None
+    );
+
+    let mut fired = switch();
+    fired.warning_started_at = Some(START + 30 * DAY);
+    fired.triggered_at = Some(START + 37 * DAY);
+    assert_eq!(
+        deadman::evaluate(&fired, START + 365 * DAY),
+        SwitchAction::None
+    );
+}
@@ -0,0 +1,116 @@
+// Unit tests for the loan disbursement journal
+
+use candid::{Nat, Principal};
+use vault::ckbtc::TransferError;
+use vault::disbursements::{self, Outcome, LEDGER_TX_WINDOW_NANOS, RETRY_MARGIN_NANOS};
+use vault::helpers::{draw_memo, loan_memo};
+use vault::types::LoanAsset;
+
+const NOW: u64 = 1_700_000_000_000_000_000;
+
+#[test]
+fn test_new_entry_fixes_the_dedup_key() {
+    let borrower = Principal::from_slice(&[1; 29]);
+    let entry = disbursements::new_entry(7, LoanAsset::CkBtc, borrower, 50_000, false, NOW);
+
+    assert_eq!(entry.loan_id, 7);
+    assert_eq!(entry.to, borrower);
+    assert_eq!(entry.amount, 50_000);
+    assert_eq!(entry.created_at_time, NOW);
+    assert_eq!(entry.attempts, 0);
+    assert_eq!(entry.last_error, None);
+    assert_eq!(disbursements::memo(&entry), loan_memo(7));
+}
+
+#[test]
+fn test_ledger_fee_is_deducted_from_the_payout() {
+    let borrower = Principal::from_slice(&[1; 29]);
+    let mut entry = disbursements::new_entry(7, LoanAsset::CkBtc, borrower, 50_000, false, NOW);
+    assert_eq!(entry.fee, Some(10));
+    assert_eq!(disbursements::payout_amount(&entry), 49_990);
+
+    // Entries journaled before the fee was recorded keep paying the same amount
+    entry.fee = None;
+    assert_eq!(disbursements::payout_amount(&entry), 50_000);
+
+    assert!(disbursements::validate_amount(LoanAsset::CkBtc, 10).is_err());
+    assert!(disbursements::validate_amount(LoanAsset::CkBtc, 11).is_ok());
+    assert!(disbursements::validate_amount(LoanAsset::CkUsdc, 4_000).is_err());
+}
+
+#[test]
+fn test_draws_are_told_apart_by_memo() {
+    let borrower = Principal::from_slice(&[1; 29]);
+    let opening = disbursements::new_entry(7, LoanAsset::CkBtc, borrower, 50_000, false, NOW);
+    let draw = disbursements::new_entry(7, LoanAsset::CkBtc, borrower, 50_000, true, NOW);
+
+    assert!(!disbursements::is_draw(&opening));
+    assert!(disbursements::is_draw(&draw));
+    assert_eq!(disbursements::memo(&draw), draw_memo(7));
+    // The ledger must not deduplicate a draw against the opening payout
+    assert_ne!(disbursements::memo(&draw), disbursements::memo(&opening));
+}
+
+#[test]
+fn test_successful_transfer_is_paid() {
+    assert_eq!(disbursements::classify(Ok(Ok(42))), Outcome::Paid(42));
+}
+
+#[test]
+fn test_duplicate_counts_as_paid() {
+    // A retry of a transfer that already went through must not cancel the loan
+    let result = Ok(Err(TransferError::Duplicate {
+        duplicate_of: Nat::from(17u64),
+    }));
+    assert_eq!(disbursements::classify(result), Outcome::Paid(17));
+}
+
+#[test]
+fn test_ledger_rejections_cancel() {
+    let result = Ok(Err(TransferError::InsufficientFunds {
+        balance: Nat::from(0u64),
+    }));
+    assert!(matches!(
+        disbursements::classify(result),
+        Outcome::Rejected(_)
+    ));
+
+    let result = Ok(Err(TransferError::BadFee {
+        expected_fee: Nat::from(10u64),
+    }));
+    assert!(matches!(
+        disbursements::classify(result),
+        Outcome::Rejected(_)
+    ));
+}
+
+#[test]
+fn test_transient_failures_stay_pending() {
+    for error in [
+        TransferError::TemporarilyUnavailable,
+        TransferError::TooOld,
+        TransferError::CreatedInFuture { ledger_time: NOW },
+    ] {
+        assert!(matches!(
+            disbursements::classify(Ok(Err(error))),
+            Outcome::Unknown(_)
+        ));
+    }
+
+    // The call itself failed: the transfer may or may not have happened
+    assert!(matches!(
+        disbursements::classify(Err("Call failed".to_string())),
+        Outcome::Unknown(_)
+    ));
+}
+
+#[test]
+fn test_retries_stop_before_the_dedup_window_closes() {
+    let borrower = Principal::from_slice(&[1; 29]);
+    let entry = disbursements::new_entry(7, LoanAsset::CkBtc, borrower, 50_000, false, NOW);
+    let cutoff = NOW + LEDGER_TX_WINDOW_NANOS - RETRY_MARGIN_NANOS;
+
+    assert!(disbursements::can_retry(&entry, NOW));
+    assert!(disbursements::can_retry(&entry, cutoff - 1));
+    assert!(!disbursements::can_retry(&entry, cutoff));
+}
@@ -0,0 +1,100 @@
+// Unit tests for client-encrypted loan document uploads and storage limits
+
+use vault::documents;
+use vault::types::LoanDocument;
+
+fn document(total_size: u64) -> LoanDocument {
+    LoanDocument {
+        id: 1,
+        loan_id: 1,
+        name: "agreement.pdf".to_string(),
+        content_type: "application/pdf".to_string(),
+        total_size,
+        uploaded_size: 0,
+        chunks: Vec::new(),
+        complete: false,
+        created_at: 0,
+        rekeyed_chunks: None,
+    }
+}
+
+#[test]
+fn test_new_document_limits() {
+    assert!(documents::validate_new_document("agreement.pdf", 1024, 0).is_ok());
+    assert!(documents::validate_new_document("  ", 1024, 0).is_err());
+    assert!(documents::validate_new_document("agreement.pdf", 0, 0).is_err());
+    assert!(
+        documents::validate_new_document("agreement.pdf", documents::MAX_DOCUMENT_SIZE + 1, 0)
+            .is_err()
+    );
+    assert!(documents::validate_new_document(
+        "agreement.pdf",
+        1024,
+        documents::MAX_DOCUMENTS_PER_LOAN
+    )
+    .is_err());
+}
+
+#[test]
+fn test_total_document_storage_is_capped() {
+    let max = documents::MAX_TOTAL_DOCUMENT_SIZE;
+    assert!(documents::validate_storage(0, documents::MAX_DOCUMENT_SIZE).is_ok());
+    assert!(documents::validate_storage(max - 1024, 1024).is_ok());
+    assert!(documents::validate_storage(max - 1024, 1025).is_err());
+    assert!(documents::validate_storage(u64::MAX, 1).is_err());
+}
+
+#[test]
+fn test_chunks_must_be_sequential_and_within_size() {
+    let mut doc = document(10);
+
+    assert!(documents::validate_chunk(&doc, 1, 5).is_err());
+    assert!(documents::validate_chunk(&doc, 0, 11).is_err());
+    assert!(documents::validate_chunk(&doc, 0, 0).is_err());
+    assert!(documents::validate_chunk(&doc, 0, 6).is_ok());
+
+    doc.chunks.push(vec![0; 6]);
+    doc.uploaded_size = 6;
+    assert!(documents::validate_chunk(&doc, 0, 4).is_err());
+    assert!(documents::validate_chunk(&doc, 1, 5).is_err());
+    assert!(documents::validate_chunk(&doc, 1, 4).is_ok());
+
+    doc.complete = true;
+    assert!(documents::validate_chunk(&doc, 1, 4).is_err());
+}
+
+#[test]
+fn test_rekeyed_chunks_follow_the_finalized_document() {
+    let mut doc = document(10);
+    doc.chunks = vec![vec![0; 6], vec![0; 4]];
+    doc.uploaded_size = 10;
+    assert!(documents::validate_rekeyed_chunk(&doc, 0, 6).is_err());
+
+    doc.complete = true;
+    assert!(documents::validate_rekeyed_chunk(&doc, 1, 6).is_err());
+    assert!(documents::validate_rekeyed_chunk(&doc, 0, 0).is_err());
+    assert!(documents::validate_rekeyed_chunk(&doc, 0, 6).is_ok());
+
+    doc.rekeyed_chunks = Some(vec![vec![1; 6]]);
+    assert_eq!(documents::stored_size(&doc), 16);
+    assert!(documents::validate_rekeyed_chunk(&doc, 1, 4).is_ok());
+
+    doc.rekeyed_chunks = Some(vec![vec![1; 6], vec![1; 4]]);
+    assert!(documents::validate_rekeyed_chunk(&doc, 2, 4).is_err());
+}
+
+#[test]
+fn test_rekey_needs_every_chunk_of_every_document() {
+    let mut doc = document(10);
+    doc.chunks = vec![vec![0; 6], vec![0; 4]];
+    doc.uploaded_size = 10;
+    assert!(documents::ensure_rekeyed([&doc].into_iter()).is_err());
+
+    doc.complete = true;
+    doc.rekeyed_chunks = Some(vec![vec![1; 6]]);
+    assert!(documents::ensure_rekeyed([&doc].into_iter()).is_err());
+
+    doc.rekeyed_chunks = Some(vec![vec![1; 6], vec![1; 5]]);
+    assert!(documents::ensure_rekeyed([&doc].into_iter()).is_ok());
+    assert!(documents::ensure_rekeyed(std::iter::empty()).is_ok());
+}
@@ -0,0 +1,311 @@
+// Unit tests for MuSig2 key aggregation, signing and vault construction
+
+use bitcoin::secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use candid::Principal;
+use std::collections::HashMap;
+use vault::collateral;
+use vault::musig2::{self, SessionContext};
+use vault::psbt::{self, PartialSignature, PsbtInputSpec, PsbtOutputSpec, TaprootSpend};
+use vault::types::{Loan, LoanAsset, LoanStatus, UtxoStatus, UTXO};
+
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+struct Signer {
+    secret: [u8; 32],
+    public: Vec<u8>,
+}
+
+fn signer(byte: u8) -> Signer {
+    let secret = [byte; 32];
+    Signer {
+        secret,
+        public: musig2::public_key_from_secret(&secret).unwrap().to_vec(),
+    }
+}
+
+fn vault_psbt(taproot: &musig2::VaultTaproot) -> bitcoin::Psbt {
+    spend_psbt(
+        taproot,
+        TaprootSpend::KeyPath {
+            internal_key: taproot.internal_key.to_vec(),
+            merkle_root: Some(taproot.merkle_root.to_vec()),
+        },
+    )
+}
+
+fn cosign_psbt(taproot: &musig2::VaultTaproot) -> bitcoin::Psbt {
+    spend_psbt(
+        taproot,
+        TaprootSpend::ScriptPath {
+            internal_key: taproot.internal_key.to_vec(),
+            leaf_script: taproot.cosign_script.clone(),
+            control_block: taproot.cosign_control_block.clone(),
+        },
+    )
+}
+
+fn spend_psbt(taproot: &musig2::VaultTaproot, spend: TaprootSpend) -> bitcoin::Psbt {
+    let input = PsbtInputSpec {
+        txid: TXID.to_string(),
+        vout: 0,
+        amount: 100_000,
+        script_pubkey: psbt::parse_address(&taproot.address)
+            .unwrap()
+            .script_pubkey()
+            .to_bytes(),
+        sequence: None,
+        taproot: Some(spend),
+    };
+    let output = PsbtOutputSpec {
+        address: taproot.address.clone(),
+        amount: 90_000,
+    };
+    psbt::build_psbt(&[input], &[output], 0).unwrap()
+}
+
+/// Runs both signers through nonce exchange and returns their partial signatures
+fn sign_session(
+    user: &Signer,
+    canister: &Signer,
+    taproot: &musig2::VaultTaproot,
+    message: &[u8; 32],
+) -> (SessionContext, Vec<u8>, Vec<Vec<u8>>) {
+    let (user_sec, user_pub) = musig2::nonce_gen(
+        &[7u8; 32],
+        Some(&user.secret),
+        &user.public,
+        Some(&taproot.output_key),
+        Some(message),
+    )
+    .unwrap();
+    let (canister_sec, canister_pub) = musig2::nonce_gen(
+        &[8u8; 32],
+        Some(&canister.secret),
+        &canister.public,
+        Some(&taproot.output_key),
+        Some(message),
+    )
+    .unwrap();
+
+    let aggnonce = musig2::nonce_agg(&[canister_pub.to_vec(), user_pub.to_vec()]).unwrap();
+    let ctx = SessionContext {
+        aggnonce,
+        pubkeys: vec![user.public.clone(), canister.public.clone()],
+        tweaks: vec![taproot.tweak],
+        message: message.to_vec(),
+    };
+
+    let user_psig = musig2::partial_sign(&user_sec, &user.secret, &ctx).unwrap();
+    let canister_psig = musig2::partial_sign(&canister_sec, &canister.secret, &ctx).unwrap();
+    (
+        ctx,
+        user_pub.to_vec(),
+        vec![user_psig.to_vec(), canister_psig.to_vec()],
+    )
+}
+
+#[test]
+fn test_key_agg_bip327_vectors() {
+    let keys: Vec<Vec<u8>> = [
+        "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+        "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+    ]
+    .iter()
+    .map(|k| hex::decode(k).unwrap())
+    .collect();
+
+    let ctx = musig2::key_agg(&keys).unwrap();
+    assert_eq!(
+        hex::encode_upper(ctx.xonly_key()),
+        "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"
+    );
+
+    // Order matters
+    let reversed: Vec<Vec<u8>> = keys.iter().rev().cloned().collect();
+    assert_eq!(
+        hex::encode_upper(musig2::key_agg(&reversed).unwrap().xonly_key()),
+        "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"
+    );
+}
+
+#[test]
+fn test_vault_key_path_signing() {
+    let secp = Secp256k1::verification_only();
+    let user = signer(0x11);
+    let canister = signer(0x55);
+    let taproot = musig2::vault_taproot(&user.public, &canister.public, 144).unwrap();
+    assert!(taproot.address.starts_with("tb1p"));
+
+    let mut unsigned = vault_psbt(&taproot);
+    let sighash = psbt::taproot_sighash(&unsigned, 0, None).unwrap();
+    let (ctx, user_nonce, psigs) = sign_session(&user, &canister, &taproot, &sighash);
+
+    musig2::partial_sig_verify(&psigs[0], &user_nonce, &user.public, &ctx).unwrap();
+    let signature = musig2::partial_sig_agg(&psigs, &ctx).unwrap();
+
+    // The aggregate is a plain BIP-340 signature for the vault's output key
+    let output_key = XOnlyPublicKey::from_slice(&taproot.output_key).unwrap();
+    secp.verify_schnorr(
+        &schnorr::Signature::from_slice(&signature).unwrap(),
+        &Message::from_digest(sighash),
+        &output_key,
+    )
+    .unwrap();
+
+    psbt::add_partial_signature(
+        &mut unsigned,
+        0,
+        &PartialSignature::TaprootKey {
+            signature: signature.to_vec(),
+        },
+    )
+    .unwrap();
+    psbt::finalize(&mut unsigned).unwrap();
+    assert!(psbt::extract(unsigned).is_ok());
+}
+
+#[test]
+fn test_rejects_invalid_partial_signatures() {
+    let user = signer(0x22);
+    let canister = signer(0x66);
+    let taproot = musig2::vault_taproot(&user.public, &canister.public, 10).unwrap();
+    let sighash = psbt::taproot_sighash(&vault_psbt(&taproot), 0, None).unwrap();
+    let (ctx, user_nonce, psigs) = sign_session(&user, &canister, &taproot, &sighash);
+
+    // Canister's partial signature is not valid for the user's key and nonce
+    assert!(musig2::partial_sig_verify(&psigs[1], &user_nonce, &user.public, &ctx).is_err());
+
+    // Partial signature over a different message
+    let mut other = ctx.clone();
+    other.message = vec![0u8; 32];
+    assert!(musig2::partial_sig_verify(&psigs[0], &user_nonce, &user.public, &other).is_err());
+
+    // Outsiders cannot sign for the session
+    let outsider = signer(0x33);
+    assert!(musig2::partial_sig_verify(&psigs[0], &user_nonce, &outsider.public, &ctx).is_err());
+}
+
+#[test]
+fn test_vault_construction() {
+    let user = signer(0x44);
+    let canister = signer(0x77);
+
+    assert!(musig2::vault_taproot(&user.public, &canister.public, 0).is_err());
+    assert!(musig2::vault_taproot(&user.public[1..], &canister.public, 144).is_err());
+
+    // Each vault gets its own canister key
+    assert_ne!(
+        musig2::canister_derivation_path(3),
+        musig2::canister_derivation_path(4)
+    );
+
+    let a = musig2::vault_taproot(&user.public, &canister.public, 144).unwrap();
+    let b = musig2::vault_taproot(&user.public, &canister.public, 288).unwrap();
+    assert_eq!(a.internal_key, b.internal_key);
+    assert_ne!(
+        a.address, b.address,
+        "refund delay is committed in the address"
+    );
+}
+
+/// BIP-340 signature over a script path sighash, as the threshold API returns it
+fn sign_leaf(signer: &Signer, sighash: [u8; 32]) -> Vec<u8> {
+    let secp = Secp256k1::new();
+    let keypair = Keypair::from_seckey_slice(&secp, &signer.secret).unwrap();
+    secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &keypair)
+        .as_ref()
+        .to_vec()
+}
+
+#[test]
+fn test_vault_cosigned_script_path_spend() {
+    let user = signer(0x11);
+    let canister = signer(0x55);
+    let taproot = musig2::vault_taproot(&user.public, &canister.public, 144).unwrap();
+
+    let mut unsigned = cosign_psbt(&taproot);
+    let sighash = psbt::taproot_sighash(&unsigned, 0, Some(&taproot.cosign_script)).unwrap();
+
+    // The user alone cannot complete the 2-of-2 leaf
+    let user_signature = PartialSignature::TaprootScript {
+        public_key: user.public[1..].to_vec(),
+        leaf_script: taproot.cosign_script.clone(),
+        signature: sign_leaf(&user, sighash),
+    };
+    psbt::add_partial_signature(&mut unsigned, 0, &user_signature).unwrap();
+    assert!(psbt::finalize(&mut unsigned.clone()).is_err());
+
+    let canister_signature = PartialSignature::TaprootScript {
+        public_key: canister.public[1..].to_vec(),
+        leaf_script: taproot.cosign_script.clone(),
+        signature: sign_leaf(&canister, sighash),
+    };
+    psbt::add_partial_signature(&mut unsigned, 0, &canister_signature).unwrap();
+    psbt::finalize(&mut unsigned).unwrap();
+    assert!(psbt::extract(unsigned).is_ok());
+}
+
+fn vault_utxo(id: u64, txid: &str, status: UtxoStatus) -> UTXO {
+    UTXO {
+        id,
+        txid: txid.to_string(),
+        vout: 0,
+        amount: 100_000,
+        address: String::new(),
+        ordinal_info: None,
+        status,
+        deposited_at: 0,
+    }
+}
+
+fn loan_on(basket: &[u64], status: LoanStatus) -> Loan {
+    Loan {
+        id: 9,
+        user_id: Principal::anonymous(),
+        collateral_utxo_id: basket[0],
+        extra_collateral: basket[1..].to_vec(),
+        asset: LoanAsset::CkBtc,
+        borrowed_amount: 10_000,
+        repaid_amount: 0,
+        interest_rate: 500,
+        created_at: 0,
+        status,
+        solana_disbursement: None,
+        term: None,
+    }
+}
+
+#[test]
+fn test_psbt_spending_loan_collateral_is_rejected() {
+    let user = signer(0x11);
+    let canister = signer(0x55);
+    let taproot = musig2::vault_taproot(&user.public, &canister.public, 144).unwrap();
+    let outpoints = psbt::outpoints(&cosign_psbt(&taproot));
+
+    // The vault UTXO sits in the basket of an open loan, next to another UTXO
+    let utxos = HashMap::from([
+        (1, vault_utxo(1, &"ab".repeat(32), UtxoStatus::Locked)),
+        (2, vault_utxo(2, TXID, UtxoStatus::Locked)),
+    ]);
+    let mut loans = HashMap::from([(9, loan_on(&[1, 2], LoanStatus::Active))]);
+    let result = collateral::ensure_unencumbered(&outpoints, &utxos, &loans);
+    assert!(result.unwrap_err().contains("backs loan 9"));
+
+    // Txids are compared regardless of hex case
+    let upper = HashMap::from([(
+        2,
+        vault_utxo(2, &TXID.to_uppercase(), UtxoStatus::Deposited),
+    )]);
+    assert!(collateral::ensure_unencumbered(&outpoints, &upper, &loans).is_err());
+
+    // Once the loan is repaid and the UTXO released the vault can be spent
+    loans.insert(9, loan_on(&[1, 2], LoanStatus::Repaid));
+    let released = HashMap::from([(2, vault_utxo(2, TXID, UtxoStatus::Deposited))]);
+    assert!(collateral::ensure_unencumbered(&outpoints, &released, &loans).is_ok());
+
+    // Collateral of other loans does not block the spend
+    let unrelated = HashMap::from([(1, vault_utxo(1, &"ab".repeat(32), UtxoStatus::Locked))]);
+    let open = HashMap::from([(9, loan_on(&[1], LoanStatus::Active))]);
+    assert!(collateral::ensure_unencumbered(&outpoints, &unrelated, &open).is_ok());
+}
@@ -0,0 +1,92 @@
+// Unit tests for client-encrypted notes and the keys wrapped for their readers
+
+use candid::Principal;
+use vault::notes;
+use vault::vetkeys;
+
+const NONCE: [u8; vetkeys::NONCE_LEN] = [4u8; vetkeys::NONCE_LEN];
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1; 29])
+}
+
+fn bob() -> Principal {
+    Principal::from_slice(&[2; 29])
+}
+
+fn carol() -> Principal {
+    Principal::from_slice(&[3; 29])
+}
+
+/// A data key wrapped the way a client does, under `key_version`
+fn wrapped(key_version: u32) -> Vec<u8> {
+    vetkeys::encrypt_with_key_version(&[1u8; 32], key_version, &NONCE, &[7u8; 32], b"").unwrap()
+}
+
+/// Alice is on her second key version, everyone else on the first
+fn key_version(principal: Principal) -> u32 {
+    if principal == alice() {
+        2
+    } else {
+        1
+    }
+}
+
+#[test]
+fn test_note_ciphertext_limits() {
+    assert!(notes::validate_ciphertext(b"client ciphertext").is_ok());
+    assert!(notes::validate_ciphertext(b"").is_err());
+    assert!(notes::validate_ciphertext(&vec![0u8; notes::MAX_NOTE_SIZE]).is_ok());
+    assert!(notes::validate_ciphertext(&vec![0u8; notes::MAX_NOTE_SIZE + 1]).is_err());
+}
+
+#[test]
+fn test_rekeyed_note_needs_a_key_for_every_remaining_reader() {
+    // Bob's access was revoked; Alice and Carol keep reading the note
+    let readers = [alice(), carol()];
+
+    let keys = vetkeys::validate_wrapped_keys(
+        &readers,
+        vec![(alice(), wrapped(2)), (carol(), wrapped(1))],
+        key_version,
+    )
+    .unwrap();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[&carol()], wrapped(1));
+
+    // A missing reader, the revoked reader or a duplicate are refused
+    let missing =
+        vetkeys::validate_wrapped_keys(&readers, vec![(alice(), wrapped(2))], key_version);
+    assert!(missing.unwrap_err().contains("Missing"));
+    let revoked = vetkeys::validate_wrapped_keys(
+        &readers,
+        vec![
+            (alice(), wrapped(2)),
+            (carol(), wrapped(1)),
+            (bob(), wrapped(1)),
+        ],
+        key_version,
+    );
+    assert!(revoked.unwrap_err().contains("no access"));
+    let duplicate = vetkeys::validate_wrapped_keys(
+        &readers,
+        vec![
+            (alice(), wrapped(2)),
+            (alice(), wrapped(2)),
+            (carol(), wrapped(1)),
+        ],
+        key_version,
+    );
+    assert!(duplicate.unwrap_err().contains("Duplicate"));
+}
+
+#[test]
+fn test_rekeyed_note_keys_use_each_readers_current_version() {
+    let readers = [alice(), carol()];
+    let stale = vetkeys::validate_wrapped_keys(
+        &readers,
+        vec![(alice(), wrapped(1)), (carol(), wrapped(1))],
+        key_version,
+    );
+    assert!(stale.unwrap_err().contains("expected 2"));
+}
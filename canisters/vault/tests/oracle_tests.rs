@@ -0,0 +1,55 @@
+// Unit tests for oracle price normalization and cross-asset conversion
+
+use vault::oracle::{self, UsdPrice, PRICE_DECIMALS};
+
+fn price(dollars: u64) -> UsdPrice {
+    UsdPrice {
+        rate: dollars * 10u64.pow(PRICE_DECIMALS),
+        timestamp: 0,
+    }
+}
+
+#[test]
+fn test_normalize_rate() {
+    // XRC commonly reports 9 decimals
+    assert_eq!(
+        oracle::normalize_rate(150_250_000_000, 9).unwrap(),
+        150_250_000_000
+    );
+    assert_eq!(oracle::normalize_rate(15_025, 2).unwrap(), 150_250_000_000);
+    assert_eq!(
+        oracle::normalize_rate(150_250_000_000_000, 12).unwrap(),
+        150_250_000_000
+    );
+    assert!(oracle::normalize_rate(u64::MAX, 0).is_err());
+}
+
+#[test]
+fn test_convert_btc_to_solana_assets() {
+    // 0.01 BTC at $50,000 is $500
+    let sats = 1_000_000;
+
+    // $500 of SOL at $150, in lamports (9 decimals), rounded down
+    assert_eq!(
+        oracle::convert_amount(sats, 8, &price(50_000), 9, &price(150)).unwrap(),
+        3_333_333_333
+    );
+
+    // $500 of USDC (6 decimals)
+    assert_eq!(
+        oracle::convert_amount(sats, 8, &price(50_000), 6, &price(1)).unwrap(),
+        500_000_000
+    );
+}
+
+#[test]
+fn test_convert_rejects_bad_prices() {
+    let zero = UsdPrice {
+        rate: 0,
+        timestamp: 0,
+    };
+    assert!(oracle::convert_amount(1, 8, &price(50_000), 9, &zero).is_err());
+
+    // Result does not fit in u64
+    assert!(oracle::convert_amount(u64::MAX, 0, &price(50_000), 9, &price(1)).is_err());
+}
@@ -0,0 +1,180 @@
+// Unit tests for lending pool share accounting
+
+use candid::Principal;
+use vault::ckbtc::Account;
+use vault::pool::{self, EXCHANGE_RATE_SCALE, RESERVE_FACTOR_BPS};
+use vault::types::{LendingPool, Loan, LoanAsset, LoanStatus};
+
+fn supplier(n: u8) -> Account {
+    Account {
+        owner: Principal::from_slice(&[n; 29]),
+        subaccount: None,
+    }
+}
+
+fn loan(borrowed: u64, repaid: u64) -> Loan {
+    Loan {
+        id: 1,
+        user_id: Principal::anonymous(),
+        collateral_utxo_id: 1,
+        extra_collateral: vec![],
+        asset: LoanAsset::CkBtc,
+        borrowed_amount: borrowed,
+        repaid_amount: repaid,
+        interest_rate: 500,
+        created_at: 0,
+        status: LoanStatus::Active,
+        solana_disbursement: None,
+        term: None,
+    }
+}
+
+#[test]
+fn test_first_deposit_mints_one_share_per_satoshi() {
+    let mut lending_pool = LendingPool::default();
+    assert_eq!(pool::exchange_rate(&lending_pool), EXCHANGE_RATE_SCALE);
+
+    let shares = pool::supply(&mut lending_pool, &supplier(1), 1_000_000).unwrap();
+    assert_eq!(shares, 1_000_000);
+    assert_eq!(lending_pool.cash, 1_000_000);
+    assert_eq!(pool::position(&lending_pool, &supplier(1)).value, 1_000_000);
+}
+
+#[test]
+fn test_interest_accrues_to_suppliers_minus_reserve_factor() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 1_000_000).unwrap();
+
+    pool::lend(&mut lending_pool, 500_000).unwrap();
+    assert_eq!(pool::utilization_rate(&lending_pool), 5_000);
+
+    // Borrower repays principal plus 10_000 interest
+    let (principal, interest) = pool::repayment_split(&loan(500_000, 0), 510_000);
+    assert_eq!((principal, interest), (500_000, 10_000));
+    pool::repay(&mut lending_pool, principal, interest);
+
+    let reserve_cut = 10_000 * RESERVE_FACTOR_BPS / 10_000;
+    assert_eq!(lending_pool.reserves, reserve_cut);
+    assert_eq!(lending_pool.borrowed, 0);
+    assert_eq!(lending_pool.cash, 1_000_000 + 10_000 - reserve_cut);
+    assert_eq!(
+        pool::position(&lending_pool, &supplier(1)).value,
+        1_000_000 + 10_000 - reserve_cut
+    );
+    assert!(pool::exchange_rate(&lending_pool) > EXCHANGE_RATE_SCALE);
+}
+
+#[test]
+fn test_later_supplier_gets_fewer_shares_after_growth() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 1_000_000).unwrap();
+    pool::lend(&mut lending_pool, 1_000_000).unwrap();
+    pool::repay(&mut lending_pool, 1_000_000, 200_000);
+
+    let shares = pool::supply(&mut lending_pool, &supplier(2), 1_180_000).unwrap();
+    assert_eq!(shares, 1_000_000);
+    assert_eq!(pool::position(&lending_pool, &supplier(2)).value, 1_180_000);
+}
+
+#[test]
+fn test_withdrawal_limited_by_idle_cash() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 1_000_000).unwrap();
+    pool::lend(&mut lending_pool, 800_000).unwrap();
+
+    let err = pool::redeem(&mut lending_pool, &supplier(1), 1_000_000).unwrap_err();
+    assert!(err.contains("Insufficient liquidity"));
+    assert_eq!(lending_pool.total_shares, 1_000_000);
+
+    let amount = pool::redeem(&mut lending_pool, &supplier(1), 200_000).unwrap();
+    assert_eq!(amount, 200_000);
+    assert_eq!(lending_pool.cash, 0);
+    assert_eq!(pool::position(&lending_pool, &supplier(1)).shares, 800_000);
+}
+
+#[test]
+fn test_redeem_rejects_shares_not_owned() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 1_000).unwrap();
+
+    assert!(pool::redeem(&mut lending_pool, &supplier(2), 1).is_err());
+    assert!(pool::redeem(&mut lending_pool, &supplier(1), 1_001).is_err());
+    assert!(pool::redeem(&mut lending_pool, &supplier(1), 0).is_err());
+}
+
+#[test]
+fn test_restore_redemption_undoes_redeem() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 5_000).unwrap();
+
+    let amount = pool::redeem(&mut lending_pool, &supplier(1), 5_000).unwrap();
+    assert!(lending_pool.shares.is_empty());
+    pool::restore_redemption(&mut lending_pool, &supplier(1), 5_000, amount);
+
+    assert_eq!(lending_pool.cash, 5_000);
+    assert_eq!(lending_pool.total_shares, 5_000);
+    assert_eq!(pool::position(&lending_pool, &supplier(1)).shares, 5_000);
+}
+
+#[test]
+fn test_lend_requires_liquidity() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 1_000).unwrap();
+
+    assert!(pool::lend(&mut lending_pool, 1_001).is_err());
+    pool::lend(&mut lending_pool, 1_000).unwrap();
+    pool::cancel_lend(&mut lending_pool, 1_000);
+    assert_eq!(lending_pool.cash, 1_000);
+    assert_eq!(lending_pool.borrowed, 0);
+}
+
+#[test]
+fn test_repayment_split_is_principal_first() {
+    assert_eq!(pool::repayment_split(&loan(1_000, 0), 400), (400, 0));
+    assert_eq!(pool::repayment_split(&loan(1_000, 800), 250), (200, 50));
+    assert_eq!(pool::repayment_split(&loan(1_000, 1_000), 50), (0, 50));
+}
+
+#[test]
+fn test_repricing_replaces_the_rate_instead_of_compounding() {
+    // 5% on 100,000 with 30,000 repaid leaves 75,000; at 4% it is 74,000
+    let mut loan = loan(100_000, 30_000);
+    assert_eq!(vault::helpers::reprice_loan(&mut loan, 400), Ok(74_000));
+    assert_eq!(loan.borrowed_amount, 100_000);
+    assert_eq!(loan.interest_rate, 400);
+    assert_eq!(vault::helpers::calculate_loan_value(&loan), Ok(74_000));
+
+    // The same rate leaves the debt as it was
+    assert_eq!(vault::helpers::reprice_loan(&mut loan, 400), Ok(74_000));
+}
+
+#[test]
+fn test_repricing_below_what_was_repaid_is_refused() {
+    // 104,000 of 105,000 repaid: at 3% the loan would only owe 103,000
+    let mut loan = loan(100_000, 104_000);
+    assert!(vault::helpers::reprice_loan(&mut loan, 300).is_err());
+    assert_eq!(loan.interest_rate, 500);
+}
+
+#[test]
+fn test_write_off_absorbed_by_reserves_first() {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &supplier(1), 10_000).unwrap();
+    lending_pool.reserves = 1_000;
+    pool::lend(&mut lending_pool, 5_000).unwrap();
+
+    pool::write_off(&mut lending_pool, 3_000);
+    assert_eq!(lending_pool.reserves, 0);
+    assert_eq!(lending_pool.borrowed, 2_000);
+    // Suppliers lose only what the reserves could not cover
+    assert_eq!(pool::total_value(&lending_pool), 8_000);
+}
+
+#[test]
+fn test_only_ckbtc_ledger_loans_are_pool_loans() {
+    assert!(pool::is_pool_loan(&loan(1, 0)));
+
+    let mut usdc = loan(1, 0);
+    usdc.asset = LoanAsset::CkUsdc;
+    assert!(!pool::is_pool_loan(&usdc));
+}
@@ -0,0 +1,219 @@
+// Unit tests for PSBT construction, BIP-370 conversion and finalization
+
+use bitcoin::key::{Keypair, TapTweak};
+use bitcoin::opcodes::all::OP_CHECKSIG;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::Address;
+use vault::psbt::{
+    self, PartialSignature, PsbtInputSpec, PsbtOutputSpec, PsbtVersion, TaprootSpend,
+};
+
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+fn keypair(byte: u8) -> Keypair {
+    let secp = Secp256k1::new();
+    Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[byte; 32]).unwrap())
+}
+
+fn p2tr_address(internal_key: XOnlyPublicKey) -> Address {
+    Address::p2tr(&Secp256k1::new(), internal_key, None, psbt::NETWORK)
+}
+
+fn key_path_input(keypair: &Keypair, amount: u64) -> PsbtInputSpec {
+    let (internal_key, _) = keypair.x_only_public_key();
+    PsbtInputSpec {
+        txid: TXID.to_string(),
+        vout: 0,
+        amount,
+        script_pubkey: p2tr_address(internal_key).script_pubkey().to_bytes(),
+        sequence: None,
+        taproot: Some(TaprootSpend::KeyPath {
+            internal_key: internal_key.serialize().to_vec(),
+            merkle_root: None,
+        }),
+    }
+}
+
+fn output(amount: u64) -> PsbtOutputSpec {
+    let (key, _) = keypair(9).x_only_public_key();
+    PsbtOutputSpec {
+        address: p2tr_address(key).to_string(),
+        amount,
+    }
+}
+
+#[test]
+fn test_build_and_roundtrip_v0() {
+    let input = key_path_input(&keypair(1), 100_000);
+    let built = psbt::build_psbt(&[input], &[output(90_000)], 0).unwrap();
+
+    let encoded = psbt::to_base64(&built, PsbtVersion::V0).unwrap();
+    assert!(
+        encoded.starts_with("cHNidP8"),
+        "base64 PSBT should start with magic"
+    );
+
+    let (parsed, version) = psbt::from_base64(&encoded).unwrap();
+    assert_eq!(version, PsbtVersion::V0);
+    assert_eq!(parsed.unsigned_tx, built.unsigned_tx);
+    assert_eq!(
+        parsed.inputs[0].tap_internal_key,
+        built.inputs[0].tap_internal_key
+    );
+}
+
+#[test]
+fn test_roundtrip_v2() {
+    let input = key_path_input(&keypair(1), 100_000);
+    let built = psbt::build_psbt(&[input], &[output(40_000), output(50_000)], 800_000).unwrap();
+
+    let v0 = psbt::to_base64(&built, PsbtVersion::V0).unwrap();
+    let v2 = psbt::to_base64(&built, PsbtVersion::V2).unwrap();
+    assert_ne!(v0, v2);
+
+    let (parsed, version) = psbt::from_base64(&v2).unwrap();
+    assert_eq!(version, PsbtVersion::V2);
+    assert_eq!(parsed.unsigned_tx, built.unsigned_tx);
+    assert_eq!(parsed.inputs[0].witness_utxo, built.inputs[0].witness_utxo);
+
+    // Re-encoding the parsed v2 PSBT as v0 must give the original bytes back
+    assert_eq!(psbt::to_base64(&parsed, PsbtVersion::V0).unwrap(), v0);
+}
+
+#[test]
+fn test_key_path_sign_finalize_extract() {
+    let secp = Secp256k1::new();
+    let signer = keypair(1);
+    let mut built =
+        psbt::build_psbt(&[key_path_input(&signer, 100_000)], &[output(90_000)], 0).unwrap();
+
+    let sighash = psbt::taproot_sighash(&built, 0, None).unwrap();
+    let tweaked = signer.tap_tweak(&secp, None).to_keypair();
+    let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &tweaked);
+
+    psbt::add_partial_signature(
+        &mut built,
+        0,
+        &PartialSignature::TaprootKey {
+            signature: sig.as_ref().to_vec(),
+        },
+    )
+    .unwrap();
+    psbt::finalize(&mut built).unwrap();
+
+    let witness = built.inputs[0].final_script_witness.clone().unwrap();
+    assert_eq!(witness.len(), 1);
+    assert_eq!(witness.nth(0).unwrap().len(), 64);
+
+    let expected_txid = built.unsigned_tx.compute_txid().to_string();
+    let finalized = psbt::extract(built).unwrap();
+    assert_eq!(finalized.txid, expected_txid);
+    assert!(!finalized.tx_hex.is_empty());
+}
+
+#[test]
+fn test_script_path_cosigners_combine_and_finalize() {
+    let secp = Secp256k1::new();
+    let internal = keypair(1);
+    let user = keypair(2);
+    let vault_key = keypair(3);
+    let (internal_key, _) = internal.x_only_public_key();
+    let (user_key, _) = user.x_only_public_key();
+    let (vault_xonly, _) = vault_key.x_only_public_key();
+
+    // 2-of-2 leaf: <user> CHECKSIGVERIFY <vault> CHECKSIG
+    let leaf = Builder::new()
+        .push_x_only_key(&user_key)
+        .push_opcode(bitcoin::opcodes::all::OP_CHECKSIGVERIFY)
+        .push_x_only_key(&vault_xonly)
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, leaf.clone())
+        .unwrap()
+        .finalize(&secp, internal_key)
+        .unwrap();
+    let control_block = spend_info
+        .control_block(&(leaf.clone(), LeafVersion::TapScript))
+        .unwrap();
+    let script_pubkey =
+        Address::p2tr(&secp, internal_key, spend_info.merkle_root(), psbt::NETWORK).script_pubkey();
+
+    let input = PsbtInputSpec {
+        txid: TXID.to_string(),
+        vout: 1,
+        amount: 50_000,
+        script_pubkey: script_pubkey.to_bytes(),
+        sequence: None,
+        taproot: Some(TaprootSpend::ScriptPath {
+            internal_key: internal_key.serialize().to_vec(),
+            leaf_script: leaf.to_bytes(),
+            control_block: control_block.serialize(),
+        }),
+    };
+    let unsigned = psbt::build_psbt(&[input], &[output(45_000)], 0).unwrap();
+    let sighash = psbt::taproot_sighash(&unsigned, 0, Some(leaf.as_bytes())).unwrap();
+
+    // Each co-signer signs their own copy
+    let mut copies = Vec::new();
+    for signer in [&user, &vault_key] {
+        let mut copy = unsigned.clone();
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), signer);
+        psbt::add_partial_signature(
+            &mut copy,
+            0,
+            &PartialSignature::TaprootScript {
+                public_key: signer.x_only_public_key().0.serialize().to_vec(),
+                leaf_script: leaf.to_bytes(),
+                signature: sig.as_ref().to_vec(),
+            },
+        )
+        .unwrap();
+        copies.push(copy);
+    }
+
+    // Finalizing a single copy fails: the other signature is missing
+    assert!(psbt::finalize(&mut copies[0].clone()).is_err());
+
+    let mut combined = psbt::combine(copies).unwrap();
+    assert_eq!(combined.inputs[0].tap_script_sigs.len(), 2);
+    psbt::finalize(&mut combined).unwrap();
+
+    let witness = combined.inputs[0].final_script_witness.clone().unwrap();
+    assert_eq!(
+        witness.len(),
+        4,
+        "two signatures, leaf script and control block"
+    );
+
+    // The user signature is checked first, so the vault signature sits deeper in the stack
+    let vault_sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &vault_key);
+    assert_eq!(witness.nth(0).unwrap(), vault_sig.as_ref());
+    assert_eq!(witness.nth(2).unwrap(), leaf.as_bytes());
+    assert!(psbt::extract(combined).is_ok());
+}
+
+#[test]
+fn test_rejects_invalid_requests() {
+    let input = key_path_input(&keypair(1), 10_000);
+    assert!(psbt::build_psbt(&[], &[output(1_000)], 0).is_err());
+    assert!(psbt::build_psbt(std::slice::from_ref(&input), &[], 0).is_err());
+    assert!(psbt::build_psbt(std::slice::from_ref(&input), &[output(20_000)], 0).is_err());
+
+    let mut built = psbt::build_psbt(&[input], &[output(9_000)], 0).unwrap();
+    assert!(
+        psbt::finalize(&mut built).is_err(),
+        "unsigned input cannot be finalized"
+    );
+    assert!(psbt::add_partial_signature(
+        &mut built,
+        5,
+        &PartialSignature::TaprootKey {
+            signature: vec![0u8; 64]
+        }
+    )
+    .is_err());
+    assert!(psbt::from_base64("bm90IGEgcHNidA==").is_err());
+}
@@ -0,0 +1,169 @@
+// Unit tests for repayment subaccounts and ledger block validation
+
+use candid::{Nat, Principal};
+use vault::ckbtc::{Account, Transaction, Transfer};
+use vault::helpers::loan_memo;
+use vault::repayments::{self, DepositSplit};
+use vault::share_token;
+use vault::types::{Loan, LoanAsset, LoanStatus};
+
+fn vault_id() -> Principal {
+    Principal::from_slice(&[9; 10])
+}
+
+fn borrower() -> Principal {
+    Principal::from_slice(&[1; 29])
+}
+
+fn loan() -> Loan {
+    Loan {
+        id: 3,
+        user_id: borrower(),
+        collateral_utxo_id: 1,
+        extra_collateral: vec![],
+        asset: LoanAsset::CkBtc,
+        borrowed_amount: 100_000,
+        repaid_amount: 0,
+        interest_rate: 500,
+        created_at: 1_000,
+        status: LoanStatus::Active,
+        solana_disbursement: None,
+        term: None,
+    }
+}
+
+fn repayment_transfer(amount: u64) -> Transaction {
+    Transaction {
+        kind: "transfer".to_string(),
+        mint: None,
+        burn: None,
+        transfer: Some(Transfer {
+            from: Account {
+                owner: borrower(),
+                subaccount: None,
+            },
+            to: Account {
+                owner: vault_id(),
+                subaccount: None,
+            },
+            amount: Nat::from(amount),
+            memo: Some(loan_memo(3)),
+        }),
+        timestamp: 2_000,
+    }
+}
+
+#[test]
+fn test_loan_subaccounts_are_deterministic_and_distinct() {
+    assert_eq!(
+        repayments::loan_subaccount(7),
+        repayments::loan_subaccount(7)
+    );
+    assert_ne!(
+        repayments::loan_subaccount(7),
+        repayments::loan_subaccount(8)
+    );
+    // Never the default account, which holds the treasury
+    assert_ne!(repayments::loan_subaccount(0), [0u8; 32]);
+}
+
+#[test]
+fn test_repayment_account_is_a_vault_subaccount() {
+    let vault = Principal::from_slice(&[9; 10]);
+    let account = repayments::repayment_account(vault, 42);
+
+    assert_eq!(account.owner, vault);
+    assert_eq!(
+        account.subaccount,
+        Some(repayments::loan_subaccount(42).to_vec())
+    );
+    // Valid ICRC-1 account that is distinct from the default one
+    assert_eq!(share_token::normalize(&account).unwrap(), account);
+}
+
+#[test]
+fn test_split_deposit_partial_repayment() {
+    assert_eq!(
+        repayments::split_deposit(5_010, 10, 100_000),
+        Some(DepositSplit {
+            swept: 5_000,
+            credited: 5_000,
+            excess: 0,
+        })
+    );
+}
+
+#[test]
+fn test_split_deposit_overpayment_is_returned() {
+    assert_eq!(
+        repayments::split_deposit(1_010, 10, 600),
+        Some(DepositSplit {
+            swept: 1_000,
+            credited: 600,
+            excess: 400,
+        })
+    );
+}
+
+#[test]
+fn test_split_deposit_closed_loan_returns_everything() {
+    assert_eq!(
+        repayments::split_deposit(1_010, 10, 0),
+        Some(DepositSplit {
+            swept: 1_000,
+            credited: 0,
+            excess: 1_000,
+        })
+    );
+}
+
+#[test]
+fn test_split_deposit_ignores_dust() {
+    assert_eq!(repayments::split_deposit(0, 10, 1_000), None);
+    assert_eq!(repayments::split_deposit(10, 10, 1_000), None);
+}
+
+#[test]
+fn test_valid_repayment_block_is_accepted() {
+    let amount =
+        repayments::validate_repayment_transfer(&repayment_transfer(5_000), &loan(), vault_id());
+    assert_eq!(amount, Ok(5_000));
+}
+
+#[test]
+fn test_repayment_block_must_reference_the_loan() {
+    let mut transaction = repayment_transfer(5_000);
+    transaction.transfer.as_mut().unwrap().memo = Some(loan_memo(4));
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+
+    transaction.transfer.as_mut().unwrap().memo = None;
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+}
+
+#[test]
+fn test_repayment_block_must_be_from_borrower_to_treasury() {
+    let mut transaction = repayment_transfer(5_000);
+    transaction.transfer.as_mut().unwrap().from.owner = Principal::from_slice(&[2; 29]);
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+
+    // Repayment subaccounts are swept by polling, not credited by block
+    let mut transaction = repayment_transfer(5_000);
+    transaction.transfer.as_mut().unwrap().to = repayments::repayment_account(vault_id(), 3);
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+
+    // The all-zero subaccount is the treasury account
+    let mut transaction = repayment_transfer(5_000);
+    transaction.transfer.as_mut().unwrap().to.subaccount = Some(vec![0; 32]);
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_ok());
+}
+
+#[test]
+fn test_repayment_block_must_be_a_transfer_after_the_loan() {
+    let mut transaction = repayment_transfer(5_000);
+    transaction.timestamp = 999;
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+
+    let mut transaction = repayment_transfer(5_000);
+    transaction.kind = "mint".to_string();
+    assert!(repayments::validate_repayment_transfer(&transaction, &loan(), vault_id()).is_err());
+}
@@ -0,0 +1,56 @@
+// Unit tests for moving data to a new vetKD key version
+
+use vault::{rotation, vetkeys};
+
+const NONCE: [u8; vetkeys::NONCE_LEN] = [4u8; vetkeys::NONCE_LEN];
+
+/// A data key wrapped the way a client does, under `key_version`
+fn wrapped(key_version: u32) -> Vec<u8> {
+    vetkeys::encrypt_with_key_version(&[1u8; 32], key_version, &NONCE, &[7u8; 32], b"").unwrap()
+}
+
+#[test]
+fn test_needs_rotation() {
+    assert!(rotation::needs_rotation(&wrapped(1), 2));
+    assert!(!rotation::needs_rotation(&wrapped(2), 2));
+    assert!(!rotation::needs_rotation(&[0xff, 0, 0], 2));
+}
+
+#[test]
+fn test_older_versions_are_retired_when_rotation_finishes() {
+    assert!(vetkeys::is_derivable(2, 2, false, &[]).is_ok());
+    assert!(vetkeys::is_derivable(1, 2, true, &[]).is_ok());
+    assert!(vetkeys::is_derivable(1, 2, false, &[]).is_err());
+    assert!(vetkeys::is_derivable(3, 2, true, &[]).is_err());
+    assert!(vetkeys::is_derivable(0, 2, true, &[]).is_err());
+}
+
+#[test]
+fn test_skipped_items_keep_their_version_derivable() {
+    assert!(vetkeys::is_derivable(1, 3, false, &[1]).is_ok());
+    assert!(vetkeys::is_derivable(2, 3, false, &[1]).is_err());
+}
+
+#[test]
+fn test_only_transient_failures_are_retried() {
+    assert!(rotation::is_transient("Missing wrapped key for aaaaa-aa"));
+    assert!(rotation::is_transient(
+        "Wrapped key is for key version 1, expected 2"
+    ));
+    assert!(!rotation::is_transient(
+        "Decryption failed: wrong key or tampered ciphertext"
+    ));
+
+    assert!(!rotation::should_skip(
+        "Document 3 is still uploading; finish or delete it first",
+        1
+    ));
+    assert!(rotation::should_skip(
+        "Document 3 is still uploading; finish or delete it first",
+        rotation::MAX_ROTATION_ATTEMPTS
+    ));
+    assert!(rotation::should_skip(
+        "Note too large: maximum is 65536 bytes",
+        1
+    ));
+}
@@ -0,0 +1,293 @@
+// Unit tests for the ICRC-1/ICRC-2 pool share token
+
+use candid::{Nat, Principal};
+use vault::ckbtc::{Account, TransferArgs, TransferError, TransferFromArgs, TransferFromError};
+use vault::pool;
+use vault::share_token::{
+    self, AllowanceArgs, ApproveArgs, ApproveError, MetadataValue, PERMITTED_DRIFT_NANOS,
+    TRANSFER_FEE, TX_WINDOW_NANOS,
+};
+use vault::types::LendingPool;
+
+const NOW: u64 = 1_700_000_000_000_000_000;
+
+fn principal(n: u8) -> Principal {
+    Principal::from_slice(&[n; 29])
+}
+
+fn account(n: u8) -> Account {
+    Account {
+        owner: principal(n),
+        subaccount: None,
+    }
+}
+
+fn funded_pool(shares: u64) -> LendingPool {
+    let mut lending_pool = LendingPool::default();
+    pool::supply(&mut lending_pool, &account(1), shares).unwrap();
+    lending_pool
+}
+
+fn transfer_args(to: Account, amount: u64) -> TransferArgs {
+    TransferArgs {
+        from_subaccount: None,
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn approve_args(spender: Account, amount: u64) -> ApproveArgs {
+    ApproveArgs {
+        from_subaccount: None,
+        spender,
+        amount: Nat::from(amount),
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn transfer_from_args(from: Account, to: Account, amount: u64) -> TransferFromArgs {
+    TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+#[test]
+fn test_transfer_moves_shares_and_burns_fee() {
+    let mut lending_pool = funded_pool(1_000);
+
+    let block = share_token::transfer(
+        &mut lending_pool,
+        principal(1),
+        transfer_args(account(2), 400),
+        NOW,
+    )
+    .unwrap();
+    assert_eq!(block, Nat::from(0u64));
+
+    assert_eq!(
+        share_token::balance_of(&lending_pool, &account(1)),
+        600 - TRANSFER_FEE
+    );
+    assert_eq!(share_token::balance_of(&lending_pool, &account(2)), 400);
+    assert_eq!(lending_pool.total_shares, 1_000 - TRANSFER_FEE);
+}
+
+#[test]
+fn test_transfer_rejects_insufficient_funds_and_bad_fee() {
+    let mut lending_pool = funded_pool(100);
+
+    let result = share_token::transfer(
+        &mut lending_pool,
+        principal(1),
+        transfer_args(account(2), 95),
+        NOW,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferError::InsufficientFunds { .. })
+    ));
+
+    let mut args = transfer_args(account(2), 10);
+    args.fee = Some(Nat::from(1u64));
+    let result = share_token::transfer(&mut lending_pool, principal(1), args, NOW);
+    assert!(matches!(result, Err(TransferError::BadFee { .. })));
+
+    assert_eq!(share_token::balance_of(&lending_pool, &account(1)), 100);
+}
+
+#[test]
+fn test_zero_subaccount_is_the_default_account() {
+    let mut lending_pool = funded_pool(1_000);
+    let explicit_default = Account {
+        owner: principal(2),
+        subaccount: Some(vec![0; 32]),
+    };
+
+    share_token::transfer(
+        &mut lending_pool,
+        principal(1),
+        transfer_args(explicit_default, 100),
+        NOW,
+    )
+    .unwrap();
+    assert_eq!(share_token::balance_of(&lending_pool, &account(2)), 100);
+
+    let bad_subaccount = Account {
+        owner: principal(2),
+        subaccount: Some(vec![1; 3]),
+    };
+    let result = share_token::transfer(
+        &mut lending_pool,
+        principal(1),
+        transfer_args(bad_subaccount, 1),
+        NOW,
+    );
+    assert!(matches!(result, Err(TransferError::GenericError { .. })));
+}
+
+#[test]
+fn test_created_at_time_deduplicates_and_bounds_transfers() {
+    let mut lending_pool = funded_pool(1_000);
+    let mut args = transfer_args(account(2), 100);
+    args.created_at_time = Some(NOW);
+
+    let block = share_token::transfer(&mut lending_pool, principal(1), args.clone(), NOW).unwrap();
+    let result = share_token::transfer(&mut lending_pool, principal(1), args.clone(), NOW);
+    assert!(
+        matches!(result, Err(TransferError::Duplicate { duplicate_of }) if duplicate_of == block)
+    );
+
+    args.created_at_time = Some(NOW - TX_WINDOW_NANOS - PERMITTED_DRIFT_NANOS - 1);
+    let result = share_token::transfer(&mut lending_pool, principal(1), args.clone(), NOW);
+    assert!(matches!(result, Err(TransferError::TooOld)));
+
+    args.created_at_time = Some(NOW + PERMITTED_DRIFT_NANOS + 1);
+    let result = share_token::transfer(&mut lending_pool, principal(1), args, NOW);
+    assert!(
+        matches!(result, Err(TransferError::CreatedInFuture { ledger_time }) if ledger_time == NOW)
+    );
+
+    assert_eq!(share_token::balance_of(&lending_pool, &account(2)), 100);
+}
+
+#[test]
+fn test_approve_and_transfer_from_spend_allowance() {
+    let mut lending_pool = funded_pool(1_000);
+
+    share_token::approve(
+        &mut lending_pool,
+        principal(1),
+        approve_args(account(2), 300),
+        NOW,
+    )
+    .unwrap();
+    assert_eq!(
+        share_token::balance_of(&lending_pool, &account(1)),
+        1_000 - TRANSFER_FEE
+    );
+
+    share_token::transfer_from(
+        &mut lending_pool,
+        principal(2),
+        transfer_from_args(account(1), account(3), 200),
+        NOW,
+    )
+    .unwrap();
+    assert_eq!(share_token::balance_of(&lending_pool, &account(3)), 200);
+
+    let allowance = share_token::allowance(
+        &lending_pool,
+        &AllowanceArgs {
+            account: account(1),
+            spender: account(2),
+        },
+        NOW,
+    );
+    assert_eq!(allowance.allowance, Nat::from(300 - 200 - TRANSFER_FEE));
+
+    let result = share_token::transfer_from(
+        &mut lending_pool,
+        principal(2),
+        transfer_from_args(account(1), account(3), 200),
+        NOW,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferFromError::InsufficientAllowance { .. })
+    ));
+}
+
+#[test]
+fn test_expired_allowance_cannot_be_spent() {
+    let mut lending_pool = funded_pool(1_000);
+    let mut args = approve_args(account(2), 500);
+    args.expires_at = Some(NOW + 10);
+    share_token::approve(&mut lending_pool, principal(1), args, NOW).unwrap();
+
+    let result = share_token::transfer_from(
+        &mut lending_pool,
+        principal(2),
+        transfer_from_args(account(1), account(2), 100),
+        NOW + 10,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferFromError::InsufficientAllowance { .. })
+    ));
+}
+
+#[test]
+fn test_approve_checks_expected_allowance_and_expiry() {
+    let mut lending_pool = funded_pool(1_000);
+    share_token::approve(
+        &mut lending_pool,
+        principal(1),
+        approve_args(account(2), 50),
+        NOW,
+    )
+    .unwrap();
+
+    let mut args = approve_args(account(2), 80);
+    args.expected_allowance = Some(Nat::from(40u64));
+    let result = share_token::approve(&mut lending_pool, principal(1), args, NOW);
+    assert!(
+        matches!(result, Err(ApproveError::AllowanceChanged { current_allowance }) if current_allowance == 50u64)
+    );
+
+    let mut args = approve_args(account(2), 80);
+    args.expires_at = Some(NOW);
+    let result = share_token::approve(&mut lending_pool, principal(1), args, NOW);
+    assert!(matches!(result, Err(ApproveError::Expired { .. })));
+
+    let result = share_token::approve(
+        &mut lending_pool,
+        principal(1),
+        approve_args(account(1), 1),
+        NOW,
+    );
+    assert!(matches!(result, Err(ApproveError::GenericError { .. })));
+}
+
+#[test]
+fn test_exchange_rate_in_metadata_grows_with_interest() {
+    let mut lending_pool = funded_pool(1_000_000);
+    let rate = |lending_pool: &LendingPool| {
+        share_token::metadata(lending_pool)
+            .into_iter()
+            .find(|(key, _)| key == "bitfold:exchange_rate")
+            .map(|(_, value)| value)
+            .unwrap()
+    };
+    let before = rate(&lending_pool);
+    assert_eq!(
+        before,
+        MetadataValue::Nat(Nat::from(pool::EXCHANGE_RATE_SCALE))
+    );
+
+    pool::lend(&mut lending_pool, 500_000).unwrap();
+    pool::repay(&mut lending_pool, 500_000, 50_000);
+    assert_ne!(rate(&lending_pool), before);
+    assert!(pool::exchange_rate(&lending_pool) > pool::EXCHANGE_RATE_SCALE);
+}
+
+#[test]
+fn test_supported_standards() {
+    let names: Vec<String> = share_token::supported_standards()
+        .into_iter()
+        .map(|standard| standard.name)
+        .collect();
+    assert_eq!(names, vec!["ICRC-1", "ICRC-2"]);
+}
@@ -0,0 +1,337 @@
+// Unit tests for Solana JSON-RPC response parsing
+
+use candid::{Decode, Encode};
+use vault::solana::{
+    self, SolanaAddress, SolanaCommitment, SolanaSignature, SolanaVerificationError,
+};
+
+#[test]
+fn test_parse_balance() {
+    let body = r#"{"jsonrpc":"2.0","result":{"context":{"apiVersion":"1.18.22","slot":284731212},"value":1500000000},"id":1}"#;
+    assert_eq!(solana::parse_balance(body).unwrap(), 1_500_000_000);
+
+    // The old bare-number shape is not what getBalance returns
+    assert!(solana::parse_balance(r#"{"jsonrpc":"2.0","result":42,"id":1}"#).is_err());
+
+    let error =
+        r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid param: WrongSize"},"id":1}"#;
+    assert!(solana::parse_balance(error)
+        .unwrap_err()
+        .contains("Invalid param: WrongSize"));
+}
+
+#[test]
+fn test_parse_account_info() {
+    let address = SolanaAddress::parse("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM").unwrap();
+    let body = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1},"value":{"data":["","base64"],"executable":false,"lamports":88849814690250,"owner":"11111111111111111111111111111111","rentEpoch":18446744073709551615,"space":0}},"id":1}"#;
+
+    let account = solana::parse_account_info(&address, body).unwrap();
+    assert_eq!(account.address, address);
+    assert_eq!(account.balance, 88_849_814_690_250);
+    assert_eq!(
+        account.owner.as_ref().map(SolanaAddress::as_str),
+        Some("11111111111111111111111111111111")
+    );
+
+    // Accounts that were never funded do not exist
+    let missing = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1},"value":null},"id":1}"#;
+    let account = solana::parse_account_info(&address, missing).unwrap();
+    assert_eq!(account.balance, 0);
+    assert_eq!(account.owner, None);
+}
+
+#[test]
+fn test_parse_token_accounts() {
+    let body = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1},"value":[{"pubkey":"C2gJg6tKpQs41PRS1nC8aw3ZKNZK3HQQZGVrDFDup5nx","account":{"data":{"program":"spl-token","parsed":{"info":{"isNative":false,"mint":"EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v","owner":"9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM","state":"initialized","tokenAmount":{"amount":"2500000","decimals":6,"uiAmount":2.5,"uiAmountString":"2.5"}},"type":"account"},"space":165},"executable":false,"lamports":2039280,"owner":"TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA","rentEpoch":18446744073709551615,"space":165}}]},"id":1}"#;
+
+    let accounts = solana::parse_token_accounts(body).unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(
+        accounts[0].address.as_str(),
+        "C2gJg6tKpQs41PRS1nC8aw3ZKNZK3HQQZGVrDFDup5nx"
+    );
+    assert_eq!(
+        accounts[0].mint.as_str(),
+        "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+    );
+    assert_eq!(
+        accounts[0].balance,
+        solana::SplTokenAmount {
+            amount: 2_500_000,
+            decimals: 6
+        }
+    );
+
+    let empty = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1},"value":[]},"id":1}"#;
+    assert!(solana::parse_token_accounts(empty).unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_token_account_balance() {
+    let body = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1114},"value":{"amount":"9864","decimals":2,"uiAmount":98.64,"uiAmountString":"98.64"}},"id":1}"#;
+    assert_eq!(
+        solana::parse_token_account_balance(body).unwrap(),
+        solana::SplTokenAmount {
+            amount: 9_864,
+            decimals: 2
+        }
+    );
+
+    let bad = r#"{"jsonrpc":"2.0","result":{"context":{"slot":1},"value":{"amount":"-1","decimals":2}},"id":1}"#;
+    assert!(solana::parse_token_account_balance(bad).is_err());
+}
+
+#[test]
+fn test_transform_drops_per_replica_fields() {
+    use ic_cdk::management_canister::{HttpHeader, HttpRequestResult, TransformArgs};
+
+    let response = |slot: u64| TransformArgs {
+        response: HttpRequestResult {
+            status: 200u64.into(),
+            headers: vec![HttpHeader {
+                name: "Date".to_string(),
+                value: format!("slot {}", slot),
+            }],
+            body: format!(
+                r#"{{"jsonrpc":"2.0","result":{{"context":{{"slot":{}}},"value":7}},"id":1}}"#,
+                slot
+            )
+            .into_bytes(),
+        },
+        context: vec![],
+    };
+
+    let a = solana::transform_response(response(1));
+    let b = solana::transform_response(response(2));
+    assert_eq!(a, b);
+    assert!(a.headers.is_empty());
+    assert_eq!(
+        solana::parse_balance(std::str::from_utf8(&a.body).unwrap()).unwrap(),
+        7
+    );
+}
+
+#[test]
+fn test_parse_slot_and_block_hash() {
+    assert_eq!(
+        solana::parse_slot(r#"{"jsonrpc":"2.0","result":284731212,"id":1}"#).unwrap(),
+        284_731_212
+    );
+
+    let blockhash = SolanaAddress::from_bytes(&[9u8; 32]);
+    let body = format!(
+        r#"{{"jsonrpc":"2.0","result":{{"blockHeight":263000000,"blockTime":1700000000,"blockhash":"{}","parentSlot":284731211,"previousBlockhash":"11111111111111111111111111111111"}},"id":1}}"#,
+        blockhash
+    );
+    assert_eq!(solana::parse_block_hash(&body).unwrap(), [9u8; 32]);
+
+    let skipped = r#"{"jsonrpc":"2.0","error":{"code":-32007,"message":"Slot 284731212 was skipped, or missing due to ledger jump to recent snapshot"},"id":1}"#;
+    assert!(solana::parse_block_hash(skipped)
+        .unwrap_err()
+        .contains("skipped"));
+}
+
+#[test]
+fn test_parse_signature_status() {
+    let confirmed = r#"{"jsonrpc":"2.0","result":{"context":{"slot":82},"value":[{"slot":72,"confirmations":10,"err":null,"status":{"Ok":null},"confirmationStatus":"confirmed"}]},"id":1}"#;
+    assert_eq!(
+        solana::parse_signature_status(confirmed).unwrap(),
+        Some(solana::SignatureStatus {
+            slot: 72,
+            confirmation_status: Some(SolanaCommitment::Confirmed),
+            err: None,
+        })
+    );
+
+    let failed = r#"{"jsonrpc":"2.0","result":{"context":{"slot":82},"value":[{"slot":48,"confirmations":null,"err":{"InstructionError":[0,{"Custom":1}]},"status":{"Err":{"InstructionError":[0,{"Custom":1}]}},"confirmationStatus":"finalized"}]},"id":1}"#;
+    let status = solana::parse_signature_status(failed).unwrap().unwrap();
+    assert_eq!(status.slot, 48);
+    assert!(status.err.unwrap().contains("InstructionError"));
+
+    let unknown = r#"{"jsonrpc":"2.0","result":{"context":{"slot":82},"value":[null]},"id":1}"#;
+    assert_eq!(solana::parse_signature_status(unknown).unwrap(), None);
+}
+
+#[test]
+fn test_check_transaction_status_against_commitment() {
+    let signature = SolanaSignature::from_bytes(&[5u8; 64]);
+    let status = |level: Option<SolanaCommitment>, err: Option<&str>| {
+        Some(solana::SignatureStatus {
+            slot: 72,
+            confirmation_status: level,
+            err: err.map(str::to_string),
+        })
+    };
+
+    let confirmed = solana::check_transaction_status(
+        &signature,
+        status(Some(SolanaCommitment::Confirmed), None),
+        SolanaCommitment::Confirmed,
+    )
+    .unwrap();
+    assert!(confirmed.verified);
+    assert_eq!(confirmed.slot, 72);
+
+    // Confirmed is not yet final
+    let pending = solana::check_transaction_status(
+        &signature,
+        status(Some(SolanaCommitment::Confirmed), None),
+        SolanaCommitment::Finalized,
+    )
+    .unwrap();
+    assert!(!pending.verified);
+    assert_eq!(pending.required_commitment, SolanaCommitment::Finalized);
+
+    // Finalized but failed on-chain
+    let failed = solana::check_transaction_status(
+        &signature,
+        status(
+            Some(SolanaCommitment::Finalized),
+            Some(r#"{"InstructionError":[0,{"Custom":1}]}"#),
+        ),
+        SolanaCommitment::Processed,
+    )
+    .unwrap();
+    assert!(!failed.verified);
+    assert!(failed.err.is_some());
+
+    // No reported level cannot satisfy any commitment
+    assert!(
+        !solana::check_transaction_status(
+            &signature,
+            status(None, None),
+            SolanaCommitment::Processed
+        )
+        .unwrap()
+        .verified
+    );
+
+    assert_eq!(
+        solana::check_transaction_status(&signature, None, SolanaCommitment::Processed),
+        Err(SolanaVerificationError::UnknownSignature(signature))
+    );
+}
+
+#[test]
+fn test_commitment_levels() {
+    assert_eq!(
+        SolanaCommitment::parse("processed"),
+        Some(SolanaCommitment::Processed)
+    );
+    assert_eq!(
+        SolanaCommitment::parse("finalized"),
+        Some(SolanaCommitment::Finalized)
+    );
+    assert_eq!(SolanaCommitment::parse("max"), None);
+    assert!(SolanaCommitment::Processed < SolanaCommitment::Confirmed);
+    assert!(SolanaCommitment::Confirmed < SolanaCommitment::Finalized);
+}
+
+#[test]
+fn test_transform_normalizes_send_and_slot_responses() {
+    use ic_cdk::management_canister::{HttpRequestResult, TransformArgs};
+
+    let transform = |body: &str, context: Vec<u8>| {
+        let result = solana::transform_response(TransformArgs {
+            response: HttpRequestResult {
+                status: 200u64.into(),
+                headers: vec![],
+                body: body.as_bytes().to_vec(),
+            },
+            context,
+        });
+        String::from_utf8(result.body).unwrap()
+    };
+
+    // Replicas reading a few slots apart agree on the rounded slot
+    let a = transform(
+        r#"{"jsonrpc":"2.0","result":1005,"id":1}"#,
+        solana::ROUND_SLOT_CONTEXT.to_vec(),
+    );
+    let b = transform(
+        r#"{"jsonrpc":"2.0","result":1019,"id":1}"#,
+        solana::ROUND_SLOT_CONTEXT.to_vec(),
+    );
+    assert_eq!(a, b);
+    assert_eq!(solana::parse_slot(&a).unwrap(), 1000);
+
+    // Only the first replica's submission is accepted; the rest see "already processed"
+    let signature = SolanaSignature::from_bytes(&[3u8; 64]);
+    let mut context = solana::SEND_TRANSACTION_CONTEXT.to_vec();
+    context.extend_from_slice(signature.as_str().as_bytes());
+    let accepted = transform(
+        &format!(r#"{{"jsonrpc":"2.0","result":"{}","id":1}}"#, signature),
+        context.clone(),
+    );
+    let duplicate = transform(
+        r#"{"jsonrpc":"2.0","error":{"code":-32002,"message":"Transaction simulation failed: This transaction has already been processed","data":{"logs":[]}},"id":1}"#,
+        context.clone(),
+    );
+    assert_eq!(accepted, duplicate);
+
+    let rejected = transform(
+        r#"{"jsonrpc":"2.0","error":{"code":-32002,"message":"Transaction simulation failed: Blockhash not found","data":{"logs":["a"],"unitsConsumed":0}},"id":1}"#,
+        context,
+    );
+    assert!(!rejected.contains("logs"));
+    assert!(rejected.contains("Blockhash not found"));
+
+    // Confirmation counts differ between replicas
+    let status = |confirmations: u64| {
+        transform(
+            &format!(
+                r#"{{"jsonrpc":"2.0","result":{{"context":{{"slot":90}},"value":[{{"slot":72,"confirmations":{},"err":null,"confirmationStatus":"confirmed"}}]}},"id":1}}"#,
+                confirmations
+            ),
+            vec![],
+        )
+    };
+    assert_eq!(status(10), status(12));
+}
+
+#[test]
+fn test_solana_address_validation() {
+    // System program: 32 zero bytes
+    let system = SolanaAddress::parse("11111111111111111111111111111111").unwrap();
+    assert_eq!(system.to_bytes(), [0u8; 32]);
+    assert_eq!(SolanaAddress::from_bytes(&[0u8; 32]), system);
+
+    let usdc = SolanaAddress::parse("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+    assert_eq!(SolanaAddress::from_bytes(&usdc.to_bytes()), usdc);
+
+    assert!(SolanaAddress::parse("").is_err());
+    assert!(SolanaAddress::parse("vault").is_err());
+    // 0, O, I and l are not in the base58 alphabet
+    assert!(SolanaAddress::parse("0PjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").is_err());
+    // Valid base58, wrong length
+    assert!(SolanaAddress::parse("EPjFWdd5AufqSSqeM2qN1xzy").is_err());
+}
+
+#[test]
+fn test_solana_signature_validation() {
+    let signature = SolanaSignature::from_bytes(&[7u8; 64]);
+    assert_eq!(
+        SolanaSignature::parse(signature.as_str()).unwrap(),
+        signature
+    );
+    assert_eq!(signature.to_bytes(), [7u8; 64]);
+
+    // An address is too short to be a signature
+    assert!(SolanaSignature::parse("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").is_err());
+    assert!(SolanaSignature::parse("not-a-signature").is_err());
+}
+
+#[test]
+fn test_newtypes_are_validated_when_decoded() {
+    let encoded = Encode!(&"vault".to_string()).unwrap();
+    assert!(Decode!(&encoded, SolanaAddress).is_err());
+
+    let address = SolanaAddress::parse("11111111111111111111111111111111").unwrap();
+    let encoded = Encode!(&address).unwrap();
+    // Encoded as plain text on the wire
+    assert_eq!(
+        Decode!(&encoded, String).unwrap(),
+        "11111111111111111111111111111111"
+    );
+    assert_eq!(Decode!(&encoded, SolanaAddress).unwrap(), address);
+}
@@ -0,0 +1,149 @@
+// Unit tests for Solana transaction building and serialization
+
+use ed25519_dalek::{Signer, SigningKey, Verifier};
+use vault::solana_tx::{self, AccountMeta, Instruction, Message};
+
+fn key(byte: u8) -> solana_tx::Pubkey {
+    [byte; 32]
+}
+
+#[test]
+fn test_encode_compact_u16() {
+    let encode = |value: usize| {
+        let mut out = Vec::new();
+        solana_tx::encode_compact_u16(value, &mut out);
+        out
+    };
+
+    assert_eq!(encode(0), vec![0x00]);
+    assert_eq!(encode(0x7f), vec![0x7f]);
+    assert_eq!(encode(0x80), vec![0x80, 0x01]);
+    assert_eq!(encode(0x3fff), vec![0xff, 0x7f]);
+    assert_eq!(encode(0x4000), vec![0x80, 0x80, 0x01]);
+}
+
+#[test]
+fn test_system_transfer_message_layout() {
+    let from = key(1);
+    let to = key(2);
+    let blockhash = [7u8; 32];
+    let instruction = solana_tx::system_transfer(&from, &to, 1_000_000);
+
+    // Transfer discriminant 2 (u32 LE) followed by lamports (u64 LE)
+    assert_eq!(instruction.data[..4], [2, 0, 0, 0]);
+    assert_eq!(instruction.data[4..], 1_000_000u64.to_le_bytes());
+
+    let message = Message::new(&[instruction], &from, blockhash).unwrap();
+    assert_eq!(message.num_required_signatures, 1);
+    assert_eq!(message.num_readonly_signed_accounts, 0);
+    assert_eq!(message.num_readonly_unsigned_accounts, 1);
+    assert_eq!(
+        message.account_keys,
+        vec![from, to, solana_tx::SYSTEM_PROGRAM_ID]
+    );
+    assert_eq!(message.signers(), [from]);
+
+    let bytes = message.serialize();
+    let mut expected = vec![1, 0, 1, 3];
+    expected.extend_from_slice(&from);
+    expected.extend_from_slice(&to);
+    expected.extend_from_slice(&solana_tx::SYSTEM_PROGRAM_ID);
+    expected.extend_from_slice(&blockhash);
+    expected.extend_from_slice(&[1, 2, 2, 0, 1, 12]);
+    expected.extend_from_slice(&[2, 0, 0, 0]);
+    expected.extend_from_slice(&1_000_000u64.to_le_bytes());
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_message_orders_and_merges_accounts() {
+    let payer = key(1);
+    let readonly_signer = key(2);
+    let program = key(3);
+    let shared = key(4);
+
+    let instructions = [
+        Instruction {
+            program_id: program,
+            accounts: vec![
+                AccountMeta::readonly(shared, false),
+                AccountMeta::readonly(readonly_signer, true),
+            ],
+            data: vec![],
+        },
+        Instruction {
+            program_id: program,
+            accounts: vec![AccountMeta::writable(shared, false)],
+            data: vec![],
+        },
+    ];
+
+    let message = Message::new(&instructions, &payer, [0u8; 32]).unwrap();
+    // Writable signer, read-only signer, writable (merged), read-only program
+    assert_eq!(
+        message.account_keys,
+        vec![payer, readonly_signer, shared, program]
+    );
+    assert_eq!(message.num_required_signatures, 2);
+    assert_eq!(message.num_readonly_signed_accounts, 1);
+    assert_eq!(message.num_readonly_unsigned_accounts, 1);
+    assert_eq!(message.instructions[0].accounts, vec![2, 1]);
+    assert_eq!(message.instructions[0].program_id_index, 3);
+}
+
+#[test]
+fn test_associated_token_address() {
+    let owner = key(5);
+    let mint = key(6);
+
+    let address = solana_tx::associated_token_address(&owner, &mint).unwrap();
+    assert_eq!(
+        address,
+        solana_tx::associated_token_address(&owner, &mint).unwrap()
+    );
+    assert_ne!(
+        address,
+        solana_tx::associated_token_address(&mint, &owner).unwrap()
+    );
+    // Program derived addresses have no private key
+    assert!(!solana_tx::is_on_curve(&address));
+
+    let create =
+        solana_tx::create_associated_token_account_idempotent(&owner, &owner, &mint).unwrap();
+    assert_eq!(create.accounts[1].pubkey, address);
+    assert_eq!(create.data, vec![1]);
+
+    let transfer = solana_tx::spl_transfer_checked(&address, &mint, &key(7), &owner, 250, 6);
+    assert_eq!(transfer.data[0], 12);
+    assert_eq!(transfer.data[1..9], 250u64.to_le_bytes());
+    assert_eq!(transfer.data[9], 6);
+}
+
+#[test]
+fn test_signed_transaction_serialization() {
+    let signing_key = SigningKey::from_bytes(&[42u8; 32]);
+    let payer = signing_key.verifying_key().to_bytes();
+    assert!(solana_tx::is_on_curve(&payer));
+
+    let message = Message::new(
+        &[solana_tx::system_transfer(&payer, &key(2), 5)],
+        &payer,
+        [1u8; 32],
+    )
+    .unwrap();
+    let message_bytes = message.serialize();
+    let signature = signing_key.sign(&message_bytes);
+
+    // The signature covers exactly the serialized message
+    assert!(signing_key
+        .verifying_key()
+        .verify(&message_bytes, &signature)
+        .is_ok());
+
+    let transaction = solana_tx::serialize_transaction(&message, &[signature.to_bytes()]).unwrap();
+    assert_eq!(transaction[0], 1);
+    assert_eq!(transaction[1..65], signature.to_bytes());
+    assert_eq!(transaction[65..], message_bytes[..]);
+
+    assert!(solana_tx::serialize_transaction(&message, &[]).is_err());
+}
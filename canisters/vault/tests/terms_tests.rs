@@ -0,0 +1,260 @@
+// Unit tests for fixed-term loans: late penalties, defaults and extensions
+
+use candid::Principal;
+use vault::helpers::{calculate_loan_value, reprice_loan};
+use vault::terms::{self, DAY_NANOS, GRACE_PERIOD_DAYS, MAX_EXTENSIONS};
+use vault::types::{Loan, LoanAsset, LoanStatus};
+
+const NOW: u64 = 1_700_000_000_000_000_000;
+
+/// A 100,000 sat loan at 5% opened at `NOW` and maturing after `days`
+fn fixed_term_loan(days: u64) -> Loan {
+    Loan {
+        id: 5,
+        user_id: Principal::from_slice(&[1; 29]),
+        collateral_utxo_id: 1,
+        extra_collateral: vec![],
+        asset: LoanAsset::CkBtc,
+        borrowed_amount: 100_000,
+        repaid_amount: 0,
+        interest_rate: 500,
+        created_at: NOW,
+        status: LoanStatus::Active,
+        solana_disbursement: None,
+        term: Some(terms::new_term(NOW, days)),
+    }
+}
+
+fn maturity(loan: &Loan) -> u64 {
+    loan.term.as_ref().unwrap().maturity
+}
+
+#[test]
+fn test_term_length_is_bounded() {
+    assert!(terms::validate_term_days(terms::MIN_TERM_DAYS).is_ok());
+    assert!(terms::validate_term_days(terms::MAX_TERM_DAYS).is_ok());
+    assert!(terms::validate_term_days(terms::MIN_TERM_DAYS - 1).is_err());
+    assert!(terms::validate_term_days(terms::MAX_TERM_DAYS + 1).is_err());
+}
+
+#[test]
+fn test_penalty_rate_escalates_weekly_up_to_the_cap() {
+    assert_eq!(terms::penalty_rate(0), terms::LATE_PENALTY_BPS_PER_DAY);
+    assert_eq!(terms::penalty_rate(6), terms::LATE_PENALTY_BPS_PER_DAY);
+    assert_eq!(terms::penalty_rate(7), 2 * terms::LATE_PENALTY_BPS_PER_DAY);
+    assert_eq!(
+        terms::penalty_rate(365),
+        terms::MAX_LATE_PENALTY_BPS_PER_DAY
+    );
+}
+
+#[test]
+fn test_no_penalty_before_maturity() {
+    let mut loan = fixed_term_loan(30);
+    let before = calculate_loan_value(&loan).unwrap();
+    let maturity = maturity(&loan);
+
+    assert_eq!(terms::accrue_penalty(&mut loan, maturity - 1), 0);
+    // Less than a full day overdue is not charged yet
+    assert_eq!(
+        terms::accrue_penalty(&mut loan, maturity + DAY_NANOS - 1),
+        0
+    );
+    assert_eq!(calculate_loan_value(&loan).unwrap(), before);
+}
+
+#[test]
+fn test_penalty_accrues_once_per_overdue_day() {
+    let mut loan = fixed_term_loan(30);
+    let before = calculate_loan_value(&loan).unwrap();
+    let ten_days_late = maturity(&loan) + 10 * DAY_NANOS;
+
+    // 7 days at 10 bps and 3 days at 20 bps of the 100,000 sat principal
+    assert_eq!(terms::accrue_penalty(&mut loan, ten_days_late), 1_300);
+    assert_eq!(calculate_loan_value(&loan).unwrap(), before + 1_300);
+
+    // Running the check again for the same day charges nothing more
+    assert_eq!(terms::accrue_penalty(&mut loan, ten_days_late), 0);
+    assert_eq!(
+        terms::accrue_penalty(&mut loan, ten_days_late + DAY_NANOS),
+        200
+    );
+}
+
+#[test]
+fn test_penalty_is_charged_on_unpaid_principal() {
+    let mut loan = fixed_term_loan(30);
+    loan.repaid_amount = 60_000;
+    let one_day_late = maturity(&loan) + DAY_NANOS;
+    assert_eq!(terms::accrue_penalty(&mut loan, one_day_late), 40);
+}
+
+#[test]
+fn test_open_ended_loans_are_never_penalized() {
+    let mut loan = fixed_term_loan(30);
+    loan.term = None;
+    assert_eq!(terms::accrue_penalty(&mut loan, u64::MAX), 0);
+    assert!(!terms::apply_term(&mut loan, u64::MAX));
+    assert_eq!(loan.status, LoanStatus::Active);
+}
+
+#[test]
+fn test_loan_defaults_after_the_grace_period() {
+    let mut loan = fixed_term_loan(30);
+    let end_of_grace = maturity(&loan) + GRACE_PERIOD_DAYS * DAY_NANOS;
+
+    assert!(!terms::apply_term(&mut loan, end_of_grace - 1));
+    assert_eq!(loan.status, LoanStatus::Active);
+
+    assert!(terms::apply_term(&mut loan, end_of_grace));
+    assert_eq!(loan.status, LoanStatus::Defaulted);
+
+    // Defaulted loans keep accruing penalties but default only once
+    let fees = loan.term.as_ref().unwrap().fees;
+    assert!(!terms::apply_term(&mut loan, end_of_grace + DAY_NANOS));
+    assert!(loan.term.as_ref().unwrap().fees > fees);
+}
+
+#[test]
+fn test_closed_loans_are_left_alone() {
+    let mut loan = fixed_term_loan(30);
+    loan.status = LoanStatus::Repaid;
+    assert!(!terms::apply_term(&mut loan, u64::MAX));
+    assert_eq!(loan.status, LoanStatus::Repaid);
+    assert_eq!(loan.term.as_ref().unwrap().fees, 0);
+}
+
+#[test]
+fn test_extension_moves_maturity_and_charges_a_fee() {
+    let mut loan = fixed_term_loan(30);
+    let before = calculate_loan_value(&loan).unwrap();
+    let old_maturity = maturity(&loan);
+
+    // 1% of the unpaid principal per 30 days
+    let fee = terms::extend(&mut loan, 30, NOW).unwrap();
+    assert_eq!(fee, 1_000);
+    assert_eq!(maturity(&loan), old_maturity + 30 * DAY_NANOS);
+    assert_eq!(loan.term.as_ref().unwrap().extensions, 1);
+    assert_eq!(calculate_loan_value(&loan).unwrap(), before + fee);
+}
+
+#[test]
+fn test_extension_during_grace_keeps_the_penalty_already_due() {
+    let mut loan = fixed_term_loan(30);
+    let three_days_late = maturity(&loan) + 3 * DAY_NANOS;
+
+    // Too short to bring the loan back before maturity
+    assert!(terms::extend(&mut loan, 2, three_days_late).is_err());
+
+    let fee = terms::extend(&mut loan, 14, three_days_late).unwrap();
+    let term = loan.term.clone().unwrap();
+    assert_eq!(term.fees, 300 + fee);
+    assert_eq!(term.penalty_days, 0);
+
+    // No penalty until the new maturity
+    assert_eq!(terms::accrue_penalty(&mut loan, term.maturity - 1), 0);
+}
+
+#[test]
+fn test_extension_is_refused_once_grace_has_run_out() {
+    let mut loan = fixed_term_loan(30);
+    let end_of_grace = maturity(&loan) + GRACE_PERIOD_DAYS * DAY_NANOS;
+
+    // The default timer may not have run yet, so the loan is still active
+    let result = terms::extend(&mut loan, 30, end_of_grace);
+    assert!(result.unwrap_err().contains("grace period"));
+    assert_eq!(loan.term.as_ref().unwrap().extensions, 0);
+
+    assert!(terms::extend(&mut loan, 30, end_of_grace - 1).is_ok());
+}
+
+#[test]
+fn test_extension_limits() {
+    let mut loan = fixed_term_loan(30);
+    assert!(terms::extend(&mut loan, 0, NOW).is_err());
+
+    for _ in 0..MAX_EXTENSIONS {
+        terms::extend(&mut loan, 7, NOW).unwrap();
+    }
+    let result = terms::extend(&mut loan, 7, NOW);
+    assert!(result.unwrap_err().contains("already been extended"));
+
+    let mut defaulted = fixed_term_loan(30);
+    defaulted.status = LoanStatus::Defaulted;
+    assert!(terms::extend(&mut defaulted, 30, NOW).is_err());
+
+    let mut open_ended = fixed_term_loan(30);
+    open_ended.term = None;
+    let result = terms::extend(&mut open_ended, 30, NOW);
+    assert_eq!(result, Err("Loan has no fixed term".to_string()));
+}
+
+#[test]
+fn test_repricing_keeps_late_fees_owed() {
+    let mut loan = fixed_term_loan(30);
+    let one_day_late = maturity(&loan) + DAY_NANOS;
+    terms::accrue_penalty(&mut loan, one_day_late);
+
+    // 100,000 at 4% plus the 100 penalty for the first overdue day
+    assert_eq!(reprice_loan(&mut loan, 400), Ok(104_100));
+    assert_eq!(loan.term.as_ref().unwrap().fees, 100);
+}
+
+#[test]
+fn test_renewed_term_carries_fees_and_extensions() {
+    let mut loan = fixed_term_loan(60);
+    terms::extend(&mut loan, 30, NOW).unwrap();
+    let current = loan.term.clone().unwrap();
+
+    let renewed = terms::renew(Some(&current), NOW + DAY_NANOS, 30).unwrap();
+    assert_eq!(renewed.maturity, NOW + 31 * DAY_NANOS);
+    assert_eq!(renewed.fees, current.fees);
+    assert_eq!(renewed.extensions, 1);
+
+    // An open-ended loan can take any valid term
+    let renewed = terms::renew(None, NOW, terms::MAX_TERM_DAYS).unwrap();
+    assert_eq!(renewed, terms::new_term(NOW, terms::MAX_TERM_DAYS));
+}
+
+#[test]
+fn test_renewal_cannot_push_back_maturity() {
+    let loan = fixed_term_loan(30);
+    let current = loan.term.as_ref().unwrap();
+
+    assert!(terms::renew(Some(current), NOW, 30).is_ok());
+    let result = terms::renew(Some(current), NOW + DAY_NANOS, 30);
+    assert!(result.unwrap_err().contains("extend the loan instead"));
+}
+
+#[test]
+fn test_past_maturity_starts_at_maturity() {
+    let loan = fixed_term_loan(30);
+    let term = loan.term.as_ref().unwrap();
+    assert!(!terms::is_past_maturity(term, term.maturity - 1));
+    assert!(terms::is_past_maturity(term, term.maturity));
+}
+
+#[test]
+fn test_refinance_rate_bounds_depend_on_the_term() {
+    assert_eq!(terms::rate_bounds(500, None), (500, 500));
+    assert_eq!(
+        terms::rate_bounds(500, Some(terms::MAX_TERM_DAYS)),
+        (500, 500)
+    );
+    assert_eq!(terms::rate_bounds(500, Some(180)), (450, 500));
+    assert_eq!(terms::rate_bounds(500, Some(7)), (425, 500));
+    assert_eq!(terms::rate_bounds(50, Some(7)), (0, 50));
+
+    assert!(terms::validate_rate(425, 500, Some(7)).is_ok());
+    assert!(terms::validate_rate(424, 500, Some(7)).is_err());
+    assert!(terms::validate_rate(501, 500, Some(7)).is_err());
+    assert!(terms::validate_rate(450, 500, None).is_err());
+}
+
+#[test]
+fn test_remaining_days_round_up() {
+    let term = terms::new_term(NOW, 30);
+    assert_eq!(terms::remaining_days(&term, NOW), 30);
+    assert_eq!(terms::remaining_days(&term, NOW + DAY_NANOS / 2), 30);
+    assert_eq!(terms::remaining_days(&term, NOW + 30 * DAY_NANOS), 0);
+}
@@ -0,0 +1,168 @@
+// Unit tests for time-locked collateral and the CLTV tapscript leaf
+
+use bitcoin::key::Keypair;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use candid::Principal;
+use vault::psbt::{self, PartialSignature, PsbtInputSpec, PsbtOutputSpec, TaprootSpend};
+use vault::timelock::{self, UnlockAction};
+use vault::types::{TimeLockConfig, UtxoStatus};
+
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+const NOW: u64 = 1_700_000_000_000_000_000; // Nov 2023 in nanoseconds
+const DAY: u64 = 86_400_000_000_000;
+
+fn lock(unlock_timestamp: u64) -> TimeLockConfig {
+    TimeLockConfig {
+        unlock_timestamp,
+        auto_withdraw: false,
+        beneficiary: None,
+    }
+}
+
+#[test]
+fn test_validate_time_lock() {
+    assert!(timelock::validate_time_lock(&lock(NOW + DAY), None, NOW).is_ok());
+    assert!(timelock::validate_time_lock(&lock(NOW), None, NOW).is_err());
+    assert!(timelock::validate_time_lock(&lock(NOW - DAY), None, NOW).is_err());
+
+    // Active locks can be extended but not shortened
+    let current = lock(NOW + 10 * DAY);
+    assert!(timelock::validate_time_lock(&lock(NOW + 20 * DAY), Some(&current), NOW).is_ok());
+    assert!(timelock::validate_time_lock(&lock(NOW + DAY), Some(&current), NOW).is_err());
+
+    // An expired lock can be replaced freely
+    let expired = lock(NOW - DAY);
+    assert!(timelock::validate_time_lock(&lock(NOW + DAY), Some(&expired), NOW).is_ok());
+}
+
+#[test]
+fn test_is_unlocked() {
+    let config = lock(NOW + DAY);
+    assert!(!timelock::is_unlocked(&config, NOW));
+    assert!(timelock::is_unlocked(&config, NOW + DAY));
+    assert!(timelock::is_unlocked(&config, NOW + 2 * DAY));
+}
+
+#[test]
+fn test_unlock_waits_while_the_utxo_is_collateral() {
+    let beneficiary = Principal::from_slice(&[7; 29]);
+    let config = TimeLockConfig {
+        beneficiary: Some(beneficiary),
+        ..lock(NOW)
+    };
+
+    let action = timelock::unlock_action(&config, Some(&UtxoStatus::Locked), NOW + DAY);
+    assert!(matches!(action, UnlockAction::Deferred(_)));
+
+    // Once the loan releases the UTXO the transfer goes ahead
+    assert_eq!(
+        timelock::unlock_action(&config, Some(&UtxoStatus::Deposited), NOW + DAY),
+        UnlockAction::TransferredTo(beneficiary)
+    );
+}
+
+#[test]
+fn test_unlock_actions() {
+    let deposited = Some(&UtxoStatus::Deposited);
+    assert!(matches!(
+        timelock::unlock_action(&lock(NOW + DAY), deposited, NOW),
+        UnlockAction::Skipped(_)
+    ));
+    assert_eq!(
+        timelock::unlock_action(&lock(NOW), deposited, NOW),
+        UnlockAction::Released
+    );
+
+    let auto = TimeLockConfig {
+        auto_withdraw: true,
+        ..lock(NOW)
+    };
+    assert_eq!(
+        timelock::unlock_action(&auto, deposited, NOW),
+        UnlockAction::AutoWithdrawn
+    );
+    assert!(matches!(
+        timelock::unlock_action(&auto, Some(&UtxoStatus::Withdrawn), NOW),
+        UnlockAction::Skipped(_)
+    ));
+    assert!(matches!(
+        timelock::unlock_action(&auto, None, NOW),
+        UnlockAction::Skipped(_)
+    ));
+}
+
+#[test]
+fn test_lock_time_from_timestamp() {
+    assert_eq!(
+        timelock::lock_time_from_timestamp(NOW).unwrap(),
+        1_700_000_000
+    );
+
+    // Values below 500M seconds would be read as block heights
+    assert!(timelock::lock_time_from_timestamp(1_000_000_000).is_err());
+    assert!(timelock::lock_time_from_timestamp(u64::MAX).is_err());
+}
+
+#[test]
+fn test_cltv_address_spend() {
+    let secp = Secp256k1::new();
+    let user = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[5u8; 32]).unwrap());
+    let user_key = user.x_only_public_key().0.serialize();
+
+    let locked = timelock::time_lock_address(&user_key, NOW + DAY).unwrap();
+    assert!(locked.address.starts_with("tb1p"));
+    assert_eq!(locked.lock_time, 1_700_086_400);
+
+    let leaf = timelock::cltv_leaf(&user_key, locked.lock_time).unwrap();
+    assert_eq!(leaf.to_bytes(), locked.leaf_script);
+    assert!(leaf.to_asm_string().contains("OP_CLTV"));
+
+    // Spending must set nLockTime to at least the leaf's locktime
+    let input = PsbtInputSpec {
+        txid: TXID.to_string(),
+        vout: 0,
+        amount: 50_000,
+        script_pubkey: psbt::parse_address(&locked.address)
+            .unwrap()
+            .script_pubkey()
+            .to_bytes(),
+        sequence: None,
+        taproot: Some(TaprootSpend::ScriptPath {
+            internal_key: locked.internal_key.clone(),
+            leaf_script: locked.leaf_script.clone(),
+            control_block: locked.control_block.clone(),
+        }),
+    };
+    let output = PsbtOutputSpec {
+        address: locked.address.clone(),
+        amount: 45_000,
+    };
+    let mut built = psbt::build_psbt(&[input], &[output], locked.lock_time).unwrap();
+    assert!(built.unsigned_tx.input[0]
+        .sequence
+        .enables_absolute_lock_time());
+
+    let sighash = psbt::taproot_sighash(&built, 0, Some(&locked.leaf_script)).unwrap();
+    let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &user);
+    psbt::add_partial_signature(
+        &mut built,
+        0,
+        &PartialSignature::TaprootScript {
+            public_key: user_key.to_vec(),
+            leaf_script: locked.leaf_script.clone(),
+            signature: sig.as_ref().to_vec(),
+        },
+    )
+    .unwrap();
+    psbt::finalize(&mut built).unwrap();
+    assert_eq!(
+        built.inputs[0].final_script_witness.as_ref().unwrap().len(),
+        3
+    );
+}
+
+#[test]
+fn test_rejects_invalid_user_key() {
+    assert!(timelock::time_lock_address(&[0u8; 31], NOW + DAY).is_err());
+    assert!(timelock::cltv_leaf(&[0u8; 32], 1_700_000_000).is_err());
+}
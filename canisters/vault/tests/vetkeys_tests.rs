@@ -0,0 +1,180 @@
+// Unit tests for vetKD key derivation and AES-GCM encryption using the local stand-in
+
+use vault::vetkeys::{self, LocalVetKd, TransportSecretKey};
+
+const NONCE: [u8; vetkeys::NONCE_LEN] = [3u8; vetkeys::NONCE_LEN];
+
+fn derive(vetkd: &LocalVetKd, input: &[u8], transport_seed: u8) -> [u8; 32] {
+    let transport = TransportSecretKey::from_seed(&[transport_seed; 32]);
+    let encrypted = vetkd
+        .derive_key(input, vetkeys::CONTEXT, &transport.public_key(), &[9u8; 32])
+        .unwrap();
+    let vetkey = transport
+        .decrypt_and_verify(&encrypted, &vetkd.public_key(vetkeys::CONTEXT), input)
+        .unwrap();
+    vetkey.derive_symmetric_key(vetkeys::SYMMETRIC_KEY_DOMAIN)
+}
+
+#[test]
+fn test_derived_key_is_independent_of_transport_key() {
+    let vetkd = LocalVetKd::new(&[1u8; 32]);
+
+    // Different transport keys must unwrap to the same vetKey
+    assert_eq!(
+        derive(&vetkd, b"user_alice", 1),
+        derive(&vetkd, b"user_alice", 2)
+    );
+
+    // Different identities get different keys
+    assert_ne!(
+        derive(&vetkd, b"user_alice", 1),
+        derive(&vetkd, b"user_bob", 1)
+    );
+}
+
+#[test]
+fn test_wrong_transport_key_or_input_is_rejected() {
+    let vetkd = LocalVetKd::new(&[1u8; 32]);
+    let transport = TransportSecretKey::from_seed(&[1u8; 32]);
+    let other = TransportSecretKey::from_seed(&[2u8; 32]);
+    let public_key = vetkd.public_key(vetkeys::CONTEXT);
+
+    let encrypted = vetkd
+        .derive_key(
+            b"user_alice",
+            vetkeys::CONTEXT,
+            &transport.public_key(),
+            &[9u8; 32],
+        )
+        .unwrap();
+    assert_eq!(encrypted.len(), 192);
+
+    assert!(other
+        .decrypt_and_verify(&encrypted, &public_key, b"user_alice")
+        .is_err());
+    assert!(transport
+        .decrypt_and_verify(&encrypted, &public_key, b"user_bob")
+        .is_err());
+    assert!(transport
+        .decrypt_and_verify(&encrypted[..100], &public_key, b"user_alice")
+        .is_err());
+
+    // Keys from another deployment do not verify
+    let foreign = LocalVetKd::new(&[2u8; 32]).public_key(vetkeys::CONTEXT);
+    assert!(transport
+        .decrypt_and_verify(&encrypted, &foreign, b"user_alice")
+        .is_err());
+}
+
+#[test]
+fn test_aes_gcm_roundtrip() {
+    let key = derive(&LocalVetKd::new(&[1u8; 32]), b"user_alice", 1);
+    let ciphertext = vetkeys::encrypt_with_key(&key, &NONCE, b"my seed phrase", b"alice").unwrap();
+
+    assert_eq!(ciphertext[0], vetkeys::CIPHERTEXT_VERSION);
+    assert_eq!(&ciphertext[1..13], &NONCE);
+    assert!(!ciphertext.windows(14).any(|w| w == b"my seed phrase"));
+
+    let plaintext = vetkeys::decrypt_with_key(&key, &ciphertext, b"alice").unwrap();
+    assert_eq!(plaintext, b"my seed phrase");
+}
+
+#[test]
+fn test_aes_gcm_rejects_tampering() {
+    let vetkd = LocalVetKd::new(&[1u8; 32]);
+    let key = derive(&vetkd, b"user_alice", 1);
+    let ciphertext = vetkeys::encrypt_with_key(&key, &NONCE, b"secret", b"alice").unwrap();
+
+    // Wrong associated data
+    assert!(vetkeys::decrypt_with_key(&key, &ciphertext, b"bob").is_err());
+
+    // Wrong key
+    let bob_key = derive(&vetkd, b"user_bob", 1);
+    assert!(vetkeys::decrypt_with_key(&bob_key, &ciphertext, b"alice").is_err());
+
+    // Flipped ciphertext bit
+    let mut tampered = ciphertext.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(vetkeys::decrypt_with_key(&key, &tampered, b"alice").is_err());
+
+    // Unknown version and truncated input
+    let mut wrong_version = ciphertext.clone();
+    wrong_version[0] = 0xff;
+    assert!(vetkeys::decrypt_with_key(&key, &wrong_version, b"alice").is_err());
+    assert!(vetkeys::decrypt_with_key(&key, &ciphertext[..20], b"alice").is_err());
+}
+
+#[test]
+fn test_nonces_change_ciphertext() {
+    let key = derive(&LocalVetKd::new(&[1u8; 32]), b"user_alice", 1);
+    let a = vetkeys::encrypt_with_key(&key, &[1u8; 12], b"same", b"").unwrap();
+    let b = vetkeys::encrypt_with_key(&key, &[2u8; 12], b"same", b"").unwrap();
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_key_version_is_read_from_header() {
+    let key = derive(&LocalVetKd::new(&[1u8; 32]), b"user_alice", 1);
+    let ciphertext =
+        vetkeys::encrypt_with_key_version(&key, 3, &NONCE, b"secret", b"alice").unwrap();
+
+    assert_eq!(ciphertext[0], vetkeys::KEYED_CIPHERTEXT_VERSION);
+    assert_eq!(vetkeys::ciphertext_key_version(&ciphertext).unwrap(), 3);
+    assert_eq!(
+        vetkeys::decrypt_with_key(&key, &ciphertext, b"alice").unwrap(),
+        b"secret"
+    );
+
+    // The key version is authenticated
+    let mut relabelled = ciphertext.clone();
+    relabelled[4] = 2;
+    assert_eq!(vetkeys::ciphertext_key_version(&relabelled).unwrap(), 2);
+    assert!(vetkeys::decrypt_with_key(&key, &relabelled, b"alice").is_err());
+
+    // Unversioned ciphertexts were made with the initial key
+    let legacy = vetkeys::encrypt_with_key(&key, &NONCE, b"secret", b"alice").unwrap();
+    assert_eq!(
+        vetkeys::ciphertext_key_version(&legacy).unwrap(),
+        vetkeys::INITIAL_KEY_VERSION
+    );
+}
+
+#[test]
+fn test_versioned_key_ids() {
+    let alice = candid::Principal::from_slice(&[1; 29]);
+
+    // The initial version keeps the pre-versioning input
+    assert_eq!(
+        vetkeys::versioned_key_id(alice, vetkeys::INITIAL_KEY_VERSION),
+        vetkeys::user_key_id(alice)
+    );
+    assert_ne!(
+        vetkeys::versioned_key_id(alice, 2),
+        vetkeys::versioned_key_id(alice, 3)
+    );
+
+    let vetkd = LocalVetKd::new(&[1u8; 32]);
+    assert_ne!(
+        derive(&vetkd, vetkeys::versioned_key_id(alice, 1).as_bytes(), 1),
+        derive(&vetkd, vetkeys::versioned_key_id(alice, 2).as_bytes(), 1)
+    );
+}
+
+#[test]
+fn test_wrapped_key_must_name_the_current_version() {
+    let key = derive(&LocalVetKd::new(&[1u8; 32]), b"user_alice", 1);
+    let wrapped = vetkeys::encrypt_with_key_version(&key, 2, &NONCE, &[7u8; 32], b"").unwrap();
+
+    assert!(vetkeys::validate_wrapped_key(&wrapped, 2).is_ok());
+    assert!(vetkeys::validate_wrapped_key(&wrapped, 1).is_err());
+    assert!(vetkeys::validate_wrapped_key(&wrapped, 3).is_err());
+
+    // Unversioned, empty and oversized keys are refused
+    let legacy = vetkeys::encrypt_with_key(&key, &NONCE, &[7u8; 32], b"").unwrap();
+    assert!(vetkeys::validate_wrapped_key(&legacy, vetkeys::INITIAL_KEY_VERSION).is_err());
+    assert!(vetkeys::validate_wrapped_key(&wrapped[..5], 2).is_err());
+    let mut oversized = wrapped.clone();
+    oversized.resize(vetkeys::MAX_WRAPPED_KEY_SIZE + 1, 0);
+    assert!(vetkeys::validate_wrapped_key(&oversized, 2).is_err());
+}